@@ -0,0 +1,171 @@
+//! Writing dotme's own files without losing them to a crash
+//!
+//! The config and the symlink state are written to a temporary file next to them, which is then
+//! renamed over the old one, so a crash mid-write leaves either the old or the new version, never
+//! half of one. The previous version is kept as `<file>.bak`. If a file can't be parsed when it is
+//! loaded anyway, e.g. after a disk error, it is moved aside to `<file>.corrupt` and the backup is
+//! put in its place.
+//!
+//! A file that is a symlink, such as a config kept in a dotfiles repository, is written through
+//! the link rather than replacing it.
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// `path` with `suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Path of the previous version of a file, e.g. `symlinks.yml.bak`
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Path a file that can't be parsed is moved to, e.g. `symlinks.yml.corrupt`
+fn corrupt_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".corrupt")
+}
+
+/// The file a symlink at `path` points to, or `path` itself
+fn resolve(path: &Path) -> PathBuf {
+    match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_symlink() => {
+            std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Replace the contents of the file at `path` in one step, keeping the previous version as its
+/// backup
+pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let path = resolve(path);
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()
+    })();
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to write {}", temp.display()));
+    }
+
+    if path.exists() {
+        // A second name for the old contents, which the rename below leaves in place
+        let backup = backup_path(&path);
+        let _ = std::fs::remove_file(&backup);
+        if std::fs::hard_link(&path, &backup).is_err() {
+            std::fs::copy(&path, &backup)
+                .with_context(|| format!("Failed to back up {}", path.display()))?;
+        }
+    }
+
+    if let Err(e) = std::fs::rename(&temp, &path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to replace {}", path.display()));
+    }
+
+    // Make the rename itself survive a crash
+    #[cfg(unix)]
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = std::fs::File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+/// Put the backup of the file at `path` in its place after loading the file failed with `error`,
+/// if `load` accepts the backup; the broken file is kept as `<file>.corrupt`
+pub fn recover<T>(path: &Path, error: anyhow::Error, load: impl Fn() -> Result<T>) -> Result<T> {
+    let path = resolve(path);
+    let backup = backup_path(&path);
+    if !path.exists() || !backup.exists() {
+        return Err(error);
+    }
+
+    let corrupt = corrupt_path(&path);
+    std::fs::rename(&path, &corrupt)
+        .with_context(|| format!("Failed to move {} aside", path.display()))?;
+    std::fs::copy(&backup, &path)
+        .with_context(|| format!("Failed to restore {}", backup.display()))?;
+
+    match load() {
+        Ok(value) => {
+            log::warn!(
+                "{} couldn't be loaded ({:#}), so its backup was restored. The broken file was kept as {}.",
+                path.display(),
+                error,
+                corrupt.display()
+            );
+            Ok(value)
+        }
+        Err(_) => {
+            // The backup is no better, leave everything as it was
+            std::fs::rename(&corrupt, &path)
+                .with_context(|| format!("Failed to move {} back", corrupt.display()))?;
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writes_keep_a_backup_to_recover_from() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.yml");
+        let load = || -> Result<u32> { Ok(std::fs::read_to_string(&path)?.trim().parse()?) };
+
+        write(&path, b"1").unwrap();
+        write(&path, b"2").unwrap();
+        assert_eq!(std::fs::read_to_string(backup_path(&path)).unwrap(), "1");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // A corrupt file is replaced by the backup, and kept aside
+        std::fs::write(&path, "garbage").unwrap();
+        let error = load().unwrap_err();
+        assert_eq!(recover(&path, error, load).unwrap(), 1);
+        assert_eq!(
+            std::fs::read_to_string(corrupt_path(&path)).unwrap(),
+            "garbage"
+        );
+
+        // Unless the backup is broken too
+        std::fs::write(backup_path(&path), "garbage").unwrap();
+        std::fs::write(&path, "more garbage").unwrap();
+        let error = load().unwrap_err();
+        assert!(recover(&path, error, load).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "more garbage");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_files_are_written_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("repo-config.yml");
+        let link = dir.path().join("config.yml");
+        std::fs::write(&real, "old").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        write(&link, b"new").unwrap();
+        assert!(link.symlink_metadata().unwrap().is_symlink());
+        assert_eq!(std::fs::read_to_string(&real).unwrap(), "new");
+    }
+}
@@ -0,0 +1,201 @@
+//! Team baseline configs for dotme
+//!
+//! A config can reference a shared baseline config by URL (or local path). The baseline is
+//! re-fetched on every `dotme update` and cached in `~/.dotme`, so it keeps working offline.
+//! Its entries are applied read-only: they are never written to the local config, and a local
+//! entry with the same source overrides the baseline entry. Their `scripts` are dropped, as the
+//! baseline can change without anyone looking; copy an entry into the local config to run them.
+//!
+//! ```yaml
+//! baseline: https://example.com/team/dotme.yml
+//! ```
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::config::{Config, DotfileEntry};
+use crate::dotfiles;
+use crate::scripts::Scripts;
+
+/// Get the cache file of a baseline, keeping the extension so its format is detected
+fn cache_path(source: &str) -> Result<PathBuf> {
+    let name = source.rsplit('/').next().unwrap_or_default();
+    let ext = Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| ["yml", "yaml", "toml", "json"].contains(ext))
+        .unwrap_or("yml");
+
+    Ok(dotfiles::get_dotme_dir()?.join(format!("baseline.{}", ext)))
+}
+
+/// Local path of a baseline that isn't fetched over HTTP
+fn local_source(source: &str) -> Option<PathBuf> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        None
+    } else {
+        let path = source.strip_prefix("file://").unwrap_or(source);
+        Some(crate::config::expand_home(Path::new(path)))
+    }
+}
+
+/// Fetch the baseline into the cache, keeping the cached copy if it can't be fetched or parsed
+pub async fn fetch(source: &str) -> Result<()> {
+    fetch_into(source, &cache_path(source)?).await
+}
+
+/// Fetch the baseline into the cache file at `path`
+async fn fetch_into(source: &str, path: &Path) -> Result<()> {
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    let download = path.with_extension(format!("download.{}", ext));
+
+    if let Some(local) = local_source(source) {
+        fs::copy(&local, &download)
+            .await
+            .with_context(|| format!("Failed to read baseline {}", local.display()))?;
+    } else {
+        let output = Command::new("curl")
+            .args(["-fsSL", "--output"])
+            .arg(&download)
+            .arg(source)
+            .output()
+            .await
+            .context("Failed to run curl")?;
+
+        if !output.status.success() {
+            let _ = fs::remove_file(&download).await;
+            anyhow::bail!(
+                "Failed to fetch baseline: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+
+    // Only replace the cached baseline with one that parses
+    if let Err(e) = Config::load(Some(download.clone())) {
+        let _ = fs::remove_file(&download).await;
+        return Err(e.context("Baseline is not a valid dotme config"));
+    }
+
+    fs::rename(&download, path)
+        .await
+        .context("Failed to save baseline")?;
+    log::debug!("Saved baseline to {}", path.display());
+
+    Ok(())
+}
+
+/// Entries of the cached baseline that aren't overridden by a local entry
+pub fn entries(config: &Config) -> Result<Vec<DotfileEntry>> {
+    let Some(source) = &config.baseline else {
+        return Ok(Vec::new());
+    };
+
+    let path = cache_path(source)?;
+    if !path.exists() {
+        log::warn!("Baseline has not been fetched yet. Run 'dotme update' to fetch it.");
+        return Ok(Vec::new());
+    }
+
+    cached_entries(config, &path)
+}
+
+/// Entries of the baseline cached at `path` that aren't overridden by a local entry
+fn cached_entries(config: &Config, path: &Path) -> Result<Vec<DotfileEntry>> {
+    let baseline = Config::load(Some(path.to_path_buf()))?;
+    let mut entries = Vec::new();
+    for mut entry in baseline.dotfiles {
+        if config.dotfiles.iter().any(|e| e.source == entry.source) {
+            log::debug!("Baseline entry overridden locally: {}", entry.source);
+            continue;
+        }
+
+        if !entry.scripts.is_default() {
+            log::warn!(
+                "Not running the scripts of baseline entry {}. Add it to your config to run them.",
+                entry.source
+            );
+            entry.scripts = Scripts::default();
+        }
+        dotfiles::localize_entry(&mut entry)?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Baseline config with a directory entry for each of `sources`
+    fn baseline(sources: &[&Path]) -> String {
+        let mut baseline = "dotfiles:\n".to_string();
+        for source in sources {
+            let source = source.display();
+            baseline.push_str(&format!(
+                "  - source: {source}\n    target: {source}\n    path: {source}\n    type: directory\n"
+            ));
+        }
+        baseline
+    }
+
+    #[test]
+    fn test_local_entries_override_baseline_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (shared, own) = (dir.path().join("shared"), dir.path().join("own"));
+        let cache = dir.path().join("baseline.yml");
+        std::fs::write(&cache, baseline(&[&shared, &own])).unwrap();
+
+        let local: Config = serde_yaml::from_str(&baseline(&[&own])).unwrap();
+
+        let entries = cached_entries(&local, &cache).unwrap();
+        let sources: Vec<_> = entries.iter().map(|e| PathBuf::from(&e.source)).collect();
+        assert_eq!(sources, vec![shared]);
+    }
+
+    #[tokio::test]
+    async fn test_cached_baseline_is_kept_when_it_cant_be_fetched() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("baseline.yml");
+        let cached = baseline(&[dir.path()]);
+        std::fs::write(&cache, &cached).unwrap();
+
+        // Nothing listens on the discard port, so curl fails
+        let fetched = fetch_into("http://127.0.0.1:9/team/dotme.yml", &cache).await;
+        assert!(fetched.is_err());
+        assert_eq!(std::fs::read_to_string(&cache).unwrap(), cached);
+        assert!(!dir.path().join("baseline.download.yml").exists());
+        assert_eq!(cached_entries(&Config::default(), &cache).unwrap().len(), 1);
+
+        // An invalid baseline doesn't replace the cached one either
+        let broken = dir.path().join("broken.yml");
+        std::fs::write(&broken, "dotfiles: 42").unwrap();
+        assert!(fetch_into(broken.to_str().unwrap(), &cache).await.is_err());
+        assert_eq!(std::fs::read_to_string(&cache).unwrap(), cached);
+    }
+
+    #[test]
+    fn test_scripts_of_baseline_entries_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("baseline.yml");
+        let dotfiles = dir.path().display();
+        let baseline = format!(
+            "dotfiles:
+  - source: {dotfiles}
+    target: {dotfiles}
+    path: {dotfiles}
+    type: directory
+    scripts:
+      install: curl -fsSL https://example.com/setup.sh | sh
+"
+        );
+        std::fs::write(&cache, baseline).unwrap();
+
+        let entries = cached_entries(&Config::default(), &cache).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].scripts, Scripts::default());
+    }
+}
@@ -0,0 +1,259 @@
+//! Portable bundles of a dotme setup
+//!
+//! `dotme export` packs `config.yml` and `symlinks.yml` (and with `--repos`, the clones and
+//! mirrors dotme keeps in `~/.dotme`) into a `.tar.gz`, and `dotme import` unpacks it into
+//! `~/.dotme` on another machine, so it can be set up without network access. Bundles are
+//! written and read with the `tar` command. Entries marked `private: true` are left out, along
+//! with their links and clones.
+//!
+//! ```text
+//! config.yml
+//! symlinks.yml
+//! git/dotfiles/...
+//! remote/dotfiles/...
+//! ```
+
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::dotfiles;
+use crate::sources;
+use crate::symlinks::{self, SymlinkState};
+
+/// Files of the dotme directory that make up a bundle
+const STATE_FILES: &[&str] = &["config.yml", "symlinks.yml"];
+
+/// Run tar with `args`
+async fn tar(args: &[&std::ffi::OsStr]) -> Result<String> {
+    let output = Command::new("tar")
+        .args(args)
+        .output()
+        .await
+        .context("Failed to run tar. Is it installed?")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "tar failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Check that an archive member unpacks inside the directory it is extracted to
+fn is_contained(member: &str) -> bool {
+    Path::new(member)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Clones and mirrors of the dotme directory to put in a bundle, relative to it
+fn repo_paths(config: &Config, dotme_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in config.dotfiles.iter().filter(|entry| !entry.private) {
+        let Some(copy) = sources::provider(entry.r#type).managed_copy(entry, &config.paths)? else {
+            continue;
+        };
+        if !copy.exists() {
+            log::warn!("Not bundling {}: it hasn't been fetched yet", entry.source);
+            continue;
+        }
+        match copy.strip_prefix(dotme_dir) {
+            Ok(relative) => paths.push(relative.to_path_buf()),
+            Err(_) => log::warn!(
+                "Not bundling {}: {} is outside {}",
+                entry.source,
+                copy.display(),
+                dotme_dir.display()
+            ),
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Write the config and symlink state without the private entries and their links to `dir`.
+/// Comments are dropped from the config, as they may name the private entries.
+async fn write_public_state(config: &Config, private: &[PathBuf], dir: &Path) -> Result<()> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .context("Failed to create export directory")?;
+
+    let mut config = config.clone();
+    config.dotfiles.retain(|entry| !entry.private);
+    tokio::fs::write(
+        dir.join("config.yml"),
+        serde_yaml::to_string(&config).context("Failed to serialize config")?,
+    )
+    .await
+    .context("Failed to write config for export")?;
+
+    let mut state = SymlinkState::load().await?;
+    state
+        .symlinks
+        .retain(|link| !private.iter().any(|root| link.target.starts_with(root)));
+    tokio::fs::write(
+        dir.join("symlinks.yml"),
+        serde_yaml::to_string(&state).context("Failed to serialize symlink state")?,
+    )
+    .await
+    .context("Failed to write symlink state for export")?;
+
+    Ok(())
+}
+
+/// Pack the config, the symlink state and optionally the managed clones into `file`
+pub async fn export(file: &Path, repos: bool) -> Result<()> {
+    let config_path = dotfiles::get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path.clone()))?;
+    let dotme_dir = dotfiles::get_dotme_dir()?;
+    let state_files: Vec<PathBuf> = STATE_FILES
+        .iter()
+        .map(PathBuf::from)
+        .filter(|file| {
+            file == Path::new("config.yml")
+                || symlinks::state_path().is_ok_and(|path| path.exists())
+        })
+        .collect();
+    let repos = if repos {
+        repo_paths(&config, &dotme_dir)?
+    } else {
+        Vec::new()
+    };
+
+    // With private entries, the config and state are bundled without them. The XDG layout keeps
+    // the config in its own directory, and a state kept in SQLite is bundled as `symlinks.yml`,
+    // so those are staged too.
+    let private = dotfiles::private_roots(&config)?;
+    let state_dir = if private.is_empty()
+        && config_path.parent() == Some(dotme_dir.as_path())
+        && symlinks::state_path()? == dotme_dir.join("symlinks.yml")
+    {
+        dotme_dir.clone()
+    } else {
+        let staging = dotme_dir.join("export.tmp");
+        write_public_state(&config, &private, &staging).await?;
+        staging
+    };
+
+    let mut args = vec![
+        "-czf".as_ref(),
+        file.as_os_str(),
+        "-C".as_ref(),
+        state_dir.as_os_str(),
+    ];
+    args.extend(state_files.iter().map(|path| path.as_os_str()));
+    if !repos.is_empty() {
+        args.extend(["-C".as_ref(), dotme_dir.as_os_str()]);
+        args.extend(repos.iter().map(|path| path.as_os_str()));
+    }
+    let result = tar(&args).await;
+
+    if state_dir != dotme_dir {
+        let _ = tokio::fs::remove_dir_all(&state_dir).await;
+    }
+    result?;
+
+    let paths: Vec<&PathBuf> = state_files.iter().chain(&repos).collect();
+
+    log::info!("✓ Exported {} item(s) to {}", paths.len(), file.display());
+    for path in &paths {
+        log::debug!("Bundled {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Unpack a bundle made by `export` into the dotme directory
+pub async fn import(file: &Path, force: bool) -> Result<()> {
+    let listing = tar(&["-tzf".as_ref(), file.as_os_str()]).await?;
+    let members: Vec<&str> = listing.lines().collect();
+
+    if !members
+        .iter()
+        .any(|member| member.trim_start_matches("./") == "config.yml")
+    {
+        anyhow::bail!("{} is not a dotme bundle (no config.yml)", file.display());
+    }
+    if let Some(member) = members.iter().find(|member| !is_contained(member)) {
+        anyhow::bail!(
+            "{} contains '{}', which would be unpacked outside the dotme directory",
+            file.display(),
+            member
+        );
+    }
+
+    let config_path = dotfiles::get_config_path()?;
+    if config_path.exists() && !force {
+        anyhow::bail!(
+            "DotMe is already initialized at {}. Use --force to replace its config and state.",
+            dotfiles::get_dotme_dir()?.display()
+        );
+    }
+
+    let dotme_dir = dotfiles::get_dotme_dir()?;
+    tokio::fs::create_dir_all(&dotme_dir)
+        .await
+        .context("Failed to create .dotme directory")?;
+    tar(&[
+        "-xzf".as_ref(),
+        file.as_os_str(),
+        "-C".as_ref(),
+        dotme_dir.as_os_str(),
+    ])
+    .await?;
+
+    // The bundled state replaces one kept in SQLite, which is rebuilt from it on the next run
+    let state_db = dotme_dir.join("state.db");
+    if state_db.exists()
+        && members
+            .iter()
+            .any(|member| member.trim_start_matches("./") == "symlinks.yml")
+    {
+        tokio::fs::remove_file(&state_db)
+            .await
+            .with_context(|| format!("Failed to remove {}", state_db.display()))?;
+    }
+
+    // The XDG layout keeps the config in its own directory
+    let unpacked_config = dotme_dir.join("config.yml");
+    if unpacked_config != config_path {
+        if let Some(config_dir) = config_path.parent() {
+            tokio::fs::create_dir_all(config_dir)
+                .await
+                .context("Failed to create config directory")?;
+        }
+        tokio::fs::rename(&unpacked_config, &config_path)
+            .await
+            .with_context(|| format!("Failed to move the config to {}", config_path.display()))?;
+    }
+
+    log::info!("✓ Imported {} into {}", file.display(), dotme_dir.display());
+    log::info!(
+        "Run 'dotme relink' to create the links, or 'dotme update' to also fetch sources that weren't bundled."
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_members_outside_the_dotme_dir_are_rejected() {
+        assert!(is_contained("config.yml"));
+        assert!(is_contained("./git/dotfiles/.vimrc"));
+        assert!(!is_contained("../.bashrc"));
+        assert!(!is_contained("git/../../.bashrc"));
+        assert!(!is_contained("/etc/passwd"));
+    }
+}
@@ -1,7 +1,14 @@
-use clap::{Parser, Subcommand};
+use clap::builder::ArgPredicate;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use console::style;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use crate::config::{Config, ConflictPolicy, LinkDepth, LinkMode, Naming, Os, UpdatePolicy};
+use crate::dotfiles::ListSort;
+use crate::packages::PackageManager;
+
 pub const VERSION_NUMBER: &str = env!("CARGO_PKG_VERSION");
 pub const AUTHOR: &str = env!("CARGO_PKG_AUTHORS");
 
@@ -16,10 +23,22 @@ ________          __     _____
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Arguments {
-    /// Enable Debugging
+    /// Enable Debugging (same as -v)
     #[clap(long, env, default_value_t = false)]
     pub debug: bool,
 
+    /// Log more: -v for debug messages, -vv for everything
+    #[clap(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Log less: -q for warnings and errors only, -qq for errors only
+    #[clap(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    pub quiet: u8,
+
+    /// Format of log lines; json writes one object per line for automation to parse
+    #[clap(long, value_enum, env = "DOTME_LOG_FORMAT", default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+
     /// Disable Banner
     #[clap(long, default_value_t = false)]
     pub disable_banner: bool,
@@ -28,63 +47,774 @@ pub struct Arguments {
     #[clap(short, long, env)]
     pub config: Option<PathBuf>,
 
+    /// Enable an opt-in feature for this run (see `dotme features list`)
+    #[clap(long = "enable-feature", value_name = "FEATURE", global = true)]
+    pub enable_features: Vec<String>,
+
+    /// Record every linking decision of this run to a JSON file, e.g. for bug reports
+    #[clap(long, value_name = "FILE", global = true)]
+    pub trace_file: Option<PathBuf>,
+
+    /// Answer prompts with their defaults instead of asking, e.g. in scripts and CI
+    #[clap(
+        short,
+        long,
+        env = "DOTME_NONINTERACTIVE",
+        value_parser = clap::builder::FalseyValueParser::new(),
+        global = true
+    )]
+    pub yes: bool,
+
+    /// Use the separate setup in this directory: its own config, clones, and symlink state
+    #[clap(long, env = "DOTME_ROOT", value_name = "DIR", global = true)]
+    pub root: Option<PathBuf>,
+
     /// Subcommands
     #[clap(subcommand)]
     pub commands: Option<ArgumentCommands>,
 }
 
+/// Format of log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// `[time LEVEL target] message`
+    #[default]
+    Text,
+    /// `{"time": ..., "level": ..., "target": ..., "message": ...}`
+    Json,
+}
+
+impl Arguments {
+    /// Level to log at, raised by -v and --debug and lowered by -q
+    fn log_level(&self) -> log::LevelFilter {
+        let verbose = self.verbose.max(u8::from(self.debug));
+        match i16::from(verbose) - i16::from(self.quiet) {
+            ..=-2 => log::LevelFilter::Error,
+            -1 => log::LevelFilter::Warn,
+            0 => log::LevelFilter::Info,
+            1 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        }
+    }
+
+    /// Check if the command writes data to stdout, which the banner would end up in
+    fn writes_to_stdout(&self) -> bool {
+        matches!(
+            self.commands,
+            Some(ArgumentCommands::Manifest { output: None, .. })
+                | Some(ArgumentCommands::Completions {
+                    install: false,
+                    uninstall: false,
+                    ..
+                })
+        )
+    }
+
+    /// Check if the command may change links, clones, or the state, so it has to hold the lock
+    pub fn changes_state(&self) -> bool {
+        !matches!(
+            self.commands,
+            None | Some(ArgumentCommands::Status { .. })
+                | Some(ArgumentCommands::Show { .. })
+                | Some(ArgumentCommands::List { .. })
+                | Some(ArgumentCommands::Outdated { .. })
+                | Some(ArgumentCommands::Manifest { .. })
+                | Some(ArgumentCommands::Export { .. })
+                | Some(ArgumentCommands::Features { .. })
+                | Some(ArgumentCommands::Config { .. })
+                | Some(ArgumentCommands::Packages { .. })
+                | Some(ArgumentCommands::Schedule { .. })
+                | Some(ArgumentCommands::Stats)
+                | Some(ArgumentCommands::History { .. })
+                | Some(ArgumentCommands::Snapshot {
+                    command: SnapshotCommands::List,
+                })
+                | Some(ArgumentCommands::State {
+                    command: StateCommands::Fsck { repair: false },
+                })
+                | Some(ArgumentCommands::Completions {
+                    install: false,
+                    uninstall: false,
+                    ..
+                })
+        )
+    }
+}
+
 #[derive(Subcommand, Debug)]
+// Parsed once per run, so the size of `Add` doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum ArgumentCommands {
     /// Initialize dotfiles management
     Init,
     /// Add a file, directory, or git repository to dotfiles management
     Add {
         /// Path to file, directory, or git repository URL
-        source: String,
+        #[clap(required_unless_present = "from_config")]
+        source: Option<String>,
+        /// Merge entries from another dotme config file, asking for each one
+        #[clap(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["source", "target", "path", "folders", "clone_path", "branch", "rev", "depth", "no_submodules", "name"]
+        )]
+        from_config: Option<PathBuf>,
+
+        /// Short name to refer to the entry by instead of its source, e.g. in update or remove
+        #[clap(long)]
+        name: Option<String>,
+        /// Labels grouping related entries, e.g. "shell,work" (comma-separated)
+        #[clap(long = "tag", value_name = "TAG", value_delimiter = ',')]
+        tags: Vec<String>,
         /// Optional target location (defaults to home directory)
         #[clap(short, long)]
         target: Option<PathBuf>,
         /// Path where symlinks should be created (defaults to current working directory)
         #[clap(short, long)]
         path: Option<PathBuf>,
-        /// Select specific folders from git repository (comma-separated, e.g., "dev,geek")
+        /// Select specific folders or files from git repository (comma-separated, e.g.,
+        /// "dev,zsh/aliases.zsh")
         #[clap(short, long, value_delimiter = ',')]
         folders: Option<Vec<String>>,
+        /// Glob patterns to exclude from linking (comma-separated, e.g., "README.md,*.png")
+        #[clap(short, long, value_delimiter = ',')]
+        exclude: Option<Vec<String>>,
+        /// Only link files and directories matching these glob patterns (comma-separated,
+        /// e.g., ".config/*,.zshrc")
+        #[clap(long, value_delimiter = ',')]
+        include: Option<Vec<String>>,
+        /// Branch or tag to check out (git repositories only)
+        #[clap(short, long)]
+        branch: Option<String>,
+        /// Commit to pin the repository to; `update` will not move past it (git repositories only)
+        #[clap(long)]
+        rev: Option<String>,
+        /// Create a shallow clone with history truncated to this many commits (git repositories only)
+        #[clap(long)]
+        depth: Option<u32>,
+        /// Clone the repository here instead of ~/.dotme/git (git repositories only)
+        #[clap(long, value_name = "PATH", conflicts_with = "target")]
+        clone_path: Option<PathBuf>,
+        /// Leave the repository's submodules alone, saved on the entry (git repositories only)
+        #[clap(long, default_value_t = false)]
+        no_submodules: bool,
+        /// Short description of what the entry is for
+        #[clap(short, long)]
+        description: Option<String>,
+        /// Freeform notes about the entry
+        #[clap(long)]
+        notes: Option<String>,
+        /// What to do when a plain file is in the way of a symlink, saved on the entry
+        #[clap(long, value_enum, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+        /// Back up plain files that are in the way of a symlink instead of skipping them
+        #[clap(long, default_value_t = false)]
+        force: bool,
+        /// Create symlinks with relative target paths (same as symlinks.relative: true)
+        #[clap(long, default_value_t = false)]
+        relative: bool,
+        /// Copy or hardlink files instead of symlinking them, saved on the entry
+        #[clap(long, value_enum, value_name = "MODE")]
+        mode: Option<LinkMode>,
+        /// Link directories as a whole (dir), create them and link their contents (contents), or
+        /// link files individually (deep), saved on the entry
+        #[clap(long, value_enum, value_name = "DEPTH")]
+        link_depth: Option<LinkDepth>,
+        /// Map source names to link names, e.g. chezmoi's dot_bashrc to .bashrc, saved on the entry
+        #[clap(long, value_enum)]
+        naming: Option<Naming>,
+        /// Refresh the entry on every update (auto), only when named (manual), or never
+        /// (frozen), saved on the entry
+        #[clap(long, value_enum, value_name = "POLICY")]
+        update: Option<UpdatePolicy>,
+        /// Order the entry is applied in; the higher priority wins when two entries link the
+        /// same path, saved on the entry
+        #[clap(long, allow_negative_numbers = true)]
+        priority: Option<i32>,
+        /// Sources or names of entries that have to be in place before this one, e.g. the
+        /// framework a plugin is cloned into (comma-separated), saved on the entry
+        #[clap(long, value_name = "ENTRY", value_delimiter = ',')]
+        depends_on: Vec<String>,
+        /// Only apply the entry on these operating systems (comma-separated, e.g. "linux,macos")
+        #[clap(long, value_enum, value_delimiter = ',')]
+        os: Option<Vec<Os>>,
         /// Dry run mode - show what would be done without creating symlinks
         #[clap(long, default_value_t = false)]
         dry_run: bool,
     },
-    /// Update/sync all managed dotfiles
+    /// Set up this machine from a git repository holding a dotme config, then update
+    Bootstrap {
+        /// URL of the git repository with the config
+        url: String,
+        /// Path of the config inside the repository
+        #[clap(long, default_value = "config.yml")]
+        file: PathBuf,
+        /// Branch or tag of the repository to check out
+        #[clap(long)]
+        branch: Option<String>,
+        /// Environment variable holding an HTTPS token for a private repository, also saved as
+        /// git.auth.token_env unless the config sets one
+        #[clap(long, value_name = "VAR")]
+        token_env: Option<String>,
+        /// Replace the config if dotme is already initialized
+        #[clap(long, default_value_t = false)]
+        force: bool,
+        /// Maximum number of repositories to update at the same time (overrides update.concurrency)
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Update/sync all managed dotfiles, or only the ones named
     Update {
+        /// Sources or names (e.g. "nvim" for a repository nvim.git) of the only entries to update
+        sources: Vec<String>,
+        /// Only update entries whose source or name matches this glob pattern (repeatable)
+        #[clap(long, value_name = "PATTERN")]
+        only: Vec<String>,
+        /// Only update entries with one of these tags (repeatable or comma-separated)
+        #[clap(long = "tag", value_name = "TAG", value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Dry run mode - show what would be done without creating symlinks
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+        /// Back up plain files that are in the way of a symlink instead of skipping them
+        #[clap(long, default_value_t = false)]
+        force: bool,
+        /// Create symlinks with relative target paths (same as symlinks.relative: true)
+        #[clap(long, default_value_t = false)]
+        relative: bool,
+        /// Maximum number of repositories to update at the same time (overrides update.concurrency)
+        #[clap(short, long)]
+        jobs: Option<usize>,
+        /// Leave submodules alone this time, whatever the entries' submodules setting
+        #[clap(long, default_value_t = false)]
+        no_submodules: bool,
+        /// Print one line with what was done and exit with a code per outcome (2 if sources
+        /// failed, 3 if scripts failed), e.g. for cron or a shell prompt
+        #[clap(long, default_value_t = false)]
+        summary: bool,
+    },
+    /// Recreate the links of all entries from their current sources, without pulling
+    Relink {
         /// Dry run mode - show what would be done without creating symlinks
         #[clap(long, default_value_t = false)]
         dry_run: bool,
+        /// Back up plain files that are in the way of a symlink instead of skipping them
+        #[clap(long, default_value_t = false)]
+        force: bool,
+        /// Create symlinks with relative target paths (same as symlinks.relative: true)
+        #[clap(long, default_value_t = false)]
+        relative: bool,
+    },
+    /// List git repositories with new upstream commits, without pulling them
+    Outdated {
+        /// Ask every remote again instead of reusing recent results
+        #[clap(long, default_value_t = false)]
+        refresh: bool,
+        /// Maximum number of remotes to check at the same time (overrides update.concurrency)
+        #[clap(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Review existing files in the way of your dotfiles, back them up, and link the dotfiles
+    Adopt,
+    /// Remove links left behind by entries or folders that were removed from the config
+    #[clap(visible_alias = "prune")]
+    Clean {
+        /// Only list the links that would be removed
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+        /// Remove the links without asking
+        #[clap(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Show all details of a managed dotfile entry
+    Show {
+        /// Source or name of the entry (optional - will prompt if not provided)
+        source: Option<String>,
     },
     /// Show status of managed dotfiles
-    Status,
+    Status {
+        /// Sources or names of the only entries to show
+        sources: Vec<String>,
+        /// Only show entries with one of these tags (repeatable or comma-separated)
+        #[clap(long = "tag", value_name = "TAG", value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Keep refreshing the status in place until interrupted
+        #[clap(short, long, default_value_t = false)]
+        watch: bool,
+        /// Seconds between refreshes in watch mode
+        #[clap(short = 'n', long, default_value_t = 2, value_name = "SECONDS")]
+        interval: u64,
+        /// Fetch git repositories first, so ahead/behind counts are current
+        #[clap(long, default_value_t = false)]
+        fetch: bool,
+        /// Score the health of each entry and list what to fix first
+        #[clap(long, default_value_t = false)]
+        health: bool,
+    },
     /// Remove a dotfile entry from management
     Remove {
-        /// Source path or git repository URL to remove (optional - will prompt if not provided)
+        /// Source or name of the entry to remove (optional - will prompt if not provided)
         source: Option<String>,
     },
     /// List all currently applied symlinks
+    List {
+        /// Only show links that are broken, missing, or modified
+        #[clap(long, default_value_t = false)]
+        broken: bool,
+        /// Only show links of the entry with this source or name
+        #[clap(long, value_name = "SOURCE")]
+        entry: Option<String>,
+        /// Only show links of entries with one of these tags (repeatable or comma-separated)
+        #[clap(long = "tag", value_name = "TAG", value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Only show links inside this directory
+        #[clap(long, value_name = "PATH")]
+        under: Option<PathBuf>,
+        /// Order of the links (default: as recorded in the state file)
+        #[clap(long, value_enum)]
+        sort: Option<ListSort>,
+    },
+    /// Check that managed symlinks still point where they should
+    Verify {
+        /// Only re-check links not verified within this age (e.g. 30d, 12h, 2w)
+        #[clap(long, value_name = "AGE", value_parser = parse_age)]
+        stale: Option<chrono::Duration>,
+        /// Recreate missing links and stop tracking links whose target is gone
+        #[clap(long, default_value_t = false)]
+        fix: bool,
+    },
+    /// Write a manifest of every managed link and a checksum of its content
+    Manifest {
+        /// File to write the manifest to (YAML, or JSON for .json files; defaults to stdout)
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+        /// Sign the manifest with this minisign secret key, writing <OUTPUT>.minisig
+        #[clap(long, value_name = "SECRET_KEY", requires = "output")]
+        sign: Option<PathBuf>,
+    },
+    /// Pack the config and symlink state into a .tar.gz to set up another machine
+    Export {
+        /// Bundle to write, e.g. dotme.tar.gz
+        file: PathBuf,
+        /// Include the clones and mirrors kept in ~/.dotme, so sources don't have to be fetched
+        #[clap(long, default_value_t = false)]
+        repos: bool,
+    },
+    /// Unpack a bundle made by `export` into ~/.dotme
+    Import {
+        /// Bundle to read
+        file: PathBuf,
+        /// Replace the config and state if dotme is already initialized
+        #[clap(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Remove the links of entries but keep them managed; `relink` links them again
+    Unlink {
+        /// Source or name of the entry to unlink (optional - will prompt if not provided)
+        #[clap(conflicts_with = "all")]
+        source: Option<String>,
+        /// Unlink every entry
+        #[clap(long, default_value_t = false)]
+        all: bool,
+        /// Dry run mode - show which links would be removed
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Keep an entry's links as they are and skip it during `update`
+    Freeze {
+        /// Source or name of the entry to freeze (optional - will prompt if not provided)
+        source: Option<String>,
+    },
+    /// Let `update` refresh and relink a frozen entry again
+    Unfreeze {
+        /// Source or name of the entry to unfreeze (optional - will prompt if not provided)
+        source: Option<String>,
+    },
+    /// Unlink an entry and move it into the archive instead of deleting it
+    Archive {
+        /// Source or name of the entry to archive (optional - will prompt if not provided)
+        source: Option<String>,
+    },
+    /// Restore an archived entry back into management
+    Unarchive {
+        /// Name of the archived entry (optional - will prompt if not provided)
+        name: Option<String>,
+    },
+    /// Commit and push local changes of git repositories
+    Push {
+        /// Source or name of the git repository to push (defaults to all git repositories)
+        source: Option<String>,
+        /// Commit message (prompts with a generated message if not provided)
+        #[clap(short, long)]
+        message: Option<String>,
+        /// Use the generated commit message without prompting
+        #[clap(long, default_value_t = false, conflicts_with = "message")]
+        auto: bool,
+        /// Dry run mode - show what would be committed and pushed
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Copy deployed files that were edited in place (copies, replaced symlinks) back into
+    /// their sources
+    Syncback {
+        /// Dry run mode - show which files would be copied back
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+        /// Commit the synced files in their git repositories
+        #[clap(long, default_value_t = false)]
+        commit: bool,
+        /// Commit message (generated from the file names if not provided)
+        #[clap(short, long, requires = "commit")]
+        message: Option<String>,
+    },
+    /// Open the source file behind a deployed path in $EDITOR
+    Edit {
+        /// Deployed path to edit, e.g. ~/.bashrc
+        path: PathBuf,
+        /// Commit the change in the source's git repository afterwards
+        #[clap(long, default_value_t = false)]
+        commit: bool,
+        /// Commit message (generated from the file name if not provided)
+        #[clap(short, long, requires = "commit")]
+        message: Option<String>,
+    },
+    /// Manage opt-in features
+    Features {
+        /// Features subcommands
+        #[clap(subcommand)]
+        command: FeaturesCommands,
+    },
+    /// Check the config
+    Config {
+        /// Config subcommands
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Show what dotme changed: entries added or removed, updates, and links created or removed
+    History {
+        /// Only show changes to this path (or in it), including updates of the source it links to
+        path: Option<PathBuf>,
+        /// Only show changes within this age (e.g. 7d, 12h, 2w)
+        #[clap(long, value_name = "AGE", value_parser = parse_age)]
+        since: Option<chrono::Duration>,
+        /// Show at most this many of the latest changes
+        #[clap(long, short = 'n', default_value_t = 50)]
+        limit: usize,
+    },
+    /// Undo the last command that changed entries or links: restore the links it removed,
+    /// remove the ones it created, and add back or remove its entries
+    Undo {
+        /// Dry run mode - show what would be undone
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Show counts of entries and links, clone sizes, and which entries changed least recently
+    Stats,
+    /// Inspect the symlink state file
+    State {
+        /// State subcommands
+        #[clap(subcommand)]
+        command: StateCommands,
+    },
+    /// Encrypt the config and the symlink state with an age key, or decrypt them again
+    Encrypt {
+        /// Existing age key file (AGE-SECRET-KEY-...) to encrypt with
+        #[clap(long, value_name = "FILE", conflicts_with_all = ["passphrase", "off"])]
+        identity: Option<PathBuf>,
+        /// Generate a key protected with a passphrase, asked once per run
+        #[clap(long, default_value_t = false, conflicts_with = "off")]
+        passphrase: bool,
+        /// Decrypt the files and stop encrypting them
+        #[clap(long, default_value_t = false)]
+        off: bool,
+    },
+    /// Run the scripts in the scripts/ and install.d/ directories of the entries' sources
+    Run {
+        /// Only this script: its path in the source, file name, or name without extensions
+        script: Option<String>,
+        /// Only the scripts of the entry with this source or name
+        #[clap(long, value_name = "SOURCE")]
+        entry: Option<String>,
+        /// List the scripts and when they last ran instead of running them
+        #[clap(long, default_value_t = false)]
+        list: bool,
+        /// Show what would run without running it
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Manage the system packages declared in the config
+    Packages {
+        /// Packages subcommands
+        #[clap(subcommand)]
+        command: PackagesCommands,
+    },
+    /// Run `dotme update` regularly with a systemd user timer (Linux) or a LaunchAgent (macOS)
+    Schedule {
+        /// Schedule subcommands
+        #[clap(subcommand)]
+        command: ScheduleCommands,
+    },
+    /// Capture the config, the symlink state, and the commit of each repository
+    Snapshot {
+        /// Snapshot subcommands
+        #[clap(subcommand)]
+        command: SnapshotCommands,
+    },
+    /// Print a shell completion script to stdout, or install it for your shell
+    Completions {
+        /// Shell to generate completions for (detected from $SHELL with --install)
+        #[clap(value_enum, required_unless_present_any = ["install", "uninstall"])]
+        shell: Option<Shell>,
+        /// Install the completions where the shell loads them from
+        #[clap(long, default_value_t = false, conflicts_with = "uninstall")]
+        install: bool,
+        /// Remove completions installed with --install
+        #[clap(long, default_value_t = false)]
+        uninstall: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FeaturesCommands {
+    /// List available features and whether they are enabled
     List,
 }
 
-pub fn init() -> Arguments {
-    let arguments = Arguments::parse();
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Parse the config and check its entries, exiting non-zero if there are errors
+    Validate {
+        /// Config file to check instead of the dotme config
+        #[clap(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StateCommands {
+    /// Check ~/.dotme/symlinks.yml for duplicates, relative paths, and links that aren't symlinks
+    Fsck {
+        /// Fix the problems found, dropping entries that can't be fixed
+        #[clap(long, default_value_t = false)]
+        repair: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PackagesCommands {
+    /// List declared packages and whether they are installed
+    List {
+        /// Package manager to use instead of the detected one
+        #[clap(long, value_enum)]
+        manager: Option<PackageManager>,
+    },
+    /// Install declared packages that are missing
+    Install {
+        /// Show the install command without running it
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+        /// Package manager to use instead of the detected one
+        #[clap(long, value_enum)]
+        manager: Option<PackageManager>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ScheduleCommands {
+    /// Schedule `dotme update --quiet`, replacing an existing schedule
+    Install {
+        /// Time between updates, e.g. 12h, 1d, or 1w
+        #[clap(long, value_name = "INTERVAL", default_value = "1d", value_parser = parse_age)]
+        interval: chrono::Duration,
+    },
+    /// Stop running updates on a schedule
+    Remove,
+    /// Show whether updates are scheduled, and when they last ran and run next
+    Status,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommands {
+    /// Capture the current setup into the snapshots directory
+    Create {
+        /// Name of the snapshot (default: the current time)
+        name: Option<String>,
+    },
+    /// List the snapshots taken
+    List,
+}
+
+/// Parse an age argument such as `30d`
+fn parse_age(value: &str) -> Result<chrono::Duration, String> {
+    crate::symlinks::parse_age(value).map_err(|e| e.to_string())
+}
+
+/// Convert a configured default to the values clap parses
+fn default_values(value: &serde_yaml::Value) -> Option<Vec<String>> {
+    match value {
+        serde_yaml::Value::Bool(value) => Some(vec![value.to_string()]),
+        serde_yaml::Value::Number(value) => Some(vec![value.to_string()]),
+        serde_yaml::Value::String(value) => Some(vec![value.clone()]),
+        serde_yaml::Value::Sequence(values) => values
+            .iter()
+            .map(|value| default_values(value)?.into_iter().next())
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Apply the `defaults:` section of the config to the flags of `command` (named `path`, e.g.
+/// `dotme update`) and its subcommands. Problems are returned as warnings, as logging isn't set
+/// up yet.
+fn apply_defaults(
+    mut command: clap::Command,
+    path: &str,
+    defaults: &BTreeMap<String, serde_yaml::Value>,
+    warnings: &mut Vec<String>,
+) -> clap::Command {
+    for (key, value) in defaults {
+        if let serde_yaml::Value::Mapping(table) = value {
+            let subcommand_path = format!("{} {}", path, key);
+            if command.find_subcommand(key).is_none() {
+                warnings.push(format!("Unknown command '{}' in defaults", subcommand_path));
+                continue;
+            }
+            let table: BTreeMap<String, serde_yaml::Value> = table
+                .iter()
+                .filter_map(|(key, value)| Some((key.as_str()?.to_string(), value.clone())))
+                .collect();
+            command = command.mut_subcommand(key, |subcommand| {
+                apply_defaults(subcommand, &subcommand_path, &table, warnings)
+            });
+            continue;
+        }
+
+        // Flags can be named by their field (`dry_run`) or their long name (`dry-run`)
+        let id = key.replace('-', "_");
+        let Some(arg) = command
+            .get_arguments()
+            .filter(|arg| !arg.is_positional())
+            .find(|arg| arg.get_id() == id.as_str() || arg.get_long() == Some(key.as_str()))
+        else {
+            warnings.push(format!("Unknown flag '{}' of '{}' in defaults", key, path));
+            continue;
+        };
+        let Some(values) = default_values(value) else {
+            warnings.push(format!(
+                "Default of '{}' for '{}' must be a value or a list of values",
+                key, path
+            ));
+            continue;
+        };
+
+        let id = arg.get_id().clone();
+        // A switch turned on by default gets a `--no-` flag to turn it off for a run
+        let negation = (matches!(arg.get_action(), ArgAction::SetTrue) && values == ["true"])
+            .then(|| arg.get_long())
+            .flatten()
+            .map(|long| (format!("no_{}", id), format!("no-{}", long)));
+        command = command.mut_arg(&id, |arg| {
+            let arg = arg.default_values(values);
+            match &negation {
+                Some((negation, _)) => arg
+                    .default_value_if(negation, ArgPredicate::IsPresent, Some("false"))
+                    .overrides_with(negation),
+                None => arg,
+            }
+        });
+        if let Some((negation, long)) = negation
+            && !command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(&long))
+        {
+            let help = format!(
+                "Turn off --{} for this run (on by default in the config)",
+                long.trim_start_matches("no-")
+            );
+            command = command.arg(
+                clap::Arg::new(negation)
+                    .long(long)
+                    .action(ArgAction::SetTrue)
+                    .overrides_with(&id)
+                    .help(help),
+            );
+        }
+    }
+
+    command
+}
+
+/// Parse the command line, with defaults for its flags from the config; also returns the config
+/// if it could be loaded
+fn parse(warnings: &mut Vec<String>) -> (Arguments, Option<Config>) {
+    let mut command = Arguments::command();
+
+    // The root decides which config the defaults come from, so it is read before parsing
+    if let Some(root) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches()
+        .ok()
+        .and_then(|matches| matches.get_one::<PathBuf>("root").cloned())
+        && let Err(e) = crate::layout::set_root(&root)
+    {
+        warnings.push(e.to_string());
+    }
 
-    let log_level = match &arguments.debug {
-        false => log::LevelFilter::Info,
-        true => log::LevelFilter::Debug,
-    };
+    // A config that fails to load is reported, or restored from its backup, by the command
+    // itself once logging is set up
+    let config = crate::dotfiles::get_config_path()
+        .ok()
+        .filter(|path| path.exists())
+        .and_then(|path| Config::load_file(&path).ok());
+    if let Some(config) = &config {
+        command = apply_defaults(command, "dotme", &config.defaults, warnings);
+    }
 
-    env_logger::builder()
+    let matches = command.get_matches();
+    let arguments = Arguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    (arguments, config)
+}
+
+pub fn init() -> Arguments {
+    let mut warnings = Vec::new();
+    let (arguments, config) = parse(&mut warnings);
+
+    let mut logger = env_logger::builder();
+    logger
         .parse_default_env()
-        .filter_level(log_level)
-        .init();
+        .filter_level(arguments.log_level());
+    if arguments.log_format == LogFormat::Json {
+        logger.format(|buf, record| {
+            use std::io::Write;
+            let line = serde_json::json!({
+                "time": chrono::Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+    let logging = config.map(|config| config.logging).unwrap_or_default();
+    if let Some(e) = crate::logfile::init(logger.build(), &logging) {
+        warnings.push(format!("{:#}", e));
+    }
 
-    if !arguments.disable_banner {
+    for warning in warnings {
+        log::warn!("{}", warning);
+    }
+
+    crate::features::enable_from_cli(&arguments.enable_features);
+    crate::prompt::assume_yes(arguments.yes);
+    crate::progress::enable(
+        arguments.log_format == LogFormat::Text && arguments.log_level() >= log::LevelFilter::Info,
+    );
+    if let Some(path) = &arguments.trace_file {
+        crate::trace::start(path);
+    }
+
+    if !arguments.disable_banner && !arguments.writes_to_stdout() {
         println!(
             "{}    {} - v{}",
             style(BANNER).green(),
@@ -95,3 +825,97 @@ pub fn init() -> Arguments {
 
     arguments
 }
+
+/// Write the completion script of a shell to stdout
+pub fn print_completions(shell: Shell) {
+    clap_complete::generate(
+        shell,
+        &mut Arguments::command(),
+        "dotme",
+        &mut std::io::stdout(),
+    );
+}
+
+/// Generate the completion script of a shell
+pub fn completion_script(shell: Shell) -> Vec<u8> {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Arguments::command(), "dotme", &mut script);
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_defaults_sit_beneath_cli_flags() {
+        let defaults: BTreeMap<String, serde_yaml::Value> = serde_yaml::from_str(
+            "disable-banner: true\nupdate:\n  jobs: 2\n  dry_run: true\nlist:\n  unknown: 1\n",
+        )
+        .unwrap();
+        let mut warnings = Vec::new();
+        let command = apply_defaults(Arguments::command(), "dotme", &defaults, &mut warnings);
+        assert_eq!(
+            warnings,
+            vec!["Unknown flag 'unknown' of 'dotme list' in defaults"]
+        );
+
+        let parse = |args: &[&str]| {
+            Arguments::from_arg_matches(&command.clone().get_matches_from(args)).unwrap()
+        };
+
+        let arguments = parse(&["dotme", "update"]);
+        assert!(arguments.disable_banner);
+        assert!(matches!(
+            arguments.commands,
+            Some(ArgumentCommands::Update {
+                dry_run: true,
+                jobs: Some(2),
+                ..
+            })
+        ));
+
+        let arguments = parse(&["dotme", "update", "--jobs", "8"]);
+        assert!(matches!(
+            arguments.commands,
+            Some(ArgumentCommands::Update { jobs: Some(8), .. })
+        ));
+
+        let arguments = parse(&["dotme", "update", "--no-submodules"]);
+        assert!(matches!(
+            arguments.commands,
+            Some(ArgumentCommands::Update {
+                no_submodules: true,
+                ..
+            })
+        ));
+
+        // Switches turned on in the config can be turned off on the command line
+        let arguments = parse(&["dotme", "--no-disable-banner", "update", "--no-dry-run"]);
+        assert!(!arguments.disable_banner);
+        assert!(matches!(
+            arguments.commands,
+            Some(ArgumentCommands::Update { dry_run: false, .. })
+        ));
+        let arguments = parse(&["dotme", "update", "--no-dry-run", "--dry-run"]);
+        assert!(matches!(
+            arguments.commands,
+            Some(ArgumentCommands::Update { dry_run: true, .. })
+        ));
+    }
+
+    #[test]
+    fn test_verbosity_flags_set_the_log_level() {
+        let level = |args: &[&str]| Arguments::parse_from(args).log_level();
+
+        assert_eq!(level(&["dotme", "status"]), log::LevelFilter::Info);
+        assert_eq!(level(&["dotme", "status", "-v"]), log::LevelFilter::Debug);
+        assert_eq!(
+            level(&["dotme", "--debug", "status"]),
+            log::LevelFilter::Debug
+        );
+        assert_eq!(level(&["dotme", "-vv", "status"]), log::LevelFilter::Trace);
+        assert_eq!(level(&["dotme", "status", "-q"]), log::LevelFilter::Warn);
+        assert_eq!(level(&["dotme", "-qqq", "status"]), log::LevelFilter::Error);
+    }
+}
@@ -0,0 +1,417 @@
+//! Comment preservation for YAML files rewritten by dotme
+//!
+//! `serde_yaml` drops comments and reorders keys when a document is re-serialized. To keep
+//! hand-written configuration readable, the freshly serialized document is merged with the
+//! original text: comments (and blank lines) that preceded a key in the original are placed
+//! in front of the same key in the new output, inline comments are carried over, and the
+//! original ordering of top-level keys is kept.
+//!
+//! Keys are identified by their path from the document root. Sequence items that are mappings
+//! are identified by their first key/value pair (e.g. `dotfiles[source=~/.vimrc]`), and scalar
+//! sequence items by their value, so comments follow entries even when others are added or
+//! removed around them.
+
+use std::collections::HashMap;
+
+/// A structural line of a YAML document with the comments attached to it
+#[derive(Debug, Default, Clone)]
+struct Anchor {
+    /// Comment and blank lines directly preceding the line
+    leading: Vec<String>,
+    /// Comment at the end of the line itself
+    inline: Option<String>,
+}
+
+/// Merge the comments and top-level key ordering of `original` into `generated`
+pub fn preserve(original: &str, generated: &str) -> String {
+    let (anchors, trailing) = collect_anchors(original);
+    let generated = reorder_top_level(original, generated);
+
+    let mut output = Vec::new();
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut block = None;
+
+    for line in generated.lines() {
+        // Block scalar content is copied verbatim
+        if in_block_scalar(line, &mut block) {
+            output.push(line.to_string());
+            continue;
+        }
+        block = block_scalar_indent(line);
+
+        let Some(path) = anchor_path(line, &mut stack) else {
+            output.push(line.to_string());
+            continue;
+        };
+
+        match anchors.get(&path) {
+            Some(anchor) => {
+                // Re-indent comments to match the newly serialized line
+                let indent = &line[..line.len() - line.trim_start().len()];
+                output.extend(anchor.leading.iter().map(|l| match l.trim() {
+                    "" => String::new(),
+                    comment => format!("{}{}", indent, comment),
+                }));
+                match &anchor.inline {
+                    Some(comment) if !line.contains('#') => {
+                        output.push(format!("{} {}", line, comment))
+                    }
+                    _ => output.push(line.to_string()),
+                }
+            }
+            None => output.push(line.to_string()),
+        }
+    }
+
+    output.extend(trailing);
+
+    let mut result = output.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Collect the comments attached to each anchor, plus comments trailing the document
+fn collect_anchors(original: &str) -> (HashMap<String, Anchor>, Vec<String>) {
+    let mut anchors = HashMap::new();
+    let mut pending = Vec::new();
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut block = None;
+
+    for line in original.lines() {
+        // Lines inside block scalars are content, not comments
+        if in_block_scalar(line, &mut block) {
+            continue;
+        }
+        block = block_scalar_indent(line);
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            pending.push(line.to_string());
+            continue;
+        }
+        if trimmed == "---" {
+            continue;
+        }
+
+        match anchor_path(line, &mut stack) {
+            Some(path) => {
+                let anchor = Anchor {
+                    leading: std::mem::take(&mut pending),
+                    inline: inline_comment(line).map(str::to_string),
+                };
+                anchors.entry(path).or_insert(anchor);
+            }
+            None => pending.clear(),
+        }
+    }
+
+    // Drop blank lines at the very end, keep any closing comments
+    while pending.last().is_some_and(|l| l.trim().is_empty()) {
+        pending.pop();
+    }
+
+    (anchors, pending)
+}
+
+/// Compute the anchor path of a line, updating the indentation stack.
+///
+/// Returns `None` for lines that are not keys or sequence items (e.g. block scalar content).
+fn anchor_path(line: &str, stack: &mut Vec<(usize, String)>) -> Option<String> {
+    let indent = line.len() - line.trim_start().len();
+    let content = strip_inline_comment(line.trim_start()).trim_end();
+
+    if content.is_empty() || content.starts_with('#') {
+        return None;
+    }
+
+    let is_item = content.starts_with('-');
+
+    // Sequence items may sit at the same indentation as their parent key
+    while stack.last().is_some_and(|(i, segment)| {
+        *i > indent || (*i == indent && (!is_item || segment.starts_with('[')))
+    }) {
+        stack.pop();
+    }
+
+    let parent = stack
+        .iter()
+        .map(|(_, segment)| segment.as_str())
+        .collect::<String>();
+
+    if let Some(item) = content.strip_prefix('-').filter(|_| is_item) {
+        let item = item.trim();
+        let id = match split_key(item) {
+            Some((key, value)) => format!("[{}={}]", key, unquote(value)),
+            None => format!("[{}]", unquote(item)),
+        };
+        let path = format!("{}{}", parent, id);
+        stack.push((indent, id));
+
+        // Keys of a mapping item sit two columns further in
+        if let Some((key, _)) = split_key(item) {
+            let segment = format!(".{}", key);
+            stack.push((indent + 2, segment));
+        }
+
+        return Some(path);
+    }
+
+    let (key, _) = split_key(content)?;
+    let segment = if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!(".{}", key)
+    };
+    let path = format!("{}{}", parent, segment);
+    stack.push((indent, segment));
+
+    Some(path)
+}
+
+/// Get the indentation of the key owning a block scalar (`key: |`, `- >-`) started on `line`
+fn block_scalar_indent(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start().len();
+    let content = strip_inline_comment(line.trim_start()).trim_end();
+
+    let (indent, content) = match content.strip_prefix("- ") {
+        Some(item) => match split_key(item.trim_start()) {
+            Some((_, value)) => (indent + 2, value),
+            None => (indent, item.trim_start()),
+        },
+        None => (indent, split_key(content)?.1),
+    };
+
+    content.starts_with(['|', '>']).then_some(indent)
+}
+
+/// Check if `line` is content of the current block scalar, ending the block when it isn't
+fn in_block_scalar(line: &str, block: &mut Option<usize>) -> bool {
+    let Some(owner) = *block else {
+        return false;
+    };
+
+    let indent = line.len() - line.trim_start().len();
+    if line.trim().is_empty() || indent > owner {
+        return true;
+    }
+
+    *block = None;
+    false
+}
+
+/// Split a `key: value` line into its parts
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let (key, value) = if let Some(key) = content.strip_suffix(':') {
+        (key, "")
+    } else {
+        content.split_once(": ")?
+    };
+
+    let key = key.trim();
+    let quoted = key.starts_with(['"', '\'']) && key.ends_with(['"', '\'']);
+    if key.is_empty() || (key.starts_with(['"', '\'', '{', '[']) && !quoted) {
+        return None;
+    }
+
+    Some((unquote(key), value.trim()))
+}
+
+/// Remove surrounding quotes from a scalar
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    if value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')))
+    {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+/// Find the position of an inline comment, ignoring `#` inside quotes
+fn comment_start(line: &str) -> Option<usize> {
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut previous = ' ';
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '#' if !in_single && !in_double && previous.is_whitespace() => return Some(i),
+            _ => {}
+        }
+        previous = c;
+    }
+
+    None
+}
+
+/// Get the inline comment of a line, if any
+fn inline_comment(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let start = comment_start(trimmed)?;
+    if trimmed[..start].trim().is_empty() {
+        return None;
+    }
+    Some(trimmed[start..].trim_end())
+}
+
+/// Strip the inline comment from a line
+fn strip_inline_comment(line: &str) -> &str {
+    match comment_start(line) {
+        Some(start) => &line[..start],
+        None => line,
+    }
+}
+
+/// Reorder the top-level blocks of `generated` to follow the key order of `original`
+fn reorder_top_level(original: &str, generated: &str) -> String {
+    let order: Vec<&str> = original
+        .lines()
+        .filter(|l| !l.starts_with([' ', '\t', '#', '-']) && !l.trim().is_empty())
+        .filter_map(|l| split_key(strip_inline_comment(l).trim_end()).map(|(k, _)| k))
+        .collect();
+
+    let mut blocks: Vec<(Option<String>, Vec<&str>)> = Vec::new();
+    for line in generated.lines() {
+        let top_level = !line.starts_with([' ', '\t', '-']) && !line.trim().is_empty();
+        match split_key(line).filter(|_| top_level) {
+            Some((key, _)) => blocks.push((Some(key.to_string()), vec![line])),
+            None => match blocks.last_mut() {
+                Some((_, lines)) => lines.push(line),
+                None => blocks.push((None, vec![line])),
+            },
+        }
+    }
+
+    // Stable sort: keys unknown to the original keep their relative order at the end
+    blocks.sort_by_key(|(key, _)| {
+        key.as_deref()
+            .and_then(|k| order.iter().position(|o| *o == k))
+            .unwrap_or(usize::MAX)
+    });
+
+    let mut result = blocks
+        .into_iter()
+        .flat_map(|(_, lines)| lines)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Keep trailing blank lines, they may belong to a block scalar
+    if generated.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preserve_comments_on_entries() {
+        let original = "\
+# My dotfiles
+dotfiles:
+# shell config
+- source: ~/dotfiles/zsh # keep this
+  target: /home/user/zsh
+  type: directory
+paths: {}
+";
+        let generated = "\
+paths: {}
+dotfiles:
+- source: ~/dotfiles/zsh
+  target: /home/user/zsh
+  type: directory
+- source: ~/dotfiles/vim
+  target: /home/user/vim
+  type: directory
+";
+
+        let result = preserve(original, generated);
+        assert_eq!(
+            result,
+            "\
+# My dotfiles
+dotfiles:
+# shell config
+- source: ~/dotfiles/zsh # keep this
+  target: /home/user/zsh
+  type: directory
+- source: ~/dotfiles/vim
+  target: /home/user/vim
+  type: directory
+paths: {}
+"
+        );
+    }
+
+    #[test]
+    fn test_preserve_drops_comments_of_removed_entries() {
+        let original = "\
+dotfiles:
+# removed soon
+- source: a
+  type: file
+# stays
+- source: b
+  type: file
+";
+        let generated = "\
+dotfiles:
+- source: b
+  type: file
+";
+
+        let result = preserve(original, generated);
+        assert_eq!(result, "dotfiles:\n# stays\n- source: b\n  type: file\n");
+    }
+
+    #[test]
+    fn test_preserve_keeps_block_scalars_intact() {
+        let original = "\
+dotfiles:
+# tools
+- source: a
+  notes: |
+    first: line
+
+    # not a comment
+  type: file
+";
+        let generated = "\
+dotfiles:
+- source: a
+  notes: |
+    first: line
+
+    # not a comment
+  type: file
+";
+
+        let result = preserve(original, generated);
+        assert_eq!(result, original);
+    }
+
+    #[test]
+    fn test_preserve_nested_and_trailing_comments() {
+        let original = "\
+paths:
+  # custom location
+  git_dir: /srv/git
+dotfiles: []
+# end of file
+";
+        let generated = "paths:\n  git_dir: /srv/git\ndotfiles: []\n";
+
+        let result = preserve(original, generated);
+        assert_eq!(
+            result,
+            "paths:\n  # custom location\n  git_dir: /srv/git\ndotfiles: []\n# end of file\n"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Installing shell completions
+//!
+//! `dotme completions --install` writes the completion script of the current shell (from
+//! `$SHELL`) to where the shell loads completions from. zsh also needs the directory on its
+//! `$fpath`, so a marked block is added to `~/.zshrc`; when `~/.zshrc` is itself a managed
+//! dotfile, the block is printed to add by hand instead, so a shared config isn't edited behind
+//! your back. Installed scripts are recorded in the symlink state, so
+//! `dotme completions --uninstall` can remove them and `dotme state fsck` notices when they are
+//! gone.
+//!
+//! | Shell | Script                                             | Startup file |
+//! |-------|----------------------------------------------------|--------------|
+//! | bash  | `~/.local/share/bash-completion/completions/dotme` |              |
+//! | zsh   | `~/.zfunc/_dotme`                                  | `~/.zshrc`   |
+//! | fish  | `~/.config/fish/completions/dotme.fish`            |              |
+
+use anyhow::{Context, Result};
+use clap_complete::Shell;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::cli;
+use crate::symlinks::{InstalledCompletions, SymlinkState};
+
+/// First line of the block added to shell startup files
+const BLOCK_START: &str = "# >>> dotme completions >>>";
+
+/// Last line of the block added to shell startup files
+const BLOCK_END: &str = "# <<< dotme completions <<<";
+
+/// Where the completions of a shell are installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Location {
+    /// Completion script
+    script: PathBuf,
+    /// Startup file that needs a block to load the script, and the block's contents
+    rc: Option<(PathBuf, String)>,
+}
+
+/// Directory for user data, `$XDG_DATA_HOME` or `~/.local/share`
+fn data_home(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local").join("share"))
+}
+
+/// Directory for user config, `$XDG_CONFIG_HOME` or `~/.config`
+fn config_home(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"))
+}
+
+/// Where the completions of `shell` go
+fn location(shell: Shell, home: &Path) -> Result<Location> {
+    match shell {
+        Shell::Bash => Ok(Location {
+            script: data_home(home)
+                .join("bash-completion")
+                .join("completions")
+                .join("dotme"),
+            rc: None,
+        }),
+        Shell::Zsh => Ok(Location {
+            script: home.join(".zfunc").join("_dotme"),
+            rc: Some((
+                home.join(".zshrc"),
+                "fpath=(~/.zfunc $fpath)\nautoload -Uz compinit && compinit".to_string(),
+            )),
+        }),
+        Shell::Fish => Ok(Location {
+            script: config_home(home)
+                .join("fish")
+                .join("completions")
+                .join("dotme.fish"),
+            rc: None,
+        }),
+        shell => anyhow::bail!(
+            "Installing completions for {} isn't supported. Load the output of 'dotme completions {}' from your shell's profile instead.",
+            shell,
+            shell
+        ),
+    }
+}
+
+/// Put `block` between the markers into `contents`, replacing a block added before
+fn with_block(contents: &str, block: &str) -> String {
+    let block = format!("{}\n{}\n{}\n", BLOCK_START, block, BLOCK_END);
+    let (before, after) = split_block(contents);
+    match after {
+        Some(after) => format!("{}{}{}", before, block, after),
+        None if before.is_empty() || before.ends_with('\n') => format!("{}{}", before, block),
+        None => format!("{}\n{}", before, block),
+    }
+}
+
+/// Remove the block between the markers from `contents`
+fn without_block(contents: &str) -> String {
+    match split_block(contents) {
+        (before, Some(after)) => format!("{}{}", before, after),
+        (before, None) => before.to_string(),
+    }
+}
+
+/// Split `contents` around the marked block; the second part is `None` if there is no block
+fn split_block(contents: &str) -> (&str, Option<&str>) {
+    let Some(start) = contents.find(BLOCK_START) else {
+        return (contents, None);
+    };
+    let Some(end) = contents[start..].find(BLOCK_END) else {
+        return (contents, None);
+    };
+
+    let end = start + end + BLOCK_END.len();
+    let after = contents[end..]
+        .strip_prefix('\n')
+        .unwrap_or(&contents[end..]);
+    (&contents[..start], Some(after))
+}
+
+/// Check if a startup file is a dotfile dotme links, which shouldn't be edited behind its back
+fn is_managed(rc_file: &Path, state: &SymlinkState) -> bool {
+    state.find_entry(rc_file).is_some()
+        || rc_file
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_symlink())
+}
+
+/// Install the completions of `shell`, or of the shell in `$SHELL`
+pub async fn install(shell: Option<Shell>) -> Result<()> {
+    let shell = match shell {
+        Some(shell) => shell,
+        None => Shell::from_env()
+            .context("Couldn't detect your shell from $SHELL. Pass it, e.g. 'dotme completions zsh --install'.")?,
+    };
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    let location = location(shell, &home)?;
+
+    if let Some(parent) = location.script.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::write(&location.script, cli::completion_script(shell))
+        .await
+        .with_context(|| format!("Failed to write {}", location.script.display()))?;
+    log::info!(
+        "✓ Installed {} completions to {}",
+        shell,
+        location.script.display()
+    );
+
+    let mut state = SymlinkState::load().await?;
+    let mut rc_file = None;
+    if let Some((path, block)) = &location.rc {
+        if is_managed(path, &state) {
+            println!(
+                "{} is a managed dotfile, so it wasn't changed. Add these lines to it to load the completions:\n\n{}\n",
+                path.display(),
+                block
+            );
+        } else {
+            let contents = fs::read_to_string(path).await.unwrap_or_default();
+            fs::write(path, with_block(&contents, block))
+                .await
+                .with_context(|| format!("Failed to update {}", path.display()))?;
+            log::info!("✓ Loaded them from {}", path.display());
+            rc_file = Some(path.clone());
+        }
+    }
+
+    state
+        .completions
+        .retain(|installed| installed.shell != shell.to_string());
+    state.completions.push(InstalledCompletions {
+        shell: shell.to_string(),
+        script: location.script,
+        rc_file,
+    });
+    state.save().await?;
+
+    println!("Restart your shell to use the completions.");
+    Ok(())
+}
+
+/// Remove every completion script installed by `install`, and the blocks loading them
+pub async fn uninstall() -> Result<()> {
+    let mut state = SymlinkState::load().await?;
+    if state.completions.is_empty() {
+        println!("No completions are installed.");
+        return Ok(());
+    }
+
+    for installed in std::mem::take(&mut state.completions) {
+        match fs::remove_file(&installed.script).await {
+            Ok(()) => log::info!("✓ Removed {}", installed.script.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to remove {}", installed.script.display()));
+            }
+        }
+
+        if let Some(rc_file) = &installed.rc_file
+            && let Ok(contents) = fs::read_to_string(rc_file).await
+        {
+            fs::write(rc_file, without_block(&contents))
+                .await
+                .with_context(|| format!("Failed to update {}", rc_file.display()))?;
+            log::info!("✓ Removed the completions block from {}", rc_file.display());
+        }
+    }
+
+    state.save().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rc_blocks_are_replaced_and_removed() {
+        let rc = "export EDITOR=vim\n";
+        let installed = with_block(rc, "fpath=(~/.zfunc $fpath)");
+        assert_eq!(
+            installed,
+            "export EDITOR=vim\n# >>> dotme completions >>>\nfpath=(~/.zfunc $fpath)\n# <<< dotme completions <<<\n"
+        );
+
+        // Installing again replaces the block rather than adding another one
+        let reinstalled = with_block(&format!("{}alias ll='ls -l'\n", installed), "compinit");
+        assert_eq!(reinstalled.matches(BLOCK_START).count(), 1);
+        assert!(reinstalled.ends_with("# <<< dotme completions <<<\nalias ll='ls -l'\n"));
+
+        assert_eq!(without_block(&installed), rc);
+        assert_eq!(
+            with_block("no newline", "x").lines().next(),
+            Some("no newline")
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Conditions for applying entries on specific machines
+//!
+//! An entry with a `when:` condition is only applied on machines that match it, so the same
+//! config (or repository) can drive different machines. Every condition that is set must hold.
+//!
+//! ```yaml
+//! dotfiles:
+//!   - source: https://github.com/user/work-dotfiles.git
+//!     when:
+//!       hostname: ["work-*", "laptop-??"]
+//!       env: [WORK_LAPTOP, "SHELL=*zsh"]
+//!       host: [work, "dpi=1??"]
+//!       is_wsl: false
+//!       is_ssh: false
+//! ```
+//!
+//! Hostnames are matched case-insensitively against glob patterns, and match if any pattern
+//! does. Environment variables are given as `NAME` (set and not empty) or `NAME=<glob>`, and
+//! so are the values of this machine's [host file](crate::host), where `NAME` also has to be
+//! something other than `false`.
+
+use globset::GlobBuilder;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::host;
+
+/// Condition a machine has to match for an entry to apply
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Condition {
+    /// Hostname glob patterns, any of which has to match
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub hostname: Vec<String>,
+    /// Environment variables that have to be set, as `NAME` or `NAME=<glob>`
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub env: Vec<String>,
+    /// Values of the host file that have to be set, as `NAME` or `NAME=<glob>`
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub host: Vec<String>,
+    /// Whether dotme has to be running inside (or outside) Windows Subsystem for Linux
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_wsl: Option<bool>,
+    /// Whether dotme has to be running in (or outside) an SSH session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_ssh: Option<bool>,
+}
+
+/// Accept a single string where a list is expected
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// What is known about the machine dotme is running on
+#[derive(Debug, Clone, Default)]
+pub struct Facts {
+    /// Hostname, if it can be determined
+    pub hostname: Option<String>,
+    /// Environment variables
+    pub env: BTreeMap<String, String>,
+    /// Values of the host file
+    pub host: BTreeMap<String, String>,
+    /// Running inside Windows Subsystem for Linux
+    pub is_wsl: bool,
+    /// Running in an SSH session
+    pub is_ssh: bool,
+}
+
+impl Facts {
+    /// Gather the facts about this machine
+    pub fn detect() -> Self {
+        let env: BTreeMap<String, String> = std::env::vars().collect();
+        let is_wsl = env.contains_key("WSL_DISTRO_NAME")
+            || std::fs::read_to_string("/proc/sys/kernel/osrelease")
+                .is_ok_and(|release| release.to_lowercase().contains("microsoft"));
+        let is_ssh = ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+            .iter()
+            .any(|name| env.contains_key(*name));
+
+        // A broken host file shouldn't stop entries that don't depend on it
+        let host = host::load().unwrap_or_else(|e| {
+            log::warn!("{:#}", e);
+            BTreeMap::new()
+        });
+
+        Self {
+            hostname: hostname(),
+            env,
+            host,
+            is_wsl,
+            is_ssh,
+        }
+    }
+}
+
+/// Name of this machine, if it can be determined
+pub fn hostname() -> Option<String> {
+    let name = match std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(name) => name,
+        Err(_) => {
+            let output = std::process::Command::new("hostname").output().ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            String::from_utf8_lossy(&output.stdout).to_string()
+        }
+    };
+
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Match a value against a glob pattern, treating invalid patterns as not matching
+fn glob_matches(pattern: &str, value: &str, case_insensitive: bool) -> bool {
+    match GlobBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+    {
+        Ok(glob) => glob.compile_matcher().is_match(value),
+        Err(e) => {
+            log::warn!("Invalid pattern '{}' in when condition: {}", pattern, e);
+            false
+        }
+    }
+}
+
+/// Check a `NAME` or `NAME=<glob>` requirement against `values`; a bare `NAME` holds if `set`
+/// accepts its value
+fn value_matches(
+    requirement: &str,
+    values: &BTreeMap<String, String>,
+    set: impl Fn(&str) -> bool,
+) -> bool {
+    let (name, pattern) = match requirement.split_once('=') {
+        Some((name, pattern)) => (name, Some(pattern)),
+        None => (requirement, None),
+    };
+    match (values.get(name), pattern) {
+        (Some(value), Some(pattern)) => glob_matches(pattern, value, false),
+        (Some(value), None) => set(value),
+        (None, _) => false,
+    }
+}
+
+impl Condition {
+    /// Check if a machine matches every part of the condition
+    pub fn matches(&self, facts: &Facts) -> bool {
+        let hostname = self.hostname.is_empty()
+            || facts.hostname.as_deref().is_some_and(|hostname| {
+                self.hostname
+                    .iter()
+                    .any(|pattern| glob_matches(pattern, hostname, true))
+            });
+
+        let env = self
+            .env
+            .iter()
+            .all(|requirement| value_matches(requirement, &facts.env, |value| !value.is_empty()));
+        let host = self.host.iter().all(|requirement| {
+            value_matches(requirement, &facts.host, |value| {
+                !value.is_empty() && value != "false"
+            })
+        });
+
+        hostname
+            && env
+            && host
+            && self.is_wsl.is_none_or(|is_wsl| is_wsl == facts.is_wsl)
+            && self.is_ssh.is_none_or(|is_ssh| is_ssh == facts.is_ssh)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.hostname.is_empty() {
+            parts.push(format!("hostname {}", self.hostname.join(" or ")));
+        }
+        parts.extend(self.env.iter().map(|env| format!("env {}", env)));
+        parts.extend(self.host.iter().map(|host| format!("host {}", host)));
+        match self.is_wsl {
+            Some(true) => parts.push("WSL".to_string()),
+            Some(false) => parts.push("not WSL".to_string()),
+            None => {}
+        }
+        match self.is_ssh {
+            Some(true) => parts.push("over SSH".to_string()),
+            Some(false) => parts.push("not over SSH".to_string()),
+            None => {}
+        }
+
+        if parts.is_empty() {
+            write!(f, "always")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> Facts {
+        Facts {
+            hostname: Some("Work-Laptop".to_string()),
+            env: BTreeMap::from([
+                ("SHELL".to_string(), "/usr/bin/zsh".to_string()),
+                ("WORK".to_string(), "1".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]),
+            host: BTreeMap::from([
+                ("work".to_string(), "true".to_string()),
+                ("personal".to_string(), "false".to_string()),
+                ("dpi".to_string(), "144".to_string()),
+            ]),
+            is_wsl: false,
+            is_ssh: true,
+        }
+    }
+
+    #[test]
+    fn test_empty_condition_always_matches() {
+        assert!(Condition::default().matches(&facts()));
+        assert!(Condition::default().matches(&Facts::default()));
+    }
+
+    #[test]
+    fn test_hostname_globs() {
+        let condition = |patterns: &[&str]| Condition {
+            hostname: patterns.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert!(condition(&["work-*"]).matches(&facts()));
+        assert!(condition(&["server-*", "work-lap???"]).matches(&facts()));
+        assert!(!condition(&["server-*"]).matches(&facts()));
+        assert!(!condition(&["["]).matches(&facts()));
+        assert!(!condition(&["*"]).matches(&Facts::default()));
+    }
+
+    #[test]
+    fn test_env_requirements() {
+        let condition = |env: &[&str]| Condition {
+            env: env.iter().map(|e| e.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert!(condition(&["WORK"]).matches(&facts()));
+        assert!(condition(&["WORK", "SHELL=*zsh"]).matches(&facts()));
+        assert!(!condition(&["SHELL=*bash"]).matches(&facts()));
+        assert!(!condition(&["EMPTY"]).matches(&facts()));
+        assert!(condition(&["EMPTY="]).matches(&facts()));
+        assert!(!condition(&["MISSING"]).matches(&facts()));
+    }
+
+    #[test]
+    fn test_host_requirements() {
+        let condition = |host: &[&str]| Condition {
+            host: host.iter().map(|h| h.to_string()).collect(),
+            ..Default::default()
+        };
+
+        assert!(condition(&["work", "dpi=1??"]).matches(&facts()));
+        assert!(!condition(&["personal"]).matches(&facts()));
+        assert!(condition(&["personal=false"]).matches(&facts()));
+        assert!(!condition(&["dpi=96"]).matches(&facts()));
+        assert!(!condition(&["proxy"]).matches(&facts()));
+    }
+
+    #[test]
+    fn test_wsl_and_ssh() {
+        let condition = |is_wsl, is_ssh| Condition {
+            is_wsl,
+            is_ssh,
+            ..Default::default()
+        };
+
+        assert!(condition(Some(false), Some(true)).matches(&facts()));
+        assert!(condition(None, Some(true)).matches(&facts()));
+        assert!(!condition(Some(true), None).matches(&facts()));
+        assert!(!condition(None, Some(false)).matches(&facts()));
+    }
+
+    #[test]
+    fn test_every_part_has_to_match() {
+        let condition = Condition {
+            hostname: vec!["work-*".to_string()],
+            env: vec!["WORK".to_string()],
+            is_ssh: Some(false),
+            ..Default::default()
+        };
+        assert!(!condition.matches(&facts()));
+    }
+
+    #[test]
+    fn test_single_values_are_accepted() {
+        let condition: Condition = serde_yaml::from_str("hostname: work-*\nenv: WORK").unwrap();
+        assert_eq!(condition.hostname, vec!["work-*"]);
+        assert_eq!(condition.env, vec!["WORK"]);
+    }
+}
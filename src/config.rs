@@ -1,11 +1,18 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use figment::{
     Figment,
-    providers::{Env, Format, Json, Toml, Yaml},
+    providers::{Env, Format, Json, Serialized, Toml, Yaml},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use crate::conditions::{Condition, Facts};
+use crate::migrate::{self, CONFIG_VERSION, ConfigVersion};
+use crate::packages::Package;
+use crate::scripts::Scripts;
+use crate::variables::Variable;
+
 const PROJECT_NAME: &str = env!("CARGO_PKG_NAME");
 
 /// Type of dotfile source
@@ -15,6 +22,7 @@ pub enum SourceType {
     File,
     Directory,
     Git,
+    Rclone,
 }
 
 impl std::fmt::Display for SourceType {
@@ -23,6 +31,244 @@ impl std::fmt::Display for SourceType {
             SourceType::File => write!(f, "file"),
             SourceType::Directory => write!(f, "directory"),
             SourceType::Git => write!(f, "git"),
+            SourceType::Rclone => write!(f, "rclone"),
+        }
+    }
+}
+
+/// What to do when a plain file is in the way of a symlink
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Leave the file alone and don't link
+    #[default]
+    Skip,
+    /// Move the file aside to `<name>.dotme-backup` and link
+    Backup,
+    /// Delete the file and link
+    Overwrite,
+}
+
+impl ConflictPolicy {
+    /// Check if this is the default policy
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Skip => write!(f, "skip"),
+            ConflictPolicy::Backup => write!(f, "backup"),
+            ConflictPolicy::Overwrite => write!(f, "overwrite"),
+        }
+    }
+}
+
+/// How an entry's files are put in place
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkMode {
+    /// Symlink to the source
+    #[default]
+    Symlink,
+    /// Copy the file, refreshed by `update` unless edited locally
+    Copy,
+    /// Hardlink the file (source and link must be on the same filesystem)
+    Hardlink,
+    /// Copy-on-write clone of the file (btrfs, XFS, APFS), tracked like a copy
+    Reflink,
+    /// Directory junction, used automatically on Windows when symlinks aren't allowed
+    #[value(skip)]
+    Junction,
+}
+
+impl LinkMode {
+    /// Check if this is the default mode
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for LinkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkMode::Symlink => write!(f, "symlink"),
+            LinkMode::Copy => write!(f, "copy"),
+            LinkMode::Hardlink => write!(f, "hardlink"),
+            LinkMode::Reflink => write!(f, "reflink"),
+            LinkMode::Junction => write!(f, "junction"),
+        }
+    }
+}
+
+/// How far into an entry's directories links are made
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkDepth {
+    /// Link a directory as a whole unless a directory is already in its place, then descend
+    #[default]
+    Auto,
+    /// Link every directory of the source as a whole, never descending into existing ones
+    Dir,
+    /// Create the directories of the source and link what is inside them as a whole
+    Contents,
+    /// Create every directory and link files individually
+    Deep,
+}
+
+impl LinkDepth {
+    /// Check if this is the default depth
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Check if a directory `level` directories below the source is created rather than linked
+    /// as a whole (`level` 1 is a directory directly inside the source)
+    pub fn creates(&self, level: usize) -> bool {
+        match self {
+            LinkDepth::Auto | LinkDepth::Dir => false,
+            LinkDepth::Contents => level <= 1,
+            LinkDepth::Deep => true,
+        }
+    }
+
+    /// Check if an existing directory `level` directories below the source is descended into
+    pub fn descends(&self, level: usize) -> bool {
+        matches!(self, LinkDepth::Auto) || self.creates(level)
+    }
+}
+
+impl std::fmt::Display for LinkDepth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkDepth::Auto => write!(f, "auto"),
+            LinkDepth::Dir => write!(f, "dir"),
+            LinkDepth::Contents => write!(f, "contents"),
+            LinkDepth::Deep => write!(f, "deep"),
+        }
+    }
+}
+
+/// Which submodules of a git repository are checked out
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Submodules {
+    /// Leave submodules alone
+    None,
+    /// Check out the submodules of the repository, but not theirs
+    Init,
+    /// Check out submodules and their submodules
+    #[default]
+    Recursive,
+}
+
+impl Submodules {
+    /// Check if this is the default setting
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for Submodules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Submodules::None => write!(f, "none"),
+            Submodules::Init => write!(f, "init"),
+            Submodules::Recursive => write!(f, "recursive"),
+        }
+    }
+}
+
+/// When `dotme update` refreshes an entry
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdatePolicy {
+    /// Refresh and relink the entry on every update
+    #[default]
+    Auto,
+    /// Only refresh the entry when it is named, relinking it as it is otherwise
+    Manual,
+    /// Keep the links as they are: neither refresh nor relink the entry
+    Frozen,
+}
+
+impl UpdatePolicy {
+    /// Check if this is the default policy
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for UpdatePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdatePolicy::Auto => write!(f, "auto"),
+            UpdatePolicy::Manual => write!(f, "manual"),
+            UpdatePolicy::Frozen => write!(f, "frozen"),
+        }
+    }
+}
+
+/// How the names of an entry's files map to the names of their links
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Naming {
+    /// Links have the names of their sources
+    #[default]
+    Plain,
+    /// Like chezmoi: `dot_bashrc` is linked as `.bashrc`, and `private_` items are made
+    /// readable only by their owner
+    Chezmoi,
+}
+
+impl Naming {
+    /// Check if this is the default naming
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for Naming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Naming::Plain => write!(f, "plain"),
+            Naming::Chezmoi => write!(f, "chezmoi"),
+        }
+    }
+}
+
+/// Operating system an entry can be restricted to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Os {
+    /// Linux
+    Linux,
+    /// macOS
+    Macos,
+    /// Windows
+    Windows,
+}
+
+impl Os {
+    /// The operating system dotme is running on, if it is a known one
+    pub fn current() -> Option<Self> {
+        match std::env::consts::OS {
+            "linux" => Some(Os::Linux),
+            "macos" => Some(Os::Macos),
+            "windows" => Some(Os::Windows),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Os {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Os::Linux => write!(f, "linux"),
+            Os::Macos => write!(f, "macos"),
+            Os::Windows => write!(f, "windows"),
         }
     }
 }
@@ -32,6 +278,12 @@ impl std::fmt::Display for SourceType {
 pub struct DotfileEntry {
     /// Source path or git repository URL
     pub source: String,
+    /// Short name to refer to the entry by instead of its source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Labels grouping related entries, e.g. `shell` or `work`, to select them with `--tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     /// Target location in the filesystem
     pub target: PathBuf,
     /// Type of source
@@ -40,13 +292,165 @@ pub struct DotfileEntry {
     /// Path where symlinks should be created (defaults to home directory if not specified)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<PathBuf>,
-    /// Optional folders to select (only for git repositories)
+    /// Optional folders or files to select, relative to the repository root (only for git
+    /// repositories). Folders link their contents, files link themselves.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub folders: Option<Vec<String>>,
+    /// Glob patterns for files and directories that should never be linked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Vec<String>>,
+    /// Glob patterns for the only files and directories that are linked; everything else is
+    /// left out, as if excluded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    /// Branch or tag to check out (only for git repositories)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Commit the repository is pinned to (only for git repositories)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Clone depth for shallow clones, overriding `git.depth` (only for git repositories)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    /// Where the repository is cloned, instead of the dotme git directory (only for git
+    /// repositories)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clone_path: Option<PathBuf>,
+    /// URLs to clone or pull from, in order, when the source can't be reached (only for git
+    /// repositories)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// Which submodules are checked out (only for git repositories)
+    #[serde(default, skip_serializing_if = "Submodules::is_default")]
+    pub submodules: Submodules,
+    /// Short description of what the entry is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Freeform notes about the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// What to do when a plain file is in the way of a symlink
+    #[serde(default, skip_serializing_if = "ConflictPolicy::is_default")]
+    pub on_conflict: ConflictPolicy,
+    /// Whether files are symlinked, copied, or hardlinked
+    #[serde(default, skip_serializing_if = "LinkMode::is_default")]
+    pub mode: LinkMode,
+    /// Whether directories are linked as a whole or created with their contents linked
+    #[serde(default, skip_serializing_if = "LinkDepth::is_default")]
+    pub link_depth: LinkDepth,
+    /// How the names of the entry's files map to the names of their links
+    #[serde(default, skip_serializing_if = "Naming::is_default")]
+    pub naming: Naming,
+    /// Operating systems the entry applies to (all if not set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<Vec<Os>>,
+    /// Condition a machine has to match for the entry to apply (always if not set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<Condition>,
+    /// When `update` refreshes the entry
+    #[serde(default, skip_serializing_if = "UpdatePolicy::is_default")]
+    pub update: UpdatePolicy,
+    /// Order the entry is applied in (default: 0); when two entries link the same path, the
+    /// higher priority wins, and the later one in the config among equals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Sources or names of entries that have to be in place before this one, e.g. the framework
+    /// a plugin repository is cloned into
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Commands run when the entry is installed, updated, or removed
+    #[serde(default, skip_serializing_if = "Scripts::is_default")]
+    pub scripts: Scripts,
+    /// Leave the entry out of exports and manifests, e.g. for work-internal repositories
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    /// Included file the entry is defined in, if it isn't in the config itself
+    #[serde(skip)]
+    pub included_from: Option<PathBuf>,
+}
+
+impl DotfileEntry {
+    /// Entry for `source` of the given type with everything else left at its default. The
+    /// target is empty until it is set or computed by the source's provider.
+    pub fn new(source: impl Into<String>, r#type: SourceType) -> Self {
+        Self {
+            source: source.into(),
+            name: None,
+            tags: Vec::new(),
+            target: PathBuf::new(),
+            r#type,
+            path: None,
+            folders: None,
+            exclude: None,
+            include: None,
+            branch: None,
+            rev: None,
+            depth: None,
+            clone_path: None,
+            mirrors: Vec::new(),
+            submodules: Submodules::default(),
+            description: None,
+            notes: None,
+            on_conflict: ConflictPolicy::default(),
+            mode: LinkMode::default(),
+            link_depth: LinkDepth::default(),
+            naming: Naming::default(),
+            os: None,
+            when: None,
+            update: UpdatePolicy::default(),
+            priority: None,
+            depends_on: Vec::new(),
+            scripts: Scripts::default(),
+            private: false,
+            included_from: None,
+        }
+    }
+
+    /// Get the local path of the entry: the clone location for git repositories, the target
+    /// otherwise
+    pub fn local_path(&self) -> PathBuf {
+        match &self.clone_path {
+            Some(path) if matches!(self.r#type, SourceType::Git) => expand_home(path),
+            _ => self.target.clone(),
+        }
+    }
+
+    /// Check if the entry applies to the operating system dotme is running on
+    pub fn applies_to_current_os(&self) -> bool {
+        match &self.os {
+            Some(os) => Os::current().is_some_and(|current| os.contains(&current)),
+            None => true,
+        }
+    }
+
+    /// Check if the entry applies to this machine: its operating system and `when` condition
+    pub fn applies_to(&self, facts: &Facts) -> bool {
+        self.applies_to_current_os() && self.when.as_ref().is_none_or(|when| when.matches(facts))
+    }
+
+    /// Fail if the entry is defined in an included file, which dotme never edits
+    pub fn ensure_editable(&self) -> Result<()> {
+        match &self.included_from {
+            Some(file) => anyhow::bail!(
+                "'{}' is defined in {}. Change it there instead.",
+                self.source,
+                file.display()
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
 }
 
 /// Paths configuration for dotme directories and files
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct PathsConfig {
     /// Path to the dotme directory (default: ~/.dotme)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -59,24 +463,13 @@ pub struct PathsConfig {
     pub symlinks_file: Option<PathBuf>,
 }
 
-impl Default for PathsConfig {
-    fn default() -> Self {
-        Self {
-            dotme_dir: None,
-            git_dir: None,
-            symlinks_file: None,
-        }
-    }
-}
-
 impl PathsConfig {
     /// Get the dotme directory path, using configured value or default
     pub fn get_dotme_dir(&self) -> Result<PathBuf> {
         if let Some(ref path) = self.dotme_dir {
             Ok(path.clone())
         } else {
-            let home = dirs::home_dir().context("Failed to get home directory")?;
-            Ok(home.join(".dotme"))
+            crate::layout::state_dir()
         }
     }
 
@@ -99,70 +492,485 @@ impl PathsConfig {
     }
 }
 
+/// Implementation used to run git operations
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GitBackend {
+    /// Run the `git` command line tool
+    #[default]
+    Cli,
+    /// Use the built-in pure-Rust implementation (requires the `gix-backend` build feature)
+    Gix,
+}
+
+impl GitBackend {
+    /// Check if this is the default backend
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for GitBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitBackend::Cli => write!(f, "cli"),
+            GitBackend::Gix => write!(f, "gix"),
+        }
+    }
+}
+
+/// Credentials used to access private repositories
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GitAuthConfig {
+    /// SSH private key used for SSH remotes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_key: Option<PathBuf>,
+    /// Environment variable holding an access token for HTTPS remotes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    /// Username sent along with the token (default: x-access-token)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Git credential helper for HTTPS remotes (e.g. `store`, `osxkeychain`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+}
+
+impl GitAuthConfig {
+    /// Check if no credentials are configured
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Git configuration shared by all git entries
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GitConfig {
+    /// Git implementation to use
+    #[serde(default, skip_serializing_if = "GitBackend::is_default")]
+    pub backend: GitBackend,
+    /// Default clone depth for shallow clones (full history if not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    /// Credentials for private repositories
+    #[serde(default, skip_serializing_if = "GitAuthConfig::is_default")]
+    pub auth: GitAuthConfig,
+}
+
+impl GitConfig {
+    /// Check if no git settings are configured
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Settings for `dotme update`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UpdateConfig {
+    /// Maximum number of repositories updated at the same time (default: 4)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    /// Minimum time between upstream checks of a repository by `dotme outdated`, e.g. `1h`
+    /// (default: 1h)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check_interval: Option<String>,
+}
+
+impl UpdateConfig {
+    /// Check if no update settings are configured
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Settings for managed symlinks
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SymlinksConfig {
+    /// Age after which a link that hasn't been verified is reported as stale, e.g. `30d` (default: 30d)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_after: Option<String>,
+    /// Point links at their targets with relative paths, so they survive the home directory
+    /// being mounted elsewhere
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub relative: bool,
+    /// Where the symlink state is kept
+    #[serde(default, skip_serializing_if = "StateBackend::is_default")]
+    pub store: StateBackend,
+}
+
+/// Settings for dotme's own log
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LoggingConfig {
+    /// Also append everything logged to `logs/dotme.log` in the dotme directory
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub file: bool,
+    /// Size after which the log file is rotated, e.g. `10M` (default: 1M)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<String>,
+    /// Number of rotated log files to keep (default: 5)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep: Option<usize>,
+}
+
+impl LoggingConfig {
+    /// Check if no logging settings are configured
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Which updates are reported to the notification channels
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyOn {
+    /// Every update
+    #[default]
+    Always,
+    /// Updates that pulled commits or failed
+    Changes,
+    /// Updates that failed
+    Failure,
+}
+
+impl NotifyOn {
+    /// Check if this is the default
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Where the results of `dotme update` are reported, e.g. for alerts from unattended machines
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NotificationsConfig {
+    /// Shell command run after an update, with the JSON report on stdin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// URL the JSON report is POSTed to after an update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+    /// Which updates are reported (default: always)
+    #[serde(default, skip_serializing_if = "NotifyOn::is_default")]
+    pub on: NotifyOn,
+}
+
+impl NotificationsConfig {
+    /// Check if no notification settings are configured
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Where the symlink state is kept
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StateBackend {
+    /// `symlinks.yml`, rewritten whenever a link changes
+    #[default]
+    Yaml,
+    /// `state.db`, an SQLite database updated one link at a time (requires the `sqlite-state`
+    /// build feature)
+    Sqlite,
+}
+
+impl StateBackend {
+    /// Check if this is the default store
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl std::fmt::Display for StateBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateBackend::Yaml => write!(f, "yaml"),
+            StateBackend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+impl SymlinksConfig {
+    /// Check if no symlink settings are configured
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
 /// Configuration settings
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Config {
+    /// Version of the config layout, upgraded automatically when an older config is loaded
+    #[serde(default)]
+    pub version: ConfigVersion,
     /// Last time dotme update was run (ISO 8601 format)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated: Option<String>,
+    /// URL or path of a shared baseline config whose entries are applied read-only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<String>,
+    /// Move `~/.dotme` to `~/.config/dotme` and `~/.local/state/dotme` on the next run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub xdg: bool,
+    /// Other config files merged into this one, relative to it; files that don't exist are
+    /// skipped, so machine-local ones can be left out of the repository
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
     /// Paths configuration
     #[serde(default)]
     pub paths: PathsConfig,
+    /// Git configuration
+    #[serde(default, skip_serializing_if = "GitConfig::is_default")]
+    pub git: GitConfig,
+    /// Update configuration
+    #[serde(default, skip_serializing_if = "UpdateConfig::is_default")]
+    pub update: UpdateConfig,
+    /// Symlink configuration
+    #[serde(default, skip_serializing_if = "SymlinksConfig::is_default")]
+    pub symlinks: SymlinksConfig,
+    /// Settings for dotme's own log
+    #[serde(default, skip_serializing_if = "LoggingConfig::is_default")]
+    pub logging: LoggingConfig,
+    /// Where the results of updates are reported
+    #[serde(default, skip_serializing_if = "NotificationsConfig::is_default")]
+    pub notifications: NotificationsConfig,
+    /// Feature flags, overriding the default for each feature
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, bool>,
+    /// Default values of command-line flags, keyed by subcommand; flags given on the command
+    /// line still take precedence
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defaults: BTreeMap<String, serde_yaml::Value>,
+    /// Values entry scripts refer to as `{{ name }}`, written here, read from the environment,
+    /// or asked once
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, Variable>,
+    /// System packages the dotfiles need, installed by `dotme packages install`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<Package>,
     /// List of managed dotfiles
     #[serde(default)]
     pub dotfiles: Vec<DotfileEntry>,
+    /// How the config looked before the included files were merged in, if there were any
+    #[serde(skip)]
+    pub layers: Option<Layers>,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            updated: None,
-            paths: PathsConfig::default(),
-            dotfiles: Vec::new(),
+/// The settings of a config with includes, so saving it writes back only what is its own
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layers {
+    /// Document of the config file itself
+    own: serde_yaml::Mapping,
+    /// Settings after the included files were merged in, without the entries
+    merged: serde_yaml::Mapping,
+}
+
+/// A config file to merge, with the document it was parsed into
+struct ConfigFile {
+    /// Where the file is
+    path: PathBuf,
+    /// Parsed contents, upgraded to the current version; `None` if it can't be parsed
+    document: Option<serde_yaml::Value>,
+    /// Version the file had, if it was upgraded
+    upgraded_from: Option<u32>,
+    /// Whether the file is encrypted, so only its decrypted document can be read
+    encrypted: bool,
+}
+
+impl ConfigFile {
+    /// Read the file at `path` and upgrade its contents
+    fn read(path: PathBuf) -> Result<Self> {
+        let mut document = migrate::read_document(&path)?;
+        let upgraded_from = match &mut document {
+            Some(document) => migrate::upgrade_config(&path, document)?,
+            None => None,
+        };
+        let encrypted = crate::encryption::is_encrypted_file(&path);
+        Ok(Self {
+            path,
+            document,
+            upgraded_from,
+            encrypted,
+        })
+    }
+
+    /// Files the config includes, resolved against its directory
+    fn includes(&self) -> Result<Vec<PathBuf>> {
+        let Some(include) = self.document.as_ref().and_then(|d| d.get("include")) else {
+            return Ok(Vec::new());
+        };
+        let include: Vec<PathBuf> = serde_yaml::from_value(include.clone()).map_err(|e| {
+            anyhow::anyhow!(
+                "'include' in {} must be a list of files: {}",
+                self.path.display(),
+                e
+            )
+        })?;
+        let dir = self.path.parent().unwrap_or(std::path::Path::new(""));
+        Ok(include
+            .iter()
+            .map(|file| dir.join(expand_home(file)))
+            .collect())
+    }
+
+    /// Number of entries the file defines
+    fn entries(&self) -> usize {
+        self.document
+            .as_ref()
+            .and_then(|d| d.get("dotfiles"))
+            .and_then(|d| d.as_sequence())
+            .map_or(0, Vec::len)
+    }
+
+    /// Provider of the file's settings
+    fn provider(&self) -> Result<Figment> {
+        let path = self.path.as_path();
+        if (self.upgraded_from.is_some() || self.encrypted)
+            && let Some(document) = &self.document
+        {
+            Ok(Figment::from(Serialized::defaults(document)))
+        } else if self.encrypted {
+            anyhow::bail!("Failed to parse {} after decrypting it", path.display())
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        {
+            log::debug!("Loading configuration from YAML file");
+            Ok(Figment::from(Yaml::file(path)))
+        } else if path.extension().is_some_and(|ext| ext == "toml") {
+            log::debug!("Loading configuration from TOML file");
+            Ok(Figment::from(Toml::file(path)))
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            log::debug!("Loading configuration from JSON file");
+            Ok(Figment::from(Json::file(path)))
+        } else {
+            log::warn!("Unsupported configuration file format");
+            Err(anyhow::anyhow!("Unsupported configuration file format"))
+        }
+    }
+}
+
+/// Files the config file at `path` includes directly, resolved against its directory
+pub fn includes_of(path: &std::path::Path) -> Result<Vec<PathBuf>> {
+    ConfigFile::read(path.to_path_buf())?.includes()
+}
+
+/// Add the file at `path` and the files it includes, depth first, to `files`
+fn collect_files(path: PathBuf, files: &mut Vec<ConfigFile>) -> Result<()> {
+    if files.iter().any(|file| file.path == path) {
+        log::warn!(
+            "{} is included more than once; using it once",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let file = ConfigFile::read(path)?;
+    let includes = file.includes()?;
+    files.push(file);
+    for include in includes {
+        if include.exists() {
+            collect_files(include, files)?;
+        } else {
+            log::debug!("Skipping {}, as it doesn't exist", include.display());
         }
     }
+    Ok(())
 }
 
 impl Config {
-    /// Load configuration from both environment variables and a configuration file
+    /// Load configuration from both environment variables and a configuration file, restoring
+    /// the file from its backup if it can't be loaded
     pub fn load(path: Option<PathBuf>) -> Result<Self> {
         let path = match path {
             Some(p) => p,
-            None => {
-                let home = dirs::home_dir().context("Failed to get home directory")?;
-                home.join(".dotme").join("config.yml")
-            }
+            None => crate::layout::config_dir()?.join("config.yml"),
         };
 
-        let path = path.as_path();
+        Self::load_file(&path)
+            .or_else(|e| crate::atomic::recover(&path, e, || Self::load_file(&path)))
+    }
+
+    /// Load configuration from both environment variables and exactly the file at `path`
+    pub fn load_file(path: &std::path::Path) -> Result<Self> {
         log::debug!("Loading configuration from {}", path.display());
 
         let project_name = PROJECT_NAME.to_uppercase();
         log::debug!("Loading environment prefix: {}", project_name);
         let mut fig = Figment::new().merge(Env::prefixed(project_name.as_str()));
 
+        let mut files = Vec::new();
         if path.exists() {
-            if path
-                .extension()
-                .is_some_and(|ext| ext == "yaml" || ext == "yml")
-            {
-                log::debug!("Loading configuration from YAML file");
-                fig = fig.merge(Yaml::file(path));
-            } else if path.extension().is_some_and(|ext| ext == "toml") {
-                log::debug!("Loading configuration from TOML file");
-                fig = fig.merge(Toml::file(path));
-            } else if path.extension().is_some_and(|ext| ext == "json") {
-                log::debug!("Loading configuration from JSON file");
-                fig = fig.merge(Json::file(path));
-            } else {
-                log::warn!("Unsupported configuration file format");
-                return Err(anyhow::anyhow!("Unsupported configuration file format"));
-            }
+            collect_files(path.to_path_buf(), &mut files)?;
         } else {
             log::warn!("Configuration file not found");
         }
+        // Included files override the settings before them and add to their lists
+        for (index, file) in files.iter().enumerate() {
+            fig = if index == 0 {
+                fig.merge(file.provider()?)
+            } else {
+                log::debug!("Including {}", file.path.display());
+                fig.admerge(file.provider()?)
+            };
+        }
+
+        let mut config: Self = fig.extract()?;
+        if files.len() > 1 {
+            let mut entries = config.dotfiles.iter_mut().skip(files[0].entries());
+            for file in &files[1..] {
+                for entry in entries.by_ref().take(file.entries()) {
+                    entry.included_from = Some(file.path.clone());
+                }
+            }
+
+            let mut merged = match serde_yaml::to_value(&config)? {
+                serde_yaml::Value::Mapping(merged) => merged,
+                _ => serde_yaml::Mapping::new(),
+            };
+            merged.remove("dotfiles");
+            let own = match &files[0].document {
+                Some(serde_yaml::Value::Mapping(own)) => own.clone(),
+                _ => serde_yaml::Mapping::new(),
+            };
+            config.layers = Some(Layers { own, merged });
+        }
+
+        // Only dotme's own config is rewritten; baselines and shared configs are left alone
+        if let Some(version) = files.first().and_then(|file| file.upgraded_from)
+            && crate::layout::config_dir().is_ok_and(|dir| path == dir.join("config.yml"))
+        {
+            migrate::backup(path, version, CONFIG_VERSION)?;
+            config.save(path)?;
+        }
+        Ok(config)
+    }
+
+    /// Document to save for a config with includes: its own entries, and its own settings
+    /// unless they were changed since it was loaded
+    fn own_document(&self) -> Result<Option<serde_yaml::Value>> {
+        let Some(layers) = &self.layers else {
+            return Ok(None);
+        };
+
+        let mut own = self.clone();
+        own.dotfiles.retain(|entry| entry.included_from.is_none());
+        let serde_yaml::Value::Mapping(mut document) = serde_yaml::to_value(&own)? else {
+            return Ok(None);
+        };
+        for (key, merged) in &layers.merged {
+            if document.get(key) != Some(merged) {
+                continue;
+            }
+            match layers.own.get(key) {
+                Some(value) => document.insert(key.clone(), value.clone()),
+                None => document.remove(key),
+            };
+        }
+        Ok(Some(serde_yaml::Value::Mapping(document)))
+    }
 
-        Ok(fig.extract()?)
+    /// Check if a feature is enabled for this configuration
+    pub fn feature_enabled(&self, name: &str) -> bool {
+        crate::features::is_enabled(&self.features, name)
     }
 
     /// Update the last updated timestamp to current time
@@ -182,22 +990,455 @@ impl Config {
         let path = path.into();
         log::debug!("Saving configuration to {}", path.display());
 
+        let own = self.own_document()?;
         let data = if path
             .extension()
             .is_some_and(|ext| ext == "yaml" || ext == "yml")
         {
-            serde_yaml::to_string(self)?
+            let data = match &own {
+                Some(own) => serde_yaml::to_string(own)?,
+                None => serde_yaml::to_string(self)?,
+            };
+
+            // Keep any comments the user wrote in the existing file
+            match crate::encryption::read(&path) {
+                Ok(original)
+                    if original.contains('#')
+                        && self.feature_enabled(crate::features::PRESERVE_COMMENTS) =>
+                {
+                    crate::comments::preserve(&original, &data)
+                }
+                _ => data,
+            }
         } else if path.extension().is_some_and(|ext| ext == "toml") {
-            toml::to_string(self)?
+            match &own {
+                Some(own) => toml::to_string(own)?,
+                None => toml::to_string(self)?,
+            }
         } else if path.extension().is_some_and(|ext| ext == "json") {
-            serde_json::to_string(self)?
+            match &own {
+                Some(own) => serde_json::to_string(own)?,
+                None => serde_json::to_string(self)?,
+            }
         } else {
             log::warn!("Unsupported configuration file format");
             return Err(anyhow::anyhow!("Unsupported configuration file format"));
         };
 
-        std::fs::write(path, data)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        crate::encryption::write(&path, &data)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packages::PackageSpec;
+    use crate::variables::Lookup;
+    use proptest::prelude::*;
+
+    /// Strategy for plain strings that survive every supported format
+    fn text() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9_./~:@-]{1,24}"
+    }
+
+    fn source_type() -> impl Strategy<Value = SourceType> {
+        prop_oneof![
+            Just(SourceType::File),
+            Just(SourceType::Directory),
+            Just(SourceType::Git),
+            Just(SourceType::Rclone),
+        ]
+    }
+
+    prop_compose! {
+        fn dotfile_entry()(
+            source in text(),
+            target in text(),
+            r#type in source_type(),
+            path in proptest::option::of(text()),
+            folders in proptest::option::of(proptest::collection::vec(text(), 0..4)),
+            (exclude, include, mirrors) in (
+                proptest::option::of(proptest::collection::vec(text(), 0..4)),
+                proptest::option::of(proptest::collection::vec(text(), 0..4)),
+                proptest::collection::vec(text(), 0..3),
+            ),
+            (branch, rev, depth, clone_path, submodules) in (
+                proptest::option::of(text()),
+                proptest::option::of(text()),
+                proptest::option::of(1u32..1000),
+                proptest::option::of(text()),
+                prop_oneof![
+                    Just(Submodules::None),
+                    Just(Submodules::Init),
+                    Just(Submodules::Recursive),
+                ],
+            ),
+            (name, tags, description, notes) in (
+                proptest::option::of("[a-z0-9-]{1,16}"),
+                proptest::collection::vec("[a-z]{1,8}", 0..3),
+                proptest::option::of("[a-zA-Z0-9 ,.'-]{1,40}"),
+                proptest::option::of("[a-zA-Z0-9 ,.'\n-]{1,80}"),
+            ),
+            (on_conflict, mode, link_depth, os, when) in (
+                prop_oneof![
+                    Just(ConflictPolicy::Skip),
+                    Just(ConflictPolicy::Backup),
+                    Just(ConflictPolicy::Overwrite),
+                ],
+                prop_oneof![
+                    Just(LinkMode::Symlink),
+                    Just(LinkMode::Copy),
+                    Just(LinkMode::Hardlink),
+                    Just(LinkMode::Reflink),
+                ],
+                prop_oneof![
+                    Just(LinkDepth::Auto),
+                    Just(LinkDepth::Dir),
+                    Just(LinkDepth::Contents),
+                    Just(LinkDepth::Deep),
+                ],
+                proptest::option::of(proptest::collection::vec(
+                    prop_oneof![Just(Os::Linux), Just(Os::Macos), Just(Os::Windows)],
+                    1..3,
+                )),
+                proptest::option::of(condition()),
+            ),
+            (update, priority, depends_on, scripts, private, naming) in (
+                prop_oneof![
+                    Just(UpdatePolicy::Auto),
+                    Just(UpdatePolicy::Manual),
+                    Just(UpdatePolicy::Frozen),
+                ],
+                proptest::option::of(-10..10i32),
+                proptest::collection::vec("[a-z]{1,8}", 0..2),
+                (
+                    proptest::option::of("[a-z +-]{1,16}"),
+                    proptest::option::of("[a-z +-]{1,16}"),
+                    proptest::option::of("[a-z +-]{1,16}"),
+                )
+                    .prop_map(|(install, update, remove)| Scripts {
+                        install,
+                        update,
+                        remove,
+                    }),
+                any::<bool>(),
+                prop_oneof![Just(Naming::Plain), Just(Naming::Chezmoi)],
+            ),
+        ) -> DotfileEntry {
+            DotfileEntry {
+                source,
+                name,
+                tags,
+                target: PathBuf::from(target),
+                r#type,
+                path: path.map(PathBuf::from),
+                folders,
+                exclude,
+                include,
+                branch,
+                rev,
+                depth,
+                clone_path: clone_path.map(PathBuf::from),
+                mirrors,
+                submodules,
+                description,
+                notes,
+                on_conflict,
+                mode,
+                link_depth,
+                naming,
+                os,
+                when,
+                update,
+                priority,
+                depends_on,
+                scripts,
+                private,
+                included_from: None,
+            }
+        }
+    }
+
+    prop_compose! {
+        fn condition()(
+            hostname in proptest::collection::vec("[a-z*?-]{1,12}", 0..3),
+            env in proptest::collection::vec("[A-Z_]{1,8}(=[a-z*]{0,5})?", 0..3),
+            host in proptest::collection::vec("[a-z_]{1,8}(=[a-z0-9*]{0,5})?", 0..3),
+            is_wsl in proptest::option::of(any::<bool>()),
+            is_ssh in proptest::option::of(any::<bool>()),
+        ) -> Condition {
+            Condition { hostname, env, host, is_wsl, is_ssh }
+        }
+    }
+
+    prop_compose! {
+        fn git_config()(
+            backend in prop_oneof![Just(GitBackend::Cli), Just(GitBackend::Gix)],
+            depth in proptest::option::of(1u32..1000),
+            ssh_key in proptest::option::of(text()),
+            token_env in proptest::option::of("[A-Z_]{1,16}"),
+            username in proptest::option::of(text()),
+            credential_helper in proptest::option::of(text()),
+        ) -> GitConfig {
+            GitConfig {
+                backend,
+                depth,
+                auth: GitAuthConfig {
+                    ssh_key: ssh_key.map(PathBuf::from),
+                    token_env,
+                    username,
+                    credential_helper,
+                },
+            }
+        }
+    }
+
+    prop_compose! {
+        fn config()(
+            updated in proptest::option::of(text()),
+            (baseline, xdg, include) in (
+                proptest::option::of("https://[a-z]{1,12}\\.com/[a-z]{1,12}\\.yml"),
+                any::<bool>(),
+                proptest::collection::vec("[a-z]{1,12}\\.yml", 0..3),
+            ),
+            dotme_dir in proptest::option::of(text()),
+            git_dir in proptest::option::of(text()),
+            symlinks_file in proptest::option::of(text()),
+            git in git_config(),
+            (concurrency, check_interval) in (
+                proptest::option::of(1usize..64),
+                proptest::option::of("[1-9][0-9]{0,2}[smhdw]"),
+            ),
+            stale_after in proptest::option::of("[1-9][0-9]{0,2}[smhdw]"),
+            (relative, store) in (
+                any::<bool>(),
+                prop_oneof![Just(StateBackend::Yaml), Just(StateBackend::Sqlite)],
+            ),
+            (file, max_size, keep) in (
+                any::<bool>(),
+                proptest::option::of("[1-9][0-9]{0,2}[KMG]"),
+                proptest::option::of(0usize..10),
+            ),
+            (command, webhook_url, on) in (
+                proptest::option::of("[a-z]{1,12}( [a-z-]{1,8})?"),
+                proptest::option::of("https://[a-z]{1,12}\\.sh/[a-z]{1,12}"),
+                prop_oneof![Just(NotifyOn::Always), Just(NotifyOn::Changes), Just(NotifyOn::Failure)],
+            ),
+            features in proptest::collection::btree_map("[a-z-]{1,12}", any::<bool>(), 0..3),
+            defaults in proptest::collection::btree_map(
+                "[a-z-]{1,12}",
+                any::<bool>().prop_map(serde_yaml::Value::Bool),
+                0..3,
+            ),
+            variables in proptest::collection::btree_map("[a-z_]{1,12}", variable(), 0..3),
+            packages in proptest::collection::vec(package(), 0..4),
+            dotfiles in proptest::collection::vec(dotfile_entry(), 0..5),
+        ) -> Config {
+            Config {
+                version: ConfigVersion::default(),
+                updated,
+                baseline,
+                xdg,
+                include: include.into_iter().map(PathBuf::from).collect(),
+                paths: PathsConfig {
+                    dotme_dir: dotme_dir.map(PathBuf::from),
+                    git_dir: git_dir.map(PathBuf::from),
+                    symlinks_file: symlinks_file.map(PathBuf::from),
+                },
+                git,
+                update: UpdateConfig { concurrency, check_interval },
+                symlinks: SymlinksConfig {
+                    stale_after,
+                    relative,
+                    store,
+                },
+                logging: LoggingConfig {
+                    file,
+                    max_size,
+                    keep,
+                },
+                notifications: NotificationsConfig {
+                    command,
+                    webhook_url,
+                    on,
+                },
+                features,
+                defaults,
+                variables,
+                packages,
+                dotfiles,
+                layers: None,
+            }
+        }
+    }
+
+    fn variable() -> impl Strategy<Value = Variable> {
+        prop_oneof![
+            "[a-z@. ]{1,16}".prop_map(Variable::Value),
+            (
+                proptest::option::of("[A-Z_]{1,12}"),
+                proptest::option::of("[A-Za-z ]{1,16}"),
+                proptest::option::of("[a-z]{1,12}"),
+            )
+                .prop_map(|(env, prompt, default)| Variable::Lookup(Lookup {
+                    env,
+                    prompt,
+                    default,
+                })),
+        ]
+    }
+
+    fn package() -> impl Strategy<Value = Package> {
+        prop_oneof![
+            "[a-z0-9-]{1,12}".prop_map(Package::Name),
+            (
+                "[a-z0-9-]{1,12}",
+                proptest::option::of("[a-z0-9-]{1,12}"),
+                proptest::option::of("[a-z0-9-]{1,12}"),
+                proptest::option::of("[a-z0-9-]{1,12}"),
+                proptest::option::of("[a-z0-9-]{1,12}"),
+            )
+                .prop_map(|(name, brew, apt, dnf, pacman)| {
+                    Package::Detailed(PackageSpec {
+                        name,
+                        brew,
+                        apt,
+                        dnf,
+                        pacman,
+                    })
+                }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_config_round_trip(config in config(), ext in prop_oneof!["yml", "yaml", "toml", "json"]) {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join(format!("config.{}", ext));
+
+            config.save(&path).unwrap();
+            let loaded = Config::load(Some(path)).unwrap();
+
+            prop_assert_eq!(loaded, config);
+        }
+
+        #[test]
+        fn test_config_round_trip_preserving_comments(config in config(), other in config()) {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("config.yml");
+
+            // A commented file rewritten with different content must still load back exactly
+            let original = serde_yaml::to_string(&other).unwrap();
+            let commented = original
+                .lines()
+                .map(|l| format!("# note\n{} # inline", l))
+                .collect::<Vec<_>>()
+                .join("\n");
+            std::fs::write(&path, commented).unwrap();
+
+            config.save(&path).unwrap();
+            let loaded = Config::load(Some(path)).unwrap();
+
+            prop_assert_eq!(loaded, config);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.ini");
+
+        assert!(Config::default().save(&path).is_err());
+    }
+
+    #[test]
+    fn test_os_filter() {
+        let current = Os::current().unwrap();
+        let other = [Os::Linux, Os::Macos, Os::Windows]
+            .into_iter()
+            .find(|os| *os != current)
+            .unwrap();
+
+        let mut entry = DotfileEntry::new("/dots", SourceType::Directory);
+        assert!(entry.applies_to_current_os());
+
+        entry.os = Some(vec![other]);
+        assert!(!entry.applies_to_current_os());
+
+        entry.os = Some(vec![other, current]);
+        assert!(entry.applies_to_current_os());
+    }
+    #[test]
+    fn test_link_depth_levels() {
+        // Existing directories are descended into unless the depth links them as a whole
+        assert!(LinkDepth::Auto.descends(3) && !LinkDepth::Auto.creates(1));
+        assert!(!LinkDepth::Dir.descends(1) && !LinkDepth::Dir.creates(1));
+        assert!(LinkDepth::Contents.creates(1) && !LinkDepth::Contents.creates(2));
+        assert!(!LinkDepth::Contents.descends(2));
+        assert!(LinkDepth::Deep.creates(5));
+
+        let entry: DotfileEntry = serde_yaml::from_str(
+            "source: /tmp/dots\ntarget: /tmp/dots\ntype: directory\nlink_depth: contents",
+        )
+        .unwrap();
+        assert_eq!(entry.link_depth, LinkDepth::Contents);
+    }
+
+    #[test]
+    fn test_includes_are_merged_but_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        let entry = |source: &str| {
+            format!(
+                "- source: /tmp/{}\n  target: /tmp/{}\n  type: directory\n",
+                source, source
+            )
+        };
+        std::fs::write(
+            &path,
+            format!(
+                "include: [work.yml, local.yml, missing.yml]\nsymlinks:\n  stale_after: 30d\ndotfiles:\n{}",
+                entry("dots")
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("work.yml"),
+            format!("packages: [git]\ndotfiles:\n{}", entry("work")),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("local.yml"),
+            "symlinks:\n  stale_after: 1w\ninclude: [work.yml]\n",
+        )
+        .unwrap();
+
+        // Later files override settings and add entries; missing and repeated files are skipped
+        let mut config = Config::load(Some(path.clone())).unwrap();
+        assert_eq!(config.symlinks.stale_after.as_deref(), Some("1w"));
+        assert_eq!(config.packages, vec![Package::Name("git".to_string())]);
+        let sources: Vec<_> = config.dotfiles.iter().map(|e| e.source.as_str()).collect();
+        assert_eq!(sources, ["/tmp/dots", "/tmp/work"]);
+        assert!(config.dotfiles[0].ensure_editable().is_ok());
+        assert_eq!(
+            config.dotfiles[1].included_from.as_deref(),
+            Some(dir.path().join("work.yml").as_path())
+        );
+        assert!(config.dotfiles[1].ensure_editable().is_err());
+
+        // Saving writes back only the file's own settings and entries, plus what changed
+        config.update.concurrency = Some(2);
+        config.save(&path).unwrap();
+        let saved: Config = serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.symlinks.stale_after.as_deref(), Some("30d"));
+        assert!(saved.packages.is_empty());
+        assert_eq!(saved.dotfiles.len(), 1);
+        assert_eq!(saved.update.concurrency, Some(2));
+        assert_eq!(Config::load(Some(path)).unwrap().dotfiles.len(), 2);
+    }
+}
@@ -0,0 +1,200 @@
+//! Pre-flight checks for free disk space and inodes
+//!
+//! Before cloning a repository or copying an entry's files (`mode: copy`), dotme estimates how
+//! much will be written and checks that the destination filesystem has room for it, so a full
+//! disk fails early with a clear message instead of in the middle of a clone, leaving a partial
+//! checkout behind. Estimates are rough:
+//!
+//! - Local repositories (paths and `file://` URLs) need about twice the size of their `.git`
+//!   directory, for the objects and the checkout.
+//! - Remote repositories can't be sized without fetching them, so only the headroom below is
+//!   required.
+//! - Copies need the size of every file that isn't deployed yet.
+//!
+//! A little headroom is always kept free on top of the estimate. The checks are skipped on
+//! platforms and filesystems that don't report free space.
+
+use anyhow::Result;
+use std::path::Path;
+
+/// Space always left free on top of the estimate
+const HEADROOM_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Inodes always left free on top of the estimate
+const HEADROOM_FILES: u64 = 256;
+
+/// Space and inodes an operation needs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Bytes written
+    pub bytes: u64,
+    /// Files and directories created
+    pub files: u64,
+}
+
+impl Usage {
+    /// Add the usage of a single file or directory
+    fn add(&mut self, bytes: u64) {
+        self.bytes += bytes;
+        self.files += 1;
+    }
+}
+
+/// Free space and inodes of a filesystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Available {
+    /// Bytes available to unprivileged users
+    bytes: u64,
+    /// Inodes available to unprivileged users, if the filesystem has a fixed number of them
+    files: Option<u64>,
+}
+
+/// Free space on the filesystem holding `path`, or `None` if it can't be determined
+#[cfg(unix)]
+fn available(path: &Path) -> Option<Available> {
+    let stats = rustix::fs::statvfs(path)
+        .inspect_err(|e| log::debug!("Failed to get free space of {}: {}", path.display(), e))
+        .ok()?;
+
+    Some(Available {
+        bytes: stats.f_bavail.saturating_mul(stats.f_frsize),
+        // Filesystems that allocate inodes dynamically (btrfs, ZFS) report none at all
+        files: (stats.f_files > 0).then_some(stats.f_favail),
+    })
+}
+
+/// Free space on the filesystem holding `path`, or `None` if it can't be determined
+#[cfg(not(unix))]
+fn available(_path: &Path) -> Option<Available> {
+    None
+}
+
+/// Check that an operation needing `usage` can fit, with headroom to spare
+fn check(usage: Usage, available: Available) -> std::result::Result<(), String> {
+    let bytes = usage.bytes.saturating_add(HEADROOM_BYTES);
+    if bytes > available.bytes {
+        return Err(format!(
+            "needs about {} but only {} is free",
+            format_size(bytes),
+            format_size(available.bytes)
+        ));
+    }
+
+    let files = usage.files.saturating_add(HEADROOM_FILES);
+    match available.files {
+        Some(free) if files > free => Err(format!(
+            "needs about {} inodes but only {} are free",
+            files, free
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Fail if the filesystem `path` will be written to doesn't have room for `usage`. `what`
+/// describes the operation, e.g. `cloning <url>`.
+pub fn ensure(path: &Path, usage: Usage, what: &str) -> Result<()> {
+    // The destination may not exist yet; its closest existing parent is on the same filesystem
+    let Some(existing) = path.ancestors().find(|dir| dir.exists()) else {
+        return Ok(());
+    };
+    let Some(available) = available(existing) else {
+        return Ok(());
+    };
+
+    log::debug!(
+        "Disk space for {}: need {} in {} file(s), {} free",
+        what,
+        format_size(usage.bytes),
+        usage.files,
+        format_size(available.bytes)
+    );
+
+    check(usage, available).map_err(|reason| {
+        anyhow::anyhow!(
+            "Not enough disk space for {} in {}: {}. Free up some space and try again.",
+            what,
+            existing.display(),
+            reason
+        )
+    })
+}
+
+/// Space and inodes taken up by `path` and everything inside it, not following symlinks
+pub fn usage_of(path: &Path) -> Usage {
+    let mut usage = Usage::default();
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let Ok(metadata) = path.symlink_metadata() else {
+            continue;
+        };
+        usage.add(metadata.len());
+        if metadata.is_dir()
+            && let Ok(entries) = std::fs::read_dir(&path)
+        {
+            pending.extend(entries.flatten().map(|entry| entry.path()));
+        }
+    }
+    usage
+}
+
+/// Format a number of bytes for humans, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_space_and_inodes_are_checked() {
+        let usage = Usage {
+            bytes: 100 * 1024 * 1024,
+            files: 5000,
+        };
+        let plenty = Available {
+            bytes: 10 * 1024 * 1024 * 1024,
+            files: Some(1_000_000),
+        };
+        assert!(check(usage, plenty).is_ok());
+
+        // The headroom is needed on top of the estimate
+        let exact = Available {
+            bytes: usage.bytes,
+            ..plenty
+        };
+        assert!(check(usage, exact).unwrap_err().contains("MiB"));
+
+        let no_inodes = Available {
+            files: Some(100),
+            ..plenty
+        };
+        assert!(check(usage, no_inodes).unwrap_err().contains("inodes"));
+
+        let dynamic_inodes = Available {
+            files: None,
+            ..plenty
+        };
+        assert!(check(usage, dynamic_inodes).is_ok());
+    }
+
+    #[test]
+    fn test_sizes_are_formatted() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
@@ -1,21 +1,47 @@
 use anyhow::{Context, Result};
-use dialoguer::{Select, theme::ColorfulTheme};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
-
-use crate::config::{Config, DotfileEntry, SourceType};
+use tokio::sync::{Semaphore, watch};
+use tokio::task::JoinSet;
+
+use crate::baseline;
+use crate::conditions::Facts;
+use crate::config::{
+    Config, ConflictPolicy, DotfileEntry, GitAuthConfig, LinkDepth, LinkMode, Naming, Os,
+    SourceType, Submodules, UpdatePolicy,
+};
+use crate::diskspace::{self, Usage};
 use crate::git;
+use crate::gpg;
+use crate::health;
+use crate::history;
+use crate::hyperlinks::Hyperlinks;
+use crate::layout;
+use crate::manifest::{self, Manifest};
+use crate::notify;
+use crate::outdated;
+use crate::progress;
+use crate::prompt;
+use crate::redact;
+use crate::scripts::{self, Scripts, Stage};
+use crate::sources::{self, LinkSource, is_rclone_remote, is_remote};
 use crate::symlinks;
+use crate::trace::{self, Event};
 
-/// Get the dotme configuration directory (~/.dotme)
+/// Get the dotme directory holding clones and state (~/.dotme, or ~/.local/state/dotme with the
+/// XDG layout)
 pub fn get_dotme_dir() -> Result<PathBuf> {
-    let home = dirs::home_dir().context("Failed to get home directory")?;
-    Ok(home.join(".dotme"))
+    layout::state_dir()
 }
 
-/// Get the default config file path (~/.dotme/config.yml)
+/// Get the default config file path (~/.dotme/config.yml, or ~/.config/dotme/config.yml with the
+/// XDG layout)
 pub fn get_config_path() -> Result<PathBuf> {
-    Ok(get_dotme_dir()?.join("config.yml"))
+    Ok(layout::config_dir()?.join("config.yml"))
 }
 
 /// Get the git repositories directory (~/.dotme/git)
@@ -23,6 +49,16 @@ pub fn get_git_dir() -> Result<PathBuf> {
     Ok(get_dotme_dir()?.join("git"))
 }
 
+/// Get the directory rclone remotes are mirrored into (~/.dotme/remote)
+pub fn get_remote_dir() -> Result<PathBuf> {
+    Ok(get_dotme_dir()?.join("remote"))
+}
+
+/// Get the archive directory (~/.dotme/archive)
+pub fn get_archive_dir() -> Result<PathBuf> {
+    Ok(get_dotme_dir()?.join("archive"))
+}
+
 /// Initialize the dotme configuration
 pub async fn init() -> Result<()> {
     let dotme_dir = get_dotme_dir()?;
@@ -41,6 +77,12 @@ pub async fn init() -> Result<()> {
         .await
         .context("Failed to create .dotme directory")?;
 
+    if let Some(config_dir) = config_path.parent() {
+        fs::create_dir_all(config_dir)
+            .await
+            .context("Failed to create config directory")?;
+    }
+
     // Create git directory for storing cloned repositories
     fs::create_dir_all(&git_dir)
         .await
@@ -57,6 +99,65 @@ pub async fn init() -> Result<()> {
     Ok(())
 }
 
+/// Set up this machine from a git repository holding a dotme config: clone it, install the
+/// config as `~/.dotme/config.yml`, and run a full update. `token_env` names the variable holding
+/// an HTTPS token, as there is no config yet to say where to find credentials.
+pub async fn bootstrap(
+    url: &str,
+    file: &Path,
+    branch: Option<String>,
+    token_env: Option<String>,
+    force: bool,
+    jobs: Option<usize>,
+) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if config_path.exists() && !force {
+        anyhow::bail!(
+            "DotMe is already initialized at {}. Use --force to replace its config.",
+            get_dotme_dir()?.display()
+        );
+    }
+
+    let clone = get_git_dir()?.join(sources::repo_name(url));
+    let options = git::CloneOptions {
+        branch,
+        auth: GitAuthConfig {
+            token_env: token_env.clone(),
+            ..Default::default()
+        },
+        credential_url: git::credential_url(url),
+        ..Default::default()
+    };
+    git::clone(url, &clone, &options).await?;
+
+    let bootstrap_config = clone.join(file);
+    if !bootstrap_config.exists() {
+        anyhow::bail!("No dotme config at {} in {}", file.display(), url);
+    }
+
+    // Entries were added on another machine, so their targets are recomputed for this one
+    let mut config = Config::load(Some(bootstrap_config))
+        .with_context(|| format!("{} in {} is not a valid dotme config", file.display(), url))?;
+    for entry in &mut config.dotfiles {
+        localize_entry(entry)?;
+    }
+    // Entries are likely to be as private as the config
+    if config.git.auth.token_env.is_none() {
+        config.git.auth.token_env = token_env;
+    }
+    config.save(&config_path)?;
+    log::info!("✓ Installed config from {}", url);
+
+    update(UpdateOptions {
+        jobs,
+        ..Default::default()
+    })
+    .await?
+    .into_result()
+    .map(|_| ())
+}
+
 /// Detect the type of source based on its format/path
 fn detect_source_type(source: &str) -> Result<SourceType> {
     // Check for git repository patterns
@@ -102,20 +203,106 @@ fn detect_source_type(source: &str) -> Result<SourceType> {
         return Ok(SourceType::File);
     }
 
+    // Paths that don't exist locally may be rclone remotes such as `dropbox:dotfiles`
+    if is_rclone_remote(source) {
+        return Ok(SourceType::Rclone);
+    }
+
     anyhow::bail!(
-        "Could not determine source type for '{}'. Path does not exist or is not a valid git repository URL.",
+        "Could not determine source type for '{}'. Path does not exist or is not a valid git repository URL or rclone remote.",
         source
     )
 }
 
+/// Options for adding a new dotfile entry
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Path to file, directory, or git repository URL
+    pub source: String,
+    /// Short name to refer to the entry by
+    pub name: Option<String>,
+    /// Labels grouping related entries
+    pub tags: Vec<String>,
+    /// Optional target location
+    pub target: Option<PathBuf>,
+    /// Path where symlinks should be created
+    pub path: Option<PathBuf>,
+    /// Folders to select from a git repository
+    pub folders: Option<Vec<String>>,
+    /// Glob patterns to exclude from linking
+    pub exclude: Option<Vec<String>>,
+    /// Glob patterns of the only paths to link
+    pub include: Option<Vec<String>>,
+    /// Branch or tag to check out (git only)
+    pub branch: Option<String>,
+    /// Commit to pin the repository to (git only)
+    pub rev: Option<String>,
+    /// Clone depth for shallow clones (git only)
+    pub depth: Option<u32>,
+    /// Where to clone the repository (remote git only)
+    pub clone_path: Option<PathBuf>,
+    /// Leave the repository's submodules alone (git only)
+    pub no_submodules: bool,
+    /// Short description of the entry
+    pub description: Option<String>,
+    /// Freeform notes about the entry
+    pub notes: Option<String>,
+    /// What to do when a plain file is in the way of a symlink
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Back up plain files that are in the way of a symlink
+    pub force: bool,
+    /// Create symlinks with relative target paths
+    pub relative: bool,
+    /// Copy or hardlink files instead of symlinking them
+    pub mode: Option<LinkMode>,
+    /// Whether directories are linked as a whole or created with their contents linked
+    pub link_depth: Option<LinkDepth>,
+    /// How the names of the entry's files map to the names of their links
+    pub naming: Option<Naming>,
+    /// When `update` refreshes the entry
+    pub update: Option<UpdatePolicy>,
+    /// Order the entry is applied in
+    pub priority: Option<i32>,
+    /// Entries that have to be in place before this one
+    pub depends_on: Vec<String>,
+    /// Operating systems the entry applies to
+    pub os: Option<Vec<Os>>,
+    /// Show what would be done without creating symlinks
+    pub dry_run: bool,
+}
+
 /// Add a new dotfile entry
-pub async fn add(
-    source: &str,
-    target: Option<PathBuf>,
-    path: Option<PathBuf>,
-    folders: Option<Vec<String>>,
-    dry_run: bool,
-) -> Result<()> {
+pub async fn add(options: AddOptions) -> Result<()> {
+    let AddOptions {
+        source,
+        name,
+        tags,
+        target,
+        path,
+        folders,
+        exclude,
+        include,
+        branch,
+        rev,
+        depth,
+        clone_path,
+        no_submodules,
+        description,
+        notes,
+        on_conflict,
+        force,
+        relative,
+        mode,
+        link_depth,
+        naming,
+        update,
+        priority,
+        depends_on,
+        os,
+        dry_run,
+    } = options;
+    let source = source.as_str();
+
     let config_path = get_config_path()?;
 
     if !config_path.exists() {
@@ -126,6 +313,7 @@ pub async fn add(
 
     // Detect source type
     let source_type = detect_source_type(source)?;
+    let provider = sources::provider(source_type);
 
     log::info!("Detected source type: {}", source_type);
 
@@ -140,99 +328,104 @@ pub async fn add(
     log::debug!("Symlinks will be created in: {}", base_path.display());
 
     // Validate that base_path is not inside the source directory to prevent circular symlinks
-    let source_path = if matches!(source_type, SourceType::Git)
-        && !source.starts_with("http")
-        && !source.starts_with("git@")
-        && !source.starts_with("ssh://")
-    {
-        // For local git repos or directories, normalize the path
-        let p = Path::new(source);
-        if p.is_absolute() {
-            p.to_path_buf()
-        } else {
-            std::env::current_dir()?.join(p)
-        }
-    } else if !matches!(source_type, SourceType::Git) {
-        // For local files/directories
+    let source_path = if is_remote(source) {
+        // For remote git repos, skip this check
+        PathBuf::new()
+    } else {
+        // For local sources, normalize the path
         let p = Path::new(source);
         if p.is_absolute() {
             p.to_path_buf()
         } else {
             std::env::current_dir()?.join(p)
         }
-    } else {
-        // For remote git repos, skip this check
-        PathBuf::new()
     };
 
     // Check if base_path is the same as or inside the source directory
-    if !source_path.as_os_str().is_empty() && source_path.is_dir() {
-        if base_path == source_path || base_path.starts_with(&source_path) {
-            anyhow::bail!(
-                "Cannot create symlinks in '{}' because it is the same as or inside the source directory '{}'. \
-                Use --path to specify a different location for symlinks.",
-                base_path.display(),
-                source_path.display()
-            );
-        }
+    if !source_path.as_os_str().is_empty()
+        && source_path.is_dir()
+        && (base_path == source_path || base_path.starts_with(&source_path))
+    {
+        anyhow::bail!(
+            "Cannot create symlinks in '{}' because it is the same as or inside the source directory '{}'. \
+            Use --path to specify a different location for symlinks.",
+            base_path.display(),
+            source_path.display()
+        );
     }
 
-    // Check if this is a local git repository (has .git folder)
-    let is_local_git = matches!(source_type, SourceType::Git)
-        && !source.starts_with("https://")
-        && !source.starts_with("http://")
-        && !source.starts_with("git@")
-        && !source.starts_with("ssh://");
+    // Check for duplicates
+    if config.dotfiles.iter().any(|e| e.source == source) {
+        anyhow::bail!("Source '{}' is already being managed", source);
+    }
+    if let Some(name) = &name
+        && let Some(other) = config
+            .dotfiles
+            .iter()
+            .find(|e| e.name.as_ref() == Some(name))
+    {
+        anyhow::bail!("The name '{}' is already used by {}", name, other.source);
+    }
+    for dependency in &depends_on {
+        find_entry(&config.dotfiles, dependency)?;
+    }
 
-    // Determine target location
-    let target = if let Some(t) = target {
-        t
-    } else {
-        // For remote git repos, store in ~/.dotme/git directory
-        if matches!(source_type, SourceType::Git) && !is_local_git {
-            let git_dir = get_git_dir()?;
-            // Extract repo name from git URL
-            let repo_name = source
-                .rsplit('/')
-                .next()
-                .unwrap_or("repo")
-                .trim_end_matches(".git");
-            git_dir.join(repo_name)
-        } else if is_local_git {
-            // For local git repos, use the source path as-is
-            let source_path = Path::new(source);
-            if source_path.is_absolute() {
-                source_path.to_path_buf()
-            } else {
-                std::env::current_dir()?.join(source_path)
-            }
+    // Create the entry now so its git settings drive the clone
+    let mut entry = DotfileEntry {
+        name,
+        tags,
+        path: Some(base_path.clone()),
+        exclude,
+        include,
+        branch,
+        rev,
+        depth,
+        clone_path,
+        submodules: if no_submodules {
+            Submodules::None
         } else {
-            // For local files/directories, use base_path
-            let source_path = Path::new(source);
-            let filename = source_path
-                .file_name()
-                .context("Failed to get filename from source")?;
-            base_path.join(filename)
-        }
+            Submodules::default()
+        },
+        description,
+        notes,
+        on_conflict: on_conflict.unwrap_or_default(),
+        mode: mode.unwrap_or_default(),
+        link_depth: link_depth.unwrap_or_default(),
+        naming: naming.unwrap_or_default(),
+        update: update.unwrap_or_default(),
+        priority,
+        depends_on,
+        os,
+        ..DotfileEntry::new(source, source_type)
     };
+    provider.validate(&entry)?;
 
-    // Check for duplicates
-    if config.dotfiles.iter().any(|e| e.source == source) {
-        anyhow::bail!("Source '{}' is already being managed", source);
+    // Determine target location
+    entry.target = match target {
+        Some(target) => target,
+        None => provider.default_target(&entry)?,
+    };
+
+    if matches!(source_type, SourceType::Git) {
+        check_clone_path(&config.dotfiles, &entry)?;
     }
 
-    // For git repositories, clone them immediately (only remote repos)
-    let selected_folders = if matches!(source_type, SourceType::Git) {
-        // Check if git is available
-        git::check_git_available().await?;
+    // Keep entries for other machines in the config without applying them here
+    if !entry.applies_to(&Facts::detect()) {
+        history::added(&entry);
+        config.dotfiles.push(entry);
+        config.save(&config_path)?;
+        log::info!(
+            "Added '{}' to dotfiles management; it doesn't apply to this machine, so nothing was linked",
+            source
+        );
+        return Ok(());
+    }
 
-        // Only clone if it's a remote repository
-        if !is_local_git {
-            git::clone(source, &target).await?;
-        } else {
-            log::info!("Using local git repository at: {}", target.display());
-        }
+    // Put the source in place, e.g. clone remote git repositories
+    provider.fetch(&entry, &config.git).await?;
 
+    let selected_folders = if provider.selects_folders() {
         // If path is set, skip folder selection and use repo root (None means entire repo)
         // This overrides any --folders flag to ensure root-level symlinking
         if path.is_some() {
@@ -244,7 +437,7 @@ pub async fn add(
             None
         } else if folders.is_none() {
             // If folders weren't specified via CLI and no path, prompt the user
-            prompt_folder_selection(&target).await?
+            prompt_folder_selection(&entry.local_path()).await?
         } else {
             folders
         }
@@ -252,319 +445,2749 @@ pub async fn add(
         folders
     };
 
-    // Create final entry with selected folders
-    let entry = DotfileEntry {
-        source: source.to_string(),
-        target: target.clone(),
-        r#type: source_type,
-        path: Some(base_path.clone()),
-        folders: selected_folders,
-    };
+    // Finalise the entry with selected folders
+    entry.folders = selected_folders;
 
     config.dotfiles.push(entry.clone());
     config.save(&config_path)?;
+    history::added(&entry);
 
     log::info!("Added '{}' to dotfiles management", source);
 
     // Create symlinks for the newly added entry
+    let link_options = LinkOptions {
+        dry_run,
+        force,
+        relative: relative || config.symlinks.relative,
+        ..Default::default()
+    }
+    .for_entry(&entry);
     if dry_run {
         println!("\n[DRY RUN] Symlinks that would be created:");
-        create_symlinks_for_entry(&entry, &base_path, dry_run).await?;
+        create_symlinks_for_entry(&entry, &base_path, &link_options).await?;
     } else {
         log::info!("Creating symlinks...");
-        create_symlinks_for_entry(&entry, &base_path, dry_run).await?;
+        create_symlinks_for_entry(&entry, &base_path, &link_options).await?;
     }
+    scripts::run(&entry, Stage::Install, &config.variables, dry_run).await?;
 
     Ok(())
 }
 
-/// Show status of managed dotfiles
-pub async fn status() -> Result<()> {
+/// Merge entries from another dotme config file, asking for each entry whether to add it
+pub async fn add_from_config(file: &Path, dry_run: bool) -> Result<()> {
     let config_path = get_config_path()?;
 
     if !config_path.exists() {
-        println!("DotMe is not initialized. Run 'dotme init' to set up dotfiles management.");
-        return Ok(());
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
     }
 
-    let config = Config::load(Some(config_path))?;
+    if !file.exists() {
+        anyhow::bail!("Config file not found: {}", file.display());
+    }
 
-    if config.dotfiles.is_empty() {
-        println!("No dotfiles are currently being managed.");
-        println!("Use 'dotme add <source>' to add dotfiles.");
+    let mut config = Config::load(Some(config_path.clone()))?;
+    let other = Config::load(Some(file.to_path_buf()))
+        .with_context(|| format!("Failed to load {}", file.display()))?;
+
+    if other.dotfiles.is_empty() {
+        log::info!("No dotfiles found in {}", file.display());
         return Ok(());
     }
 
-    println!("Managed Dotfiles:");
-    if let Some(updated) = &config.updated {
-        println!("Last updated: {}", format_timestamp(updated));
-    }
-    println!("─────────────────────────────────────────");
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    let mut added = 0;
 
-    for entry in &config.dotfiles {
-        let status = if entry.target.exists() {
-            "✓ exists"
-        } else {
-            "✗ missing"
-        };
+    for mut entry in other.dotfiles {
+        localize_entry(&mut entry)?;
+
+        if let Err(reason) = check_merge_conflict(&config, &entry) {
+            println!("  ✗ {} ({})", entry.source, reason);
+            continue;
+        }
+
+        println!("\n{} [{}]", entry.source, entry.r#type);
+        if let Some(description) = &entry.description {
+            println!("  {}", description);
+        }
+        println!(
+            "  Links in: {}",
+            entry.path.as_deref().unwrap_or(&home).display()
+        );
+        // Scripts run unattended on every update, so their commands are shown up front
+        for stage in [Stage::Install, Stage::Update, Stage::Remove] {
+            if let Some(script) = entry.scripts.get(stage) {
+                println!("  Runs on {}: {}", stage, script);
+            }
+        }
 
-        println!("  {} [{}]", status, entry.r#type);
-        println!("    Source: {}", entry.source);
+        if dry_run {
+            println!(
+                "[DRY RUN] Would offer to add: {}",
+                redact::redact(&entry.source)
+            );
+            continue;
+        }
 
-        // For git repos, show they're stored in ~/.dotme/git
-        if matches!(entry.r#type, SourceType::Git) {
-            println!("    Local:  {}", entry.target.display());
-            if let Some(folders) = &entry.folders {
-                println!("    Folders: {}", folders.join(", "));
+        let accept = prompt::confirm(&format!("Add '{}'?", entry.source), true)?;
+        if accept {
+            // Scripts from someone else's config only come along when asked for explicitly
+            if !entry.scripts.is_default()
+                && !prompt::confirm("Keep its scripts? They run on this machine.", false)?
+            {
+                log::warn!(
+                    "Not adding the scripts of {}. Add them to your config to run them.",
+                    entry.source
+                );
+                entry.scripts = Scripts::default();
             }
-        } else {
-            println!("    Target: {}", entry.target.display());
+            log::info!("Added '{}' to dotfiles management", entry.source);
+            config.dotfiles.push(entry);
+            added += 1;
         }
-        println!();
+    }
+
+    if added > 0 {
+        config.save(&config_path)?;
+        for entry in &config.dotfiles[config.dotfiles.len() - added..] {
+            history::added(entry);
+        }
+        println!(
+            "\nAdded {} entry(ies). Run 'dotme update' to clone and link them.",
+            added
+        );
+    } else if !dry_run {
+        println!("\nNo entries added.");
     }
 
     Ok(())
 }
 
-/// Update all managed dotfiles
-pub async fn update(dry_run: bool) -> Result<()> {
-    let config_path = get_config_path()?;
-
-    if !config_path.exists() {
-        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+/// Adapt an entry from another machine's config to this machine
+pub fn localize_entry(entry: &mut DotfileEntry) -> Result<()> {
+    // Paths from another machine only make sense if they exist here
+    if entry.path.as_ref().is_some_and(|p| !p.exists()) {
+        entry.path = None;
     }
 
-    let mut config = Config::load(Some(config_path.clone()))?;
+    // Recompute the target the same way `add` does
+    entry.target = sources::provider(entry.r#type).default_target(entry)?;
 
-    if config.dotfiles.is_empty() {
-        log::info!("No dotfiles to update.");
-        return Ok(());
+    Ok(())
+}
+
+/// Check if an entry from another config can be merged into `config`
+fn check_merge_conflict(config: &Config, entry: &DotfileEntry) -> Result<()> {
+    if config.dotfiles.iter().any(|e| e.source == entry.source) {
+        anyhow::bail!("already managed");
     }
 
-    if dry_run {
-        println!("\n[DRY RUN] Update operation - showing what would be done:\n");
+    if matches!(entry.r#type, SourceType::Git) {
+        if !is_remote(&entry.source) && !Path::new(&entry.source).exists() {
+            anyhow::bail!("local repository does not exist on this machine");
+        }
+        check_clone_path(&config.dotfiles, entry)?;
+    } else {
+        if !matches!(entry.r#type, SourceType::Rclone) && !Path::new(&entry.source).exists() {
+            anyhow::bail!("source does not exist on this machine");
+        }
+        if let Some(other) = config
+            .dotfiles
+            .iter()
+            .find(|e| e.local_path() == entry.local_path())
+        {
+            anyhow::bail!(
+                "'{}' is already used by '{}'",
+                entry.local_path().display(),
+                other.source
+            );
+        }
     }
 
-    log::info!("Updating {} dotfile(s)...", config.dotfiles.len());
+    Ok(())
+}
 
-    for entry in &config.dotfiles {
-        log::info!("Processing: {} [{}]", entry.source, entry.r#type);
+/// Show status of managed dotfiles, re-rendering every `watch` seconds if set. With `fetch`,
+/// git repositories are fetched first so ahead/behind counts are current; with `health`, entries
+/// are scored and their problems listed instead.
+pub async fn status(
+    watch: Option<u64>,
+    fetch: bool,
+    health: bool,
+    names: Vec<String>,
+    tags: Vec<String>,
+) -> Result<()> {
+    if fetch {
+        fetch_repositories(&names, &tags).await?;
+    }
 
-        // Determine the base path for symlinks
-        let base_path = if let Some(ref p) = entry.path {
-            p.clone()
+    let render = || async {
+        if health {
+            render_health(&names, &tags).await
         } else {
-            // Default to home directory for backward compatibility
-            dirs::home_dir().context("Failed to get home directory")?
-        };
-
-        // Step 1: Update git repositories first (if applicable)
-        if matches!(entry.r#type, SourceType::Git) {
-            // If repository doesn't exist, clone it
-            if !entry.target.exists() {
-                if dry_run {
-                    println!("[DRY RUN] Would clone repository: {}", entry.source);
-                } else {
-                    log::info!("Repository not found, cloning...");
-                    git::clone(&entry.source, &entry.target).await?;
-                }
-            } else {
-                // Otherwise, pull latest changes
-                if dry_run {
-                    println!("[DRY RUN] Would pull latest changes from: {}", entry.source);
-                } else {
-                    git::pull(&entry.target).await?;
-                }
-            }
-        }
-
-        // Step 2: Remove old symlinks before creating new ones
-        let removed_count = remove_symlinks_for_entry(entry, Some(&base_path), dry_run).await?;
-        if removed_count > 0 {
-            log::info!("Removing old symlinks");
+            render_status(&names, &tags).await
         }
+    };
 
-        // Step 3: Create new symlinks
-        log::info!("Creating new symlinks");
-        create_symlinks_for_entry(entry, &base_path, dry_run).await?;
-    }
-
-    // Update the timestamp (only if not dry run)
-    if !dry_run {
-        config.update_timestamp();
-        config.save(&config_path)?;
-    } else {
-        println!("\n[DRY RUN] Would update timestamp in config");
-    }
+    let Some(interval) = watch else {
+        print!("{}", render().await?);
+        return Ok(());
+    };
 
-    log::info!("Update complete!");
+    let term = console::Term::stdout();
+    let interval = interval.max(1);
 
-    Ok(())
-}
+    // Runs until interrupted with Ctrl-C
+    loop {
+        let output = render().await?;
 
-async fn copy_file(source: &str, target: &Path) -> Result<()> {
-    let source_path = Path::new(source);
+        term.clear_screen()?;
+        println!(
+            "Every {}s: dotme status    {}\n",
+            interval,
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        );
+        print!("{}", output);
 
-    if !source_path.exists() {
-        anyhow::bail!("Source file '{}' does not exist", source);
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
     }
+}
 
-    // Create parent directory if needed
-    if let Some(parent) = target.parent() {
-        fs::create_dir_all(parent).await?;
+/// Fetch every git repository that is cloned, or the ones in `names` or with one of `tags`,
+/// without touching the checkouts
+async fn fetch_repositories(names: &[String], tags: &[String]) -> Result<()> {
+    let config_path = get_config_path()?;
+    if !config_path.exists() {
+        return Ok(());
     }
 
-    fs::copy(source_path, target).await.context(format!(
-        "Failed to copy {} to {}",
-        source,
-        target.display()
-    ))?;
+    let config = Config::load(Some(config_path))?;
+    for entry in select_entries(active_entries(&config)?, names, &[], tags)? {
+        let repo_path = entry.local_path();
+        if !matches!(entry.r#type, SourceType::Git) || !repo_path.join(".git").exists() {
+            continue;
+        }
 
-    log::info!("  ✓ Copied to {}", target.display());
+        log::info!("Fetching {}...", entry.source);
+        if let Err(e) = git::fetch(&repo_path, &git::CloneOptions::new(&entry, &config.git)).await {
+            log::warn!("Failed to fetch {}: {:#}", entry.source, e);
+        }
+    }
 
     Ok(())
 }
 
-async fn copy_directory(source: &str, target: &Path) -> Result<()> {
-    let source_path = Path::new(source);
-
-    if !source_path.exists() {
-        anyhow::bail!("Source directory '{}' does not exist", source);
-    }
-
-    fs::create_dir_all(target).await?;
-
-    let mut entries = fs::read_dir(source_path).await?;
-
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-        let file_name = path.file_name().unwrap();
-        let target_path = target.join(file_name);
+/// Branch, ahead/behind counts and working tree state of a cloned repository, e.g.
+/// `main, 2 ahead, 1 behind, dirty`
+async fn git_summary(repo_path: &Path) -> Result<String> {
+    let mut parts = vec![
+        git::current_branch(repo_path)
+            .await?
+            .unwrap_or_else(|| "detached HEAD".to_string()),
+    ];
 
-        if path.is_dir() {
-            Box::pin(copy_directory(path.to_str().unwrap(), &target_path)).await?;
-        } else {
-            fs::copy(&path, &target_path).await?;
+    match git::ahead_behind(repo_path).await? {
+        Some((0, 0)) => parts.push("up to date".to_string()),
+        Some((ahead, behind)) => {
+            if ahead > 0 {
+                parts.push(format!("{} ahead", ahead));
+            }
+            if behind > 0 {
+                parts.push(format!("{} behind", behind));
+            }
         }
+        None => parts.push("no upstream".to_string()),
     }
 
-    log::info!("  ✓ Copied directory to {}", target.display());
+    let dirty = !git::status(repo_path).await?.trim().is_empty();
+    parts.push(if dirty { "dirty" } else { "clean" }.to_string());
 
-    Ok(())
+    Ok(parts.join(", "))
 }
 
-/// Remove a dotfile entry from management
-pub async fn remove(source: Option<String>) -> Result<()> {
-    let config_path = get_config_path()?;
+/// Score the health of every active entry and list what to fix first
+async fn render_health(names: &[String], tags: &[String]) -> Result<String> {
+    use health::{Health, Problem};
 
+    let config_path = get_config_path()?;
     if !config_path.exists() {
         anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
     }
 
-    let mut config = Config::load(Some(config_path.clone()))?;
-
-    if config.dotfiles.is_empty() {
-        log::info!("No dotfiles are currently being managed.");
-        return Ok(());
-    }
+    let config = Config::load(Some(config_path))?;
+    let entries = select_entries(active_entries(&config)?, names, &[], tags)?;
+    let stale_after = config
+        .symlinks
+        .stale_after
+        .as_deref()
+        .unwrap_or(DEFAULT_STALE_AFTER);
+    let max_age = symlinks::parse_age(stale_after)?;
+    let now = chrono::Utc::now();
+    let links = symlinks::list_symlinks().await?;
+
+    let mut scores = Vec::new();
+    for entry in &entries {
+        let mut problems = Vec::new();
+        let root = sources::provider(entry.r#type).root(entry);
+        if !root.exists() {
+            problems.push(Problem::MissingSource);
+        }
 
-    // Determine which entry to remove
-    let entry_to_remove = if let Some(src) = source {
-        // Find the entry by source
-        config
-            .dotfiles
+        let (mut broken, mut replaced, mut drifted, mut stale) = (0, 0, 0, 0);
+        for (link, status) in links
             .iter()
-            .find(|e| e.source == src)
-            .ok_or_else(|| anyhow::anyhow!("Source '{}' is not being managed", src))?
-            .clone()
-    } else {
-        // Interactive selection
-        let items: Vec<String> = config
-            .dotfiles
-            .iter()
-            .map(|e| format!("[{}] {}", e.r#type, e.source))
-            .collect();
+            .filter(|(link, _)| gpg::source_of(&link.target).starts_with(&root))
+        {
+            if link.is_stale(max_age, now) {
+                stale += 1;
+            }
+            match (link.mode, status) {
+                (_, Ok(true)) => {}
+                (LinkMode::Symlink, _) if link.replaced_by_file() => replaced += 1,
+                (LinkMode::Copy | LinkMode::Reflink | LinkMode::Hardlink, Ok(false)) => {
+                    drifted += 1
+                }
+                _ => broken += 1,
+            }
+        }
+        for (count, problem) in [
+            (broken, Problem::BrokenLinks as fn(usize) -> Problem),
+            (replaced, Problem::ReplacedLinks),
+            (drifted, Problem::DriftedCopies),
+            (stale, Problem::StaleLinks),
+        ] {
+            if count > 0 {
+                problems.push(problem(count));
+            }
+        }
 
-        if items.is_empty() {
-            log::info!("No dotfiles to remove.");
-            return Ok(());
+        if matches!(entry.r#type, SourceType::Git) && root.join(".git").exists() {
+            if git::status(&root)
+                .await
+                .is_ok_and(|status| !status.trim().is_empty())
+            {
+                problems.push(Problem::DirtyClone);
+            }
+            if let Ok(Some((ahead, behind))) = git::ahead_behind(&root).await {
+                if ahead > 0 {
+                    problems.push(Problem::Unpushed(ahead));
+                }
+                if behind > 0 {
+                    problems.push(Problem::Behind(behind));
+                }
+            }
         }
 
-        let selection = Select::with_theme(&ColorfulTheme::default())
-            .with_prompt("Select dotfile to remove")
-            .items(&items)
-            .default(0)
-            .interact()?;
+        scores.push(Health {
+            source: entry.source.clone(),
+            problems,
+        });
+    }
+
+    if scores.is_empty() {
+        return Ok("No dotfiles are currently being managed.\n".to_string());
+    }
+    Ok(health::render(&scores, stale_after)?)
+}
 
-        config.dotfiles[selection].clone()
-    };
+/// Render the status of managed dotfiles
+async fn render_status(names: &[String], tags: &[String]) -> Result<String> {
+    use std::fmt::Write;
 
-    log::info!("Removing '{}' from management", entry_to_remove.source);
+    let mut out = String::new();
+    let config_path = get_config_path()?;
 
-    // Remove associated symlinks
-    log::info!("Removing associated symlinks...");
-    let removed_count = remove_symlinks_for_entry(&entry_to_remove, None, false).await?;
-    if removed_count > 0 {
-        log::info!("✓ Removed {} symlink(s)", removed_count);
-    } else {
-        log::info!("No symlinks to remove");
+    if !config_path.exists() {
+        writeln!(
+            out,
+            "DotMe is not initialized. Run 'dotme init' to set up dotfiles management."
+        )?;
+        return Ok(out);
     }
 
-    // If it's a git repository, remove the cloned directory only if it's in the git_dir
-    if matches!(entry_to_remove.r#type, SourceType::Git) {
-        let git_dir = config.paths.get_git_dir()?;
+    let config = Config::load(Some(config_path))?;
+    let baseline = baseline::entries(&config)?;
+    let links = Hyperlinks::new(&config);
 
-        // Check if the target is within the git_dir
-        if entry_to_remove.target.starts_with(&git_dir) {
-            if entry_to_remove.target.exists() {
-                log::info!(
-                    "Deleting git repository at: {}",
-                    entry_to_remove.target.display()
-                );
-                fs::remove_dir_all(&entry_to_remove.target)
+    if config.dotfiles.is_empty() && baseline.is_empty() {
+        writeln!(out, "No dotfiles are currently being managed.")?;
+        writeln!(out, "Use 'dotme add <source>' to add dotfiles.")?;
+        return Ok(out);
+    }
+
+    writeln!(out, "Managed Dotfiles:")?;
+    if let Some(root) = crate::layout::root() {
+        writeln!(out, "Root: {}", links.path(root))?;
+    }
+    if let Some(updated) = &config.updated {
+        writeln!(out, "Last updated: {}", format_timestamp(updated))?;
+    }
+    if let Some(source) = &config.baseline {
+        writeln!(out, "Baseline: {} (read-only)", source)?;
+    }
+    writeln!(out, "─────────────────────────────────────────")?;
+
+    let state = symlinks::SymlinkState::load().await?;
+    let mut uncommitted = 0;
+    let all: Vec<_> = config.dotfiles.iter().chain(&baseline).cloned().collect();
+    let selected: HashSet<_> = select_entries(all, names, &[], tags)?
+        .into_iter()
+        .map(|entry| entry.source)
+        .collect();
+    let entries = config
+        .dotfiles
+        .iter()
+        .map(|entry| (entry, false))
+        .chain(baseline.iter().map(|entry| (entry, true)))
+        .filter(|(entry, _)| selected.contains(&entry.source));
+    let facts = Facts::detect();
+    for (entry, from_baseline) in entries {
+        let status = if !entry.applies_to(&facts) {
+            "- skipped"
+        } else if entry.local_path().exists() {
+            "✓ exists"
+        } else {
+            "✗ missing"
+        };
+
+        let mut tags: Vec<_> = entry.tags.iter().map(|tag| format!("#{}", tag)).collect();
+        if from_baseline {
+            tags.push("baseline".to_string());
+        }
+        if let Some(os) = &entry.os {
+            let os: Vec<String> = os.iter().map(|os| os.to_string()).collect();
+            tags.push(format!("{} only", os.join(", ")));
+        }
+        if let Some(when) = &entry.when {
+            tags.push(format!("when {}", when));
+        }
+        if !entry.update.is_default() {
+            tags.push(entry.update.to_string());
+        }
+        if entry.private {
+            tags.push("private".to_string());
+        }
+
+        if tags.is_empty() {
+            writeln!(out, "  {} [{}]", status, entry.r#type)?;
+        } else {
+            writeln!(out, "  {} [{}] ({})", status, entry.r#type, tags.join("; "))?;
+        }
+        writeln!(out, "    Source: {}", links.source(&entry.source))?;
+        if let Some(name) = &entry.name {
+            writeln!(out, "    Name:   {}", name)?;
+        }
+        if let Some(description) = &entry.description {
+            writeln!(out, "    About:  {}", description)?;
+        }
+
+        // Where the source is kept, e.g. git repos in ~/.dotme/git
+        for (name, value) in sources::provider(entry.r#type).describe(entry) {
+            writeln!(
+                out,
+                "    {:<7} {}",
+                format!("{}:", name),
+                links.path(Path::new(&value))
+            )?;
+        }
+        if matches!(entry.r#type, SourceType::Git) && entry.local_path().join(".git").exists() {
+            match git_summary(&entry.local_path()).await {
+                Ok(summary) => writeln!(out, "    Git:    {}", summary)?,
+                Err(e) => log::debug!("Failed to read git state of {}: {:#}", entry.source, e),
+            }
+
+            // Files edited through a link differ from the committed version in the clone
+            let changed = git::changed_files(&entry.local_path())
+                .await
+                .unwrap_or_default();
+            let edited = edited_through_links(&state.symlinks, &entry.local_path(), &changed);
+            for (i, path) in edited.iter().enumerate() {
+                let label = if i == 0 { "Edited:" } else { "" };
+                writeln!(out, "    {:<7} {} (not committed)", label, links.path(path))?;
+            }
+            uncommitted += edited.len();
+        }
+        writeln!(out)?;
+    }
+
+    // Flag links that haven't been checked in a while
+    let stale_after = config
+        .symlinks
+        .stale_after
+        .as_deref()
+        .unwrap_or(DEFAULT_STALE_AFTER);
+    let max_age = symlinks::parse_age(stale_after)?;
+    let now = chrono::Utc::now();
+    let stale = state
+        .symlinks
+        .iter()
+        .filter(|entry| entry.is_stale(max_age, now))
+        .count();
+    if stale > 0 {
+        writeln!(
+            out,
+            "⚠ {} symlink(s) not verified in {}. Run 'dotme verify --stale {}' to re-check them.",
+            stale, stale_after, stale_after
+        )?;
+    }
+
+    if uncommitted > 0 {
+        writeln!(
+            out,
+            "⚠ {} file(s) were edited through links but not committed. Run 'dotme push' to commit them.",
+            uncommitted
+        )?;
+    }
+
+    // Apps that save by replacing the file silently turn links into unmanaged copies
+    let replaced: Vec<_> = state
+        .symlinks
+        .iter()
+        .filter(|link| link.replaced_by_file())
+        .collect();
+    if !replaced.is_empty() {
+        writeln!(
+            out,
+            "⚠ {} symlink(s) were replaced by regular files, likely by an app that saves by replacing the file:",
+            replaced.len()
+        )?;
+        let entries: Vec<_> = config.dotfiles.iter().chain(baseline.iter()).collect();
+        for link in replaced {
+            match owning_entry(&entries, link) {
+                Some(entry) => writeln!(
+                    out,
+                    "    {} (from {})",
+                    links.path(&link.link),
+                    links.source(&entry.source)
+                )?,
+                None => writeln!(out, "    {}", links.path(&link.link))?,
+            }
+        }
+        writeln!(
+            out,
+            "  Set 'mode: reflink' (or copy) on these entries so saving doesn't break them."
+        )?;
+    }
+
+    Ok(out)
+}
+
+/// Find the entry a tracked link points into
+fn owning_entry<'a>(
+    entries: &[&'a DotfileEntry],
+    link: &symlinks::SymlinkEntry,
+) -> Option<&'a DotfileEntry> {
+    entries
+        .iter()
+        .find(|entry| {
+            gpg::source_of(&link.target).starts_with(sources::provider(entry.r#type).root(entry))
+        })
+        .copied()
+}
+
+/// Files among the `changed` paths of the clone at `root` that are reachable through one of the
+/// symlinks in `links`, as seen through the link, e.g. `~/.config/nvim/init.lua` for a changed
+/// `nvim/init.lua`
+fn edited_through_links(
+    links: &[symlinks::SymlinkEntry],
+    root: &Path,
+    changed: &[String],
+) -> Vec<PathBuf> {
+    let mut edited: Vec<PathBuf> = changed
+        .iter()
+        .map(|path| root.join(path))
+        .filter_map(|file| {
+            links
+                .iter()
+                .filter(|link| link.mode == LinkMode::Symlink)
+                .find_map(|link| {
+                    let rest = file.strip_prefix(&link.target).ok()?;
+                    Some(if rest.as_os_str().is_empty() {
+                        link.link.clone()
+                    } else {
+                        link.link.join(rest)
+                    })
+                })
+        })
+        .collect();
+    edited.sort();
+    edited.dedup();
+    edited
+}
+
+/// Age after which unverified symlinks are flagged unless configured otherwise
+const DEFAULT_STALE_AFTER: &str = "30d";
+
+/// Verify managed symlinks, or only those not verified within `stale`, fixing what can be
+/// fixed safely if `fix` is set
+pub async fn verify(stale: Option<chrono::Duration>, fix: bool) -> Result<()> {
+    let results = symlinks::verify_symlinks(stale).await?;
+
+    if results.is_empty() {
+        println!("No symlinks need verifying.");
+        return Ok(());
+    }
+
+    // Recreated links follow the configured style
+    let config_path = get_config_path()?;
+    let relative = config_path.exists() && Config::load(Some(config_path))?.symlinks.relative;
+
+    let mut failed = 0;
+    let mut fixed = 0;
+    for (entry, status) in &results {
+        let problem = match status {
+            Ok(true) => continue,
+            Ok(false) if !entry.mode.is_default() => "⚠ modified locally".to_string(),
+            Ok(false) => "⚠ points to wrong target".to_string(),
+            Err(e) => format!("✗ {}", e),
+        };
+        failed += 1;
+        println!("  {}: {}", entry.link.display(), problem);
+        println!("    → {}", entry.target.display());
+
+        if !fix {
+            continue;
+        }
+        match symlinks::repair_symlink(entry, relative).await {
+            Ok(symlinks::Repair::Recreated) => {
+                fixed += 1;
+                println!("    ✓ recreated");
+            }
+            Ok(symlinks::Repair::Forgotten) => {
+                fixed += 1;
+                println!("    ✓ target is gone, no longer tracked");
+            }
+            Ok(symlinks::Repair::Released) => {
+                fixed += 1;
+                println!("    ✓ replaced by another tool, no longer tracked");
+            }
+            Ok(symlinks::Repair::Untouched) => {
+                println!("    - left in place, as fixing it could lose changes")
+            }
+            Err(e) => println!("    ✗ could not fix: {}", e),
+        }
+    }
+
+    println!(
+        "Verified {} symlink(s): {} valid, {} with problems",
+        results.len(),
+        results.len() - failed,
+        failed
+    );
+    if fix && fixed > 0 {
+        println!("Fixed {} of {} problem(s)", fixed, failed);
+    }
+
+    if failed > fixed {
+        anyhow::bail!("{} symlink(s) failed verification", failed - fixed);
+    }
+
+    Ok(())
+}
+
+/// Check the state file against the filesystem, repairing it if `repair` is set
+pub async fn fsck(repair: bool) -> Result<()> {
+    let problems =
+        symlinks::check_state(repair)
+            .await
+            .map_err(|e| match symlinks::state_path() {
+                Ok(path) => anyhow::anyhow!("{:#}. Fix {} by hand.", e, path.display()),
+                Err(_) => e,
+            })?;
+
+    if problems.is_empty() {
+        println!("The state file is consistent.");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        println!("  ✗ {}", problem);
+    }
+
+    if repair {
+        println!("✓ Repaired {} problem(s)", problems.len());
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} problem(s) found. Run 'dotme state fsck --repair' to fix them.",
+        problems.len()
+    )
+}
+
+/// Sources of the entries marked private, whose links are left out of exports and manifests
+pub fn private_roots(config: &Config) -> Result<Vec<PathBuf>> {
+    let mut entries = config.dotfiles.clone();
+    entries.extend(baseline::entries(config)?);
+
+    Ok(entries
+        .iter()
+        .filter(|entry| entry.private)
+        .map(|entry| sources::provider(entry.r#type).root(entry))
+        .collect())
+}
+
+/// Write a checksum manifest of all managed links to `output` (or stdout), optionally signing it
+pub async fn manifest(output: Option<PathBuf>, sign: Option<PathBuf>) -> Result<()> {
+    let config_path = get_config_path()?;
+    let private = if config_path.exists() {
+        private_roots(&Config::load(Some(config_path))?)?
+    } else {
+        Vec::new()
+    };
+
+    let manifest = Manifest::generate(&private).await?;
+    let content = manifest.render(output.as_deref())?;
+
+    let Some(output) = output else {
+        print!("{}", content);
+        return Ok(());
+    };
+
+    fs::write(&output, content)
+        .await
+        .with_context(|| format!("Failed to write manifest to {}", output.display()))?;
+    println!(
+        "Wrote manifest of {} link(s) to {}",
+        manifest.links.len(),
+        output.display()
+    );
+
+    if let Some(secret_key) = sign {
+        manifest::sign(&output, &secret_key).await?;
+        println!("Signed manifest: {}.minisig", output.display());
+    }
+
+    let invalid = manifest.links.iter().filter(|link| !link.valid).count();
+    if invalid > 0 {
+        log::warn!(
+            "{} link(s) are broken or modified. Run 'dotme verify' for details.",
+            invalid
+        );
+    }
+
+    Ok(())
+}
+
+/// Show all details of a managed entry
+pub async fn show(source: Option<String>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+
+    if config.dotfiles.is_empty() {
+        log::info!("No dotfiles are currently being managed.");
+        return Ok(());
+    }
+
+    let entry = select_entry(&config, source, "Select dotfile to show")?;
+    let links = Hyperlinks::new(&config);
+
+    println!("{} [{}]", links.source(&entry.source), entry.r#type);
+    if let Some(description) = &entry.description {
+        println!("  {}", description);
+    }
+    println!("─────────────────────────────────────────");
+
+    let field = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            println!("  {:<12} {}", format!("{}:", name), value);
+        }
+    };
+
+    field("Name", entry.name.clone());
+    field(
+        "Tags",
+        (!entry.tags.is_empty()).then(|| entry.tags.join(", ")),
+    );
+    for (name, value) in sources::provider(entry.r#type).describe(&entry) {
+        field(name, Some(links.path(Path::new(&value))));
+    }
+    field("Links in", entry.path.as_ref().map(|p| links.path(p)));
+    field("Exclude", entry.exclude.as_ref().map(|e| e.join(", ")));
+    field("Include", entry.include.as_ref().map(|e| e.join(", ")));
+    field("Branch", entry.branch.clone());
+    field("Revision", entry.rev.clone());
+    field("Depth", entry.depth.map(|d| d.to_string()));
+    field(
+        "Mirrors",
+        (!entry.mirrors.is_empty()).then(|| entry.mirrors.join(", ")),
+    );
+    if !entry.mirrors.is_empty() {
+        let state = symlinks::SymlinkState::load().await?;
+        field(
+            "Last remote",
+            Some(
+                state
+                    .mirrors
+                    .get(&entry.source)
+                    .map_or("source".to_string(), |mirror| format!("mirror {}", mirror)),
+            ),
+        );
+    }
+    field(
+        "OS",
+        entry.os.as_ref().map(|os| {
+            os.iter()
+                .map(|os| os.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }),
+    );
+    field("When", entry.when.as_ref().map(|when| when.to_string()));
+    field(
+        "Update",
+        match entry.update {
+            UpdatePolicy::Auto => None,
+            UpdatePolicy::Manual => Some("manual (refreshed only when named)".to_string()),
+            UpdatePolicy::Frozen => Some("frozen (skipped by update)".to_string()),
+        },
+    );
+    field(
+        "Priority",
+        entry.priority.map(|priority| priority.to_string()),
+    );
+    field(
+        "Depends on",
+        (!entry.depends_on.is_empty()).then(|| entry.depends_on.join(", ")),
+    );
+    for stage in [Stage::Install, Stage::Update, Stage::Remove] {
+        field(
+            &format!("On {}", stage),
+            entry.scripts.get(stage).map(str::to_string),
+        );
+    }
+    field(
+        "Private",
+        entry
+            .private
+            .then(|| "yes (left out of exports and manifests)".to_string()),
+    );
+    field(
+        "Mode",
+        (!entry.mode.is_default()).then(|| entry.mode.to_string()),
+    );
+    field(
+        "Submodules",
+        (!entry.submodules.is_default()).then(|| entry.submodules.to_string()),
+    );
+    field(
+        "Link depth",
+        (!entry.link_depth.is_default()).then(|| entry.link_depth.to_string()),
+    );
+    field(
+        "Naming",
+        (!entry.naming.is_default()).then(|| entry.naming.to_string()),
+    );
+    field(
+        "On conflict",
+        (!entry.on_conflict.is_default()).then(|| entry.on_conflict.to_string()),
+    );
+    field(
+        "Defined in",
+        entry.included_from.as_ref().map(|file| links.path(file)),
+    );
+
+    if let Some(notes) = &entry.notes {
+        println!("\n  Notes:");
+        for line in notes.lines() {
+            println!("    {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of repositories updated at the same time unless configured otherwise
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Options for updating dotfiles
+#[derive(Debug, Clone, Default)]
+pub struct UpdateOptions {
+    /// Show what would be done without changing anything
+    pub dry_run: bool,
+    /// Back up plain files that are in the way of a symlink
+    pub force: bool,
+    /// Create symlinks with relative target paths
+    pub relative: bool,
+    /// Maximum number of repositories updated at the same time
+    pub jobs: Option<usize>,
+    /// Leave submodules alone this time
+    pub no_submodules: bool,
+    /// Sources or names of the only entries to update (all if empty)
+    pub sources: Vec<String>,
+    /// Glob patterns a source or name has to match for its entry to be updated
+    pub only: Vec<String>,
+    /// Tags an entry needs one of to be updated
+    pub tags: Vec<String>,
+}
+
+/// What an update did, for reporting it on one line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    /// Sources that got new commits or were fetched for the first time
+    pub pulled: usize,
+    /// Links that weren't there before the update
+    pub links_created: usize,
+    /// Sources that failed to update, whose links were left alone
+    pub failed_sources: usize,
+    /// Entry scripts that failed
+    pub failed_scripts: usize,
+}
+
+impl UpdateSummary {
+    /// Exit code for the outcome: 0 if everything went well, 2 if sources failed to update, 3
+    /// if only scripts failed (1 is left for updates that couldn't run at all)
+    pub fn exit_code(&self) -> i32 {
+        if self.failed_sources > 0 {
+            2
+        } else if self.failed_scripts > 0 {
+            3
+        } else {
+            0
+        }
+    }
+
+    /// The summary if everything went well, otherwise an error saying what failed
+    pub fn into_result(self) -> Result<Self> {
+        if self.failed_sources > 0 {
+            anyhow::bail!("{} source(s) failed to update", self.failed_sources);
+        }
+        if self.failed_scripts > 0 {
+            anyhow::bail!("{} script(s) failed", self.failed_scripts);
+        }
+        Ok(self)
+    }
+}
+
+impl std::fmt::Display for UpdateSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors = self.failed_sources + self.failed_scripts;
+        write!(
+            f,
+            "{} pulled, {} link(s) created, {} error{}",
+            self.pulled,
+            self.links_created,
+            errors,
+            if errors == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Short name of an entry: its `name:`, or the name of its repository, directory, or file
+pub fn entry_name(entry: &DotfileEntry) -> &str {
+    match &entry.name {
+        Some(name) => name,
+        None => sources::repo_name(entry.source.trim_end_matches('/')),
+    }
+}
+
+/// Find the entry `name` refers to: the one with that source, else the one with that `name:`,
+/// else the one whose repository, directory, or file has that name
+pub fn find_entry<'a>(entries: &'a [DotfileEntry], name: &str) -> Result<&'a DotfileEntry> {
+    if let Some(entry) = entries.iter().find(|entry| entry.source == name) {
+        return Ok(entry);
+    }
+
+    let named = |matches: &dyn Fn(&DotfileEntry) -> bool| -> Vec<&'a DotfileEntry> {
+        entries.iter().filter(|entry| matches(entry)).collect()
+    };
+    for candidates in [
+        named(&|entry| entry.name.as_deref() == Some(name)),
+        named(&|entry| entry_name(entry) == name),
+    ] {
+        match candidates.as_slice() {
+            [] => continue,
+            [entry] => return Ok(entry),
+            _ => anyhow::bail!(
+                "'{}' could be any of {}. Pass the source instead.",
+                name,
+                candidates
+                    .iter()
+                    .map(|entry| entry.source.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+    anyhow::bail!("Source '{}' is not being managed", name)
+}
+
+/// Sources of the entries `names` refer to
+fn named_sources(entries: &[DotfileEntry], names: &[String]) -> Result<HashSet<String>> {
+    names
+        .iter()
+        .map(|name| Ok(find_entry(entries, name)?.source.clone()))
+        .collect()
+}
+
+/// Keep the entries named in `names` (all if empty) whose source or name matches one of the
+/// `only` patterns and that have one of the `tags` (any if empty); naming an entry that doesn't
+/// exist is an error
+fn select_entries(
+    entries: Vec<DotfileEntry>,
+    names: &[String],
+    only: &[String],
+    tags: &[String],
+) -> Result<Vec<DotfileEntry>> {
+    let named = named_sources(&entries, names)?;
+
+    let mut patterns = GlobSetBuilder::new();
+    for pattern in only {
+        patterns.add(Glob::new(pattern).with_context(|| format!("Invalid pattern '{}'", pattern))?);
+    }
+    let patterns = patterns.build()?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| names.is_empty() || named.contains(&entry.source))
+        .filter(|entry| {
+            only.is_empty()
+                || patterns.is_match(&entry.source)
+                || patterns.is_match(entry_name(entry))
+        })
+        .filter(|entry| tags.is_empty() || entry.tags.iter().any(|tag| tags.contains(tag)))
+        .collect())
+}
+
+/// Commit a git source is at, or an empty one for other sources; `None` if it isn't on this
+/// machine
+async fn head(entry: &DotfileEntry, root: &Path) -> Option<String> {
+    if !root.exists() {
+        None
+    } else if entry.r#type == SourceType::Git {
+        git::head_commit(root).await.ok()
+    } else {
+        Some(String::new())
+    }
+}
+
+/// Update managed dotfiles, all of them or the ones selected in `options`. Sources and scripts
+/// that fail are counted in the summary rather than failing the update, which keeps the
+/// timestamp unchanged then
+pub async fn update(options: UpdateOptions) -> Result<UpdateSummary> {
+    let UpdateOptions {
+        dry_run,
+        force,
+        relative,
+        jobs,
+        no_submodules,
+        sources: names,
+        only,
+        tags,
+    } = options;
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let mut config = Config::load(Some(config_path.clone()))?;
+    let everything = names.is_empty() && only.is_empty() && tags.is_empty();
+
+    // Re-fetch the team baseline, falling back to the cached copy
+    if let Some(source) = config.baseline.as_ref().filter(|_| everything) {
+        if dry_run {
+            println!("[DRY RUN] Would fetch baseline: {}", redact::redact(source));
+        } else if let Err(e) = baseline::fetch(source).await {
+            log::warn!("{:#}. Using the cached baseline.", e);
+        }
+    }
+
+    let entries = active_entries(&config)?;
+    let ranked = entries.clone();
+
+    if entries.is_empty() {
+        log::info!("No dotfiles to update.");
+        return Ok(UpdateSummary::default());
+    }
+
+    if dry_run {
+        println!("\n[DRY RUN] Update operation - showing what would be done:\n");
+    }
+
+    for entry in entries
+        .iter()
+        .filter(|e| matches!(e.r#type, SourceType::Git))
+    {
+        check_clone_path(&entries, entry)?;
+    }
+
+    let entries = select_entries(entries, &names, &only, &tags)?;
+    if entries.is_empty() {
+        log::info!("No dotfiles match the selection.");
+        return Ok(UpdateSummary::default());
+    }
+
+    // Frozen entries keep their links and source exactly as they are
+    let (frozen, entries): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|e| e.update == UpdatePolicy::Frozen);
+    for entry in &frozen {
+        log::info!("Skipping frozen entry: {}", entry.source);
+    }
+
+    log::info!("Updating {} dotfile(s)...", entries.len());
+
+    // Step 1: Update sources (e.g. pull git repositories) concurrently
+    let concurrency = jobs
+        .or(config.update.concurrency)
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .max(1);
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    // Whether each refreshed entry succeeded, once it is done, for the entries depending on it
+    let mut refreshed: HashMap<String, watch::Receiver<Option<bool>>> = HashMap::new();
+    // Entries that aren't on this machine yet run their install script instead of update
+    let fresh: HashSet<_> = entries
+        .iter()
+        .filter(|e| !sources::provider(e.r#type).root(e).exists())
+        .map(|e| e.source.clone())
+        .collect();
+
+    for entry in &entries {
+        // Manual entries are linked from their sources as they are, once they were fetched,
+        // unless they are named
+        if entry.update == UpdatePolicy::Manual
+            && names.is_empty()
+            && sources::provider(entry.r#type).root(entry).exists()
+        {
+            log::info!("Not refreshing manual entry: {}", entry.source);
+            continue;
+        }
+
+        let mut entry = entry.clone();
+        if no_submodules {
+            entry.submodules = Submodules::None;
+        }
+        let git = config.git.clone();
+        let semaphore = semaphore.clone();
+        let dependencies: Vec<_> = entry
+            .depends_on
+            .iter()
+            .filter_map(|name| find_entry(&ranked, name).ok())
+            .filter_map(|dependency| {
+                let done = refreshed.get(&dependency.source)?.clone();
+                Some((dependency.source.clone(), done))
+            })
+            .collect();
+        let (done, receiver) = watch::channel(None);
+        refreshed.insert(entry.source.clone(), receiver);
+
+        tasks.spawn(async move {
+            // Wait for the entries this one depends on before taking a slot
+            for (dependency, mut receiver) in dependencies {
+                let succeeded = receiver
+                    .wait_for(Option::is_some)
                     .await
-                    .context("Failed to remove git repository directory")?;
-                log::info!("✓ Git repository deleted");
+                    .is_ok_and(|succeeded| *succeeded == Some(true));
+                if !succeeded {
+                    done.send_replace(Some(false));
+                    let error = anyhow::anyhow!("{} failed to update", dependency);
+                    return anyhow::Ok((entry.source, Err(error)));
+                }
+            }
+
+            let _permit = semaphore.acquire_owned().await?;
+            let root = sources::provider(entry.r#type).root(&entry);
+            let before = head(&entry, &root).await;
+            let result = sources::provider(entry.r#type)
+                .refresh(&entry, &git, dry_run)
+                .await;
+            done.send_replace(Some(result.is_ok()));
+            // Fetched for the first time, or moved to other commits
+            let after = if dry_run {
+                None
+            } else {
+                head(&entry, &root).await
+            };
+            let pulled = after.is_some() && after != before;
+            if pulled {
+                let detail = match (&before, &after) {
+                    (Some(before), Some(after)) if !before.is_empty() => {
+                        format!("{}..{}", short_hash(before), short_hash(after))
+                    }
+                    _ => "fetched".to_string(),
+                };
+                history::pulled(&entry, &root, detail);
+            }
+            anyhow::Ok((entry.source, result.map(|()| pulled)))
+        });
+    }
+
+    // Wait for every repository so no git process is left running, then report failures
+    let mut failed = Vec::new();
+    let mut pulled = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let (source, result) = joined.context("Update task failed")??;
+        match result {
+            Ok(true) => pulled += 1,
+            Ok(false) => {}
+            Err(e) => {
+                log::error!("Failed to update {}: {}", source, e);
+                failed.push(source);
+            }
+        }
+    }
+    let links_before: HashSet<PathBuf> = symlinks::SymlinkState::load()
+        .await?
+        .symlinks
+        .into_iter()
+        .map(|symlink| symlink.link)
+        .collect();
+
+    // Step 2: Recreate symlinks
+    let linkable: Vec<_> = entries
+        .into_iter()
+        .filter(|e| !failed.contains(&e.source))
+        .collect();
+    let link_options = LinkOptions {
+        dry_run,
+        force,
+        relative: relative || config.symlinks.relative,
+        ..Default::default()
+    };
+    relink_entries(linkable.clone(), &ranked, link_options).await?;
+
+    // Step 3: Run the scripts of the refreshed entries, in the order they apply
+    let mut failed_scripts = 0;
+    for entry in linkable
+        .iter()
+        .filter(|e| refreshed.contains_key(&e.source))
+    {
+        let stage = if fresh.contains(&entry.source) {
+            Stage::Install
+        } else {
+            Stage::Update
+        };
+        if let Err(e) = scripts::run(entry, stage, &config.variables, dry_run).await {
+            log::error!("{:#}", e);
+            failed_scripts += 1;
+        }
+    }
+
+    let links_created = symlinks::SymlinkState::load()
+        .await?
+        .symlinks
+        .iter()
+        .filter(|symlink| !links_before.contains(&symlink.link))
+        .count();
+    let summary = UpdateSummary {
+        pulled,
+        links_created,
+        failed_sources: failed.len(),
+        failed_scripts,
+    };
+    if !dry_run {
+        notify::update_finished(&config, &summary).await;
+    }
+    if summary.exit_code() != 0 {
+        return Ok(summary);
+    }
+
+    // Update the timestamp (only if not dry run), which tells when everything was updated
+    if !everything {
+        log::debug!("Only some entries were updated, keeping the timestamp");
+    } else if !dry_run {
+        config.update_timestamp();
+        config.save(&config_path)?;
+    } else {
+        println!("\n[DRY RUN] Would update timestamp in config");
+    }
+
+    log::info!("Update complete!");
+
+    Ok(summary)
+}
+
+/// Recreate the links of entries from their sources as they are, without refreshing them
+pub async fn relink(dry_run: bool, force: bool, relative: bool) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let entries = active_entries(&config)?;
+    let ranked = entries.clone();
+
+    if entries.is_empty() {
+        log::info!("No dotfiles to relink.");
+        return Ok(());
+    }
+
+    // Only sources that are already on this machine can be linked without fetching them
+    let (present, missing): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|e| sources::provider(e.r#type).root(e).exists());
+    for entry in &missing {
+        log::warn!(
+            "Skipping {}: its source isn't on this machine yet. Run 'dotme update' to fetch it.",
+            entry.source
+        );
+    }
+
+    log::info!("Relinking {} dotfile(s)...", present.len());
+    let link_options = LinkOptions {
+        dry_run,
+        force,
+        relative: relative || config.symlinks.relative,
+        ..Default::default()
+    };
+    relink_entries(present, &ranked, link_options).await?;
+
+    log::info!("Relink complete!");
+
+    Ok(())
+}
+
+/// Remove and recreate the links of entries, in parallel for unrelated base paths.
+///
+/// `ranked` are all active entries in the order they apply; an entry takes over links created
+/// by the ones before it.
+async fn relink_entries(
+    entries: Vec<DotfileEntry>,
+    ranked: &[DotfileEntry],
+    link_options: LinkOptions,
+) -> Result<()> {
+    let dry_run = link_options.dry_run;
+    let mut linkable = Vec::new();
+    for entry in entries {
+        linkable.push((base_path(&entry)?, entry));
+    }
+
+    let skipped = ConflictLog::default();
+    let link_options = LinkOptions {
+        skipped: Some(skipped.clone()),
+        ..link_options
+    };
+    let ranked = Arc::new(ranked.to_vec());
+    let mut tasks = JoinSet::new();
+    for group in group_by_base_path(linkable) {
+        let link_options = link_options.clone();
+        let ranked = ranked.clone();
+        tasks.spawn(async move {
+            for (base_path, entry) in group {
+                if let Some(dependency) = missing_dependency(&ranked, &entry) {
+                    log::warn!(
+                        "Skipping {}: it depends on {}, which isn't in place yet",
+                        entry.source,
+                        dependency
+                    );
+                    continue;
+                }
+                log::info!("Processing: {} [{}]", entry.source, entry.r#type);
+
+                // Remove old symlinks before creating new ones
+                let removed_count =
+                    remove_symlinks_for_entry(&entry, Some(&base_path), dry_run).await?;
+                if removed_count > 0 {
+                    log::info!("Removing old symlinks");
+                }
+
+                // Create new symlinks
+                log::info!("Creating new symlinks");
+                let options = LinkOptions {
+                    outranks: outranked_roots(&ranked, &entry),
+                    ..link_options.for_entry(&entry)
+                };
+                create_symlinks_for_entry(&entry, &base_path, &options).await?;
+            }
+            anyhow::Ok(())
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        joined.context("Linking task failed")??;
+    }
+
+    let skipped = skipped.lock().unwrap_or_else(|e| e.into_inner()).len();
+    if skipped > 0 && !dry_run {
+        println!(
+            "{} existing file(s) were left in place. Run 'dotme adopt' to back them up and link your dotfiles instead.",
+            skipped
+        );
+    }
+
+    Ok(())
+}
+
+/// First entry `entry` depends on whose source isn't on this machine
+fn missing_dependency<'a>(ranked: &[DotfileEntry], entry: &'a DotfileEntry) -> Option<&'a str> {
+    entry
+        .depends_on
+        .iter()
+        .find(|name| {
+            find_entry(ranked, name).is_ok_and(|dependency| {
+                !sources::provider(dependency.r#type)
+                    .root(dependency)
+                    .exists()
+            })
+        })
+        .map(String::as_str)
+}
+
+/// Roots of the entries applied before `entry`, whose links it replaces
+fn outranked_roots(ranked: &[DotfileEntry], entry: &DotfileEntry) -> Vec<PathBuf> {
+    ranked
+        .iter()
+        .take_while(|other| other.source != entry.source)
+        .map(|other| sources::provider(other.r#type).root(other))
+        .collect()
+}
+
+/// Directory an entry is linked into
+pub fn base_path(entry: &DotfileEntry) -> Result<PathBuf> {
+    match &entry.path {
+        Some(path) => Ok(path.clone()),
+        // Default to home directory for backward compatibility
+        None => dirs::home_dir().context("Failed to get home directory"),
+    }
+}
+
+/// Remove tracked links that no configured entry produces anymore, e.g. after an entry was
+/// removed from the config or a folder was dropped from `folders:`
+pub async fn clean(dry_run: bool, force: bool) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let entries = active_entries(&config)?;
+    let state = symlinks::SymlinkState::load().await?;
+
+    let mut orphans = Vec::new();
+    for link in &state.symlinks {
+        let mut owned = false;
+        for entry in &entries {
+            if produces_link(entry, link)? {
+                owned = true;
+                break;
+            }
+        }
+        if !owned {
+            orphans.push(link);
+        }
+    }
+
+    if orphans.is_empty() {
+        println!("No orphaned links found.");
+        return Ok(());
+    }
+
+    println!("Links no configured entry creates anymore:");
+    for orphan in &orphans {
+        println!("  {} → {}", orphan.link.display(), orphan.target.display());
+    }
+
+    if dry_run {
+        println!("\n[DRY RUN] Would remove {} link(s)", orphans.len());
+        return Ok(());
+    }
+
+    let confirmed = force || prompt::confirm(&format!("Remove {} link(s)?", orphans.len()), false)?;
+    if !confirmed {
+        println!("No links removed. Pass --force to remove them without asking.");
+        return Ok(());
+    }
+
+    let mut removed = 0;
+    for orphan in &orphans {
+        match symlinks::remove_symlink(&orphan.link).await {
+            Ok(()) => removed += 1,
+            Err(e) => log::warn!("Failed to remove {}: {}", orphan.link.display(), e),
+        }
+    }
+
+    println!("✓ Removed {} of {} link(s)", removed, orphans.len());
+
+    Ok(())
+}
+
+/// Check if linking an entry, as configured now, would create `link`
+fn produces_link(entry: &DotfileEntry, link: &symlinks::SymlinkEntry) -> Result<bool> {
+    if !link.link.starts_with(base_path(entry)?) {
+        return Ok(false);
+    }
+
+    let provider = sources::provider(entry.r#type);
+    let filter = EntryFilter::new(entry, &provider.root(entry))?;
+    let target = gpg::source_of(&link.target);
+    let excluded = |root: &Path| {
+        target
+            .ancestors()
+            .take_while(|path| *path != root)
+            .any(|path| filter.is_excluded(path))
+    };
+
+    for source in provider.list_files(entry)? {
+        let produced = match &source {
+            LinkSource::File(file) => target == *file && !filter.is_excluded(file),
+            LinkSource::Contents(dir) => target.starts_with(dir) && !excluded(dir),
+        };
+        if produced {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Walk through the files in the way of managed links on this machine, backing up the chosen
+/// ones and linking the dotfiles in their place
+pub async fn adopt() -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let link_options = LinkOptions {
+        relative: config.symlinks.relative,
+        ..Default::default()
+    };
+
+    // Find the files each entry would leave in place, without changing anything. Copies and
+    // hardlinks made by dotme are plain files too, but they are refreshed by update instead.
+    let state = symlinks::SymlinkState::load().await?;
+    let mut groups = Vec::new();
+    for entry in active_entries(&config)? {
+        if !entry.local_path().exists() {
+            log::warn!(
+                "{} is not cloned yet. Run 'dotme update' first.",
+                entry.source
+            );
+            continue;
+        }
+
+        let skipped = ConflictLog::default();
+        let plan = LinkOptions {
+            dry_run: true,
+            quiet: true,
+            on_conflict: ConflictPolicy::Skip,
+            skipped: Some(skipped.clone()),
+            ..link_options.for_entry(&entry)
+        };
+        let base_path = base_path(&entry)?;
+        create_symlinks_for_entry(&entry, &base_path, &plan).await?;
+
+        let mut conflicts = std::mem::take(&mut *skipped.lock().unwrap_or_else(|e| e.into_inner()));
+        conflicts.retain(|conflict| !state.symlinks.iter().any(|s| s.link == conflict.link));
+        if !conflicts.is_empty() {
+            groups.push((entry, base_path, conflicts));
+        }
+    }
+
+    if groups.is_empty() {
+        println!("No existing files are in the way of your dotfiles.");
+        return Ok(());
+    }
+
+    let total: usize = groups.iter().map(|(_, _, conflicts)| conflicts.len()).sum();
+    println!(
+        "{} existing file(s) are in the way of {} entry(ies).",
+        total,
+        groups.len()
+    );
+    println!(
+        "Adopted files are backed up next to the original (<name>.dotme-backup) before linking."
+    );
+
+    let mut adopted: Vec<(DotfileEntry, PathBuf, Vec<Conflict>)> = Vec::new();
+    for (entry, base_path, conflicts) in groups {
+        println!("\n{} ({} file(s))", entry.source, conflicts.len());
+        for conflict in &conflicts {
+            println!("  {}", conflict.link.display());
+        }
+
+        let chosen = loop {
+            let choice = prompt::select(
+                "What should happen to these files?",
+                &[
+                    "Back up all and link",
+                    "Decide for each file",
+                    "Show differences",
+                    "Keep all (skip this entry)",
+                ],
+                0,
+            )?;
+
+            match choice {
+                0 => break conflicts.clone(),
+                1 => break choose_conflicts(&conflicts).await?,
+                2 => {
+                    for conflict in &conflicts {
+                        show_conflict_diff(conflict).await?;
+                    }
+                }
+                _ => break Vec::new(),
+            }
+        };
+
+        if !chosen.is_empty() {
+            adopted.push((entry, base_path, chosen));
+        }
+    }
+
+    let count: usize = adopted.iter().map(|(_, _, chosen)| chosen.len()).sum();
+    if count == 0 {
+        println!("\nNo files adopted.");
+        return Ok(());
+    }
+
+    let confirmed = prompt::confirm(
+        &format!("Back up {} file(s) and link your dotfiles?", count),
+        true,
+    )?;
+    if !confirmed {
+        println!("No files adopted.");
+        return Ok(());
+    }
+
+    for (entry, base_path, chosen) in adopted {
+        for conflict in &chosen {
+            let backup = backup_path(&conflict.link);
+            fs::rename(&conflict.link, &backup)
+                .await
+                .with_context(|| format!("Failed to back up {}", conflict.link.display()))?;
+            log::info!(
+                "Backed up {} to {}",
+                conflict.link.display(),
+                backup.display()
+            );
+        }
+
+        // Link the entry again; the paths that were backed up are free now
+        create_symlinks_for_entry(&entry, &base_path, &link_options.for_entry(&entry)).await?;
+    }
+
+    println!("\n✓ Adopted {} file(s)", count);
+
+    Ok(())
+}
+
+/// Ask about each conflict of an entry, returning the ones to back up and link
+async fn choose_conflicts(conflicts: &[Conflict]) -> Result<Vec<Conflict>> {
+    let mut chosen = Vec::new();
+    for conflict in conflicts {
+        loop {
+            let choice = prompt::select(
+                &conflict.link.display().to_string(),
+                &["Back up and link", "Show difference", "Keep existing file"],
+                0,
+            )?;
+
+            match choice {
+                0 => chosen.push(conflict.clone()),
+                1 => {
+                    show_conflict_diff(conflict).await?;
+                    continue;
+                }
+                _ => {}
+            }
+            break;
+        }
+    }
+
+    Ok(chosen)
+}
+
+/// Print how an existing file differs from the dotfile that would replace it
+async fn show_conflict_diff(conflict: &Conflict) -> Result<()> {
+    println!("\n{}", conflict.link.display());
+    if conflict.target.is_dir() {
+        println!(
+            "  The existing file is in the way of the directory {}",
+            conflict.target.display()
+        );
+        return Ok(());
+    }
+
+    let diff = git::diff_files(&conflict.link, &conflict.target).await?;
+    if diff.is_empty() {
+        println!("  Identical to {}", conflict.target.display());
+    } else {
+        println!("{}", diff);
+    }
+
+    Ok(())
+}
+
+/// Local entries and the baseline entries they don't override, in the order they apply (by
+/// priority and dependencies), leaving out entries for other machines
+pub fn active_entries(config: &Config) -> Result<Vec<DotfileEntry>> {
+    let mut entries = config.dotfiles.clone();
+    entries.extend(baseline::entries(config)?);
+
+    // Lower priorities are applied first, so higher ones win; the sort keeps the config order
+    // among equals
+    entries.sort_by_key(|entry| entry.priority.unwrap_or_default());
+
+    let facts = Facts::detect();
+    let mut active: Vec<DotfileEntry> = Vec::new();
+    for entry in order_by_dependencies(entries)? {
+        if !entry.applies_to(&facts) {
+            log::debug!("Skipping {}: doesn't apply to this machine", entry.source);
+            continue;
+        }
+        // Dependencies come first, so they are active already if they apply here
+        if let Some(dependency) = entry
+            .depends_on
+            .iter()
+            .find(|dependency| find_entry(&active, dependency).is_err())
+        {
+            log::debug!(
+                "Skipping {}: {} doesn't apply to this machine",
+                entry.source,
+                dependency
+            );
+            continue;
+        }
+        active.push(entry);
+    }
+
+    Ok(active)
+}
+
+/// Order entries so the ones an entry depends on come right before it, keeping their order
+/// otherwise; fails on unknown dependencies and cycles
+pub fn order_by_dependencies(entries: Vec<DotfileEntry>) -> Result<Vec<DotfileEntry>> {
+    let mut dependencies = Vec::new();
+    for entry in &entries {
+        let mut indices = Vec::new();
+        for name in &entry.depends_on {
+            let dependency = find_entry(&entries, name)
+                .map_err(|e| anyhow::anyhow!("{} depends on {}: {}", entry.source, name, e))?;
+            indices.extend(entries.iter().position(|e| std::ptr::eq(e, dependency)));
+        }
+        dependencies.push(indices);
+    }
+
+    /// Where the depth-first walk is with an entry
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        dependencies: &[Vec<usize>],
+        entries: &[DotfileEntry],
+        visits: &mut [Visit],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<()> {
+        match visits[index] {
+            Visit::Done => return Ok(()),
+            Visit::InProgress => {
+                let start = path.iter().position(|&i| i == index).unwrap_or_default();
+                let cycle: Vec<_> = path[start..]
+                    .iter()
+                    .chain([&index])
+                    .map(|&i| entry_name(&entries[i]))
+                    .collect();
+                anyhow::bail!("Entries depend on each other: {}", cycle.join(" -> "));
+            }
+            Visit::New => {}
+        }
+
+        visits[index] = Visit::InProgress;
+        path.push(index);
+        for &dependency in &dependencies[index] {
+            visit(dependency, dependencies, entries, visits, path, order)?;
+        }
+        path.pop();
+        visits[index] = Visit::Done;
+        order.push(index);
+        Ok(())
+    }
+
+    let mut visits = vec![Visit::New; entries.len()];
+    let mut order = Vec::with_capacity(entries.len());
+    for index in 0..entries.len() {
+        visit(
+            index,
+            &dependencies,
+            &entries,
+            &mut visits,
+            &mut Vec::new(),
+            &mut order,
+        )?;
+    }
+
+    let mut entries: Vec<_> = entries.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .filter_map(|index| entries[index].take())
+        .collect())
+}
+
+/// Upstream checks are reused for this long unless configured otherwise
+const DEFAULT_CHECK_INTERVAL: &str = "1h";
+
+/// List git entries with upstream commits that haven't been pulled, without changing anything
+pub async fn outdated(refresh: bool, jobs: Option<usize>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let entries = active_entries(&config)?;
+
+    let max_age = if refresh {
+        chrono::Duration::zero()
+    } else {
+        symlinks::parse_age(
+            config
+                .update
+                .check_interval
+                .as_deref()
+                .unwrap_or(DEFAULT_CHECK_INTERVAL),
+        )
+        .context("Invalid update.check_interval")?
+    };
+    let concurrency = jobs
+        .or(config.update.concurrency)
+        .unwrap_or(DEFAULT_CONCURRENCY);
+
+    let checks = outdated::check(&entries, &config.git, max_age, concurrency).await?;
+    if checks.is_empty() {
+        println!("No git repositories to check.");
+        return Ok(());
+    }
+
+    let (mut behind, mut failed) = (0, 0);
+    for check in &checks {
+        let line = match &check.status {
+            Ok(outdated::Upstream::UpToDate) => continue,
+            Ok(outdated::Upstream::Behind { local, remote }) => {
+                behind += 1;
+                let branch = check.entry.branch.as_deref().unwrap_or("HEAD");
+                format!(
+                    "⬆ new commits on {}: {} → {}",
+                    branch,
+                    short_hash(local),
+                    short_hash(remote)
+                )
+            }
+            Ok(outdated::Upstream::Pinned(rev)) => format!("- pinned to {}", rev),
+            Ok(outdated::Upstream::NotCloned) => "- not cloned yet".to_string(),
+            Ok(outdated::Upstream::MissingBranch) => {
+                failed += 1;
+                let branch = check.entry.branch.as_deref().unwrap_or("HEAD");
+                format!("✗ branch {} not found on remote", branch)
+            }
+            Err(e) => {
+                failed += 1;
+                format!("✗ {}", e)
+            }
+        };
+        println!("  {}: {}", check.entry.source, line);
+    }
+
+    println!(
+        "Checked {} repository(ies): {} with new commits",
+        checks.len(),
+        behind
+    );
+    if checks.iter().any(|check| check.cached) {
+        println!("Some results are cached. Run 'dotme outdated --refresh' to check again.");
+    }
+    if behind > 0 {
+        println!("Run 'dotme update' to pull them.");
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} repository(ies) could not be checked", failed);
+    }
+
+    Ok(())
+}
+
+/// Shorten a commit hash for display
+fn short_hash(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
+
+/// Group entries whose base paths overlap, keeping their order within each group.
+///
+/// Entries in different groups link into unrelated directories and can be processed in parallel.
+fn group_by_base_path(entries: Vec<(PathBuf, DotfileEntry)>) -> Vec<Vec<(PathBuf, DotfileEntry)>> {
+    let mut groups: Vec<Vec<(usize, PathBuf, DotfileEntry)>> = Vec::new();
+
+    for (index, (base_path, entry)) in entries.into_iter().enumerate() {
+        let related = |group: &Vec<(usize, PathBuf, DotfileEntry)>| {
+            group
+                .iter()
+                .any(|(_, p, _)| p.starts_with(&base_path) || base_path.starts_with(p))
+        };
+
+        // An entry may bridge several groups, e.g. `~` after `~/.config` and `~/.local`
+        let (mut merged, rest): (Vec<_>, Vec<_>) = groups.into_iter().partition(related);
+        let mut group: Vec<_> = merged.drain(..).flatten().collect();
+        group.push((index, base_path, entry));
+        // Entries apply in order, so the merged groups are interleaved again
+        group.sort_by_key(|(index, _, _)| *index);
+
+        groups = rest;
+        groups.push(group);
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|(_, base_path, entry)| (base_path, entry))
+                .collect()
+        })
+        .collect()
+}
+
+async fn copy_file(source: &str, target: &Path) -> Result<()> {
+    let source_path = Path::new(source);
+
+    if !source_path.exists() {
+        anyhow::bail!("Source file '{}' does not exist", source);
+    }
+
+    // Create parent directory if needed
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    fs::copy(source_path, target).await.context(format!(
+        "Failed to copy {} to {}",
+        source,
+        target.display()
+    ))?;
+
+    log::info!("  ✓ Copied to {}", target.display());
+
+    Ok(())
+}
+
+async fn copy_directory(source: &str, target: &Path) -> Result<()> {
+    let source_path = Path::new(source);
+
+    if !source_path.exists() {
+        anyhow::bail!("Source directory '{}' does not exist", source);
+    }
+
+    fs::create_dir_all(target).await?;
+
+    let mut entries = fs::read_dir(source_path).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let file_name = path.file_name().unwrap();
+        let target_path = target.join(file_name);
+
+        if path.is_dir() {
+            Box::pin(copy_directory(path.to_str().unwrap(), &target_path)).await?;
+        } else {
+            fs::copy(&path, &target_path).await?;
+        }
+    }
+
+    log::info!("  ✓ Copied directory to {}", target.display());
+
+    Ok(())
+}
+
+/// Remove a dotfile entry from management
+pub async fn remove(source: Option<String>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let mut config = Config::load(Some(config_path.clone()))?;
+
+    if config.dotfiles.is_empty() {
+        log::info!("No dotfiles are currently being managed.");
+        return Ok(());
+    }
+
+    // Determine which entry to remove
+    let entry_to_remove = select_entry(&config, source, "Select dotfile to remove")?;
+    entry_to_remove.ensure_editable()?;
+
+    log::info!("Removing '{}' from management", entry_to_remove.source);
+
+    // The script may still need the entry's files, so it runs first
+    if let Err(e) = scripts::run(&entry_to_remove, Stage::Remove, &config.variables, false).await {
+        log::warn!("{:#}. Removing the entry anyway.", e);
+    }
+
+    // Remove associated symlinks
+    log::info!("Removing associated symlinks...");
+    let removed_count = remove_symlinks_for_entry(&entry_to_remove, None, false).await?;
+    if removed_count > 0 {
+        log::info!("✓ Removed {} symlink(s)", removed_count);
+    } else {
+        log::info!("No symlinks to remove");
+    }
+
+    let provider = sources::provider(entry_to_remove.r#type);
+    gpg::remove_decrypted(&provider.root(&entry_to_remove)).await?;
+
+    // Delete the clone or mirror only if dotme owns it
+    match provider.managed_copy(&entry_to_remove, &config.paths)? {
+        Some(copy) if copy.exists() => {
+            log::info!("Deleting local copy at: {}", copy.display());
+            fs::remove_dir_all(&copy)
+                .await
+                .context("Failed to remove local copy")?;
+            log::info!("✓ Local copy deleted");
+        }
+        Some(_) => {}
+        None => log::debug!(
+            "Skipping deletion of '{}' (not owned by dotme)",
+            entry_to_remove.local_path().display()
+        ),
+    }
+
+    // Remove from config
+    config
+        .dotfiles
+        .retain(|e| e.source != entry_to_remove.source);
+    config.save(&config_path)?;
+    history::removed(&entry_to_remove);
+
+    log::info!(
+        "✓ Removed '{}' from dotfiles management",
+        entry_to_remove.source
+    );
+
+    Ok(())
+}
+
+/// Find a managed entry by source, or prompt the user to select one
+fn select_entry(config: &Config, source: Option<String>, prompt: &str) -> Result<DotfileEntry> {
+    if let Some(src) = source {
+        // Find the entry by source or name
+        return find_entry(&config.dotfiles, &src).cloned();
+    }
+
+    // Interactive selection
+    prompt::require_interactive("Pass the source of the entry.")?;
+    let items: Vec<String> = config
+        .dotfiles
+        .iter()
+        .map(|e| format!("[{}] {}", e.r#type, e.source))
+        .collect();
+
+    let selection = prompt::select(prompt, &items, 0)?;
+
+    Ok(config.dotfiles[selection].clone())
+}
+
+/// Check if `entry` depends on `other` directly
+fn depends_on(entries: &[DotfileEntry], entry: &DotfileEntry, other: &DotfileEntry) -> bool {
+    entry.depends_on.iter().any(|name| {
+        find_entry(entries, name).is_ok_and(|dependency| dependency.source == other.source)
+    })
+}
+
+/// Check if the clone of `entry` at `path` collides with the clone of `other` at `other_path`;
+/// a clone may be nested in the clone of an entry it depends on, e.g. a plugin in a framework
+pub fn clones_collide(
+    entries: &[DotfileEntry],
+    (entry, path): (&DotfileEntry, &Path),
+    (other, other_path): (&DotfileEntry, &Path),
+) -> bool {
+    if path == other_path {
+        return true;
+    }
+    if path.starts_with(other_path) {
+        return !depends_on(entries, entry, other);
+    }
+    if other_path.starts_with(path) {
+        return !depends_on(entries, other, entry);
+    }
+    false
+}
+
+/// Make sure a git entry's clone location doesn't overlap with another git entry
+fn check_clone_path(entries: &[DotfileEntry], entry: &DotfileEntry) -> Result<()> {
+    let path = entry.local_path();
+
+    for other in entries
+        .iter()
+        .filter(|e| matches!(e.r#type, SourceType::Git) && e.source != entry.source)
+    {
+        let other_path = other.local_path();
+        if clones_collide(entries, (entry, &path), (other, &other_path)) {
+            anyhow::bail!(
+                "Clone path '{}' of '{}' collides with '{}' used by '{}'",
+                path.display(),
+                entry.source,
+                other_path.display(),
+                other.source
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the archive name for an entry (last path component without `.git`)
+fn archive_name(entry: &DotfileEntry) -> String {
+    entry
+        .source
+        .trim_end_matches('/')
+        .rsplit(['/', '\\', ':'])
+        .next()
+        .unwrap_or("entry")
+        .trim_end_matches(".git")
+        .to_string()
+}
+
+/// Remove the links of one or all entries, keeping the entries and their sources so
+/// `dotme relink` can link them again
+pub async fn unlink(source: Option<String>, all: bool, dry_run: bool) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let entries = if all {
+        active_entries(&config)?
+    } else if config.dotfiles.is_empty() {
+        log::info!("No dotfiles are currently being managed.");
+        return Ok(());
+    } else {
+        vec![select_entry(&config, source, "Select dotfile to unlink")?]
+    };
+
+    let mut removed = 0;
+    for entry in &entries {
+        let count = remove_symlinks_for_entry(entry, None, dry_run).await?;
+        if count > 0 && !dry_run {
+            log::info!("Unlinked {} link(s) of {}", count, entry.source);
+        }
+        removed += count;
+    }
+
+    if dry_run {
+        println!("\n[DRY RUN] Would unlink {} link(s)", removed);
+    } else {
+        log::info!(
+            "✓ Unlinked {} link(s). Run 'dotme relink' to link them again.",
+            removed
+        );
+    }
+
+    Ok(())
+}
+
+/// Freeze or unfreeze an entry; `update` leaves the source and links of frozen entries alone
+pub async fn freeze(source: Option<String>, frozen: bool) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let mut config = Config::load(Some(config_path.clone()))?;
+
+    if config.dotfiles.is_empty() {
+        log::info!("No dotfiles are currently being managed.");
+        return Ok(());
+    }
+
+    let prompt = if frozen {
+        "Select dotfile to freeze"
+    } else {
+        "Select dotfile to unfreeze"
+    };
+    let selected = select_entry(&config, source, prompt)?;
+    selected.ensure_editable()?;
+    let Some(entry) = config
+        .dotfiles
+        .iter_mut()
+        .find(|e| e.source == selected.source)
+    else {
+        anyhow::bail!("Source '{}' is not being managed", selected.source);
+    };
+
+    if (entry.update == UpdatePolicy::Frozen) == frozen {
+        log::info!(
+            "'{}' is already {}",
+            entry.source,
+            if frozen { "frozen" } else { "not frozen" }
+        );
+        return Ok(());
+    }
+
+    entry.update = if frozen {
+        UpdatePolicy::Frozen
+    } else {
+        UpdatePolicy::Auto
+    };
+    config.save(&config_path)?;
+
+    if frozen {
+        log::info!(
+            "✓ Froze '{}'; its links stay as they are until 'dotme unfreeze'",
+            selected.source
+        );
+    } else {
+        log::info!(
+            "✓ Unfroze '{}'; the next 'dotme update' brings it up to date",
+            selected.source
+        );
+    }
+
+    Ok(())
+}
+
+/// Archive a dotfile entry: unlink it and move its clone and metadata into ~/.dotme/archive
+pub async fn archive(source: Option<String>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let mut config = Config::load(Some(config_path.clone()))?;
+
+    if config.dotfiles.is_empty() {
+        log::info!("No dotfiles are currently being managed.");
+        return Ok(());
+    }
+
+    let entry = select_entry(&config, source, "Select dotfile to archive")?;
+    entry.ensure_editable()?;
+
+    let archive_dir = get_archive_dir()?.join(archive_name(&entry));
+    if archive_dir.exists() {
+        anyhow::bail!(
+            "An archive named '{}' already exists at {}",
+            archive_name(&entry),
+            archive_dir.display()
+        );
+    }
+
+    log::info!("Archiving '{}'", entry.source);
+
+    // Remove associated symlinks
+    let removed_count = remove_symlinks_for_entry(&entry, None, false).await?;
+    if removed_count > 0 {
+        log::info!("✓ Removed {} symlink(s)", removed_count);
+    }
+
+    fs::create_dir_all(&archive_dir)
+        .await
+        .context("Failed to create archive directory")?;
+
+    // Move the clone or mirror into the archive, only if dotme owns it
+    let provider = sources::provider(entry.r#type);
+    if let Some(copy) = provider.managed_copy(&entry, &config.paths)?
+        && copy.exists()
+    {
+        let archived_repo = archive_dir.join("repo");
+        fs::rename(&copy, &archived_repo)
+            .await
+            .context("Failed to move local copy into archive")?;
+        log::info!("✓ Moved local copy to {}", archived_repo.display());
+    }
+
+    // Store the entry metadata alongside the clone
+    let metadata = serde_yaml::to_string(&entry).context("Failed to serialize entry")?;
+    fs::write(archive_dir.join("entry.yml"), metadata)
+        .await
+        .context("Failed to write archive metadata")?;
+
+    config.dotfiles.retain(|e| e.source != entry.source);
+    config.save(&config_path)?;
+
+    log::info!("✓ Archived '{}' to {}", entry.source, archive_dir.display());
+
+    Ok(())
+}
+
+/// Restore an archived entry back into management
+pub async fn unarchive(name: Option<String>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let mut config = Config::load(Some(config_path.clone()))?;
+    let archive_root = get_archive_dir()?;
+
+    // Collect available archives
+    let mut archives = Vec::new();
+    if archive_root.exists() {
+        let mut entries = fs::read_dir(&archive_root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().join("entry.yml").exists()
+                && let Some(name) = entry.file_name().to_str()
+            {
+                archives.push(name.to_string());
+            }
+        }
+    }
+    archives.sort();
+
+    if archives.is_empty() {
+        log::info!("No archived entries found.");
+        return Ok(());
+    }
+
+    let name = if let Some(name) = name {
+        if !archives.contains(&name) {
+            anyhow::bail!("No archived entry named '{}'", name);
+        }
+        name
+    } else {
+        prompt::require_interactive("Pass the name of the archive to restore.")?;
+        let selection = prompt::select("Select archive to restore", &archives, 0)?;
+        archives[selection].clone()
+    };
+
+    let archive_dir = archive_root.join(&name);
+    let contents = fs::read_to_string(archive_dir.join("entry.yml"))
+        .await
+        .context("Failed to read archive metadata")?;
+    let entry: DotfileEntry =
+        serde_yaml::from_str(&contents).context("Failed to parse archive metadata")?;
+
+    if config.dotfiles.iter().any(|e| e.source == entry.source) {
+        anyhow::bail!("Source '{}' is already being managed", entry.source);
+    }
+
+    // Move the clone back to where it used to live
+    let archived_repo = archive_dir.join("repo");
+    if archived_repo.exists() {
+        let repo_path = entry.local_path();
+        if repo_path.exists() {
+            anyhow::bail!(
+                "Cannot restore repository: {} already exists",
+                repo_path.display()
+            );
+        }
+        if let Some(parent) = repo_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(&archived_repo, &repo_path)
+            .await
+            .context("Failed to move git repository out of archive")?;
+        log::info!("✓ Restored repository to {}", repo_path.display());
+    }
+
+    fs::remove_dir_all(&archive_dir)
+        .await
+        .context("Failed to remove archive directory")?;
+
+    config.dotfiles.push(entry.clone());
+    config.save(&config_path)?;
+
+    let base_path = if let Some(ref p) = entry.path {
+        p.clone()
+    } else {
+        dirs::home_dir().context("Failed to get home directory")?
+    };
+
+    log::info!("Creating symlinks...");
+    let options = LinkOptions {
+        relative: config.symlinks.relative,
+        ..Default::default()
+    }
+    .for_entry(&entry);
+    create_symlinks_for_entry(&entry, &base_path, &options).await?;
+
+    log::info!("✓ Restored '{}' to dotfiles management", entry.source);
+
+    Ok(())
+}
+
+/// Commit and push local changes of managed git repositories
+pub async fn push(
+    source: Option<String>,
+    message: Option<String>,
+    auto: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+
+    let selected = source
+        .as_deref()
+        .map(|source| find_entry(&config.dotfiles, source))
+        .transpose()?;
+    let entries: Vec<&DotfileEntry> = config
+        .dotfiles
+        .iter()
+        .filter(|e| matches!(e.r#type, SourceType::Git))
+        .filter(|e| selected.is_none_or(|selected| selected.source == e.source))
+        .collect();
+
+    if entries.is_empty() {
+        match source {
+            Some(src) => anyhow::bail!("Source '{}' is not a managed git repository", src),
+            None => log::info!("No git repositories are currently being managed."),
+        }
+        return Ok(());
+    }
+
+    for entry in entries {
+        let repo_path = entry.local_path();
+        if !repo_path.exists() {
+            log::warn!("Repository not cloned yet, skipping: {}", entry.source);
+            continue;
+        }
+
+        if git::current_branch(&repo_path).await?.is_none() {
+            log::info!("Skipping {} (not on a branch)", entry.source);
+            continue;
+        }
+
+        let changed = git::changed_files(&repo_path).await?;
+
+        if !changed.is_empty() {
+            let message = match &message {
+                Some(message) => message.clone(),
+                None if auto || dry_run => commit_message(&changed),
+                None => {
+                    println!("\nChanges in {}:", entry.source);
+                    for file in &changed {
+                        println!("  {}", file);
+                    }
+                    prompt::input("Commit message", commit_message(&changed))?
+                }
+            };
+
+            if dry_run {
+                println!(
+                    "[DRY RUN] Would commit {} file(s) in {}: {}",
+                    changed.len(),
+                    redact::redact(&entry.source),
+                    message
+                );
+            } else {
+                git::commit_all(&repo_path, &message).await?;
+                log::info!("✓ Committed {} file(s) in {}", changed.len(), entry.source);
             }
+        }
+
+        // Also picks up commits made by hand that were never pushed
+        let mut unpushed = git::unpushed_commits(&repo_path).await?;
+        if dry_run && !changed.is_empty() {
+            // The commit wasn't actually made
+            unpushed += 1;
+        }
+
+        if unpushed == 0 {
+            log::info!("Nothing to push for {}", entry.source);
+            continue;
+        }
+
+        if dry_run {
+            println!(
+                "[DRY RUN] Would push {} commit(s) to {}",
+                unpushed,
+                redact::redact(&entry.source)
+            );
         } else {
-            log::debug!(
-                "Skipping deletion of git repository at '{}' (not in git_dir: '{}')",
-                entry_to_remove.target.display(),
-                git_dir.display()
+            git::push(&repo_path, &git::CloneOptions::new(entry, &config.git)).await?;
+            log::info!("✓ Pushed {} commit(s) to {}", unpushed, entry.source);
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy deployed files that were edited in place back into their sources: changed copies, and
+/// symlinks an app replaced with a regular file. With `commit`, the synced files are committed
+/// in their git repositories.
+pub async fn syncback(dry_run: bool, commit: bool, message: Option<String>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+
+    let mut changed = Vec::new();
+    for (link, status) in symlinks::list_symlinks().await? {
+        let edited = match link.mode {
+            LinkMode::Copy | LinkMode::Hardlink | LinkMode::Reflink => matches!(status, Ok(false)),
+            LinkMode::Symlink => link.replaced_by_file(),
+            LinkMode::Junction => false,
+        };
+        if !edited {
+            continue;
+        }
+
+        let source = match symlinks::checksum(&link.target).await {
+            Ok(source) => source,
+            Err(e) => {
+                log::warn!("Skipping {}: {:#}", link.link.display(), e);
+                continue;
+            }
+        };
+        // A replacing app may have saved what the source already has
+        if symlinks::checksum(&link.link).await? == source {
+            continue;
+        }
+        if link
+            .checksum
+            .as_ref()
+            .is_some_and(|deployed| *deployed != source)
+        {
+            log::warn!(
+                "Skipping {}: its source changed as well. Merge the changes by hand.",
+                link.link.display()
             );
+            continue;
         }
+
+        changed.push(link);
     }
 
-    // Remove from config
-    config
-        .dotfiles
-        .retain(|e| e.source != entry_to_remove.source);
-    config.save(&config_path)?;
+    if changed.is_empty() {
+        println!("Nothing to sync back.");
+        return Ok(());
+    }
 
-    log::info!(
-        "✓ Removed '{}' from dotfiles management",
-        entry_to_remove.source
-    );
+    println!("Changed deployed files ({}):", changed.len());
+    for link in &changed {
+        println!("  {} → {}", link.link.display(), link.target.display());
+    }
+
+    if dry_run {
+        println!(
+            "\n[DRY RUN] Would copy {} file(s) back into their sources{}",
+            changed.len(),
+            if commit { " and commit them" } else { "" }
+        );
+        return Ok(());
+    }
+
+    for link in &changed {
+        fs::copy(&link.link, &link.target).await.with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                link.link.display(),
+                link.target.display()
+            )
+        })?;
+        if !link.mode.is_default() {
+            symlinks::record_checksum(&link.link).await?;
+        }
+        log::info!("✓ Synced {}", link.link.display());
+    }
+
+    if !commit {
+        return Ok(());
+    }
+
+    let entries = active_entries(&config)?;
+    let repositories: Vec<&DotfileEntry> = entries
+        .iter()
+        .filter(|e| matches!(e.r#type, SourceType::Git))
+        .collect();
+
+    let mut commits: Vec<(&DotfileEntry, Vec<String>)> = Vec::new();
+    for link in &changed {
+        let Some(entry) = owning_entry(&repositories, link) else {
+            log::info!(
+                "Not committing {}: it isn't in a git repository",
+                link.target.display()
+            );
+            continue;
+        };
+        let Ok(file) = link.target.strip_prefix(entry.local_path()) else {
+            continue;
+        };
+        let file = file.to_string_lossy().to_string();
+
+        match commits.iter_mut().find(|(e, _)| e.source == entry.source) {
+            Some((_, files)) => files.push(file),
+            None => commits.push((entry, vec![file])),
+        }
+    }
+
+    for (entry, files) in commits {
+        let message = message.clone().unwrap_or_else(|| commit_message(&files));
+        git::commit_paths(&entry.local_path(), &files, &message).await?;
+        log::info!("✓ Committed {} file(s) in {}", files.len(), entry.source);
+    }
+
+    Ok(())
+}
+
+/// Find the link that deploys `path`: the link itself, or a linked directory it is inside of
+fn deploying_link<'a>(
+    links: &'a [symlinks::SymlinkEntry],
+    path: &Path,
+) -> Option<(&'a symlinks::SymlinkEntry, PathBuf)> {
+    links
+        .iter()
+        .filter_map(|link| {
+            let rest = path.strip_prefix(&link.link).ok()?;
+            let source = if rest.as_os_str().is_empty() {
+                link.target.clone()
+            } else {
+                link.target.join(rest)
+            };
+            Some((link, source))
+        })
+        // The innermost link wins
+        .max_by_key(|(link, _)| link.link.components().count())
+}
+
+/// Open a file in the user's editor (`$VISUAL`, `$EDITOR`, or `vi`) and wait for it to close
+async fn open_in_editor(file: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    // Editors are often set with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("$EDITOR is empty")?;
+
+    let status = tokio::process::Command::new(program)
+        .args(words)
+        .arg(file)
+        .status()
+        .await
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+    if !status.success() {
+        anyhow::bail!("Editor '{}' exited with {}", editor, status);
+    }
+
+    Ok(())
+}
+
+/// Open the source file behind a deployed path in the editor, optionally committing the change
+pub async fn edit(path: &Path, commit: bool, message: Option<String>) -> Result<()> {
+    let config_path = get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let path = symlinks::normalize_path(&crate::config::expand_home(path))?;
+    let state = symlinks::SymlinkState::load().await?;
+    let Some((link, source)) = deploying_link(&state.symlinks, &path) else {
+        anyhow::bail!(
+            "{} isn't managed by dotme. Run 'dotme list' to see the managed paths.",
+            path.display()
+        );
+    };
+    if !source.is_file() {
+        anyhow::bail!(
+            "{} is deployed from {}, which isn't a file",
+            path.display(),
+            source.display()
+        );
+    }
+
+    log::info!("Editing {}", source.display());
+    open_in_editor(&source).await?;
+
+    // Copies don't follow their source; refresh them unless they were edited in place
+    if matches!(link.mode, LinkMode::Copy | LinkMode::Reflink) {
+        let deployed = symlinks::checksum(&link.link).await.ok();
+        if deployed.is_some() && deployed == link.checksum {
+            fs::copy(&source, &link.link).await.with_context(|| {
+                format!(
+                    "Failed to copy {} to {}",
+                    source.display(),
+                    link.link.display()
+                )
+            })?;
+            symlinks::record_checksum(&link.link).await?;
+            log::info!("✓ Refreshed the copy at {}", link.link.display());
+        } else {
+            log::warn!(
+                "{} was edited in place, so it wasn't refreshed. Run 'dotme syncback' to keep those edits instead.",
+                link.link.display()
+            );
+        }
+    }
+
+    if !commit {
+        return Ok(());
+    }
+
+    let entries = active_entries(&config)?;
+    let Some(entry) = entries.iter().find(|entry| {
+        matches!(entry.r#type, SourceType::Git) && source.starts_with(entry.local_path())
+    }) else {
+        anyhow::bail!(
+            "Not committing {}: it isn't in a git repository",
+            source.display()
+        );
+    };
+    let repo_path = entry.local_path();
+    let file = source
+        .strip_prefix(&repo_path)?
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    if !git::changed_files(&repo_path).await?.contains(&file) {
+        println!("No changes to commit.");
+        return Ok(());
+    }
+
+    let message = message.unwrap_or_else(|| commit_message(std::slice::from_ref(&file)));
+    git::commit_paths(&repo_path, &[file], &message).await?;
+    log::info!("✓ Committed {} in {}", source.display(), entry.source);
 
     Ok(())
 }
 
+/// Generate a commit message from the list of changed files
+fn commit_message(changed: &[String]) -> String {
+    let names: Vec<&str> = changed
+        .iter()
+        .take(3)
+        .map(|file| file.rsplit('/').next().unwrap_or(file))
+        .collect();
+
+    match changed.len() {
+        count if count > 3 => format!("Update {} and {} more", names.join(", "), count - 3),
+        _ => format!("Update {}", names.join(", ")),
+    }
+}
+
 /// Prompt user to select indexing mode and folders from a git repository
 async fn prompt_folder_selection(repo_path: &Path) -> Result<Option<Vec<String>>> {
-    use dialoguer::{MultiSelect, Select, theme::ColorfulTheme};
-
     // First prompt: Choose indexing mode
     let indexing_options = vec![
         "Root (map repository root to HOME)",
-        "Folders (select specific folders)",
+        "Folders (select specific folders or files)",
     ];
 
     println!("\nSelect indexing mode for git repository:");
-    let indexing_selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Indexing mode")
-        .items(&indexing_options)
-        .default(0)
-        .interact()?;
+    let indexing_selection = prompt::select("Indexing mode", &indexing_options, 0)?;
 
     // If "root" is selected (index 0), return None to indicate entire repo
     if indexing_selection == 0 {
@@ -575,36 +3198,24 @@ async fn prompt_folder_selection(repo_path: &Path) -> Result<Option<Vec<String>>
     // If "folders" is selected (index 1), proceed with folder selection
     log::info!("Folders indexing selected - prompting for folder selection");
 
-    // Get all top-level directories in the repository
-    let mut folders = Vec::new();
-    let mut entries = fs::read_dir(repo_path).await?;
-
-    while let Some(entry) = entries.next_entry().await? {
-        let path = entry.path();
-
-        // Skip hidden files/directories and .git directory
-        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if !name.starts_with('.') && path.is_dir() {
-                folders.push(name.to_string());
-            }
-        }
-    }
-
+    let folders = selectable_paths(repo_path)?;
     if folders.is_empty() {
         log::info!("No folders found in repository");
         return Ok(None);
     }
 
-    // Sort folders alphabetically
-    folders.sort();
-
-    println!("\nSelect folders to sync to your home directory:");
+    println!("\nSelect folders or files to sync to your home directory:");
     println!("(Use Space to select/deselect, Enter to confirm)");
 
-    let selections = MultiSelect::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select folders")
-        .items(&folders)
-        .interact()?;
+    // Folders are shown with a trailing slash to tell them apart from files
+    let items: Vec<String> = folders
+        .iter()
+        .map(|folder| match repo_path.join(folder).is_dir() {
+            true => format!("{}/", folder),
+            false => folder.clone(),
+        })
+        .collect();
+    let selections = prompt::multi_select("Select folders or files", &items)?;
 
     if selections.is_empty() {
         log::info!("No folders selected, repository will be managed without folder filtering");
@@ -618,6 +3229,38 @@ async fn prompt_folder_selection(repo_path: &Path) -> Result<Option<Vec<String>>
     Ok(Some(selected_folders))
 }
 
+/// Paths of a repository that can be selected, relative to its root: folders and the folders
+/// and files inside them, sorted. Top-level files are left out, as selecting the root links them.
+fn selectable_paths(repo_path: &Path) -> Result<Vec<String>> {
+    let visible = |path: &Path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| !name.starts_with('.'))
+    };
+
+    let mut paths = Vec::new();
+    for folder in std::fs::read_dir(repo_path)?.flatten() {
+        let folder = folder.path();
+        if !folder.is_dir() || !visible(&folder) {
+            continue;
+        }
+        let Ok(relative) = folder.strip_prefix(repo_path) else {
+            continue;
+        };
+        paths.push(relative.to_string_lossy().to_string());
+
+        for item in std::fs::read_dir(&folder)?.flatten() {
+            if let Ok(relative) = item.path().strip_prefix(repo_path) {
+                // Use forward slashes on every platform, so configs can be shared
+                paths.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
 /// Sync specific folders from a git repository to the home directory
 async fn sync_git_folders(repo_path: &Path, folders: &[String]) -> Result<()> {
     let home = dirs::home_dir().context("Failed to get home directory")?;
@@ -647,7 +3290,7 @@ async fn sync_git_folders(repo_path: &Path, folders: &[String]) -> Result<()> {
 }
 
 /// Format a timestamp for display
-fn format_timestamp(timestamp: &str) -> String {
+pub fn format_timestamp(timestamp: &str) -> String {
     use chrono::{DateTime, Local};
 
     if let Ok(dt) = timestamp.parse::<DateTime<chrono::Utc>>() {
@@ -676,28 +3319,15 @@ async fn remove_symlinks_for_entry(
     let mut removed_count = 0;
     let mut symlinks_to_remove = Vec::new();
 
-    // Determine the base path to match against
-    let target_path = match entry.r#type {
-        SourceType::File => {
-            // For files, the target is the actual file path
-            Path::new(&entry.source).to_path_buf()
-        }
-        SourceType::Directory => {
-            // For directories, match any symlink that points into this directory
-            Path::new(&entry.source).to_path_buf()
-        }
-        SourceType::Git => {
-            // For git repos, match any symlink that points into the cloned repo
-            entry.target.clone()
-        }
-    };
+    // Match any symlink that points into the source (or at it, for files)
+    let target_path = sources::provider(entry.r#type).root(entry);
 
     log::debug!("Looking for symlinks pointing to: {:?}", target_path);
 
     // Find all symlinks that point to paths under the target path
     for symlink_entry in &state.symlinks {
         // Check if the symlink target starts with the target path
-        if symlink_entry.target.starts_with(&target_path) {
+        if gpg::source_of(&symlink_entry.target).starts_with(&target_path) {
             log::debug!(
                 "Found symlink to remove: {} -> {}",
                 symlink_entry.link.display(),
@@ -728,117 +3358,437 @@ async fn remove_symlinks_for_entry(
     Ok(removed_count)
 }
 
+/// Rules deciding which items of an entry's source get linked
+pub struct EntryFilter {
+    /// Root that patterns are matched relative to
+    root: PathBuf,
+    /// Compiled `exclude` patterns
+    exclude: GlobSet,
+    /// Compiled `include` patterns; when empty, everything not excluded is linked
+    include: GlobSet,
+}
+
+impl EntryFilter {
+    /// Build the filter for an entry rooted at `root`
+    pub fn new(entry: &DotfileEntry, root: &Path) -> Result<Self> {
+        let compile = |patterns: &Option<Vec<String>>, kind: &str| -> Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns.iter().flatten() {
+                let glob = Glob::new(pattern)
+                    .with_context(|| format!("Invalid {} pattern '{}'", kind, pattern))?;
+                builder.add(glob);
+            }
+            builder
+                .build()
+                .with_context(|| format!("Failed to build {} patterns", kind))
+        };
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            exclude: compile(&entry.exclude, "exclude")?,
+            include: compile(&entry.include, "include")?,
+        })
+    }
+
+    /// Check if a source path matches one of `patterns`.
+    ///
+    /// Patterns are matched against the path relative to the entry root, and
+    /// against the file name alone so `README.md` or `*.png` apply at any depth.
+    fn matches(&self, patterns: &GlobSet, path: &Path) -> bool {
+        if patterns.is_empty() {
+            return false;
+        }
+
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        if patterns.is_match(relative) {
+            return true;
+        }
+
+        path.file_name()
+            .is_some_and(|name| patterns.is_match(Path::new(name)))
+    }
+
+    /// Check if a source path is left out, because it matches an exclude pattern or the entry
+    /// has include patterns and the path isn't included
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        if self.matches(&self.exclude, path) {
+            return true;
+        }
+
+        !self.include.is_empty() && !self.is_included(path)
+    }
+
+    /// Check if a source path, or a directory it is in, matches an include pattern. Directories
+    /// with included paths inside them count as included, so they are descended into.
+    fn is_included(&self, path: &Path) -> bool {
+        if path
+            .ancestors()
+            .take_while(|ancestor| *ancestor != self.root)
+            .any(|ancestor| self.matches(&self.include, ancestor))
+        {
+            return true;
+        }
+
+        path.is_dir()
+            && std::fs::read_dir(path).is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| self.is_included(&entry.path()))
+            })
+    }
+
+    /// Check if anything inside a source directory is excluded, in which case the
+    /// directory can't be linked as a whole
+    fn excludes_within(&self, dir: &Path) -> bool {
+        if self.exclude.is_empty() && self.include.is_empty() {
+            return false;
+        }
+
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return false;
+        };
+
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            self.is_excluded(&path) || (path.is_dir() && self.excludes_within(&path))
+        })
+    }
+
+    /// Check if a source directory has anything left to link once exclusions apply
+    fn links_within(&self, dir: &Path) -> bool {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return false;
+        };
+
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            !self.is_excluded(&path) && (!path.is_dir() || self.links_within(&path))
+        })
+    }
+}
+
+/// A plain file left in the way of a link
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Conflict {
+    /// Where the link would be created
+    link: PathBuf,
+    /// What the link would point to
+    target: PathBuf,
+}
+
+/// Conflicts collected while linking, shared between the tasks linking entries
+type ConflictLog = Arc<std::sync::Mutex<Vec<Conflict>>>;
+
+/// Options controlling how the symlinks of an entry are created
+#[derive(Debug, Clone, Default)]
+struct LinkOptions {
+    /// Only show what would be done
+    dry_run: bool,
+    /// Back up plain files in the way even if the entry skips them
+    force: bool,
+    /// Point links at their targets with relative paths
+    relative: bool,
+    /// What to do when a plain file is in the way
+    on_conflict: ConflictPolicy,
+    /// Whether files are symlinked, copied, or hardlinked
+    mode: LinkMode,
+    /// Whether directories are linked as a whole or created with their contents linked
+    link_depth: LinkDepth,
+    /// How the names of source items map to the names of their links
+    naming: Naming,
+    /// Don't print what dry run mode would do
+    quiet: bool,
+    /// Records plain files that were left in the way of a link
+    skipped: Option<ConflictLog>,
+    /// Keep the entry's paths out of the trace
+    private: bool,
+    /// Spinner counting the items linked so far
+    progress: Option<indicatif::ProgressBar>,
+    /// Roots of lower-ranked entries, whose links this entry replaces
+    outranks: Vec<PathBuf>,
+}
+
+impl LinkOptions {
+    /// Print what dry run mode would do
+    fn dry_run_note(&self, message: String) {
+        if !self.quiet {
+            println!("[DRY RUN] {}", redact::redact(&message));
+        }
+    }
+
+    /// Record a linking decision in the trace, unless the entry is private
+    fn trace(&self, event: Event) {
+        if !self.private {
+            trace::record(event);
+        }
+    }
+
+    /// Record a plain file that was left in the way of a link
+    fn record_skipped(&self, link: &Path, target: &Path) {
+        if let Some(skipped) = &self.skipped {
+            skipped
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(Conflict {
+                    link: link.to_path_buf(),
+                    target: target.to_path_buf(),
+                });
+        }
+    }
+
+    /// Apply an entry's conflict policy and mode; `force` backs up files the entry would
+    /// otherwise skip
+    fn for_entry(&self, entry: &DotfileEntry) -> Self {
+        let on_conflict = match entry.on_conflict {
+            ConflictPolicy::Skip if self.force => ConflictPolicy::Backup,
+            policy => policy,
+        };
+        // Junctions are only chosen automatically, as a fallback for symlinks
+        let mode = match entry.mode {
+            LinkMode::Junction => LinkMode::Symlink,
+            mode => mode,
+        };
+
+        Self {
+            on_conflict,
+            mode,
+            link_depth: entry.link_depth,
+            naming: entry.naming,
+            private: entry.private,
+            ..self.clone()
+        }
+    }
+}
+
 /// Create symlinks for a dotfile entry following the symlink creation rules
 async fn create_symlinks_for_entry(
     entry: &DotfileEntry,
     base_path: &Path,
-    dry_run: bool,
+    options: &LinkOptions,
 ) -> Result<()> {
-    match entry.r#type {
-        SourceType::File => {
-            // For files: create symlink if target doesn't exist
-            let source_path = Path::new(&entry.source);
-            let filename = source_path.file_name().context("Failed to get filename")?;
-            let target_path = base_path.join(filename);
-
-            create_symlink_if_needed(&target_path, source_path, dry_run).await?;
-        }
-        SourceType::Directory => {
-            // For directories: process contents and create symlinks in base_path
-            let source_path = Path::new(&entry.source);
-
-            // Process each item in the source directory
-            let mut entries_list = fs::read_dir(source_path).await?;
+    let provider = sources::provider(entry.r#type);
+    let filter = EntryFilter::new(entry, &provider.root(entry))?;
+    options.trace(Event::new(
+        format!("entry {}", entry.source),
+        "link into",
+        base_path,
+        &provider.root(entry),
+    ));
+
+    let spinner = progress::spinner(format!("Linking {}", entry.source));
+    let options = &LinkOptions {
+        progress: Some(spinner.clone()),
+        ..options.clone()
+    };
 
-            while let Some(dir_entry) = entries_list.next_entry().await? {
-                let item_path = dir_entry.path();
-                let item_name = item_path.file_name().context("Failed to get item name")?;
+    let sources = provider.list_files(entry)?;
+    if options.mode == LinkMode::Copy && !options.dry_run {
+        diskspace::ensure(
+            base_path,
+            copy_usage(&sources, base_path, &filter, options.naming),
+            &format!("copying {}", entry.source),
+        )?;
+    }
 
-                // Skip .git directory
-                if item_name == ".git" {
-                    continue;
+    for source in sources {
+        match source {
+            LinkSource::File(path) => {
+                // For files: create symlink if target doesn't exist
+                let filename = path.file_name().context("Failed to get filename")?;
+                let (name, private) = link_name(filename, options.naming);
+                let link = base_path.join(name);
+                if private {
+                    restrict_permissions(&path, &link, options).await?;
                 }
+                link_file(&link, &path, options).await?;
+            }
+            LinkSource::Contents(dir) => {
+                // For directories: process contents and create symlinks in base_path
+                process_directory_contents(&dir, base_path, 0, &filter, options).await?;
+            }
+        }
+    }
 
-                let target_path = base_path.join(item_name);
+    Ok(())
+}
 
-                if item_path.is_dir() {
-                    process_directory_for_symlinks(&item_path, &target_path, dry_run).await?;
-                } else {
-                    create_symlink_if_needed(&target_path, &item_path, dry_run).await?;
+/// Estimate the space copying `sources` into `base_path` takes up, counting only files that
+/// aren't there yet
+fn copy_usage(
+    sources: &[LinkSource],
+    base_path: &Path,
+    filter: &EntryFilter,
+    naming: Naming,
+) -> Usage {
+    let mut pending: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for source in sources {
+        match source {
+            LinkSource::File(path) => {
+                if let Some(name) = path.file_name() {
+                    pending.push((path.clone(), base_path.join(link_name(name, naming).0)));
+                }
+            }
+            LinkSource::Contents(dir) => {
+                for item in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+                    let name = link_name(&item.file_name(), naming).0;
+                    pending.push((item.path(), base_path.join(name)));
                 }
             }
         }
-        SourceType::Git => {
-            // For git repos: handle selected folders or entire repo
+    }
 
-            if let Some(folders) = &entry.folders {
-                // Process only selected folders
-                for folder in folders {
-                    let source_folder = entry.target.join(folder);
+    let mut usage = Usage::default();
+    while let Some((source, target)) = pending.pop() {
+        if source.file_name().is_some_and(|name| name == ".git") || filter.is_excluded(&source) {
+            continue;
+        }
 
-                    if !source_folder.exists() {
-                        log::warn!("Folder '{}' does not exist in repository, skipping", folder);
-                        continue;
-                    }
+        if source.is_dir() {
+            for item in std::fs::read_dir(&source).into_iter().flatten().flatten() {
+                let name = link_name(&item.file_name(), naming).0;
+                pending.push((item.path(), target.join(name)));
+            }
+        } else if target.symlink_metadata().is_err() {
+            let copied = diskspace::usage_of(&source);
+            usage.bytes += copied.bytes;
+            usage.files += copied.files;
+        }
+    }
+    usage
+}
+
+/// Process each item inside `source_dir`, linking it into `target_dir`
+async fn process_directory_contents(
+    source_dir: &Path,
+    target_dir: &Path,
+    level: usize,
+    filter: &EntryFilter,
+    options: &LinkOptions,
+) -> Result<()> {
+    let mut entries = fs::read_dir(source_dir).await?;
 
-                    log::info!("Processing folder: {}", folder);
+    while let Some(entry) = entries.next_entry().await? {
+        let source_path = entry.path();
+        let item_name = source_path.file_name().context("Failed to get item name")?;
+        let (name, private) = link_name(item_name, options.naming);
+        let target_path = target_dir.join(name);
+
+        // Skip .git directory
+        if item_name == ".git" {
+            log::debug!("Skipping .git directory");
+            continue;
+        }
 
-                    // Process the CONTENTS of the folder, not the folder itself
-                    // This creates symlinks from items inside the folder to the base_path
-                    let mut entries_list = fs::read_dir(&source_folder).await?;
+        if filter.is_excluded(&source_path) {
+            log::debug!("Excluded by pattern, skipping: {:?}", source_path);
+            options.trace(Event::new("exclude", "skip", &target_path, &source_path));
+            continue;
+        }
 
-                    while let Some(dir_entry) = entries_list.next_entry().await? {
-                        let item_path = dir_entry.path();
-                        let item_name = item_path.file_name().context("Failed to get item name")?;
+        if private {
+            restrict_permissions(&source_path, &target_path, options).await?;
+        }
 
-                        // Skip .git directory
-                        if item_name == ".git" {
-                            continue;
-                        }
+        if source_path.is_dir() {
+            // Recursively process subdirectory (use Box::pin for async recursion)
+            Box::pin(process_directory_for_symlinks(
+                &source_path,
+                &target_path,
+                level + 1,
+                filter,
+                options,
+            ))
+            .await?;
+            if private && !options.dry_run && target_path.is_dir() && !target_path.is_symlink() {
+                // A directory created (or descended into) for the source, not a link to it
+                set_private(&target_path).await?;
+            }
+        } else {
+            // Process file
+            link_file(&target_path, &source_path, options).await?;
+        }
+    }
 
-                        let target_path = base_path.join(item_name);
+    Ok(())
+}
 
-                        if item_path.is_dir() {
-                            process_directory_for_symlinks(&item_path, &target_path, dry_run)
-                                .await?;
-                        } else {
-                            create_symlink_if_needed(&target_path, &item_path, dry_run).await?;
-                        }
-                    }
-                }
-            } else {
-                // Process entire repository - also process contents, not the repo folder itself
-                let mut entries_list = fs::read_dir(&entry.target).await?;
+/// Name of the link to a source item, and whether the item is private.
+///
+/// With `naming: chezmoi`, a `dot_` prefix becomes a dot and a `private_` prefix (which comes
+/// first, as in `private_dot_ssh`) marks the item private. Other names are kept as they are,
+/// except for the `.gpg` suffix of encrypted files, whose links lead to their decrypted copies.
+pub fn link_name(name: &OsStr, naming: Naming) -> (OsString, bool) {
+    let name = match name.to_str() {
+        Some(stem) if gpg::is_encrypted(Path::new(name)) => {
+            OsStr::new(stem.strip_suffix(".gpg").unwrap_or(stem))
+        }
+        _ => name,
+    };
+    let plain = (name.to_os_string(), false);
+    let (Naming::Chezmoi, Some(name)) = (naming, name.to_str()) else {
+        return plain;
+    };
 
-                while let Some(dir_entry) = entries_list.next_entry().await? {
-                    let item_path = dir_entry.path();
-                    let item_name = item_path.file_name().context("Failed to get item name")?;
+    let (private, rest) = match name.strip_prefix("private_") {
+        Some(rest) => (true, rest),
+        None => (false, name),
+    };
+    let mapped = match rest.strip_prefix("dot_") {
+        Some(rest) => format!(".{}", rest),
+        None => rest.to_string(),
+    };
+    if mapped.is_empty() || mapped == "." {
+        return plain;
+    }
+    (mapped.into(), private)
+}
 
-                    // Skip .git directory
-                    if item_name == ".git" {
-                        continue;
-                    }
+/// Make a private source item readable only by its owner before it is linked to `link`
+async fn restrict_permissions(source: &Path, link: &Path, options: &LinkOptions) -> Result<()> {
+    options.trace(Event::new("private_", "restrict permissions", link, source));
+    if options.dry_run {
+        options.dry_run_note(format!(
+            "Would make {} readable only by its owner",
+            source.display()
+        ));
+        return Ok(());
+    }
+    set_private(source).await
+}
 
-                    let target_path = base_path.join(item_name);
+/// Restrict a file to 600 (700 if executable) and a directory to 700
+#[cfg(unix)]
+async fn set_private(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
 
-                    if item_path.is_dir() {
-                        process_directory_for_symlinks(&item_path, &target_path, dry_run).await?;
-                    } else {
-                        create_symlink_if_needed(&target_path, &item_path, dry_run).await?;
-                    }
-                }
-            }
-        }
-    }
+    let metadata = fs::metadata(path)
+        .await
+        .with_context(|| format!("Failed to read permissions of {}", path.display()))?;
+    // Keep the executable bit, which git tracks
+    let mode = if metadata.is_dir() || metadata.permissions().mode() & 0o100 != 0 {
+        0o700
+    } else {
+        0o600
+    };
+    fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .await
+        .with_context(|| format!("Failed to restrict permissions of {}", path.display()))
+}
 
+/// Permissions are left to the ACLs of the directory on other platforms
+#[cfg(not(unix))]
+async fn set_private(_path: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Process a directory recursively to create symlinks following the rules
+/// Process a directory recursively to create symlinks following the rules. `level` counts the
+/// directories between it and the source, 1 being a directory directly inside the source.
 async fn process_directory_for_symlinks(
     source_dir: &Path,
     target_dir: &Path,
-    dry_run: bool,
+    level: usize,
+    filter: &EntryFilter,
+    options: &LinkOptions,
 ) -> Result<()> {
     log::debug!("Processing directory: {:?} -> {:?}", source_dir, target_dir);
 
@@ -850,69 +3800,145 @@ async fn process_directory_for_symlinks(
     // Check if target already exists (including broken symlinks)
     if target_dir.symlink_metadata().is_ok() {
         // Target exists (file, directory, or symlink) - check what it is
-        if target_dir.is_dir() {
+        if target_dir.is_dir() && options.link_depth.descends(level) {
             // Rule 2: Target is a directory, descend into it
             log::debug!("Target directory exists, processing contents recursively");
-
-            let mut entries = fs::read_dir(source_dir).await?;
-
-            while let Some(entry) = entries.next_entry().await? {
-                let source_path = entry.path();
-                let item_name = source_path.file_name().context("Failed to get item name")?;
-
-                // Skip .git directory
-                if item_name == ".git" {
-                    log::debug!("Skipping .git directory");
-                    continue;
-                }
-
-                let target_path = target_dir.join(item_name);
-
-                if source_path.is_dir() {
-                    // Recursively process subdirectory (use Box::pin for async recursion)
-                    Box::pin(process_directory_for_symlinks(
-                        &source_path,
-                        &target_path,
-                        dry_run,
-                    ))
-                    .await?;
-                } else {
-                    // Process file
-                    create_symlink_if_needed(&target_path, &source_path, dry_run).await?;
-                }
-            }
+            options.trace(Event::new(
+                "directory exists",
+                "descend",
+                target_dir,
+                source_dir,
+            ));
+            process_directory_contents(source_dir, target_dir, level, filter, options).await?;
+        } else if resolve_conflict(target_dir, source_dir, options).await? {
+            // The file in the way was moved aside or removed
+            link_directory(source_dir, target_dir, level, filter, options).await?;
         } else {
             // Rule 3: Target exists as a file/symlink - skip
             log::debug!("Target exists as file/symlink, skipping: {:?}", target_dir);
-            if dry_run {
-                println!("[DRY RUN] Would skip (exists): {}", target_dir.display());
+            options.trace(Event::new("exists", "skip", target_dir, source_dir));
+            if options.dry_run {
+                options.dry_run_note(format!("Would skip (exists): {}", target_dir.display()));
             }
         }
+    } else if filter.excludes_within(source_dir) && !filter.links_within(source_dir) {
+        log::debug!(
+            "Everything in directory is excluded, skipping: {:?}",
+            source_dir
+        );
+        options.trace(Event::new(
+            "everything excluded",
+            "skip",
+            target_dir,
+            source_dir,
+        ));
     } else {
+        link_directory(source_dir, target_dir, level, filter, options).await?;
+    }
+
+    Ok(())
+}
+
+/// Link a directory whose target doesn't exist
+async fn link_directory(
+    source_dir: &Path,
+    target_dir: &Path,
+    level: usize,
+    filter: &EntryFilter,
+    options: &LinkOptions,
+) -> Result<()> {
+    if options.mode.is_default()
+        && !options.link_depth.creates(level)
+        && !filter.excludes_within(source_dir)
+        && !gpg::contains_encrypted(source_dir)
+    {
         // Rule 1: Target doesn't exist, create symlink to entire directory
         log::debug!("Target directory doesn't exist, creating symlink to entire directory");
-        if dry_run {
-            println!(
-                "[DRY RUN] Would create symlink: {} -> {}",
-                target_dir.display(),
-                source_dir.display()
-            );
-        } else {
-            symlinks::create_symlink(target_dir, source_dir).await?;
-        }
+        return link_path(target_dir, source_dir, options).await;
     }
 
-    Ok(())
+    // Linking the whole directory would expose excluded items and encrypted files, copies and
+    // hardlinks only work on files, and the link depth may ask for it, so create a real
+    // directory and link its contents individually instead
+    log::debug!("Creating directory and linking its contents individually");
+    let rule = if !options.mode.is_default() {
+        format!("mode {}", options.mode)
+    } else if options.link_depth.creates(level) {
+        format!("link_depth {}", options.link_depth)
+    } else if gpg::contains_encrypted(source_dir) {
+        "encrypted files inside".to_string()
+    } else {
+        "excludes inside".to_string()
+    };
+    options.trace(Event::new(rule, "create directory", target_dir, source_dir));
+    if options.dry_run {
+        options.dry_run_note(format!("Would create directory: {}", target_dir.display()));
+    } else {
+        fs::create_dir_all(target_dir)
+            .await
+            .context("Failed to create target directory")?;
+    }
+    process_directory_contents(source_dir, target_dir, level, filter, options).await
+}
+
+/// Link `link` to the source file `source`, or to its decrypted copy if it is encrypted. A file
+/// that can't be decrypted, e.g. on a machine without the key, is left unlinked.
+async fn link_file(link: &Path, source: &Path, options: &LinkOptions) -> Result<()> {
+    if !gpg::is_encrypted(source) {
+        return create_symlink_if_needed(link, source, options).await;
+    }
+
+    let event = Event::new("encrypted", "decrypt", link, source);
+    let decrypted = if options.dry_run {
+        let decrypted = gpg::decrypted_path(source)?;
+        options.dry_run_note(format!(
+            "Would decrypt {} to {}",
+            source.display(),
+            decrypted.display()
+        ));
+        options.trace(event.outcome("dry run"));
+        if !decrypted.exists() {
+            options.dry_run_note(format!(
+                "Would create {}: {} -> {}",
+                options.mode,
+                link.display(),
+                decrypted.display()
+            ));
+            return Ok(());
+        }
+        decrypted
+    } else {
+        match gpg::decrypt(source).await {
+            Ok(decrypted) => {
+                options.trace(event.outcome("done"));
+                decrypted
+            }
+            Err(e) => {
+                log::warn!("{:#}. Not linking {}.", e, link.display());
+                options.trace(event.outcome(format!("failed: {:#}", e)));
+                return Ok(());
+            }
+        }
+    };
+    create_symlink_if_needed(link, &decrypted, options).await
 }
 
 /// Create a symlink if the target doesn't exist (Rule 1) or skip if it exists (Rule 3)
-async fn create_symlink_if_needed(link: &Path, target: &Path, dry_run: bool) -> Result<()> {
+async fn create_symlink_if_needed(link: &Path, target: &Path, options: &LinkOptions) -> Result<()> {
+    if let Some(spinner) = &options.progress {
+        progress::advance(spinner, "item(s)");
+    }
+
     // Check if target (link location) exists
-    if link.exists() || link.symlink_metadata().is_ok() {
-        // Rule 3: Target exists - skip (never overwrite)
+    if (link.exists() || link.symlink_metadata().is_ok())
+        && !take_over(link, target, options).await?
+        && !resolve_conflict(link, target, options).await?
+    {
+        // Rule 3: Target exists - skip unless the conflict policy replaces it
         log::debug!("Path already exists, skipping: {:?}", link);
-        if dry_run {
-            println!("[DRY RUN] Would skip (exists): {}", link.display());
+        options.trace(Event::new("exists", "skip", link, target));
+        if options.dry_run {
+            options.dry_run_note(format!("Would skip (exists): {}", link.display()));
         }
         return Ok(());
     }
@@ -923,35 +3949,194 @@ async fn create_symlink_if_needed(link: &Path, target: &Path, dry_run: bool) ->
     // Verify source exists before creating symlink
     if !target.exists() {
         log::warn!("Source does not exist, cannot create symlink: {:?}", target);
-        if dry_run {
-            println!(
-                "[DRY RUN] Would skip (source missing): {} -> {}",
+        options.trace(Event::new("source missing", "skip", link, target));
+        if options.dry_run {
+            options.dry_run_note(format!(
+                "Would skip (source missing): {} -> {}",
                 link.display(),
                 target.display()
-            );
+            ));
         }
         return Ok(());
     }
 
-    if dry_run {
-        println!(
-            "[DRY RUN] Would create symlink: {} -> {}",
+    link_path(link, target, options).await
+}
+
+/// Create a symlink (or copy/hardlink), or print what would be created in dry run mode
+async fn link_path(link: &Path, target: &Path, options: &LinkOptions) -> Result<()> {
+    let event = Event::new("missing", options.mode.to_string(), link, target);
+
+    let result = if options.dry_run {
+        options.dry_run_note(format!(
+            "Would create {}: {} -> {}",
+            options.mode,
             link.display(),
             target.display()
-        );
-    } else {
+        ));
+        options.trace(event.outcome("dry run"));
+        return Ok(());
+    } else if options.mode.is_default() {
         // Create the symlink (this also tracks it in symlinks.yml)
-        symlinks::create_symlink(link, target).await?;
+        symlinks::create_symlink(link, target, options.relative).await
+    } else {
+        // Copies and hardlinks are tracked with a checksum so update can refresh them
+        symlinks::materialize(link, target, options.mode).await
+    };
+
+    options.trace(match &result {
+        Ok(()) => event.outcome("done"),
+        Err(e) => event.outcome(format!("failed: {:#}", e)),
+    });
+    result
+}
+
+/// Remove the link a lower-ranked entry created at `path`, so this entry's link to `target` wins.
+///
+/// Returns `true` if the path was (or in dry run mode would be) cleared for linking.
+async fn take_over(path: &Path, target: &Path, options: &LinkOptions) -> Result<bool> {
+    if options.outranks.is_empty() {
+        return Ok(false);
+    }
+    let Some(current) = symlinks::tracked_target(path)? else {
+        return Ok(false);
+    };
+    let current = gpg::source_of(&current);
+    if current == target
+        || !options
+            .outranks
+            .iter()
+            .any(|root| current.starts_with(root))
+    {
+        return Ok(false);
     }
 
-    Ok(())
+    if options.dry_run {
+        options.dry_run_note(format!(
+            "Would replace {} (linked by a lower priority entry)",
+            path.display()
+        ));
+    } else {
+        symlinks::remove_symlink(path).await?;
+        log::info!(
+            "Replacing {}, linked by a lower priority entry",
+            path.display()
+        );
+    }
+    options.trace(
+        Event::new("linked by a lower priority entry", "replace", path, target)
+            .outcome(if options.dry_run { "dry run" } else { "done" }),
+    );
+    Ok(true)
+}
+
+/// Apply the conflict policy to a plain file that is in the way of a symlink to `target`.
+///
+/// Returns `true` if the path was (or in dry run mode would be) cleared for linking.
+/// Directories and symlinks are never touched.
+async fn resolve_conflict(path: &Path, target: &Path, options: &LinkOptions) -> Result<bool> {
+    let is_file = fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file());
+    if !is_file {
+        return Ok(false);
+    }
+
+    let event = |action: &str| {
+        Event::new(
+            format!("on_conflict: {}", options.on_conflict),
+            action,
+            path,
+            target,
+        )
+        .outcome(if options.dry_run { "dry run" } else { "done" })
+    };
+
+    // A copy dotme created is backed up rather than overwritten once it was edited
+    let policy = match options.on_conflict {
+        ConflictPolicy::Overwrite if symlinks::was_edited(path) => ConflictPolicy::Backup,
+        policy => policy,
+    };
+
+    match policy {
+        ConflictPolicy::Skip => {
+            options.record_skipped(path, target);
+            Ok(false)
+        }
+        ConflictPolicy::Backup => {
+            let backup = backup_path(path);
+            if options.dry_run {
+                options.dry_run_note(format!(
+                    "Would back up {} to {}",
+                    path.display(),
+                    backup.display()
+                ));
+            } else {
+                fs::rename(path, &backup)
+                    .await
+                    .with_context(|| format!("Failed to back up {}", path.display()))?;
+                log::info!("Backed up {} to {}", path.display(), backup.display());
+            }
+            options.trace(event("back up"));
+            Ok(true)
+        }
+        ConflictPolicy::Overwrite => {
+            if options.dry_run {
+                options.dry_run_note(format!("Would overwrite {}", path.display()));
+            } else {
+                fs::remove_file(path)
+                    .await
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+                log::info!("Overwriting {}", path.display());
+            }
+            options.trace(event("remove"));
+            Ok(true)
+        }
+    }
+}
+
+/// Find a free backup location next to `path` (`<name>.dotme-backup`)
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".dotme-backup");
+    let backup = path.with_file_name(&name);
+    if backup.symlink_metadata().is_err() {
+        return backup;
+    }
+
+    name.push(format!(".{}", chrono::Utc::now().format("%Y%m%d%H%M%S")));
+    path.with_file_name(name)
+}
+
+/// Order of the links shown by `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListSort {
+    /// By link path
+    Path,
+    /// Oldest first
+    Created,
 }
 
-/// List all currently applied symlinks
-pub async fn list() -> Result<()> {
+/// Which links `list` shows, and in what order
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// Only links that fail verification
+    pub broken: bool,
+    /// Only links into the entry with this source
+    pub entry: Option<String>,
+    /// Only links into entries with one of these tags
+    pub tags: Vec<String>,
+    /// Only links inside this directory
+    pub under: Option<PathBuf>,
+    /// Order of the links (state file order if not set)
+    pub sort: Option<ListSort>,
+}
+
+/// List the currently applied symlinks, or those matching `options`
+pub async fn list(options: ListOptions) -> Result<()> {
     log::info!("Loading symlink state...");
 
-    let symlinks = symlinks::list_symlinks().await?;
+    let mut symlinks = symlinks::list_symlinks().await?;
 
     if symlinks.is_empty() {
         println!("No symlinks are currently managed by DotMe.");
@@ -959,18 +4144,71 @@ pub async fn list() -> Result<()> {
         return Ok(());
     }
 
+    let config_path = get_config_path()?;
+    let config = if config_path.exists() {
+        Config::load(Some(config_path))?
+    } else {
+        Config::default()
+    };
+    let links = Hyperlinks::new(&config);
+
+    if let Some(source) = &options.entry {
+        let mut entries = config.dotfiles.clone();
+        entries.extend(baseline::entries(&config)?);
+        let entry = find_entry(&entries, source)?;
+        let root = sources::provider(entry.r#type).root(entry);
+        symlinks.retain(|(link, _)| gpg::source_of(&link.target).starts_with(&root));
+    }
+    if !options.tags.is_empty() {
+        let mut entries = config.dotfiles.clone();
+        entries.extend(baseline::entries(&config)?);
+        let roots: Vec<_> = select_entries(entries, &[], &[], &options.tags)?
+            .iter()
+            .map(|entry| sources::provider(entry.r#type).root(entry))
+            .collect();
+        symlinks.retain(|(link, _)| {
+            let target = gpg::source_of(&link.target);
+            roots.iter().any(|root| target.starts_with(root))
+        });
+    }
+    if let Some(under) = &options.under {
+        let under = std::path::absolute(crate::config::expand_home(under))?;
+        symlinks.retain(|(link, _)| link.link.starts_with(&under));
+    }
+    if options.broken {
+        symlinks.retain(|(_, status)| !matches!(status, Ok(true)));
+    }
+    match options.sort {
+        Some(ListSort::Path) => symlinks.sort_by(|(a, _), (b, _)| a.link.cmp(&b.link)),
+        Some(ListSort::Created) => symlinks
+            .sort_by_key(|(link, _)| chrono::DateTime::parse_from_rfc3339(&link.created_at).ok()),
+        None => {}
+    }
+
+    if symlinks.is_empty() {
+        println!("No symlinks match.");
+        return Ok(());
+    }
+
     println!("Managed Symlinks:");
     println!("─────────────────────────────────────────");
 
     for (entry, status) in symlinks {
+        let foreign = symlinks::ownership(&entry.link).await? == symlinks::Ownership::Foreign;
         let status_str = match status {
             Ok(true) => "✓ valid",
+            Ok(false) if !entry.mode.is_default() => "⚠ modified locally",
             Ok(false) => "⚠ points to wrong target",
+            Err(_) if foreign => "⚠ replaced by another tool",
             Err(_) => "✗ broken or missing",
         };
 
-        println!("  {} {}", status_str, entry.link.display());
-        println!("    → {}", entry.target.display());
+        println!("  {} {}", status_str, links.path(&entry.link));
+        if entry.mode.is_default() {
+            println!("    → {}", links.path(&entry.target));
+        } else {
+            println!("    → {} ({})", links.path(&entry.target), entry.mode);
+        }
         println!("    Created: {}", format_timestamp(&entry.created_at));
         if let Some(verified) = &entry.last_verified {
             println!("    Verified: {}", format_timestamp(verified));
@@ -980,3 +4218,513 @@ pub async fn list() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_group_by_base_path() {
+        let entry = |base: &str| {
+            (
+                PathBuf::from(base),
+                DotfileEntry::new("/dots", SourceType::Directory),
+            )
+        };
+        let groups = group_by_base_path(vec![
+            entry("/home/user/.config"),
+            entry("/home/user/.local"),
+            entry("/srv"),
+            entry("/home/user"),
+            entry("/home/user/.config/nvim"),
+        ]);
+
+        let bases: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|g| g.iter().map(|(p, _)| p.to_str().unwrap()).collect())
+            .collect();
+        assert_eq!(
+            bases,
+            vec![
+                vec!["/srv"],
+                vec![
+                    "/home/user/.config",
+                    "/home/user/.local",
+                    "/home/user",
+                    "/home/user/.config/nvim"
+                ],
+            ]
+        );
+
+        // Groups that an entry bridges keep the order the entries came in
+        let groups = group_by_base_path(vec![entry("/a"), entry("/b"), entry("/a/c"), entry("/")]);
+        let bases: Vec<&str> = groups[0].iter().map(|(p, _)| p.to_str().unwrap()).collect();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(bases, vec!["/a", "/b", "/a/c", "/"]);
+    }
+
+    #[test]
+    fn test_entries_apply_in_order_of_priority() {
+        let entry = |source: &str, priority: Option<i32>| DotfileEntry {
+            target: PathBuf::from(source),
+            priority,
+            ..DotfileEntry::new(source, SourceType::Directory)
+        };
+        let config = Config {
+            dotfiles: vec![
+                entry("/dots/work", Some(10)),
+                entry("/dots/base", None),
+                entry("/dots/shared", None),
+                entry("/dots/fallback", Some(-1)),
+            ],
+            ..Default::default()
+        };
+
+        let ranked = active_entries(&config).unwrap();
+        let sources: Vec<_> = ranked.iter().map(|e| e.source.as_str()).collect();
+        assert_eq!(
+            sources,
+            ["/dots/fallback", "/dots/base", "/dots/shared", "/dots/work"]
+        );
+        assert_eq!(
+            outranked_roots(&ranked, &ranked[2]),
+            [PathBuf::from("/dots/fallback"), PathBuf::from("/dots/base")]
+        );
+        assert!(outranked_roots(&ranked, &ranked[0]).is_empty());
+    }
+
+    #[test]
+    fn test_entries_come_after_their_dependencies() {
+        let entry = |source: &str, depends_on: &[&str]| DotfileEntry {
+            depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+            ..DotfileEntry::new(source, SourceType::Git)
+        };
+        let order = |entries: Vec<DotfileEntry>| {
+            order_by_dependencies(entries)
+                .map(|entries| entries.iter().map(entry_name).collect::<Vec<_>>().join(","))
+        };
+
+        assert_eq!(
+            order(vec![
+                entry(
+                    "https://github.com/u/zsh-autosuggestions.git",
+                    &["oh-my-zsh"]
+                ),
+                entry("/dots/tmux", &[]),
+                entry("https://github.com/ohmyzsh/oh-my-zsh.git", &["/dots/zsh"]),
+                entry("/dots/zsh", &[]),
+            ])
+            .unwrap(),
+            "zsh,oh-my-zsh,zsh-autosuggestions,tmux"
+        );
+
+        let cycle = order(vec![
+            entry("/dots/a", &["b"]),
+            entry("/dots/b", &["c"]),
+            entry("/dots/c", &["a"]),
+        ]);
+        assert_eq!(
+            cycle.unwrap_err().to_string(),
+            "Entries depend on each other: a -> b -> c -> a"
+        );
+        assert!(order(vec![entry("/dots/a", &["emacs"])]).is_err());
+    }
+
+    #[test]
+    fn test_commit_message_lists_changed_files() {
+        let changed = |files: &[&str]| files.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            commit_message(&changed(&["zsh/.zshrc", ".vimrc"])),
+            "Update .zshrc, .vimrc"
+        );
+        assert_eq!(
+            commit_message(&changed(&["a", "b", "c", "d", "e"])),
+            "Update a, b, c and 2 more"
+        );
+    }
+
+    #[test]
+    fn test_force_backs_up_instead_of_skipping() {
+        let options = LinkOptions {
+            force: true,
+            ..Default::default()
+        };
+        let mut entry = DotfileEntry::new("/dots", SourceType::Directory);
+        assert_eq!(
+            options.for_entry(&entry).on_conflict,
+            ConflictPolicy::Backup
+        );
+
+        entry.on_conflict = ConflictPolicy::Overwrite;
+        assert_eq!(
+            options.for_entry(&entry).on_conflict,
+            ConflictPolicy::Overwrite
+        );
+    }
+
+    #[test]
+    fn test_links_of_dropped_folders_are_orphaned() {
+        let dir = tempfile::tempdir().unwrap();
+        let (repo, home) = (dir.path().join("repo"), dir.path().join("home"));
+        std::fs::create_dir_all(repo.join("zsh")).unwrap();
+        std::fs::create_dir_all(repo.join("vim")).unwrap();
+
+        let mut entry = DotfileEntry {
+            target: repo.clone(),
+            path: Some(home.clone()),
+            folders: Some(vec!["zsh".to_string(), "vim".to_string()]),
+            exclude: Some(vec!["*.bak".to_string()]),
+            ..DotfileEntry::new(repo.to_string_lossy().to_string(), SourceType::Git)
+        };
+        let link = |name: &str, target: PathBuf| symlinks::SymlinkEntry {
+            link: home.join(name),
+            target,
+            created_at: String::new(),
+            last_verified: None,
+            mode: LinkMode::Symlink,
+            checksum: None,
+        };
+
+        let vimrc = link(".vimrc", repo.join("vim/.vimrc"));
+        assert!(produces_link(&entry, &vimrc).unwrap());
+        assert!(!produces_link(&entry, &link(".vimrc.bak", repo.join("vim/.vimrc.bak"))).unwrap());
+        assert!(!produces_link(&entry, &link(".other", dir.path().join("other"))).unwrap());
+
+        entry.folders = Some(vec!["zsh".to_string()]);
+        assert!(!produces_link(&entry, &vimrc).unwrap());
+    }
+
+    #[test]
+    fn test_only_included_paths_are_linked() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for folder in [".config/nvim/lua", ".config/other", "scripts"] {
+            std::fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        for file in [".zshrc", ".config/nvim/lua/init.lua", "scripts/deploy.sh"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let entry = DotfileEntry {
+            target: root.to_path_buf(),
+            include: Some(vec![".zshrc".to_string(), ".config/nvim".to_string()]),
+            exclude: Some(vec!["*.lua".to_string()]),
+            ..DotfileEntry::new(root.to_string_lossy().to_string(), SourceType::Directory)
+        };
+        let filter = EntryFilter::new(&entry, root).unwrap();
+
+        assert!(!filter.is_excluded(&root.join(".zshrc")));
+        // Directories leading to included paths are descended into, not linked whole
+        assert!(!filter.is_excluded(&root.join(".config")));
+        assert!(filter.excludes_within(&root.join(".config")));
+        assert!(!filter.is_excluded(&root.join(".config/nvim/lua")));
+        assert!(filter.is_excluded(&root.join(".config/other")));
+        assert!(filter.is_excluded(&root.join("scripts/deploy.sh")));
+        // Exclusions still apply inside included directories
+        assert!(filter.is_excluded(&root.join(".config/nvim/lua/init.lua")));
+    }
+
+    #[test]
+    fn test_edited_paths_resolve_to_their_source() {
+        let link = |link: &str, target: &str| symlinks::SymlinkEntry {
+            link: PathBuf::from(link),
+            target: PathBuf::from(target),
+            created_at: String::new(),
+            last_verified: None,
+            mode: LinkMode::Symlink,
+            checksum: None,
+        };
+        let links = vec![
+            link("/home/user/.config", "/repo/config"),
+            link("/home/user/.config/nvim", "/repo/nvim"),
+            link("/home/user/.bashrc", "/repo/bashrc"),
+        ];
+
+        let source = |path: &str| deploying_link(&links, Path::new(path)).map(|(_, source)| source);
+        assert_eq!(
+            source("/home/user/.bashrc"),
+            Some(PathBuf::from("/repo/bashrc"))
+        );
+        assert_eq!(
+            source("/home/user/.config/nvim/init.lua"),
+            Some(PathBuf::from("/repo/nvim/init.lua"))
+        );
+        assert_eq!(
+            source("/home/user/.config/git/config"),
+            Some(PathBuf::from("/repo/config/git/config"))
+        );
+        assert_eq!(source("/home/user/.bashrc.local"), None);
+    }
+
+    #[test]
+    fn test_changes_are_shown_through_their_links() {
+        let link = |link: &str, target: &str, mode| symlinks::SymlinkEntry {
+            link: PathBuf::from(link),
+            target: PathBuf::from(target),
+            created_at: String::new(),
+            last_verified: None,
+            mode,
+            checksum: None,
+        };
+        let links = vec![
+            link("/home/user/.vimrc", "/repo/vimrc", LinkMode::Symlink),
+            link("/home/user/.config/nvim", "/repo/nvim", LinkMode::Symlink),
+            link("/home/user/.gitconfig", "/repo/gitconfig", LinkMode::Copy),
+        ];
+        let changed = ["nvim/init.lua", "vimrc", "gitconfig", "README.md"].map(String::from);
+
+        assert_eq!(
+            edited_through_links(&links, Path::new("/repo"), &changed),
+            [
+                PathBuf::from("/home/user/.config/nvim/init.lua"),
+                PathBuf::from("/home/user/.vimrc")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skipped_conflicts_are_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, home) = (dir.path().join("dotfiles"), dir.path().join("home"));
+        std::fs::create_dir_all(source.join(".config")).unwrap();
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::write(source.join(".bashrc"), "new").unwrap();
+        std::fs::write(home.join(".bashrc"), "old").unwrap();
+        std::fs::write(home.join(".config"), "not a directory").unwrap();
+
+        let entry = DotfileEntry::new(source.to_string_lossy().to_string(), SourceType::Directory);
+        let skipped = ConflictLog::default();
+        let options = LinkOptions {
+            dry_run: true,
+            quiet: true,
+            skipped: Some(skipped.clone()),
+            ..Default::default()
+        };
+        create_symlinks_for_entry(&entry, &home, &options)
+            .await
+            .unwrap();
+
+        let mut conflicts = skipped.lock().unwrap().clone();
+        conflicts.sort_by(|a, b| a.link.cmp(&b.link));
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict {
+                    link: home.join(".bashrc"),
+                    target: source.join(".bashrc"),
+                },
+                Conflict {
+                    link: home.join(".config"),
+                    target: source.join(".config"),
+                },
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(home.join(".bashrc")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_chezmoi_names_are_mapped() {
+        let name = |name: &str, naming| {
+            let (name, private) = link_name(OsStr::new(name), naming);
+            (name.to_string_lossy().to_string(), private)
+        };
+        assert_eq!(
+            name("dot_bashrc", Naming::Chezmoi),
+            (".bashrc".to_string(), false)
+        );
+        assert_eq!(
+            name("private_dot_ssh", Naming::Chezmoi),
+            (".ssh".to_string(), true)
+        );
+        assert_eq!(
+            name("private_notes", Naming::Chezmoi),
+            ("notes".to_string(), true)
+        );
+        assert_eq!(
+            name("README.md", Naming::Chezmoi),
+            ("README.md".to_string(), false)
+        );
+        assert_eq!(name("dot_", Naming::Chezmoi), ("dot_".to_string(), false));
+        assert_eq!(
+            name("dot_bashrc", Naming::Plain),
+            ("dot_bashrc".to_string(), false)
+        );
+        assert_eq!(
+            name(".netrc.gpg", Naming::Plain),
+            (".netrc".to_string(), false)
+        );
+        assert_eq!(
+            name("private_dot_netrc.gpg", Naming::Chezmoi),
+            (".netrc".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_entries_are_selected_by_name_pattern_or_tag() {
+        let entries: Vec<_> = [
+            ("https://github.com/user/nvim.git", &["editor"][..]),
+            ("https://github.com/user/zsh-config.git", &["shell", "work"]),
+            ("/home/user/dots/tmux/", &["shell"]),
+        ]
+        .iter()
+        .map(|(source, tags)| DotfileEntry {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..DotfileEntry::new(*source, SourceType::Git)
+        })
+        .collect();
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let select_tagged = |names: &[&str], only: &[&str], tags: &[&str]| {
+            select_entries(
+                entries.clone(),
+                &strings(names),
+                &strings(only),
+                &strings(tags),
+            )
+            .map(|selected| {
+                selected
+                    .iter()
+                    .map(entry_name)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+        };
+        let select = |names: &[&str], only: &[&str]| select_tagged(names, only, &[]);
+
+        assert_eq!(select(&[], &[]).unwrap(), "nvim,zsh-config,tmux");
+        assert_eq!(
+            select(&["nvim", "/home/user/dots/tmux/"], &[]).unwrap(),
+            "nvim,tmux"
+        );
+        assert_eq!(
+            select(&[], &["zsh*", "*/dots/*"]).unwrap(),
+            "zsh-config,tmux"
+        );
+        assert_eq!(select(&["nvim"], &["zsh*"]).unwrap(), "");
+        assert!(select(&["emacs"], &[]).is_err());
+
+        assert_eq!(
+            select_tagged(&[], &[], &["shell"]).unwrap(),
+            "zsh-config,tmux"
+        );
+        assert_eq!(
+            select_tagged(&[], &[], &["editor", "work"]).unwrap(),
+            "nvim,zsh-config"
+        );
+        assert_eq!(select_tagged(&[], &["t*"], &["shell"]).unwrap(), "tmux");
+        assert_eq!(select_tagged(&[], &[], &["games"]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_entries_are_found_by_source_or_name() {
+        let entry = |source: &str, name: Option<&str>| DotfileEntry {
+            name: name.map(str::to_string),
+            ..DotfileEntry::new(source, SourceType::Git)
+        };
+        let entries = [
+            entry("git@github.com:user/nvim.git", Some("editor")),
+            entry("git@gitlab.com:work/nvim.git", None),
+            entry("git@github.com:user/zsh.git", None),
+            entry("git@github.com:user/work.git", None),
+            entry("git@gitlab.com:team/work.git", None),
+        ];
+        let find = |name: &str| find_entry(&entries, name).map(|entry| entry.source.as_str());
+
+        assert_eq!(
+            find("git@github.com:user/zsh.git").unwrap(),
+            entries[2].source
+        );
+        assert_eq!(find("editor").unwrap(), entries[0].source);
+        assert_eq!(find("zsh").unwrap(), entries[2].source);
+        // A named entry is no longer known by its repository's name
+        assert_eq!(find("nvim").unwrap(), entries[1].source);
+        assert!(find("emacs").is_err());
+        assert!(
+            find("work")
+                .unwrap_err()
+                .to_string()
+                .contains("could be any of")
+        );
+    }
+
+    #[test]
+    fn test_update_summary_fits_on_one_line() {
+        let mut summary = UpdateSummary {
+            pulled: 2,
+            links_created: 5,
+            ..Default::default()
+        };
+        assert_eq!(summary.to_string(), "2 pulled, 5 link(s) created, 0 errors");
+        assert_eq!(summary.exit_code(), 0);
+
+        summary.failed_scripts = 1;
+        assert_eq!(summary.to_string(), "2 pulled, 5 link(s) created, 1 error");
+        assert_eq!(summary.exit_code(), 3);
+        summary.failed_sources = 1;
+        assert_eq!(summary.exit_code(), 2);
+        assert!(summary.into_result().is_err());
+    }
+
+    #[test]
+    fn test_backup_path_never_reuses_an_existing_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".bashrc");
+
+        let backup = backup_path(&file);
+        assert_eq!(backup, dir.path().join(".bashrc.dotme-backup"));
+
+        std::fs::write(&backup, "old").unwrap();
+        let second = backup_path(&file);
+        assert_ne!(second, backup);
+        assert!(second.to_string_lossy().contains(".bashrc.dotme-backup."));
+    }
+
+    proptest! {
+            #[test]
+            fn test_detect_source_type_never_panics
+    (source in "\\PC*") {
+                let _ = detect_source_type(&source);
+            }
+
+            #[test]
+            fn test_detect_ssh_sources_as_git(path in "[a-zA-Z0-9_./-]{1,32}") {
+                let source = format!("git@github.com:{}", path);
+                prop_assert_eq!(detect_source_type(&source).unwrap(), SourceType::Git);
+            }
+
+            #[test]
+            fn test_detect_https_git_urls(host in "[a-z]{1,12}\\.[a-z]{2,4}", repo in "[a-zA-Z0-9_-]{1,16}") {
+                let source = format!("https://{}/user/{}.git", host, repo);
+                prop_assert_eq!(detect_source_type(&source).unwrap(), SourceType::Git);
+            }
+
+            #[test]
+            fn test_archive_name_is_a_single_component(source in "\\PC*") {
+                let entry = DotfileEntry::new(source, SourceType::Git);
+
+                let name = archive_name(&entry);
+                prop_assert!(!name.contains('/') && !name.contains('\\'));
+            }
+
+            #[test]
+            fn test_excluded_paths_are_never_linked(name in "[a-z]{1,12}", ext in "[a-z]{1,4}") {
+                let root = Path::new("/repo");
+                let entry = DotfileEntry {
+                    target: root.to_path_buf(),
+                    exclude: Some(vec![format!("*.{}", ext), "docs/**".to_string()]),
+                    ..DotfileEntry::new("/repo", SourceType::Directory)
+                };
+                let filter = EntryFilter::new(&entry, root).unwrap();
+
+                let file = format!("{}.{}", name, ext);
+                prop_assert!(filter.is_excluded(&root.join(&file)));
+                prop_assert!(filter.is_excluded(&root.join("nested").join(&file)));
+                prop_assert!(filter.is_excluded(&root.join("docs").join(&name)));
+                prop_assert!(!filter.is_excluded(&root.join(&name)));
+            }
+        }
+}
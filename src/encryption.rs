@@ -0,0 +1,368 @@
+//! Keeping the config and the symlink state encrypted on disk
+//!
+//! On shared machines, the config and the state give away private repository URLs and internal
+//! hostnames to anyone who can read them. `dotme encrypt` encrypts `config.yml` and
+//! `symlinks.yml` with [age](https://age-encryption.org), either to an age key you already have
+//! or to one dotme generates and protects with a passphrase, which is asked once per run (or
+//! read from `DOTME_PASSPHRASE`). Files are encrypted to the public half of the key, so saving
+//! never asks for anything. Where the key is stays readable in `encryption.yml` next to the
+//! config:
+//!
+//! ```yaml
+//! recipient: age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p
+//! identity: /home/user/.dotme/key.age
+//! ```
+//!
+//! Encrypted files are recognized by their age header, so they are decrypted when loaded
+//! whatever the settings say. Requires the `encryption` build feature.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::atomic;
+use crate::dotfiles;
+use crate::layout;
+use crate::symlinks;
+
+/// First bytes of a binary age file
+const BINARY_HEADER: &[u8] = b"age-encryption.org/";
+
+/// First bytes of an armored age file
+const ARMOR_HEADER: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Environment variable the passphrase of the key can be given in, for runs without a terminal
+#[cfg(feature = "encryption")]
+const PASSPHRASE_ENV: &str = "DOTME_PASSPHRASE";
+
+/// Where the key files are encrypted with is
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Settings {
+    /// Public key files are encrypted to
+    pub recipient: String,
+    /// File with the key that decrypts them, itself encrypted with a passphrase if dotme
+    /// generated it
+    pub identity: PathBuf,
+}
+
+/// Options for `dotme encrypt`
+#[derive(Debug, Clone, Default)]
+pub struct EncryptOptions {
+    /// Existing age key file to encrypt with
+    pub identity: Option<PathBuf>,
+    /// Generate a key protected with a passphrase instead
+    pub passphrase: bool,
+    /// Decrypt the files and stop encrypting them
+    pub off: bool,
+}
+
+/// Path of the encryption settings, next to the config
+fn settings_path() -> Result<PathBuf> {
+    Ok(layout::config_dir()?.join("encryption.yml"))
+}
+
+/// The encryption settings, if files are encrypted
+pub fn settings() -> Result<Option<Settings>> {
+    let path = settings_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let settings = serde_yaml::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(settings))
+}
+
+/// Check if `contents` are encrypted with age
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(BINARY_HEADER) || contents.starts_with(ARMOR_HEADER)
+}
+
+/// Check if the file at `path` is encrypted with age
+pub fn is_encrypted_file(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut header = [0; ARMOR_HEADER.len()];
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let read = file.read(&mut header).unwrap_or(0);
+    is_encrypted(&header[..read])
+}
+
+/// Read the file at `path` as text, decrypting it if it is encrypted
+pub fn read(path: &Path) -> Result<String> {
+    let contents =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let contents = if is_encrypted(&contents) {
+        // The cause is the useful part, e.g. a wrong passphrase
+        decrypt_bytes(&contents)
+            .map_err(|e| anyhow::anyhow!("Failed to decrypt {}: {:#}", path.display(), e))?
+    } else {
+        contents
+    };
+    String::from_utf8(contents).with_context(|| format!("{} is not text", path.display()))
+}
+
+/// Replace the contents of the file at `path` with `contents`, encrypted if encryption is on
+pub fn write(path: &Path, contents: &str) -> Result<()> {
+    match settings()? {
+        Some(settings) => {
+            let encrypted = encrypt_bytes(contents.as_bytes(), &settings)
+                .with_context(|| format!("Failed to encrypt {}", path.display()))?;
+            atomic::write(path, &encrypted)
+        }
+        None => atomic::write(path, contents.as_bytes()),
+    }
+}
+
+/// Turn encryption on, change its key, or turn it off, rewriting the config and the state
+pub async fn encrypt(options: EncryptOptions) -> Result<()> {
+    let config_path = dotfiles::get_config_path()?;
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let mut files = vec![config_path];
+    let state_path = symlinks::state_path()?;
+    if state_path.extension().is_some_and(|ext| ext == "db") {
+        log::warn!(
+            "The symlink state is kept in {}, which is not encrypted",
+            state_path.display()
+        );
+    } else if state_path.exists() {
+        files.push(state_path);
+    }
+    // Read with the old settings before they change
+    let contents = files
+        .iter()
+        .map(|path| read(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let path = settings_path()?;
+    if options.off {
+        if let Some(settings) = settings()? {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            log::info!(
+                "The key is still in {}; delete it if you don't need it anymore",
+                settings.identity.display()
+            );
+        }
+    } else {
+        let settings = match &options.identity {
+            Some(identity) => key_settings(identity)?,
+            None if options.passphrase => generate_key()?,
+            None => anyhow::bail!("Pass --identity <key file> or --passphrase"),
+        };
+        atomic::write(&path, serde_yaml::to_string(&settings)?.as_bytes())?;
+    }
+
+    for (file, contents) in files.iter().zip(&contents) {
+        write(file, contents)?;
+        // The backup holds the previous version, which may not be encrypted
+        let _ = std::fs::remove_file(atomic::backup_path(file));
+    }
+    if options.off {
+        println!("✓ Decrypted {} file(s)", files.len());
+    } else {
+        println!("✓ Encrypted {} file(s)", files.len());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+mod age_key {
+    use super::*;
+    use age::secrecy::{ExposeSecret, SecretString};
+    use std::sync::OnceLock;
+
+    use crate::prompt;
+
+    /// Text of the key that decrypts files, once it was read (and its passphrase asked)
+    static KEY: OnceLock<String> = OnceLock::new();
+
+    /// The X25519 keys in the text of an age key file
+    fn parse_keys(text: &str) -> Result<Vec<age::x25519::Identity>> {
+        let keys = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse::<age::x25519::Identity>()
+                    .map_err(|e| anyhow::anyhow!("{}", e))
+                    .context("Only age X25519 keys (AGE-SECRET-KEY-...) are supported")
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if keys.is_empty() {
+            anyhow::bail!("No age key found");
+        }
+        Ok(keys)
+    }
+
+    /// The passphrase of the key, from the environment or asked for
+    fn passphrase(confirm: bool) -> Result<SecretString> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV)
+            && !passphrase.is_empty()
+        {
+            return Ok(passphrase.into());
+        }
+        prompt::require_interactive(&format!("Set {} to the passphrase.", PASSPHRASE_ENV))?;
+        Ok(prompt::password("Passphrase of the dotme key", confirm)?.into())
+    }
+
+    /// Text of the key file at `path`, asking for its passphrase if it is protected with one
+    fn key_text(path: &Path) -> Result<String> {
+        let contents = std::fs::read(path)
+            .with_context(|| format!("Failed to read the key {}", path.display()))?;
+        let contents = if is_encrypted(&contents) {
+            let identity = age::scrypt::Identity::new(passphrase(false)?);
+            age::decrypt(&identity, &contents).with_context(|| {
+                format!("Failed to unlock {} (wrong passphrase?)", path.display())
+            })?
+        } else {
+            contents
+        };
+        String::from_utf8(contents).context("The key is not text")
+    }
+
+    /// Decrypt `contents` with the configured key
+    pub fn decrypt_bytes(contents: &[u8]) -> Result<Vec<u8>> {
+        let text = match KEY.get() {
+            Some(text) => text,
+            None => {
+                let settings = settings()?.context(
+                    "The file is encrypted, but there is no encryption.yml saying with which key",
+                )?;
+                let text = key_text(&crate::config::expand_home(&settings.identity))?;
+                KEY.get_or_init(|| text)
+            }
+        };
+
+        let keys = parse_keys(text)?;
+        let decryptor = age::Decryptor::new_buffered(age::armor::ArmoredReader::new(contents))?;
+        let mut reader = decryptor
+            .decrypt(keys.iter().map(|key| key as &dyn age::Identity))
+            .context("The configured key doesn't decrypt it")?;
+        let mut plaintext = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    /// Encrypt `contents` to the recipient of `settings`
+    pub fn encrypt_bytes(contents: &[u8], settings: &Settings) -> Result<Vec<u8>> {
+        let recipient: age::x25519::Recipient = settings
+            .recipient
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid recipient '{}': {}", settings.recipient, e))?;
+        Ok(age::encrypt_and_armor(&recipient, contents)?.into_bytes())
+    }
+
+    /// Settings for the existing key file at `path`
+    pub fn key_settings(path: &Path) -> Result<Settings> {
+        let path = std::path::absolute(crate::config::expand_home(path))?;
+        let keys = parse_keys(&key_text(&path)?)?;
+        Ok(Settings {
+            recipient: keys[0].to_public().to_string(),
+            identity: path,
+        })
+    }
+
+    /// Generate a key, protect it with a passphrase, and return the settings for it
+    pub fn generate_key() -> Result<Settings> {
+        let key = age::x25519::Identity::generate();
+        let recipient = age::scrypt::Recipient::new(passphrase(true)?);
+        let protected =
+            age::encrypt_and_armor(&recipient, key.to_string().expose_secret().as_bytes())?;
+
+        let path = dotfiles::get_dotme_dir()?.join("key.age");
+        if path.exists() {
+            anyhow::bail!(
+                "{} already exists. Move it away or pass it with --identity.",
+                path.display()
+            );
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        atomic::write(&path, protected.as_bytes())?;
+        log::info!(
+            "Generated a key protected by your passphrase at {}",
+            path.display()
+        );
+        Ok(Settings {
+            recipient: key.to_public().to_string(),
+            identity: path,
+        })
+    }
+}
+
+#[cfg(feature = "encryption")]
+use age_key::{decrypt_bytes, encrypt_bytes, generate_key, key_settings};
+
+/// Error for encrypted files in builds that can't decrypt them
+#[cfg(not(feature = "encryption"))]
+fn unsupported() -> anyhow::Error {
+    anyhow::anyhow!("dotme was built without the encryption feature")
+}
+
+#[cfg(not(feature = "encryption"))]
+fn decrypt_bytes(_contents: &[u8]) -> Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn encrypt_bytes(_contents: &[u8], _settings: &Settings) -> Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn key_settings(_path: &Path) -> Result<Settings> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn generate_key() -> Result<Settings> {
+    Err(unsupported())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_files_are_recognized_by_their_header() {
+        assert!(is_encrypted(
+            b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdlLWVuY3J5cHRpb24ub3Jn"
+        ));
+        assert!(is_encrypted(b"age-encryption.org/v1\n-> X25519 abc"));
+        assert!(!is_encrypted(b"version: 2\ndotfiles: []\n"));
+        assert!(!is_encrypted(b""));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_files_are_encrypted_to_the_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = age::x25519::Identity::generate();
+        let key_file = dir.path().join("key.txt");
+        std::fs::write(
+            &key_file,
+            format!(
+                "# created by a test\n{}\n",
+                age::secrecy::ExposeSecret::expose_secret(&key.to_string())
+            ),
+        )
+        .unwrap();
+
+        let settings = key_settings(&key_file).unwrap();
+        assert_eq!(settings.recipient, key.to_public().to_string());
+        let encrypted = encrypt_bytes(b"dotfiles: []", &settings).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(
+            age::decrypt(&key, &encrypted).unwrap(),
+            b"dotfiles: []".to_vec()
+        );
+    }
+}
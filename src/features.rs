@@ -0,0 +1,143 @@
+//! Opt-in feature flags for dotme
+//!
+//! Large subsystems ship behind named feature flags so they can be tried out without
+//! destabilising existing setups. Experimental features are disabled unless enabled in the
+//! `features:` section of the config or with `--enable-feature <name>`; stable features are
+//! enabled by default but can still be turned off in the config.
+//!
+//! ```yaml
+//! features:
+//!   preserve-comments: false
+//! ```
+
+use anyhow::Result;
+use console::style;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// Stability level of a feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stability {
+    /// Subject to change, disabled by default
+    Experimental,
+    /// Stable, enabled by default
+    Stable,
+}
+
+impl std::fmt::Display for Stability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stability::Experimental => write!(f, "experimental"),
+            Stability::Stable => write!(f, "stable"),
+        }
+    }
+}
+
+/// A feature that can be toggled at runtime
+#[derive(Debug, Clone, Copy)]
+pub struct Feature {
+    /// Name used in config and on the command line
+    pub name: &'static str,
+    /// Short description of what the feature does
+    pub description: &'static str,
+    /// Stability level
+    pub stability: Stability,
+}
+
+/// Keep comments and key order when dotme rewrites `config.yml`
+pub const PRESERVE_COMMENTS: &str = "preserve-comments";
+
+/// Make paths and repository URLs clickable in terminals that support hyperlinks
+pub const HYPERLINKS: &str = "hyperlinks";
+
+/// Send a desktop notification when an update without a terminal pulls commits or fails
+pub const NOTIFY: &str = "notify";
+
+/// All known features
+pub const FEATURES: &[Feature] = &[
+    Feature {
+        name: PRESERVE_COMMENTS,
+        description: "Keep comments and key order in config.yml when dotme rewrites it",
+        stability: Stability::Stable,
+    },
+    Feature {
+        name: HYPERLINKS,
+        description: "Make paths and repository URLs clickable in terminals that support it",
+        stability: Stability::Stable,
+    },
+    Feature {
+        name: NOTIFY,
+        description: "Notify on the desktop when a background update pulls commits or fails",
+        stability: Stability::Experimental,
+    },
+];
+
+/// Features enabled on the command line for this run
+static CLI_FEATURES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Find a feature by name
+pub fn find(name: &str) -> Option<&'static Feature> {
+    FEATURES.iter().find(|f| f.name == name)
+}
+
+/// Record the features enabled with `--enable-feature`
+pub fn enable_from_cli(names: &[String]) {
+    for name in names {
+        if find(name).is_none() {
+            log::warn!("Unknown feature '{}' (see 'dotme features list')", name);
+        }
+    }
+
+    let _ = CLI_FEATURES.set(names.to_vec());
+}
+
+/// Check if a feature is enabled given the `features:` section of the config
+pub fn is_enabled(configured: &BTreeMap<String, bool>, name: &str) -> bool {
+    if CLI_FEATURES
+        .get()
+        .is_some_and(|names| names.iter().any(|n| n == name))
+    {
+        return true;
+    }
+
+    if let Some(enabled) = configured.get(name) {
+        return *enabled;
+    }
+
+    find(name).is_some_and(|f| f.stability == Stability::Stable)
+}
+
+/// List all features and whether they are enabled
+pub async fn list() -> Result<()> {
+    let config_path = crate::dotfiles::get_config_path()?;
+    let configured = if config_path.exists() {
+        crate::config::Config::load(Some(config_path))?.features
+    } else {
+        BTreeMap::new()
+    };
+
+    println!("Features:");
+    println!("─────────────────────────────────────────");
+
+    for feature in FEATURES {
+        let status = if is_enabled(&configured, feature.name) {
+            style("✓ enabled").green()
+        } else {
+            style("✗ disabled").dim()
+        };
+
+        println!("  {} {} [{}]", status, feature.name, feature.stability);
+        println!("    {}", feature.description);
+        println!();
+    }
+
+    for name in configured.keys().filter(|n| find(n).is_none()) {
+        println!(
+            "  {} {} is set in config but is not a known feature",
+            style("⚠").yellow(),
+            name
+        );
+    }
+
+    Ok(())
+}
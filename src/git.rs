@@ -2,8 +2,199 @@ use anyhow::{Context, Result};
 use std::path::Path;
 use tokio::process::Command;
 
+use crate::config::{DotfileEntry, GitAuthConfig, GitBackend, GitConfig, Submodules, expand_home};
+use crate::diskspace::{self, Usage};
+use crate::progress;
+
+#[cfg(feature = "gix-backend")]
+mod gix_backend;
+
+/// Options controlling how a repository is cloned and updated
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Branch or tag to check out
+    pub branch: Option<String>,
+    /// Commit the repository is pinned to
+    pub rev: Option<String>,
+    /// Clone depth for shallow clones
+    pub depth: Option<u32>,
+    /// Git implementation to use
+    pub backend: GitBackend,
+    /// Credentials for private repositories
+    pub auth: GitAuthConfig,
+    /// Folders to check out, leaving the rest of the repository off the disk
+    pub sparse: Option<Vec<String>>,
+    /// Which submodules are checked out
+    pub submodules: Submodules,
+    /// Scheme and host of the entry's HTTPS remote, the only place the token is sent to
+    pub credential_url: Option<String>,
+}
+
+impl CloneOptions {
+    /// Build clone options from a dotfile entry and the global git settings
+    pub fn new(entry: &DotfileEntry, git: &GitConfig) -> Self {
+        Self {
+            branch: entry.branch.clone(),
+            rev: entry.rev.clone(),
+            depth: entry.depth.or(git.depth),
+            backend: git.backend,
+            auth: git.auth.clone(),
+            sparse: entry.folders.clone(),
+            submodules: entry.submodules,
+            credential_url: credential_url(&entry.source),
+        }
+    }
+
+    /// Sparse checkout patterns for the selected folders, or `None` to check out everything.
+    /// Braces can't be expressed in git's patterns, so folders using them are checked out fully.
+    fn sparse_patterns(&self) -> Option<Vec<String>> {
+        let folders = self.sparse.as_ref()?;
+        if folders.iter().any(|folder| folder.contains('{')) {
+            log::debug!("Brace patterns in folders, checking out the whole repository");
+            return None;
+        }
+
+        let mut patterns: Vec<String> = folders
+            .iter()
+            .map(|folder| folder.trim_start_matches("./").trim_end_matches('/'))
+            .filter(|folder| !folder.is_empty() && !folder.split('/').any(|part| part == ".."))
+            .map(|folder| format!("/{}", folder))
+            .collect();
+        // Submodules of the selected folders are still set up
+        patterns.push("/.gitmodules".to_string());
+        Some(patterns)
+    }
+}
+
+/// Check if the pure-Rust backend should be used, falling back to the git CLI when dotme
+/// was built without it
+fn use_gix(backend: GitBackend) -> bool {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+
+    match backend {
+        GitBackend::Cli => false,
+        GitBackend::Gix if cfg!(feature = "gix-backend") => true,
+        GitBackend::Gix => {
+            WARNED.call_once(|| {
+                log::warn!(
+                    "dotme was built without the gix backend, falling back to the git command line"
+                )
+            });
+            false
+        }
+    }
+}
+
+/// Username sent with HTTPS tokens when none is configured (accepted by GitHub and Gitea)
+const DEFAULT_TOKEN_USERNAME: &str = "x-access-token";
+
+/// SSH command using the configured private key
+fn ssh_command(auth: &GitAuthConfig) -> Option<String> {
+    auth.ssh_key.as_ref().map(|key| {
+        format!(
+            "ssh -i '{}' -o IdentitiesOnly=yes",
+            expand_home(key).display()
+        )
+    })
+}
+
+/// Read the HTTPS token from the configured environment variable
+fn token(auth: &GitAuthConfig) -> Option<String> {
+    let var = auth.token_env.as_ref()?;
+    match std::env::var(var) {
+        Ok(token) if !token.is_empty() => Some(token),
+        _ => {
+            log::warn!("Environment variable {} is not set, not using a token", var);
+            None
+        }
+    }
+}
+
+/// Scheme and host of an HTTP(S) remote, e.g. `https://github.com` for
+/// `https://user@github.com/user/dotfiles.git`, which git matches credential settings against
+pub fn credential_url(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !matches!(scheme, "https" | "http") {
+        return None;
+    }
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    (!host.is_empty()).then(|| format!("{}://{}", scheme, host))
+}
+
+/// Create a git command using the configured credentials
+fn git_command(options: &CloneOptions) -> Command {
+    let auth = &options.auth;
+    let mut command = Command::new("git");
+
+    if let Some(ssh) = ssh_command(auth) {
+        command.env("GIT_SSH_COMMAND", ssh);
+    }
+
+    if let Some(helper) = &auth.credential_helper {
+        command
+            .arg("-c")
+            .arg(format!("credential.helper={}", helper));
+    }
+
+    // The token is handed over through the environment so it never shows up in the
+    // process list or in the remote URL stored in the clone. It is only offered to the host of
+    // the entry's remote, never to submodules, mirrors or other repositories elsewhere.
+    if let Some(url) = &options.credential_url
+        && let Some(token) = token(auth)
+    {
+        let username = auth.username.as_deref().unwrap_or(DEFAULT_TOKEN_USERNAME);
+        command
+            .env("DOTME_GIT_USERNAME", username)
+            .env("DOTME_GIT_TOKEN", token);
+        // Helpers from the git config of the user would otherwise be asked first
+        if auth.credential_helper.is_none() {
+            command.arg("-c").arg(format!("credential.{}.helper=", url));
+        }
+        command.arg("-c").arg(format!(
+            "credential.{}.helper=!f() {{ echo \"username=$DOTME_GIT_USERNAME\"; \
+             echo \"password=$DOTME_GIT_TOKEN\"; }}; f",
+            url
+        ));
+    }
+
+    // Never block on an interactive prompt
+    command.env("GIT_TERMINAL_PROMPT", "0");
+
+    command
+}
+
+/// Run a git command in a repository and return its stdout
+async fn git(repo_path: &Path, args: &[&str]) -> Result<String> {
+    run(Command::new("git"), repo_path, args).await
+}
+
+/// Run a git command that talks to a remote, using the configured credentials
+async fn git_remote(repo_path: &Path, options: &CloneOptions, args: &[&str]) -> Result<String> {
+    run(git_command(options), repo_path, args).await
+}
+
+/// Run a prepared git command and return its stdout
+async fn run(mut command: Command, repo_path: &Path, args: &[&str]) -> Result<String> {
+    let output = command
+        .args(args)
+        .current_dir(repo_path)
+        .output()
+        .await
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Git {} failed: {}", args[0], stderr.trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
 /// Clone a git repository to the specified path
-pub async fn clone(url: &str, target: &Path) -> Result<()> {
+pub async fn clone(url: &str, target: &Path, options: &CloneOptions) -> Result<()> {
     log::info!("Cloning git repository: {}", url);
     log::debug!("Target path: {}", target.display());
 
@@ -13,6 +204,9 @@ pub async fn clone(url: &str, target: &Path) -> Result<()> {
         return Ok(());
     }
 
+    diskspace::ensure(target, clone_usage(url), &format!("cloning {}", url))?;
+    let _spinner = progress::spinner(format!("Cloning {}", url));
+
     // Create parent directory if needed
     if let Some(parent) = target.parent() {
         tokio::fs::create_dir_all(parent)
@@ -20,9 +214,44 @@ pub async fn clone(url: &str, target: &Path) -> Result<()> {
             .context("Failed to create parent directory")?;
     }
 
+    #[cfg(feature = "gix-backend")]
+    if use_gix(options.backend) {
+        let (url, target_path, clone_options) =
+            (url.to_string(), target.to_path_buf(), options.clone());
+        tokio::task::spawn_blocking(move || gix_backend::clone(&url, &target_path, &clone_options))
+            .await
+            .context("Git clone task failed")?
+            .map_err(|e| anyhow::anyhow!("Git clone failed: {:#}", e))?;
+
+        if options.sparse.is_some() {
+            log::debug!(
+                "The gix backend doesn't support sparse checkouts, checking out everything"
+            );
+        }
+        log::info!("✓ Repository cloned successfully");
+        return finish_clone(target, options).await;
+    }
+
     // Clone the repository
-    let output = Command::new("git")
-        .arg("clone")
+    let mut command = git_command(options);
+    command.arg("clone");
+    if let Some(branch) = &options.branch {
+        command.arg("--branch").arg(branch);
+    }
+    if let Some(depth) = options.depth {
+        log::debug!("Shallow clone with depth {}", depth);
+        command.arg("--depth").arg(depth.to_string());
+        // Shallow clones only fetch a single branch by default
+        if options.branch.is_none() {
+            command.arg("--no-single-branch");
+        }
+    }
+    // Only the blobs of the selected folders are downloaded, when they are checked out
+    let sparse = options.sparse_patterns();
+    if sparse.is_some() {
+        command.arg("--sparse").arg("--filter=blob:none");
+    }
+    let output = command
         .arg(url)
         .arg(target)
         .output()
@@ -34,20 +263,81 @@ pub async fn clone(url: &str, target: &Path) -> Result<()> {
         anyhow::bail!("Git clone failed: {}", stderr);
     }
 
+    if let Some(patterns) = &sparse {
+        set_sparse_checkout(target, patterns).await?;
+    }
     log::info!("✓ Repository cloned successfully");
 
+    finish_clone(target, options).await
+}
+
+/// Limit the checkout of a repository to `patterns`
+async fn set_sparse_checkout(repo_path: &Path, patterns: &[String]) -> Result<()> {
+    log::debug!("Sparse checkout of {}", patterns.join(", "));
+    let mut args = vec!["sparse-checkout", "set", "--no-cone"];
+    args.extend(patterns.iter().map(String::as_str));
+    git(repo_path, &args).await?;
+    Ok(())
+}
+
+/// Bring the sparse checkout of a repository in line with the selected folders, which may have
+/// changed since it was cloned
+async fn sync_sparse_checkout(repo_path: &Path, options: &CloneOptions) -> Result<()> {
+    match options.sparse_patterns() {
+        Some(patterns) => set_sparse_checkout(repo_path, &patterns).await,
+        None => {
+            let sparse = git(repo_path, &["config", "--bool", "core.sparseCheckout"])
+                .await
+                .unwrap_or_default();
+            if sparse == "true" {
+                log::debug!("No folders selected anymore, checking out the whole repository");
+                git(repo_path, &["sparse-checkout", "disable"]).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Estimate the space a clone of `url` takes up. Only local repositories can be measured;
+/// their objects are copied and checked out, so about twice their `.git` directory is needed.
+fn clone_usage(url: &str) -> Usage {
+    let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+    if !path.is_dir() {
+        return Usage::default();
+    }
+
+    // Bare repositories are the git directory themselves
+    let git_dir = path.join(".git");
+    let usage = diskspace::usage_of(if git_dir.is_dir() { &git_dir } else { path });
+    Usage {
+        bytes: usage.bytes.saturating_mul(2),
+        files: usage.files.saturating_mul(2),
+    }
+}
+
+/// Check out the pinned commit and initialize submodules of a fresh clone
+async fn finish_clone(target: &Path, options: &CloneOptions) -> Result<()> {
+    // Check out the pinned commit
+    if let Some(rev) = &options.rev {
+        checkout_rev(target, rev, options).await?;
+    }
+
     // Check for .gitmodules file and initialize submodules if present
     let gitmodules_path = target.join(".gitmodules");
     if gitmodules_path.exists() {
+        if options.submodules == Submodules::None {
+            log::debug!("Found .gitmodules file, leaving submodules alone");
+            return Ok(());
+        }
         log::info!("Found .gitmodules file, initializing submodules...");
-        init_submodules(target).await?;
+        init_submodules(target, options).await?;
     }
 
     Ok(())
 }
 
-/// Initialize and update git submodules
-async fn init_submodules(repo_path: &Path) -> Result<()> {
+/// Initialize and update git submodules, and theirs with `submodules: recursive`
+async fn init_submodules(repo_path: &Path, options: &CloneOptions) -> Result<()> {
     log::debug!("Initializing submodules in: {}", repo_path.display());
 
     // Initialize submodules
@@ -65,10 +355,13 @@ async fn init_submodules(repo_path: &Path) -> Result<()> {
     }
 
     // Update submodules
-    let update_output = Command::new("git")
-        .arg("submodule")
-        .arg("update")
-        .arg("--recursive")
+    let mut update = git_command(options);
+    update.arg("submodule").arg("update");
+    if options.submodules == Submodules::Recursive {
+        // Nested submodules aren't initialized by `submodule init`
+        update.arg("--init").arg("--recursive");
+    }
+    let update_output = update
         .current_dir(repo_path)
         .output()
         .await
@@ -84,45 +377,291 @@ async fn init_submodules(repo_path: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Pull latest changes from a git repository
-pub async fn pull(repo_path: &Path) -> Result<()> {
-    log::info!("Pulling latest changes: {}", repo_path.display());
+/// Check out a specific commit in detached HEAD mode, fetching it if needed
+async fn checkout_rev(repo_path: &Path, rev: &str, options: &CloneOptions) -> Result<()> {
+    let commit = format!("{}^{{commit}}", rev);
 
-    if !repo_path.exists() {
-        anyhow::bail!("Repository does not exist: {}", repo_path.display());
+    if git(repo_path, &["rev-parse", "--verify", "--quiet", &commit])
+        .await
+        .is_err()
+    {
+        log::debug!("Pinned revision {} not found locally, fetching", rev);
+        // Shallow clones may not contain the commit, so fetch it directly
+        if git_remote(repo_path, options, &["fetch", "origin", rev])
+            .await
+            .is_err()
+        {
+            git_remote(repo_path, options, &["fetch", "--unshallow", "origin"]).await?;
+        }
     }
 
-    // Pull changes
+    git(
+        repo_path,
+        &[
+            "-c",
+            "advice.detachedHead=false",
+            "checkout",
+            "--detach",
+            rev,
+        ],
+    )
+    .await
+    .with_context(|| format!("Failed to check out pinned revision {}", rev))?;
+
+    log::info!("✓ Checked out pinned revision {}", rev);
+
+    Ok(())
+}
+
+/// Get the commit hash currently checked out
+pub async fn head_commit(repo_path: &Path) -> Result<String> {
+    git(repo_path, &["rev-parse", "HEAD"]).await
+}
+
+/// Get when the checked out commit was made
+pub async fn head_commit_time(repo_path: &Path) -> Result<chrono::DateTime<chrono::Utc>> {
+    let time = git(repo_path, &["log", "-1", "--format=%cI"]).await?;
+    Ok(chrono::DateTime::parse_from_rfc3339(&time)
+        .with_context(|| format!("Failed to parse commit time '{}'", time))?
+        .to_utc())
+}
+
+/// Get the upstream commit of the tracked branch (or the remote HEAD) without fetching it
+pub async fn remote_commit(repo_path: &Path, options: &CloneOptions) -> Result<Option<String>> {
+    let reference = options.branch.as_deref().unwrap_or("HEAD");
+    let output = git_remote(
+        repo_path,
+        options,
+        &[
+            "ls-remote",
+            "origin",
+            reference,
+            &format!("{}^{{}}", reference),
+        ],
+    )
+    .await?;
+
+    // Annotated tags list the tag object first and the commit it points at (`^{}`) last
+    Ok(output
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .next_back()
+        .map(str::to_string))
+}
+
+/// Show the differences between two files outside of a repository
+pub async fn diff_files(old: &Path, new: &Path) -> Result<String> {
+    let color = if console::colors_enabled() {
+        "--color=always"
+    } else {
+        "--color=never"
+    };
     let output = Command::new("git")
-        .arg("pull")
-        .current_dir(repo_path)
+        .args(["diff", "--no-index", color, "--"])
+        .arg(old)
+        .arg(new)
         .output()
         .await
-        .context("Failed to execute git pull")?;
+        .context("Failed to execute git diff")?;
 
-    if !output.status.success() {
+    // Exit code 1 only means that the files differ
+    if !matches!(output.status.code(), Some(0 | 1)) {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Git pull failed: {}", stderr);
+        anyhow::bail!("Git diff failed: {}", stderr.trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// Check if a commit is already contained in the checked out history
+pub async fn contains_commit(repo_path: &Path, commit: &str) -> bool {
+    git(repo_path, &["merge-base", "--is-ancestor", commit, "HEAD"])
+        .await
+        .is_ok()
+}
+
+/// Pull latest changes from a git repository
+pub async fn pull(repo_path: &Path, options: &CloneOptions) -> Result<()> {
+    if !repo_path.exists() {
+        anyhow::bail!("Repository does not exist: {}", repo_path.display());
+    }
+    let _spinner = progress::spinner(format!("Pulling {}", repo_path.display()));
+
+    // Pinned repositories never move past their commit
+    if let Some(rev) = &options.rev {
+        let head = head_commit(repo_path).await?;
+        let pinned = git(repo_path, &["rev-parse", &format!("{}^{{commit}}", rev)])
+            .await
+            .ok();
+
+        if pinned.as_deref() == Some(head.as_str()) {
+            log::info!("Repository pinned to {}, not pulling", rev);
+        } else {
+            log::info!("Repository pinned to {}, restoring pinned commit", rev);
+            checkout_rev(repo_path, rev, options).await?;
+        }
+        return Ok(());
+    }
+
+    // The gix backend only fast-forwards the checked out branch
+    #[cfg(feature = "gix-backend")]
+    if use_gix(options.backend) {
+        log::info!("Pulling latest changes: {}", repo_path.display());
+        let (path, pull_options) = (repo_path.to_path_buf(), options.clone());
+        tokio::task::spawn_blocking(move || gix_backend::pull(&path, &pull_options))
+            .await
+            .context("Git pull task failed")?
+            .map_err(|e| anyhow::anyhow!("Git pull failed: {:#}", e))?;
+
+        return finish_pull(repo_path, options).await;
+    }
+
+    // A checkout left detached by a pin that was removed since goes back to the default branch
+    if options.branch.is_none() && current_branch(repo_path).await?.is_none() {
+        git_remote(
+            repo_path,
+            options,
+            &["remote", "set-head", "origin", "--auto"],
+        )
+        .await?;
+        let head = git(
+            repo_path,
+            &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"],
+        )
+        .await?;
+        let branch = head.strip_prefix("origin/").unwrap_or(&head);
+        log::info!(
+            "Repository no longer pinned, switching to branch {}",
+            branch
+        );
+        git(repo_path, &["checkout", branch]).await?;
+    }
+
+    // Tags are fixed points just like commits
+    if let Some(branch) = &options.branch
+        && git(
+            repo_path,
+            &[
+                "show-ref",
+                "--verify",
+                "--quiet",
+                &format!("refs/tags/{}", branch),
+            ],
+        )
+        .await
+        .is_ok()
+    {
+        log::info!("Repository pinned to tag {}, not pulling", branch);
+        return checkout_rev(repo_path, branch, options).await;
+    }
+
+    // Switch to the configured branch if the checkout is elsewhere
+    if let Some(branch) = &options.branch {
+        let current = git(repo_path, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+        if &current != branch {
+            log::info!("Switching to branch {}", branch);
+            git_remote(repo_path, options, &["fetch", "origin"]).await?;
+            git(repo_path, &["checkout", branch]).await?;
+        }
+    }
+
+    log::info!("Pulling latest changes: {}", repo_path.display());
+    sync_sparse_checkout(repo_path, options).await?;
+
+    // Shallow clones fetch a truncated history and move to it, as a plain pull can't
+    // fast-forward across the shallow boundary
+    let shallow = match options.depth {
+        Some(depth) => fetch_shallow(repo_path, depth, options).await?,
+        None => false,
+    };
+
+    if !shallow {
+        // Pull changes
+        let output = git_command(options)
+            .arg("pull")
+            .current_dir(repo_path)
+            .output()
+            .await
+            .context("Failed to execute git pull")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Git pull failed: {}", stderr);
+        }
     }
 
     log::info!("✓ Repository updated successfully");
 
+    finish_pull(repo_path, options).await
+}
+
+/// Check if clones can be pointed at another remote with [`set_origin`], which the gix backend
+/// can't do
+pub fn switches_remotes(backend: GitBackend) -> bool {
+    !use_gix(backend)
+}
+
+/// Point the `origin` remote of a clone at `url`, e.g. a mirror of the repository
+pub async fn set_origin(repo_path: &Path, url: &str) -> Result<()> {
+    let current = git(repo_path, &["remote", "get-url", "origin"]).await?;
+    if current != url {
+        log::debug!("Pointing origin of {} at {}", repo_path.display(), url);
+        git(repo_path, &["remote", "set-url", "origin", url]).await?;
+    }
+    Ok(())
+}
+
+/// Update submodules after a pull
+async fn finish_pull(repo_path: &Path, options: &CloneOptions) -> Result<()> {
     // Update submodules if .gitmodules exists
     let gitmodules_path = repo_path.join(".gitmodules");
-    if gitmodules_path.exists() {
+    if gitmodules_path.exists() && options.submodules != Submodules::None {
         log::info!("Updating submodules...");
-        update_submodules(repo_path).await?;
+        update_submodules(repo_path, options).await?;
     }
 
     Ok(())
 }
 
-/// Update git submodules
-async fn update_submodules(repo_path: &Path) -> Result<()> {
-    let output = Command::new("git")
-        .arg("submodule")
-        .arg("update")
-        .arg("--recursive")
+/// Fetch the upstream branch with a limited depth and move the checkout to it.
+///
+/// Returns `false` when the checkout has local commits, in which case a regular pull is
+/// needed to keep them.
+async fn fetch_shallow(repo_path: &Path, depth: u32, options: &CloneOptions) -> Result<bool> {
+    let ahead = git(repo_path, &["rev-list", "--count", "@{upstream}..HEAD"])
+        .await
+        .unwrap_or_default();
+    if ahead != "0" {
+        log::debug!("Local commits present, falling back to a regular pull");
+        return Ok(false);
+    }
+
+    log::debug!("Fetching with depth {}", depth);
+    git_remote(
+        repo_path,
+        options,
+        &["fetch", "--depth", &depth.to_string(), "origin"],
+    )
+    .await?;
+
+    // `--keep` refuses to touch files with local modifications
+    git(repo_path, &["reset", "--keep", "@{upstream}"])
+        .await
+        .context("Local changes conflict with upstream changes")?;
+
+    Ok(true)
+}
+
+/// Update git submodules, initializing ones added upstream
+async fn update_submodules(repo_path: &Path, options: &CloneOptions) -> Result<()> {
+    let mut command = git_command(options);
+    command.arg("submodule").arg("update").arg("--init");
+    if options.submodules == Submodules::Recursive {
+        command.arg("--recursive");
+    }
+    let output = command
         .arg("--remote")
         .current_dir(repo_path)
         .output()
@@ -140,7 +679,12 @@ async fn update_submodules(repo_path: &Path) -> Result<()> {
 }
 
 /// Check if git is available on the system
-pub async fn check_git_available() -> Result<()> {
+pub async fn check_git_available(backend: GitBackend) -> Result<()> {
+    if use_gix(backend) {
+        log::debug!("Using the built-in gix backend");
+        return Ok(());
+    }
+
     let output = Command::new("git")
         .arg("--version")
         .output()
@@ -158,7 +702,6 @@ pub async fn check_git_available() -> Result<()> {
 }
 
 /// Get the current status of a git repository
-#[allow(dead_code)]
 pub async fn status(repo_path: &Path) -> Result<String> {
     let output = Command::new("git")
         .arg("status")
@@ -176,3 +719,240 @@ pub async fn status(repo_path: &Path) -> Result<String> {
     let status = String::from_utf8_lossy(&output.stdout).to_string();
     Ok(status)
 }
+
+/// Get the files with uncommitted changes in a repository
+pub async fn changed_files(repo_path: &Path) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=all"])
+        .current_dir(repo_path)
+        .output()
+        .await
+        .context("Failed to execute git status")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("Git status failed: {}", stderr);
+    }
+
+    // Porcelain lines are `XY path`, renames are `XY old -> new`
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|path| path.rsplit(" -> ").next().unwrap_or(path).to_string())
+        .collect())
+}
+
+/// Get the name of the checked out branch, or `None` for a detached HEAD
+pub async fn current_branch(repo_path: &Path) -> Result<Option<String>> {
+    let branch = git(repo_path, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    Ok((branch != "HEAD").then_some(branch))
+}
+
+/// Count the local commits that haven't been pushed to the upstream branch
+pub async fn unpushed_commits(repo_path: &Path) -> Result<usize> {
+    let count = git(repo_path, &["rev-list", "--count", "@{upstream}..HEAD"])
+        .await
+        .context("Current branch has no upstream")?;
+    count.parse().context("Failed to parse commit count")
+}
+
+/// Count the commits the current branch is ahead of and behind its upstream, as of the last
+/// fetch, or `None` if it has no upstream
+pub async fn ahead_behind(repo_path: &Path) -> Result<Option<(usize, usize)>> {
+    if git(repo_path, &["rev-parse", "--abbrev-ref", "@{upstream}"])
+        .await
+        .is_err()
+    {
+        return Ok(None);
+    }
+
+    let counts = git(
+        repo_path,
+        &["rev-list", "--left-right", "--count", "HEAD...@{upstream}"],
+    )
+    .await?;
+
+    let (ahead, behind) = counts
+        .split_once('\t')
+        .context("Failed to parse commit counts")?;
+    Ok(Some((
+        ahead.parse().context("Failed to parse commit count")?,
+        behind.parse().context("Failed to parse commit count")?,
+    )))
+}
+
+/// Fetch from the remote without touching the checkout
+pub async fn fetch(repo_path: &Path, options: &CloneOptions) -> Result<()> {
+    git_remote(repo_path, options, &["fetch", "--quiet"]).await?;
+    Ok(())
+}
+
+/// Stage all changes and commit them
+pub async fn commit_all(repo_path: &Path, message: &str) -> Result<()> {
+    git(repo_path, &["add", "--all"]).await?;
+    git(repo_path, &["commit", "--quiet", "--message", message]).await?;
+    Ok(())
+}
+
+/// Stage and commit only the given paths, leaving other changes uncommitted
+pub async fn commit_paths(repo_path: &Path, paths: &[String], message: &str) -> Result<()> {
+    let mut add = vec!["add", "--"];
+    add.extend(paths.iter().map(String::as_str));
+    git(repo_path, &add).await?;
+
+    let mut commit = vec!["commit", "--quiet", "--message", message, "--"];
+    commit.extend(paths.iter().map(String::as_str));
+    git(repo_path, &commit).await?;
+    Ok(())
+}
+
+/// Push the current branch to its upstream
+pub async fn push(repo_path: &Path, options: &CloneOptions) -> Result<()> {
+    git_remote(repo_path, options, &["push", "--quiet"]).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected_folders_become_sparse_patterns() {
+        let options = |folders: &[&str]| CloneOptions {
+            sparse: Some(folders.iter().map(|folder| folder.to_string()).collect()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            options(&["nvim/", "./zsh/aliases.zsh", "config/*", "../etc"]).sparse_patterns(),
+            Some(
+                ["/nvim", "/zsh/aliases.zsh", "/config/*", "/.gitmodules"]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+        assert_eq!(options(&["{nvim,vim}"]).sparse_patterns(), None);
+        assert_eq!(CloneOptions::default().sparse_patterns(), None);
+    }
+
+    /// Arguments and environment of the git command built for `options`
+    fn command_line(options: &CloneOptions) -> (Vec<String>, Vec<(String, String)>) {
+        let command = git_command(options);
+        let command = command.as_std();
+        let args = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let envs = command
+            .get_envs()
+            .filter_map(|(key, value)| {
+                Some((
+                    key.to_string_lossy().into_owned(),
+                    value?.to_string_lossy().into_owned(),
+                ))
+            })
+            .collect();
+        (args, envs)
+    }
+
+    fn env<'a>(envs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        envs.iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_credential_url_is_scheme_and_host() {
+        assert_eq!(
+            credential_url("https://github.com/user/dotfiles.git").as_deref(),
+            Some("https://github.com")
+        );
+        assert_eq!(
+            credential_url("http://me@git.example.com:8080/dotfiles").as_deref(),
+            Some("http://git.example.com:8080")
+        );
+        assert_eq!(credential_url("git@github.com:user/dotfiles.git"), None);
+        assert_eq!(credential_url("ssh://git@github.com/user/dotfiles"), None);
+        assert_eq!(credential_url("file:///tmp/dotfiles"), None);
+    }
+
+    #[test]
+    fn test_ssh_key_is_passed_in_the_environment() {
+        let options = CloneOptions {
+            auth: GitAuthConfig {
+                ssh_key: Some("/keys/id_ed25519".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (args, envs) = command_line(&options);
+
+        assert!(args.is_empty());
+        assert_eq!(
+            env(&envs, "GIT_SSH_COMMAND"),
+            Some("ssh -i '/keys/id_ed25519' -o IdentitiesOnly=yes")
+        );
+        assert_eq!(env(&envs, "GIT_TERMINAL_PROMPT"), Some("0"));
+    }
+
+    #[test]
+    fn test_token_is_only_offered_to_the_remote_host() {
+        // Any variable that is always set stands in for the token
+        let secret = std::env::var("PATH").unwrap();
+        let options = CloneOptions {
+            auth: GitAuthConfig {
+                token_env: Some("PATH".to_string()),
+                ..Default::default()
+            },
+            credential_url: Some("https://github.com".to_string()),
+            ..Default::default()
+        };
+        let (args, envs) = command_line(&options);
+
+        assert_eq!(env(&envs, "DOTME_GIT_TOKEN"), Some(secret.as_str()));
+        assert_eq!(
+            env(&envs, "DOTME_GIT_USERNAME"),
+            Some(DEFAULT_TOKEN_USERNAME)
+        );
+        assert_eq!(args.len(), 4);
+        assert_eq!(args[..2], ["-c", "credential.https://github.com.helper="]);
+        assert!(args[3].starts_with("credential.https://github.com.helper=!f()"));
+        assert!(!args.iter().any(|arg| arg.contains(&secret)));
+        assert!(!args.iter().any(|arg| arg.starts_with("credential.helper")));
+
+        // Without an HTTPS remote there is nobody to give the token to
+        let (args, envs) = command_line(&CloneOptions {
+            credential_url: None,
+            ..options
+        });
+        assert!(args.is_empty());
+        assert_eq!(env(&envs, "DOTME_GIT_TOKEN"), None);
+    }
+
+    #[test]
+    fn test_configured_helper_is_kept_next_to_the_token() {
+        let (args, _) = command_line(&CloneOptions {
+            auth: GitAuthConfig {
+                credential_helper: Some("store".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert_eq!(args, ["-c", "credential.helper=store"]);
+
+        let (args, _) = command_line(&CloneOptions {
+            auth: GitAuthConfig {
+                credential_helper: Some("store".to_string()),
+                token_env: Some("PATH".to_string()),
+                username: Some("me".to_string()),
+                ..Default::default()
+            },
+            credential_url: Some("https://github.com".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(args.len(), 4);
+        assert_eq!(args[..2], ["-c", "credential.helper=store"]);
+        assert!(!args.iter().any(|arg| arg.ends_with(".helper=")));
+        assert!(args[3].starts_with("credential.https://github.com.helper=!f()"));
+    }
+}
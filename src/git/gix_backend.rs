@@ -0,0 +1,380 @@
+//! Pure-Rust git backend built on [gix](https://docs.rs/gix)
+//!
+//! Used when `git.backend: gix` is configured so dotme works on machines without a `git`
+//! binary. Cloning supports branches and shallow clones; pulling fetches from the default
+//! remote and fast-forwards a clean working tree. Anything else (pinned revisions, submodules)
+//! is left to the git CLI.
+
+// Credential callbacks must return gix's (large) error type
+#![allow(clippy::result_large_err)]
+
+use anyhow::{Context, Result};
+use gix::bstr::ByteSlice;
+use gix::remote::Direction;
+use gix::remote::fetch::Shallow;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+use super::CloneOptions;
+use crate::config::GitAuthConfig;
+
+/// Interrupt flag handed to gix operations (dotme never interrupts them)
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+/// Map the configured depth to a gix shallow setting
+fn shallow(options: &CloneOptions) -> Shallow {
+    match options.depth.and_then(NonZeroU32::new) {
+        Some(depth) => Shallow::DepthAtRemote(depth),
+        None => Shallow::NoChange,
+    }
+}
+
+/// Configuration overrides carrying the configured SSH key and credential helper
+fn auth_overrides(auth: &GitAuthConfig) -> Vec<String> {
+    let mut overrides = Vec::new();
+    if let Some(ssh) = super::ssh_command(auth) {
+        overrides.push(format!("core.sshCommand={}", ssh));
+    }
+    if let Some(helper) = &auth.credential_helper {
+        overrides.push(format!("credential.helper={}", helper));
+    }
+    overrides
+}
+
+/// Account built from the configured HTTPS token, with the remote it may be sent to
+fn token_account(options: &CloneOptions) -> Option<(String, gix::sec::identity::Account)> {
+    let url = options.credential_url.clone()?;
+    let auth = &options.auth;
+    let account = gix::sec::identity::Account {
+        username: auth
+            .username
+            .clone()
+            .unwrap_or_else(|| super::DEFAULT_TOKEN_USERNAME.to_string()),
+        password: super::token(auth)?,
+        oauth_refresh_token: None,
+    };
+    Some((url, account))
+}
+
+/// Answer credential requests for `url` with a fixed account
+fn provide(
+    url: &str,
+    account: &gix::sec::identity::Account,
+    action: gix::credentials::helper::Action,
+) -> gix::credentials::protocol::Result {
+    match action {
+        gix::credentials::helper::Action::Get(context)
+            if context
+                .protocol
+                .as_deref()
+                .zip(context.host.as_deref())
+                .is_some_and(|(protocol, host)| format!("{}://{}", protocol, host) == url) =>
+        {
+            Ok(Some(gix::credentials::protocol::Outcome {
+                identity: account.clone(),
+                next: context.into(),
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Clone a repository and check out its main worktree
+pub fn clone(url: &str, target: &Path, options: &CloneOptions) -> Result<()> {
+    let mut prepare = gix::prepare_clone(url, target)
+        .context("Failed to prepare clone")?
+        .with_shallow(shallow(options))
+        .with_in_memory_config_overrides(auth_overrides(&options.auth));
+
+    if let Some((url, account)) = token_account(options) {
+        prepare = prepare.configure_connection(move |connection| {
+            let (url, account) = (url.clone(), account.clone());
+            connection.set_credentials(move |action| provide(&url, &account, action));
+            Ok(())
+        });
+    }
+
+    if let Some(branch) = &options.branch {
+        prepare = prepare
+            .with_ref_name(Some(branch.as_str()))
+            .with_context(|| format!("Invalid branch name '{}'", branch))?;
+    }
+
+    let (mut checkout, _) = prepare
+        .fetch_then_checkout(gix::progress::Discard, &INTERRUPT)
+        .context("Failed to fetch repository")?;
+    checkout
+        .main_worktree(gix::progress::Discard, &INTERRUPT)
+        .context("Failed to check out repository")?;
+
+    Ok(())
+}
+
+/// Fetch from the default remote and fast-forward the current branch
+pub fn pull(repo_path: &Path, options: &CloneOptions) -> Result<()> {
+    let open_options =
+        gix::open::Options::default().config_overrides(auth_overrides(&options.auth));
+    let mut repo = gix::open_opts(repo_path, open_options).context("Failed to open repository")?;
+    // Reflogs need a committer, which machines without git often don't have configured
+    repo.committer_or_set_generic_fallback()?;
+    let remote = repo
+        .find_default_remote(Direction::Fetch)
+        .context("Repository has no remote to fetch from")??;
+
+    let mut connection = remote
+        .connect(Direction::Fetch)
+        .context("Failed to connect to remote")?;
+    if let Some((url, account)) = token_account(options) {
+        connection.set_credentials(move |action| provide(&url, &account, action));
+    }
+
+    connection
+        .prepare_fetch(gix::progress::Discard, Default::default())
+        .context("Failed to prepare fetch")?
+        .with_shallow(shallow(options))
+        .receive(gix::progress::Discard, &INTERRUPT)
+        .context("Failed to fetch from remote")?;
+
+    // Re-open so the updated remote refs are visible
+    let mut repo = gix::open(repo_path).context("Failed to open repository")?;
+    repo.committer_or_set_generic_fallback()?;
+
+    // Detached checkouts (e.g. tags) stay where they are
+    let Some(head_name) = repo.head_name()? else {
+        log::info!("HEAD is detached, not pulling");
+        return Ok(());
+    };
+    if let Some(branch) = &options.branch
+        && head_name.shorten() != branch.as_str()
+    {
+        anyhow::bail!(
+            "Checkout is not on branch {}, switching branches requires the git CLI backend",
+            branch
+        );
+    }
+    let tracking = repo
+        .branch_remote_tracking_ref_name(head_name.as_ref(), Direction::Fetch)
+        .context("Current branch has no upstream")??;
+
+    let head_id = repo.head_id()?.detach();
+    let upstream_id = repo
+        .find_reference(tracking.as_ref())?
+        .peel_to_id()?
+        .detach();
+
+    if head_id == upstream_id {
+        log::info!("✓ Already up to date");
+        return Ok(());
+    }
+
+    if repo.merge_base(head_id, upstream_id)?.detach() != head_id {
+        anyhow::bail!("Local branch has diverged from upstream, cannot fast-forward");
+    }
+
+    if repo
+        .is_dirty()
+        .context("Failed to check working tree status")?
+    {
+        anyhow::bail!("Working tree has local modifications, refusing to fast-forward");
+    }
+
+    let old_tree = repo.find_commit(head_id)?.tree()?;
+    let new_tree = repo.find_commit(upstream_id)?.tree()?;
+
+    let mut diff_options = gix::diff::Options::default().with_rewrites(None);
+    diff_options.track_path();
+    let changes = repo
+        .diff_tree_to_tree(&old_tree, &new_tree, diff_options)
+        .context("Failed to compute changes")?;
+
+    let workdir = repo.workdir().context("Repository has no working tree")?;
+
+    for change in changes {
+        use gix::object::tree::EntryKind;
+        use gix::object::tree::diff::ChangeDetached;
+
+        match change {
+            ChangeDetached::Deletion {
+                location,
+                entry_mode,
+                ..
+            } => {
+                if entry_mode.is_tree() {
+                    continue;
+                }
+                let path = workdir.join(gix::path::from_bstr(location.as_bstr()));
+                if path.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove {}", path.display()))?;
+                }
+                // Clean up directories left empty
+                let mut parent = path.parent();
+                while let Some(dir) = parent.filter(|d| *d != workdir) {
+                    if std::fs::remove_dir(dir).is_err() {
+                        break;
+                    }
+                    parent = dir.parent();
+                }
+            }
+            ChangeDetached::Addition {
+                location,
+                entry_mode,
+                id,
+                ..
+            }
+            | ChangeDetached::Modification {
+                location,
+                entry_mode,
+                id,
+                ..
+            } => {
+                let kind = entry_mode.kind();
+                if matches!(kind, EntryKind::Tree | EntryKind::Commit) {
+                    continue;
+                }
+
+                let path = workdir.join(gix::path::from_bstr(location.as_bstr()));
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                if path.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&path)?;
+                }
+
+                let data = repo.find_object(id)?.detach().data;
+                if kind == EntryKind::Link {
+                    let target = gix::path::from_bstr(data.as_bstr());
+                    #[cfg(unix)]
+                    std::os::unix::fs::symlink(&target, &path)?;
+                    #[cfg(windows)]
+                    std::os::windows::fs::symlink_file(&target, &path)?;
+                } else {
+                    std::fs::write(&path, &data)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+                    #[cfg(unix)]
+                    if kind == EntryKind::BlobExecutable {
+                        use std::os::unix::fs::PermissionsExt;
+                        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+                    }
+                }
+            }
+            ChangeDetached::Rewrite { .. } => {}
+        }
+    }
+
+    // Move the branch and bring the index in line with the new tree
+    repo.reference(
+        head_name,
+        upstream_id,
+        gix::refs::transaction::PreviousValue::MustExistAndMatch(gix::refs::Target::Object(
+            head_id,
+        )),
+        "dotme: fast-forward",
+    )
+    .context("Failed to update branch")?;
+
+    let mut index = repo
+        .index_from_tree(&new_tree.id)
+        .context("Failed to build index")?;
+    index
+        .write(Default::default())
+        .context("Failed to write index")?;
+
+    log::info!("✓ Repository updated successfully");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    /// Run git in `dir` with a fixed identity
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=Jane", "-c", "user.email=jane@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {} failed", args.join(" "));
+    }
+
+    /// Commit `contents` as `file` in the repository at `dir`
+    fn commit(dir: &Path, file: &str, contents: &str) {
+        std::fs::write(dir.join(file), contents).unwrap();
+        git(dir, &["add", file]);
+        git(dir, &["commit", "--quiet", "-m", file]);
+    }
+
+    /// A bare repository with one commit on `main`, and the working copy it was pushed from
+    fn remote(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let (bare, work) = (dir.join("remote.git"), dir.join("work"));
+        std::fs::create_dir_all(&work).unwrap();
+        git(
+            dir,
+            &["init", "--quiet", "--bare", "-b", "main", "remote.git"],
+        );
+        git(&work, &["init", "--quiet", "-b", "main"]);
+        commit(&work, ".zshrc", "v1");
+        git(&work, &["remote", "add", "origin", bare.to_str().unwrap()]);
+        git(&work, &["push", "--quiet", "origin", "main"]);
+        (bare, work)
+    }
+
+    #[test]
+    fn test_pull_fast_forwards_a_clone() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bare, work) = remote(dir.path());
+        let clone_path = dir.path().join("clone");
+        let options = CloneOptions::default();
+
+        clone(bare.to_str().unwrap(), &clone_path, &options).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(clone_path.join(".zshrc")).unwrap(),
+            "v1"
+        );
+
+        commit(&work, ".zshrc", "v2");
+        commit(&work, ".vimrc", "set number");
+        git(&work, &["push", "--quiet", "origin", "main"]);
+
+        pull(&clone_path, &options).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(clone_path.join(".zshrc")).unwrap(),
+            "v2"
+        );
+        assert!(clone_path.join(".vimrc").exists());
+        // Nothing is left over for git to report
+        let status = Command::new("git")
+            .args(["status", "--porcelain"])
+            .current_dir(&clone_path)
+            .output()
+            .unwrap();
+        assert!(status.stdout.is_empty());
+    }
+
+    #[test]
+    fn test_pull_refuses_a_diverged_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bare, work) = remote(dir.path());
+        let clone_path = dir.path().join("clone");
+        let options = CloneOptions::default();
+        clone(bare.to_str().unwrap(), &clone_path, &options).unwrap();
+
+        commit(&work, ".zshrc", "upstream");
+        git(&work, &["push", "--quiet", "origin", "main"]);
+        commit(&clone_path, ".zshrc", "local");
+
+        let error = pull(&clone_path, &options).unwrap_err();
+        assert!(format!("{:#}", error).contains("diverged"));
+        assert_eq!(
+            std::fs::read_to_string(clone_path.join(".zshrc")).unwrap(),
+            "local"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Files kept encrypted with GPG in a source
+//!
+//! A file ending in `.gpg`, such as `.netrc.gpg`, is decrypted with `gpg` (using the running
+//! agent, which asks for the passphrase if it needs to) into `decrypted/` in the dotme
+//! directory, and the link is created for the decrypted copy under the name without `.gpg`.
+//! The cache mirrors the paths of the encrypted files, so a decrypted copy can be traced back to
+//! the file it was made from. Decrypted copies are readable only by their owner, and are made
+//! again only when the encrypted file changes.
+
+use anyhow::{Context, Result};
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::dotfiles;
+
+/// Suffix of encrypted files
+const SUFFIX: &str = ".gpg";
+
+/// Directory the decrypted copies are kept in
+fn cache_dir() -> Result<PathBuf> {
+    Ok(dotfiles::get_dotme_dir()?.join("decrypted"))
+}
+
+/// Check if `path` is an encrypted file
+pub fn is_encrypted(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.len() > SUFFIX.len() && name.ends_with(SUFFIX))
+}
+
+/// Check if there are encrypted files anywhere in the directory `dir`, in which case it can't
+/// be linked as a whole
+pub fn contains_encrypted(dir: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        if path.is_dir() {
+            path.file_name() != Some(".git".as_ref()) && contains_encrypted(&path)
+        } else {
+            is_encrypted(&path)
+        }
+    })
+}
+
+/// `path` inside the cache, e.g. `decrypted/home/user/dotfiles/.netrc` for
+/// `/home/user/dotfiles/.netrc`; a Windows drive becomes a directory named by its letter
+fn mirror(path: &Path) -> Result<PathBuf> {
+    let mut mirrored = cache_dir()?;
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                mirrored.push(prefix.as_os_str().to_string_lossy().replace(':', ""))
+            }
+            Component::Normal(name) => mirrored.push(name),
+            _ => {}
+        }
+    }
+    Ok(mirrored)
+}
+
+/// Where the decrypted copy of the encrypted file `encrypted` is kept
+pub fn decrypted_path(encrypted: &Path) -> Result<PathBuf> {
+    mirror(&encrypted.with_extension(""))
+}
+
+/// The file a link target was made from: the encrypted file for a decrypted copy, the target
+/// itself otherwise
+pub fn source_of(target: &Path) -> PathBuf {
+    let relative = cache_dir()
+        .ok()
+        .and_then(|cache| Some(target.strip_prefix(cache).ok()?.to_path_buf()));
+    let Some(relative) = relative else {
+        return target.to_path_buf();
+    };
+
+    let mut components = relative.components();
+    let root = if cfg!(windows) {
+        match components.next() {
+            Some(drive) => PathBuf::from(format!("{}:\\", drive.as_os_str().to_string_lossy())),
+            None => return target.to_path_buf(),
+        }
+    } else {
+        PathBuf::from("/")
+    };
+    let mut source = root.join(components.as_path()).into_os_string();
+    source.push(SUFFIX);
+    source.into()
+}
+
+/// Decrypt `encrypted` into its place in the cache, unless the copy there is newer, and return
+/// the path of the copy
+pub async fn decrypt(encrypted: &Path) -> Result<PathBuf> {
+    let decrypted = decrypted_path(encrypted)?;
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    if let (Some(copy), Some(original)) = (modified(&decrypted), modified(encrypted))
+        && copy >= original
+    {
+        return Ok(decrypted);
+    }
+
+    log::info!("Decrypting {}", encrypted.display());
+    let output = Command::new("gpg")
+        .args(["--quiet", "--decrypt"])
+        .arg(encrypted)
+        .stdin(std::process::Stdio::inherit())
+        .output()
+        .await
+        .context("Failed to run gpg. Is GnuPG installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to decrypt {}: {}",
+            encrypted.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let parent = decrypted.parent().context("Decrypted copy has no parent")?;
+    fs::create_dir_all(parent)
+        .await
+        .with_context(|| format!("Failed to create {}", parent.display()))?;
+    restrict(&cache_dir()?, 0o700)?;
+    write_private(&decrypted, &output.stdout)
+        .with_context(|| format!("Failed to write {}", decrypted.display()))?;
+    Ok(decrypted)
+}
+
+/// Write `contents` to a new file at `path` readable only by its owner, replacing the old one
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut temp = path.as_os_str().to_os_string();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let _ = std::fs::remove_file(&temp);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)
+}
+
+/// Set the permissions of `path` to `mode`
+#[cfg(unix)]
+fn restrict(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to restrict permissions of {}", path.display()))
+}
+
+/// Permissions are left to the ACLs of the directory on other platforms
+#[cfg(not(unix))]
+fn restrict(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Delete the decrypted copies made from the files in `root`
+pub async fn remove_decrypted(root: &Path) -> Result<()> {
+    let copy = if is_encrypted(root) {
+        decrypted_path(root)?
+    } else {
+        mirror(root)?
+    };
+    let result = if copy.is_dir() {
+        fs::remove_dir_all(&copy).await
+    } else if copy.exists() {
+        fs::remove_file(&copy).await
+    } else {
+        return Ok(());
+    };
+    result.with_context(|| format!("Failed to delete {}", copy.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypted_copies_lead_back_to_their_source() {
+        assert!(is_encrypted(Path::new("/dots/.netrc.gpg")));
+        assert!(!is_encrypted(Path::new("/dots/.gpg")));
+        assert!(!is_encrypted(Path::new("/dots/gpg.conf")));
+
+        let source = std::env::temp_dir().join("dots").join(".netrc.gpg");
+        let decrypted = decrypted_path(&source).unwrap();
+        assert!(decrypted.starts_with(cache_dir().unwrap()));
+        assert_eq!(decrypted.file_name().unwrap(), ".netrc");
+        assert_eq!(source_of(&decrypted), source);
+
+        let plain = Path::new("/dots/.bashrc");
+        assert_eq!(source_of(plain), plain);
+    }
+}
@@ -0,0 +1,182 @@
+//! Health scores of entries, for `dotme status --health`
+//!
+//! Every problem dotme can detect with an entry (a missing source, broken or replaced links,
+//! copies edited in place, uncommitted changes, upstream commits not pulled yet, links not
+//! verified in a while) costs it points out of 100. Entries are listed worst first, followed by
+//! the problems sorted by how much they cost, each with the command that fixes it.
+
+use std::fmt::Write;
+
+/// Something wrong with an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The source isn't on this machine
+    MissingSource,
+    /// Links that are gone or point somewhere else
+    BrokenLinks(usize),
+    /// Symlinks an app replaced with a regular file
+    ReplacedLinks(usize),
+    /// Copies that were edited in place, drifting from their source
+    DriftedCopies(usize),
+    /// The clone has uncommitted changes
+    DirtyClone,
+    /// The clone has local commits that weren't pushed
+    Unpushed(usize),
+    /// The upstream has commits that weren't pulled, as of the last fetch
+    Behind(usize),
+    /// Links not verified within the stale age
+    StaleLinks(usize),
+}
+
+impl Problem {
+    /// Points the problem costs, out of 100
+    pub fn cost(&self) -> u32 {
+        // Problems with many links are capped so they don't drown out everything else
+        let per = |count: usize, each: u32, cap: u32| (count as u32).saturating_mul(each).min(cap);
+        match self {
+            Problem::MissingSource => 50,
+            Problem::BrokenLinks(count) => per(*count, 10, 40),
+            Problem::ReplacedLinks(count) => per(*count, 10, 30),
+            Problem::DriftedCopies(count) => per(*count, 5, 20),
+            Problem::DirtyClone => 10,
+            Problem::Behind(_) => 10,
+            Problem::Unpushed(_) => 5,
+            Problem::StaleLinks(count) => per(*count, 2, 10),
+        }
+    }
+
+    /// What is wrong, e.g. `2 broken link(s)`
+    pub fn describe(&self) -> String {
+        match self {
+            Problem::MissingSource => "source is missing".to_string(),
+            Problem::BrokenLinks(count) => format!("{} broken link(s)", count),
+            Problem::ReplacedLinks(count) => {
+                format!("{} link(s) replaced by regular files", count)
+            }
+            Problem::DriftedCopies(count) => format!("{} copy(ies) edited in place", count),
+            Problem::DirtyClone => "uncommitted changes".to_string(),
+            Problem::Unpushed(count) => format!("{} commit(s) not pushed", count),
+            Problem::Behind(count) => format!("{} upstream commit(s) not pulled", count),
+            Problem::StaleLinks(count) => format!("{} link(s) not verified recently", count),
+        }
+    }
+
+    /// Command that fixes the problem for the entry with `source`
+    pub fn fix(&self, source: &str, stale_after: &str) -> String {
+        match self {
+            Problem::MissingSource | Problem::Behind(_) => "dotme update".to_string(),
+            Problem::BrokenLinks(_) => "dotme verify --fix".to_string(),
+            Problem::ReplacedLinks(_) | Problem::DriftedCopies(_) => "dotme syncback".to_string(),
+            Problem::DirtyClone | Problem::Unpushed(_) => format!("dotme push {}", source),
+            Problem::StaleLinks(_) => format!("dotme verify --stale {}", stale_after),
+        }
+    }
+}
+
+/// Problems found with an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Health {
+    /// Source of the entry
+    pub source: String,
+    /// Everything wrong with it
+    pub problems: Vec<Problem>,
+}
+
+impl Health {
+    /// Score out of 100; 100 means nothing is wrong
+    pub fn score(&self) -> u32 {
+        100u32.saturating_sub(self.problems.iter().map(Problem::cost).sum())
+    }
+}
+
+/// Render the scores of `entries`, worst first, and the problems to fix in order
+pub fn render(entries: &[Health], stale_after: &str) -> Result<String, std::fmt::Error> {
+    let mut out = String::new();
+    let mut entries: Vec<&Health> = entries.iter().collect();
+    entries.sort_by_key(|health| health.score());
+
+    writeln!(out, "Entry Health:")?;
+    writeln!(out, "─────────────────────────────────────────")?;
+    for health in &entries {
+        let marker = match health.score() {
+            100 => "✓",
+            70.. => "⚠",
+            _ => "✗",
+        };
+        writeln!(
+            out,
+            "  {} {:>3}/100  {}",
+            marker,
+            health.score(),
+            health.source
+        )?;
+    }
+
+    let mut fixes: Vec<(&Health, &Problem)> = entries
+        .iter()
+        .flat_map(|health| {
+            health
+                .problems
+                .iter()
+                .map(move |problem| (*health, problem))
+        })
+        .collect();
+    if fixes.is_empty() {
+        writeln!(out, "\nNothing to fix.")?;
+        return Ok(out);
+    }
+
+    // Stable sort, so equally costly problems stay grouped by the worst entry first
+    fixes.sort_by_key(|(_, problem)| std::cmp::Reverse(problem.cost()));
+    writeln!(out, "\nFix these first:")?;
+    for (index, (health, problem)) in fixes.iter().enumerate() {
+        writeln!(
+            out,
+            "  {}. {}: {}",
+            index + 1,
+            health.source,
+            problem.describe()
+        )?;
+        writeln!(out, "     → {}", problem.fix(&health.source, stale_after))?;
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scores_and_fix_order() {
+        let healthy = Health {
+            source: "healthy".to_string(),
+            problems: vec![],
+        };
+        let broken = Health {
+            source: "broken".to_string(),
+            problems: vec![Problem::StaleLinks(2), Problem::BrokenLinks(10)],
+        };
+        let missing = Health {
+            source: "missing".to_string(),
+            problems: vec![Problem::MissingSource, Problem::DirtyClone],
+        };
+        assert_eq!(healthy.score(), 100);
+        // Broken links are capped at 40 points
+        assert_eq!(broken.score(), 56);
+        assert_eq!(missing.score(), 40);
+
+        let out = render(&[healthy, broken, missing], "30d").unwrap();
+        let positions: Vec<usize> = [
+            "1. missing: source",
+            "2. broken: 10",
+            "3. missing: uncommitted",
+            "4. broken: 2",
+        ]
+        .iter()
+        .map(|needle| out.find(needle).unwrap())
+        .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(out.contains("→ dotme verify --stale 30d"));
+    }
+}
@@ -0,0 +1,70 @@
+//! Values that belong to one machine, kept out of the shared config
+//!
+//! `host.yml` in the dotme directory holds what only this machine knows, such as a proxy URL,
+//! the screen DPI, or whether it is a work machine. dotme never writes it and it isn't part of
+//! the config, exports, or any repository. Its values are [variables](crate::variables) that
+//! take the place of those of the same name in the config, and `when: host:` conditions match
+//! them.
+//!
+//! ```yaml
+//! proxy: http://proxy.corp.example:3128
+//! dpi: 144
+//! work: true
+//! ```
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::dotfiles;
+
+/// Path of the host file in the dotme directory
+pub fn path() -> Result<PathBuf> {
+    Ok(dotfiles::get_dotme_dir()?.join("host.yml"))
+}
+
+/// Values of the host file, as text; none if there is no host file
+pub fn load() -> Result<BTreeMap<String, String>> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Parse the contents of a host file, which maps names to single values
+fn parse(contents: &str) -> Result<BTreeMap<String, String>> {
+    let values: Option<BTreeMap<String, serde_yaml::Value>> = serde_yaml::from_str(contents)?;
+    values
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_yaml::Value::Null => String::new(),
+                serde_yaml::Value::Bool(value) => value.to_string(),
+                serde_yaml::Value::Number(value) => value.to_string(),
+                serde_yaml::Value::String(value) => value,
+                _ => anyhow::bail!("'{}' has to be a single value, not a list or map", name),
+            };
+            Ok((name, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_values_are_text() {
+        let values = parse("proxy: http://proxy:3128\ndpi: 144\nwork: true\nnote:\n").unwrap();
+        assert_eq!(values["proxy"], "http://proxy:3128");
+        assert_eq!(values["dpi"], "144");
+        assert_eq!(values["work"], "true");
+        assert_eq!(values["note"], "");
+        assert!(parse("").unwrap().is_empty());
+        assert!(parse("screens: [1, 2]").is_err());
+    }
+}
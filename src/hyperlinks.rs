@@ -0,0 +1,209 @@
+//! Clickable paths and URLs in terminal output
+//!
+//! `status`, `show`, and `list` wrap file paths and repository URLs in
+//! [OSC 8](https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda) hyperlinks, so
+//! a misbehaving file or the upstream repository is one click away. Links are only emitted when
+//! stdout is a terminal known to support them; `FORCE_HYPERLINK=1` (or `0`) overrides the
+//! detection, and the `hyperlinks` feature turns them off entirely:
+//!
+//! ```yaml
+//! features:
+//!   hyperlinks: false
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::features;
+
+/// Wraps text in hyperlinks when the terminal supports them
+#[derive(Debug, Clone)]
+pub struct Hyperlinks {
+    /// Whether links are emitted at all
+    enabled: bool,
+    /// Host named in `file://` URLs, so terminals don't open paths of remote machines locally
+    host: String,
+}
+
+impl Hyperlinks {
+    /// Hyperlinks for stdout, if the feature is enabled and the terminal supports them
+    pub fn new(config: &Config) -> Self {
+        let env: BTreeMap<String, String> = std::env::vars().collect();
+        Self {
+            enabled: config.feature_enabled(features::HYPERLINKS)
+                && supported(&env, console::Term::stdout().is_term()),
+            host: crate::conditions::hostname().unwrap_or_default(),
+        }
+    }
+
+    /// Link a path to its `file://` URL, showing the path itself
+    pub fn path(&self, path: &Path) -> String {
+        let text = path.display().to_string();
+        if !self.enabled || !path.is_absolute() {
+            return text;
+        }
+
+        self.link(&format!("file://{}{}", self.host, encode_path(path)), &text)
+    }
+
+    /// Link a source to the web page of its repository, or to the file it names
+    pub fn source(&self, source: &str) -> String {
+        match web_url(source) {
+            Some(url) => self.link(&url, source),
+            None => self.path(Path::new(source)),
+        }
+    }
+
+    /// Wrap `text` in an OSC 8 hyperlink to `url`
+    fn link(&self, url: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Check if a terminal supports hyperlinks, judging by its environment
+fn supported(env: &BTreeMap<String, String>, is_term: bool) -> bool {
+    let var = |name: &str| env.get(name).map(String::as_str);
+
+    if let Some(force) = var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if !is_term || var("TERM") == Some("dumb") || env.contains_key("CI") {
+        return false;
+    }
+
+    if ["WT_SESSION", "KONSOLE_VERSION", "DOMTERM"]
+        .iter()
+        .any(|name| env.contains_key(*name))
+    {
+        return true;
+    }
+    // GNOME Terminal and other VTE-based terminals since 0.50
+    if var("VTE_VERSION")
+        .and_then(|version| version.parse::<u32>().ok())
+        .is_some_and(|version| version >= 5000)
+    {
+        return true;
+    }
+    if var("TERM_PROGRAM").is_some_and(|program| {
+        [
+            "iTerm.app",
+            "WezTerm",
+            "vscode",
+            "Hyper",
+            "ghostty",
+            "Tabby",
+        ]
+        .contains(&program)
+    }) {
+        return true;
+    }
+
+    var("TERM").is_some_and(|term| {
+        ["kitty", "alacritty", "foot", "wezterm", "ghostty"]
+            .iter()
+            .any(|name| term.contains(name))
+    })
+}
+
+/// Web page of a git repository URL (`https://`, `ssh://`, or `git@host:path`)
+fn web_url(source: &str) -> Option<String> {
+    if source.starts_with("https://") || source.starts_with("http://") {
+        return Some(source.trim_end_matches(".git").to_string());
+    }
+
+    let (host, path) = if let Some(rest) = source.strip_prefix("ssh://") {
+        let rest = rest.split_once('@').map_or(rest, |(_, rest)| rest);
+        let (host, path) = rest.split_once('/')?;
+        // Drop a port, which belongs to SSH rather than the web server
+        (host.split(':').next().unwrap_or(host), path)
+    } else {
+        let rest = source.strip_prefix("git@")?;
+        rest.split_once(':')?
+    };
+
+    Some(format!(
+        "https://{}/{}",
+        host,
+        path.trim_end_matches(".git")
+    ))
+}
+
+/// Percent-encode a path for a `file://` URL
+fn encode_path(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut encoded = String::new();
+    if !path.starts_with('/') {
+        // Windows paths like C:/Users
+        encoded.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> BTreeMap<String, String> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_terminal_detection() {
+        assert!(supported(&env(&[("TERM_PROGRAM", "WezTerm")]), true));
+        assert!(supported(&env(&[("VTE_VERSION", "7600")]), true));
+        assert!(supported(&env(&[("TERM", "xterm-kitty")]), true));
+        assert!(!supported(&env(&[("VTE_VERSION", "4800")]), true));
+        assert!(!supported(&env(&[("TERM", "xterm-256color")]), true));
+        assert!(!supported(&env(&[("TERM_PROGRAM", "WezTerm")]), false));
+        assert!(!supported(
+            &env(&[("TERM_PROGRAM", "vscode"), ("CI", "1")]),
+            true
+        ));
+
+        assert!(supported(&env(&[("FORCE_HYPERLINK", "1")]), false));
+        assert!(!supported(
+            &env(&[("FORCE_HYPERLINK", "0"), ("TERM", "kitty")]),
+            true
+        ));
+    }
+
+    #[test]
+    fn test_repository_web_urls() {
+        assert_eq!(
+            web_url("https://github.com/user/dotfiles.git").as_deref(),
+            Some("https://github.com/user/dotfiles")
+        );
+        assert_eq!(
+            web_url("git@github.com:user/dotfiles.git").as_deref(),
+            Some("https://github.com/user/dotfiles")
+        );
+        assert_eq!(
+            web_url("ssh://git@gitlab.example.com:2222/team/dots").as_deref(),
+            Some("https://gitlab.example.com/team/dots")
+        );
+        assert_eq!(web_url("/home/user/dotfiles"), None);
+    }
+
+    #[test]
+    fn test_paths_are_percent_encoded() {
+        assert_eq!(
+            encode_path(Path::new("/home/user/My Files/.vimrc")),
+            "/home/user/My%20Files/.vimrc"
+        );
+    }
+}
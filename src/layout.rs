@@ -0,0 +1,285 @@
+//! Where dotme keeps its own files
+//!
+//! By default the config, clones, and state all live in `~/.dotme`. With the XDG layout they
+//! follow the [XDG base directory spec](https://specifications.freedesktop.org/basedir-spec/latest/)
+//! instead:
+//!
+//! | What                                             | XDG layout                                        |
+//! |--------------------------------------------------|---------------------------------------------------|
+//! | `config.yml`                                     | `$XDG_CONFIG_HOME/dotme` (`~/.config/dotme`)      |
+//! | clones, mirrors, archive, symlink state, caches  | `$XDG_STATE_HOME/dotme` (`~/.local/state/dotme`)  |
+//!
+//! The XDG layout is used once `$XDG_CONFIG_HOME/dotme/config.yml` exists, or whenever
+//! `DOTME_XDG=1` is set; `DOTME_XDG=0` forces `~/.dotme`. Setting `DOTME_XDG=1`, or `xdg: true`
+//! in `~/.dotme/config.yml`, moves an existing `~/.dotme` over on the next run and points the
+//! links into it at the new location.
+//!
+//! `--root <dir>` (or `DOTME_ROOT`) keeps a separate setup, e.g. for a client's dotfiles, entirely
+//! in `<dir>`: its own config, clones, and symlink state, whatever the layout of the default one.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::fs;
+
+use crate::config::{Config, LinkMode};
+use crate::symlinks::{self, SymlinkState};
+
+/// Environment variable choosing the layout
+const XDG_ENV: &str = "DOTME_XDG";
+
+/// Directory of a separate setup chosen with `--root`, holding all of its files
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keep every file of this run in `root` instead of the default directories
+pub fn set_root(root: &Path) -> Result<()> {
+    let root = std::path::absolute(crate::config::expand_home(root))
+        .with_context(|| format!("Invalid root {}", root.display()))?;
+    if ROOT.get().is_some_and(|set| *set != root) {
+        anyhow::bail!(
+            "The root was already set to {}",
+            ROOT.get().unwrap().display()
+        );
+    }
+    let _ = ROOT.set(root);
+    Ok(())
+}
+
+/// Directory of the separate setup chosen with `--root`, if any
+pub fn root() -> Option<&'static Path> {
+    ROOT.get().map(PathBuf::as_path)
+}
+
+/// Home directory of the current user
+fn home() -> Result<PathBuf> {
+    dirs::home_dir().context("Failed to get home directory")
+}
+
+/// The `~/.dotme` directory of the default layout
+fn legacy_dir() -> Result<PathBuf> {
+    Ok(home()?.join(".dotme"))
+}
+
+/// `$<var>/dotme`, or `~/<fallback>/dotme` if the variable isn't set to an absolute path
+fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+    let base = std::env::var_os(var)
+        .map(PathBuf::from)
+        // Relative paths are invalid according to the spec and must be ignored
+        .filter(|dir| dir.is_absolute());
+    let base = match base {
+        Some(base) => base,
+        None => home()?.join(fallback),
+    };
+    Ok(base.join("dotme"))
+}
+
+/// Config directory of the XDG layout
+fn xdg_config_dir() -> Result<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// State directory of the XDG layout
+fn xdg_state_dir() -> Result<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Layout asked for with `DOTME_XDG`, if it is set
+fn requested() -> Option<bool> {
+    let value = std::env::var(XDG_ENV).ok()?;
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => {
+            log::warn!("Ignoring {}={}: expected 1 or 0", XDG_ENV, value);
+            None
+        }
+    }
+}
+
+/// Check if the XDG layout is used
+pub fn is_xdg() -> bool {
+    requested().unwrap_or_else(|| xdg_config_dir().is_ok_and(|dir| dir.join("config.yml").exists()))
+}
+
+/// Directory holding `config.yml`
+pub fn config_dir() -> Result<PathBuf> {
+    if let Some(root) = root() {
+        Ok(root.to_path_buf())
+    } else if is_xdg() {
+        xdg_config_dir()
+    } else {
+        legacy_dir()
+    }
+}
+
+/// Directory holding the clones, the symlink state, and everything else dotme keeps
+pub fn state_dir() -> Result<PathBuf> {
+    if let Some(root) = root() {
+        Ok(root.to_path_buf())
+    } else if is_xdg() {
+        xdg_state_dir()
+    } else {
+        legacy_dir()
+    }
+}
+
+/// Replace the `from` prefix of `path` with `to`, if it has one
+fn rebase(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    path.strip_prefix(from).ok().map(|rest| to.join(rest))
+}
+
+/// Move `~/.dotme` to the XDG directories if `DOTME_XDG=1` or `xdg: true` asks for it
+pub async fn migrate_if_requested() -> Result<()> {
+    let legacy = legacy_dir()?;
+    let legacy_config = legacy.join("config.yml");
+    if root().is_some() || requested() == Some(false) || !legacy_config.exists() {
+        return Ok(());
+    }
+
+    let config_dir = xdg_config_dir()?;
+    if config_dir.join("config.yml").exists() {
+        log::warn!(
+            "{} is no longer used, as {} exists. Remove it once nothing in it is needed.",
+            legacy.display(),
+            config_dir.join("config.yml").display()
+        );
+        return Ok(());
+    }
+
+    let config = Config::load(Some(legacy_config))?;
+    if requested() != Some(true) && !config.xdg {
+        return Ok(());
+    }
+
+    migrate(&legacy, &config_dir, &xdg_state_dir()?).await
+}
+
+/// Move the files of `legacy` to `config_dir` and `state_dir`, and repoint everything at them
+async fn migrate(legacy: &Path, config_dir: &Path, state_dir: &Path) -> Result<()> {
+    log::info!(
+        "Moving {} to {} and {}",
+        legacy.display(),
+        config_dir.display(),
+        state_dir.display()
+    );
+
+    if state_dir.exists() {
+        // An empty directory left behind is fine, anything else might be overwritten
+        fs::remove_dir(state_dir).await.with_context(|| {
+            format!(
+                "{} already exists and isn't empty. Move its contents away and try again.",
+                state_dir.display()
+            )
+        })?;
+    }
+    for dir in [Some(config_dir), state_dir.parent()].into_iter().flatten() {
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // A single rename keeps the clones intact; it fails across filesystems rather than copying
+    fs::rename(legacy, state_dir).await.with_context(|| {
+        format!(
+            "Failed to move {} to {}. If they are on different filesystems, move it by hand.",
+            legacy.display(),
+            state_dir.display()
+        )
+    })?;
+    let config_path = config_dir.join("config.yml");
+    fs::rename(state_dir.join("config.yml"), &config_path)
+        .await
+        .with_context(|| format!("Failed to move the config to {}", config_path.display()))?;
+
+    // Clones kept in the old directory are referenced by the config and the links
+    let mut config = Config::load(Some(config_path.clone()))?;
+    for entry in &mut config.dotfiles {
+        for path in [&mut entry.path, &mut entry.clone_path]
+            .into_iter()
+            .flatten()
+            .chain([&mut entry.target])
+        {
+            if let Some(moved) = rebase(path, legacy, state_dir) {
+                *path = moved;
+            }
+        }
+    }
+    for path in [
+        &mut config.paths.dotme_dir,
+        &mut config.paths.git_dir,
+        &mut config.paths.symlinks_file,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if let Some(moved) = rebase(path, legacy, state_dir) {
+            *path = moved;
+        }
+    }
+    config.save(&config_path)?;
+
+    let mut relinked = 0;
+    for entry in SymlinkState::load().await?.symlinks {
+        let Some(target) = rebase(&entry.target, legacy, state_dir) else {
+            continue;
+        };
+        if !matches!(entry.mode, LinkMode::Symlink | LinkMode::Junction) {
+            continue;
+        }
+
+        let relative = std::fs::read_link(&entry.link).is_ok_and(|link| link.is_relative());
+        let result = async {
+            symlinks::remove_symlink(&entry.link).await?;
+            symlinks::create_symlink(&entry.link, &target, relative).await
+        }
+        .await;
+        match result {
+            Ok(()) => relinked += 1,
+            Err(e) => log::warn!(
+                "Failed to point {} at {}: {}",
+                entry.link.display(),
+                target.display(),
+                e
+            ),
+        }
+    }
+
+    // Copies and hardlinks stay where they are, only the source they came from moved
+    let mut state = SymlinkState::load().await?;
+    for entry in &mut state.symlinks {
+        if let Some(target) = rebase(&entry.target, legacy, state_dir) {
+            entry.target = target;
+        }
+    }
+    state.save().await?;
+
+    log::info!(
+        "✓ Moved dotme to {} and {} ({} link(s) updated)",
+        config_dir.display(),
+        state_dir.display(),
+        relinked
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_are_rebased_into_the_new_directory() {
+        let (legacy, state) = (
+            Path::new("/home/u/.dotme"),
+            Path::new("/home/u/.local/state/dotme"),
+        );
+        assert_eq!(
+            rebase(Path::new("/home/u/.dotme/git/dots/.vimrc"), legacy, state),
+            Some(PathBuf::from("/home/u/.local/state/dotme/git/dots/.vimrc"))
+        );
+        assert_eq!(
+            rebase(Path::new("/home/u/.dotmex/git"), legacy, state),
+            None
+        );
+        assert_eq!(rebase(Path::new("/srv/dots"), legacy, state), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Keeping two dotme runs from changing things at the same time
+//!
+//! Commands that change links, clones, or the symlink state first take an advisory lock on
+//! `.lock` in the dotme directory, so e.g. a cron `dotme update` and one started by hand can't
+//! interleave their writes to `symlinks.yml`. A second run waits for the first one to finish, for
+//! up to a minute. The operating system releases the lock when the process exits, however it
+//! exits, so it never has to be cleaned up.
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::layout;
+
+/// How long to wait for another run to finish
+const WAIT: Duration = Duration::from_secs(60);
+
+/// How often to check whether the other run has finished
+const POLL: Duration = Duration::from_millis(200);
+
+/// Exclusive lock on the dotme directory, released when dropped
+#[derive(Debug)]
+pub struct Lock {
+    /// The locked `.lock` file
+    file: File,
+}
+
+/// Process holding the lock at `path`, e.g. ` (pid 1234)`, if it is recorded
+fn holder(path: &Path) -> String {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .map(|pid| format!(" (pid {})", pid))
+        .unwrap_or_default()
+}
+
+/// Take the lock on the dotme directory, waiting for another run holding it to finish; `None`
+/// if dotme isn't initialized, as there is nothing to protect yet
+pub async fn acquire() -> Result<Option<Lock>> {
+    let dir = layout::state_dir()?;
+    if !dir.exists() {
+        return Ok(None);
+    }
+
+    let path = dir.join(".lock");
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) if started.elapsed() < WAIT => {
+                if started.elapsed() < POLL {
+                    log::info!(
+                        "Waiting for another dotme run{} to finish...",
+                        holder(&path)
+                    );
+                }
+                tokio::time::sleep(POLL).await;
+            }
+            Err(TryLockError::WouldBlock) => anyhow::bail!(
+                "Another dotme run{} is still going. Try again once it has finished.",
+                holder(&path)
+            ),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        }
+    }
+
+    // Tell a run waiting for the lock who holds it
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    log::debug!("Locked {}", path.display());
+    Ok(Some(Lock { file }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".lock");
+        let open = || {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .unwrap()
+        };
+
+        let first = open();
+        first.try_lock().unwrap();
+        std::fs::write(&path, "1234").unwrap();
+        assert!(matches!(open().try_lock(), Err(TryLockError::WouldBlock)));
+        assert_eq!(holder(&path), " (pid 1234)");
+
+        drop(first);
+        assert!(open().try_lock().is_ok());
+    }
+}
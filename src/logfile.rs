@@ -0,0 +1,247 @@
+//! Keeping a copy of the log in a file, for runs nobody watches
+//!
+//! With `logging.file: true`, everything dotme logs is also appended to `logs/dotme.log` in the
+//! dotme directory, so a failed update run by cron or a systemd timer can be looked into later.
+//! When the file grows past `logging.max_size` (default: 1M), it is renamed to `dotme.log.1`, the
+//! older ones move up a number, and the oldest beyond `logging.keep` (default: 5) is deleted.
+//!
+//! ```yaml
+//! logging:
+//!   file: true
+//!   max_size: 10M
+//!   keep: 3
+//! ```
+
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::LoggingConfig;
+use crate::layout;
+use crate::progress;
+use crate::redact;
+
+/// Size after which the log file is rotated unless configured otherwise
+const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Number of rotated log files kept unless configured otherwise
+const DEFAULT_KEEP: usize = 5;
+
+/// Parse a size such as `512K`, `10M`, or `1G` (a plain number is bytes)
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid size '{}', expected e.g. 10M", value))?;
+
+    let factor = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => anyhow::bail!("Invalid size unit '{}', expected one of K, M, G", unit),
+    };
+    Ok(number.saturating_mul(factor))
+}
+
+/// Log file that is rotated once it grows past a size
+pub struct LogFile {
+    /// Path of the current log file
+    path: PathBuf,
+    /// Size after which the file is rotated
+    max_size: u64,
+    /// Number of rotated files kept
+    keep: usize,
+    /// The open current file
+    file: File,
+    /// Size of the current file
+    size: u64,
+}
+
+/// Path of the `n`th rotated log file, e.g. `dotme.log.2`
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", n));
+    path.with_file_name(name)
+}
+
+/// Open `path` for appending
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+impl LogFile {
+    /// Open the log file at `path`, creating its directory
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = append(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    /// Move the current file to `.1`, shifting the older ones up and dropping the oldest
+    fn rotate(&mut self) -> Result<()> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        let _ = std::fs::remove_file(rotated_path(&self.path, self.keep));
+        for n in (1..self.keep).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.file = append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Append a line, rotating first if it would grow the file past its size
+    pub fn write_line(&mut self, line: &str) -> Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// Logger writing to the console above any spinners and, if configured, to the log file
+struct Tee {
+    /// Logger for the console, which also decides what is logged
+    console: env_logger::Logger,
+    /// The log file
+    file: Option<Mutex<LogFile>>,
+}
+
+impl log::Log for Tee {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.console.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.console.matches(record) {
+            return;
+        }
+        let message = redact::redact(&record.args().to_string());
+        progress::suspend(|| {
+            self.console.log(
+                &log::Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(record.level())
+                    .target(record.target())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            )
+        });
+
+        let Some(file) = &self.file else {
+            return;
+        };
+        let line = format!(
+            "[{} {:<5} {}] {}",
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            record.level(),
+            record.target(),
+            message
+        );
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        // Logging can't report its own failures anywhere but the console
+        if let Err(e) = file.write_line(&line) {
+            eprintln!("Failed to write to {}: {:#}", file.path.display(), e);
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().map(|mut file| file.file.flush());
+        }
+    }
+}
+
+/// Path of the log file in the dotme directory
+pub fn path() -> Result<PathBuf> {
+    Ok(layout::state_dir()?.join("logs").join("dotme.log"))
+}
+
+/// Install `console` as the logger, copying what it logs to the log file if `config` asks for
+/// it; problems with the log file are returned, to be logged once the logger is installed
+pub fn init(console: env_logger::Logger, config: &LoggingConfig) -> Option<anyhow::Error> {
+    let level = console.filter();
+    let file = config.file.then(|| {
+        let max_size = match &config.max_size {
+            Some(size) => parse_size(size)?,
+            None => DEFAULT_MAX_SIZE,
+        };
+        LogFile::open(&path()?, max_size, config.keep.unwrap_or(DEFAULT_KEEP))
+    });
+
+    let (file, error) = match file {
+        Some(Ok(file)) => (Some(Mutex::new(file)), None),
+        Some(Err(e)) => (None, Some(e.context("Not logging to a file"))),
+        None => (None, None),
+    };
+    log::set_boxed_logger(Box::new(Tee { console, file }))
+        .expect("the logger is only installed once");
+    log::set_max_level(level);
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes_are_parsed() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10M").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("2kb").unwrap(), 2048);
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn test_log_file_is_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs").join("dotme.log");
+        let mut log = LogFile::open(&path, 10, 2).unwrap();
+
+        for line in ["first", "second", "third", "fourth"] {
+            log.write_line(line).unwrap();
+        }
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "third\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "second\n");
+        assert!(!rotated_path(&path, 3).exists());
+    }
+}
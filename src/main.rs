@@ -6,11 +6,45 @@
 use anyhow::Result;
 use log::{debug, error};
 
+mod atomic;
+mod baseline;
+mod bundle;
 mod cli;
+mod comments;
+mod completions;
+mod conditions;
 mod config;
+mod diskspace;
 mod dotfiles;
+mod encryption;
+mod features;
 mod git;
+mod gpg;
+mod health;
+mod history;
+mod host;
+mod hyperlinks;
+mod layout;
+mod lock;
+mod logfile;
+mod manifest;
+mod migrate;
+mod notify;
+mod outdated;
+mod packages;
+mod progress;
+mod prompt;
+mod redact;
+mod schedule;
+mod scripts;
+mod secrets;
+mod snapshot;
+mod sources;
+mod stats;
 mod symlinks;
+mod trace;
+mod validate;
+mod variables;
 
 use crate::cli::*;
 
@@ -19,66 +53,509 @@ async fn main() -> Result<()> {
     let arguments = init();
     debug!("Finished initialising, starting main workflow...");
 
+    if let Err(e) = layout::migrate_if_requested().await {
+        error!("Failed to move dotme to the XDG directories: {}", e);
+        fail();
+    }
+
+    // Held until the command is done
+    let _lock = if arguments.changes_state() {
+        match lock::acquire().await {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("{}", e);
+                fail();
+            }
+        }
+    } else {
+        None
+    };
+    if arguments.changes_state() {
+        history::begin();
+    }
+
     // Handle subcommands
     match &arguments.commands {
         None => {
             // No subcommand provided - show status
-            if let Err(e) = dotfiles::status().await {
+            if let Err(e) = dotfiles::status(None, false, false, Vec::new(), Vec::new()).await {
                 error!("Error: {}", e);
-                std::process::exit(1);
+                fail();
             }
         }
         Some(ArgumentCommands::Init) => {
             if let Err(e) = dotfiles::init().await {
                 error!("Failed to initialize: {}", e);
-                std::process::exit(1);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Add {
+            from_config: Some(file),
+            dry_run,
+            ..
+        }) => {
+            if let Err(e) = dotfiles::add_from_config(file, *dry_run).await {
+                error!("Failed to add dotfiles from config: {}", e);
+                fail();
             }
         }
         Some(ArgumentCommands::Add {
             source,
+            name,
+            tags,
             target,
             path,
             folders,
+            exclude,
+            include,
+            branch,
+            rev,
+            depth,
+            clone_path,
+            no_submodules,
+            description,
+            notes,
+            on_conflict,
+            force,
+            relative,
+            mode,
+            link_depth,
+            naming,
+            update,
+            priority,
+            depends_on,
+            os,
             dry_run,
+            from_config: None,
         }) => {
-            if let Err(e) = dotfiles::add(
-                source,
-                target.clone(),
-                path.clone(),
-                folders.clone(),
-                *dry_run,
-            )
-            .await
-            {
+            let options = dotfiles::AddOptions {
+                source: source.clone().unwrap_or_default(),
+                name: name.clone(),
+                tags: tags.clone(),
+
+                target: target.clone(),
+                path: path.clone(),
+                folders: folders.clone(),
+                exclude: exclude.clone(),
+                include: include.clone(),
+                branch: branch.clone(),
+                rev: rev.clone(),
+                depth: *depth,
+                clone_path: clone_path.clone(),
+                no_submodules: *no_submodules,
+                description: description.clone(),
+                notes: notes.clone(),
+                on_conflict: *on_conflict,
+                force: *force,
+                relative: *relative,
+                mode: *mode,
+                link_depth: *link_depth,
+                naming: *naming,
+                update: *update,
+                priority: *priority,
+                depends_on: depends_on.clone(),
+                os: os.clone(),
+
+                dry_run: *dry_run,
+            };
+
+            if let Err(e) = dotfiles::add(options).await {
                 error!("Failed to add dotfile: {}", e);
-                std::process::exit(1);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Bootstrap {
+            url,
+            file,
+            branch,
+            token_env,
+            force,
+            jobs,
+        }) => {
+            if let Err(e) =
+                dotfiles::bootstrap(url, file, branch.clone(), token_env.clone(), *force, *jobs)
+                    .await
+            {
+                error!("Failed to bootstrap dotme: {}", e);
+                fail();
             }
         }
-        Some(ArgumentCommands::Update { dry_run }) => {
-            if let Err(e) = dotfiles::update(*dry_run).await {
-                error!("Failed to update dotfiles: {}", e);
-                std::process::exit(1);
+        Some(ArgumentCommands::Update {
+            sources,
+            only,
+            tags,
+            dry_run,
+            force,
+            relative,
+            jobs,
+            no_submodules,
+            summary,
+        }) => {
+            let options = dotfiles::UpdateOptions {
+                dry_run: *dry_run,
+                force: *force,
+                relative: *relative,
+                jobs: *jobs,
+                no_submodules: *no_submodules,
+                sources: sources.clone(),
+                only: only.clone(),
+                tags: tags.clone(),
+            };
+            match dotfiles::update(options).await {
+                Ok(result) if *summary => {
+                    println!("{}", result);
+                    if result.exit_code() != 0 {
+                        exit(result.exit_code());
+                    }
+                }
+                Ok(result) => {
+                    if let Err(e) = result.into_result() {
+                        error!("Failed to update dotfiles: {}", e);
+                        fail();
+                    }
+                }
+                Err(e) => {
+                    if *summary {
+                        println!("update failed: {}", redact::redact(&e.to_string()));
+                    }
+                    error!("Failed to update dotfiles: {}", e);
+                    fail();
+                }
             }
         }
-        Some(ArgumentCommands::Status) => {
-            if let Err(e) = dotfiles::status().await {
+        Some(ArgumentCommands::Status {
+            sources,
+            tags,
+            watch,
+            interval,
+            fetch,
+            health,
+        }) => {
+            let watch = watch.then_some(*interval);
+            if let Err(e) =
+                dotfiles::status(watch, *fetch, *health, sources.clone(), tags.clone()).await
+            {
                 error!("Error: {}", e);
-                std::process::exit(1);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Show { source }) => {
+            if let Err(e) = dotfiles::show(source.clone()).await {
+                error!("Failed to show dotfile: {}", e);
+                fail();
             }
         }
         Some(ArgumentCommands::Remove { source }) => {
             if let Err(e) = dotfiles::remove(source.clone()).await {
                 error!("Failed to remove dotfile: {}", e);
-                std::process::exit(1);
+                fail();
             }
         }
-        Some(ArgumentCommands::List) => {
-            if let Err(e) = dotfiles::list().await {
+        Some(ArgumentCommands::List {
+            broken,
+            entry,
+            tags,
+            under,
+            sort,
+        }) => {
+            let options = dotfiles::ListOptions {
+                broken: *broken,
+                entry: entry.clone(),
+                tags: tags.clone(),
+                under: under.clone(),
+                sort: *sort,
+            };
+            if let Err(e) = dotfiles::list(options).await {
                 error!("Failed to list symlinks: {}", e);
-                std::process::exit(1);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Relink {
+            dry_run,
+            force,
+            relative,
+        }) => {
+            if let Err(e) = dotfiles::relink(*dry_run, *force, *relative).await {
+                error!("Failed to relink dotfiles: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Adopt) => {
+            if let Err(e) = dotfiles::adopt().await {
+                error!("Failed to adopt existing files: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Clean { dry_run, force }) => {
+            if let Err(e) = dotfiles::clean(*dry_run, *force).await {
+                error!("Failed to clean up links: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Outdated { refresh, jobs }) => {
+            if let Err(e) = dotfiles::outdated(*refresh, *jobs).await {
+                error!("Failed to check for upstream changes: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Verify { stale, fix }) => {
+            if let Err(e) = dotfiles::verify(*stale, *fix).await {
+                error!("Failed to verify symlinks: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Manifest { output, sign }) => {
+            if let Err(e) = dotfiles::manifest(output.clone(), sign.clone()).await {
+                error!("Failed to generate manifest: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Export { file, repos }) => {
+            if let Err(e) = bundle::export(file, *repos).await {
+                error!("Failed to export dotme: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Import { file, force }) => {
+            if let Err(e) = bundle::import(file, *force).await {
+                error!("Failed to import bundle: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Unlink {
+            source,
+            all,
+            dry_run,
+        }) => {
+            if let Err(e) = dotfiles::unlink(source.clone(), *all, *dry_run).await {
+                error!("Failed to unlink dotfiles: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Freeze { source }) => {
+            if let Err(e) = dotfiles::freeze(source.clone(), true).await {
+                error!("Failed to freeze dotfile: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Unfreeze { source }) => {
+            if let Err(e) = dotfiles::freeze(source.clone(), false).await {
+                error!("Failed to unfreeze dotfile: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Archive { source }) => {
+            if let Err(e) = dotfiles::archive(source.clone()).await {
+                error!("Failed to archive dotfile: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Unarchive { name }) => {
+            if let Err(e) = dotfiles::unarchive(name.clone()).await {
+                error!("Failed to unarchive dotfile: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Push {
+            source,
+            message,
+            auto,
+            dry_run,
+        }) => {
+            if let Err(e) = dotfiles::push(source.clone(), message.clone(), *auto, *dry_run).await {
+                error!("Failed to push dotfiles: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Syncback {
+            dry_run,
+            commit,
+            message,
+        }) => {
+            if let Err(e) = dotfiles::syncback(*dry_run, *commit, message.clone()).await {
+                error!("Failed to sync back dotfiles: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Edit {
+            path,
+            commit,
+            message,
+        }) => {
+            if let Err(e) = dotfiles::edit(path, *commit, message.clone()).await {
+                error!("Failed to edit {}: {}", path.display(), e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Features {
+            command: FeaturesCommands::List,
+        }) => {
+            if let Err(e) = features::list().await {
+                error!("Failed to list features: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Config {
+            command: ConfigCommands::Validate { file },
+        }) => {
+            if let Err(e) = validate::validate(file.clone()).await {
+                error!("Config is invalid: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::History { path, since, limit }) => {
+            if let Err(e) = history::history(path.clone(), *since, *limit).await {
+                error!("Failed to show the history: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Undo { dry_run }) => {
+            if let Err(e) = history::undo(*dry_run).await {
+                error!("Failed to undo: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Stats) => {
+            if let Err(e) = stats::stats().await {
+                error!("Failed to collect statistics: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::State {
+            command: StateCommands::Fsck { repair },
+        }) => {
+            if let Err(e) = dotfiles::fsck(*repair).await {
+                error!("Failed to check the state file: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Encrypt {
+            identity,
+            passphrase,
+            off,
+        }) => {
+            let options = encryption::EncryptOptions {
+                identity: identity.clone(),
+                passphrase: *passphrase,
+                off: *off,
+            };
+            if let Err(e) = encryption::encrypt(options).await {
+                error!("Failed to encrypt the config: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Run {
+            script,
+            entry,
+            list,
+            dry_run,
+        }) => {
+            let options = scripts::RunOptions {
+                script: script.clone(),
+                entry: entry.clone(),
+                list: *list,
+                dry_run: *dry_run,
+            };
+            if let Err(e) = scripts::run_source_scripts(options).await {
+                error!("Failed to run scripts: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Packages {
+            command: PackagesCommands::List { manager },
+        }) => {
+            if let Err(e) = packages::list(*manager).await {
+                error!("Failed to list packages: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Packages {
+            command: PackagesCommands::Install { dry_run, manager },
+        }) => {
+            if let Err(e) = packages::install(*dry_run, *manager).await {
+                error!("Failed to install packages: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Schedule {
+            command: ScheduleCommands::Install { interval },
+        }) => {
+            if let Err(e) = schedule::install(*interval).await {
+                error!("Failed to schedule updates: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Schedule {
+            command: ScheduleCommands::Remove,
+        }) => {
+            if let Err(e) = schedule::remove().await {
+                error!("Failed to remove the schedule: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Schedule {
+            command: ScheduleCommands::Status,
+        }) => {
+            if let Err(e) = schedule::status().await {
+                error!("Failed to show the schedule: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Snapshot {
+            command: SnapshotCommands::Create { name },
+        }) => {
+            if let Err(e) = snapshot::create(name.clone()).await {
+                error!("Failed to create the snapshot: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Snapshot {
+            command: SnapshotCommands::List,
+        }) => {
+            if let Err(e) = snapshot::list().await {
+                error!("Failed to list the snapshots: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Completions {
+            shell,
+            install: true,
+            ..
+        }) => {
+            if let Err(e) = completions::install(*shell).await {
+                error!("Failed to install completions: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Completions {
+            uninstall: true, ..
+        }) => {
+            if let Err(e) = completions::uninstall().await {
+                error!("Failed to remove completions: {}", e);
+                fail();
+            }
+        }
+        Some(ArgumentCommands::Completions { shell, .. }) => {
+            // Required by clap unless installing or uninstalling
+            if let Some(shell) = shell {
+                print_completions(*shell);
             }
         }
     }
 
+    history::finish();
+    trace::finish();
     Ok(())
 }
+
+/// Exit after a command failed, writing the history and the trace file first
+fn fail() -> ! {
+    exit(1)
+}
+
+/// Exit with `code`, writing the history and the trace file first
+fn exit(code: i32) -> ! {
+    history::finish();
+    trace::finish();
+    std::process::exit(code)
+}
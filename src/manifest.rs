@@ -0,0 +1,202 @@
+//! Checksum manifests of managed links
+//!
+//! `dotme manifest` records every managed link together with a SHA-256 of the content it
+//! resolves to, so what a machine was running at a point in time can be proven later. A
+//! directory is hashed as the `sha256sum`-style listing of the files inside it. Manifests can be
+//! signed with [minisign](https://jedisct1.github.io/minisign/). Links into entries marked
+//! `private: true` are left out.
+//!
+//! ```yaml
+//! generated_at: "2024-01-15T12:45:00Z"
+//! host: web-01
+//! version: 0.3.0
+//! links:
+//!   - link: /home/user/.vimrc
+//!     target: /home/user/.dotme/git/dotfiles/.vimrc
+//!     valid: true
+//!     sha256: 2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::conditions;
+use crate::config::LinkMode;
+use crate::symlinks;
+
+/// Snapshot of every managed link and its content
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    /// When the manifest was generated (ISO 8601 format)
+    pub generated_at: String,
+    /// Host the manifest was generated on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Version of dotme that generated the manifest
+    pub version: String,
+    /// Managed links
+    pub links: Vec<ManifestLink>,
+}
+
+/// A managed link and the content it resolves to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ManifestLink {
+    /// The path to the link
+    pub link: PathBuf,
+    /// The target the link points to
+    pub target: PathBuf,
+    /// Whether this is a symlink, a copy, or a hardlink
+    #[serde(default, skip_serializing_if = "LinkMode::is_default")]
+    pub mode: LinkMode,
+    /// Whether the link still points to its target unmodified
+    pub valid: bool,
+    /// SHA-256 of the resolved content, or none if it can't be read
+    pub sha256: Option<String>,
+}
+
+impl Manifest {
+    /// Build a manifest of the links in the state file, leaving out those into `private`
+    /// sources
+    pub async fn generate(private: &[PathBuf]) -> Result<Self> {
+        let mut links = Vec::new();
+        for (entry, status) in symlinks::list_symlinks().await? {
+            if private.iter().any(|root| entry.target.starts_with(root)) {
+                continue;
+            }
+
+            let sha256 = match content_hash(&entry.link).await {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    log::warn!("Failed to hash {}: {:#}", entry.link.display(), e);
+                    None
+                }
+            };
+
+            links.push(ManifestLink {
+                link: entry.link,
+                target: entry.target,
+                mode: entry.mode,
+                valid: matches!(status, Ok(true)),
+                sha256,
+            });
+        }
+
+        Ok(Self {
+            generated_at: chrono::Utc::now().to_rfc3339(),
+            host: conditions::hostname(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            links,
+        })
+    }
+
+    /// Serialize the manifest, as JSON for `.json` files and YAML otherwise
+    pub fn render(&self, path: Option<&Path>) -> Result<String> {
+        if path.is_some_and(|path| path.extension().is_some_and(|ext| ext == "json")) {
+            serde_json::to_string_pretty(self).context("Failed to serialize manifest")
+        } else {
+            serde_yaml::to_string(self).context("Failed to serialize manifest")
+        }
+    }
+}
+
+/// SHA-256 of a file, or of the sorted `<hash>  <path>` listing of every file in a directory
+pub async fn content_hash(path: &Path) -> Result<String> {
+    let metadata = fs::metadata(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if !metadata.is_dir() {
+        return symlinks::checksum(path).await;
+    }
+
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut read_dir = fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        while let Some(child) = read_dir.next_entry().await? {
+            let child = child.path();
+            if fs::metadata(&child).await.is_ok_and(|m| m.is_dir()) {
+                dirs.push(child);
+            } else {
+                files.push(child);
+            }
+        }
+    }
+    files.sort();
+
+    let mut listing = String::new();
+    for file in files {
+        let relative = file.strip_prefix(path).unwrap_or(&file);
+        listing.push_str(&format!(
+            "{}  {}\n",
+            symlinks::checksum(&file).await?,
+            relative.display()
+        ));
+    }
+
+    Ok(Sha256::digest(listing.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Sign a manifest with minisign, writing `<manifest>.minisig` next to it
+pub async fn sign(manifest: &Path, secret_key: &Path) -> Result<()> {
+    // Inherit the terminal so minisign can ask for the key's password
+    let status = Command::new("minisign")
+        .arg("-S")
+        .arg("-s")
+        .arg(secret_key)
+        .arg("-m")
+        .arg(manifest)
+        .status()
+        .await
+        .context("Failed to run minisign. Is it installed?")?;
+
+    if !status.success() {
+        anyhow::bail!("minisign failed to sign {}", manifest.display());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_directory_hash_covers_names_and_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("nvim");
+        std::fs::create_dir_all(root.join("lua")).unwrap();
+        std::fs::write(root.join("init.lua"), "require('config')").unwrap();
+        std::fs::write(root.join("lua/config.lua"), "vim.o.number = true").unwrap();
+
+        let original = content_hash(&root).await.unwrap();
+        assert_eq!(original, content_hash(&root).await.unwrap());
+
+        std::fs::write(root.join("lua/config.lua"), "vim.o.number = false").unwrap();
+        let edited = content_hash(&root).await.unwrap();
+        assert_ne!(original, edited);
+
+        std::fs::rename(root.join("init.lua"), root.join("main.lua")).unwrap();
+        assert_ne!(edited, content_hash(&root).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_file_hash_is_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".vimrc");
+        std::fs::write(&file, "foo").unwrap();
+
+        assert_eq!(
+            content_hash(&file).await.unwrap(),
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Versions of the config and state files, and the migrations between them
+//!
+//! `config.yml` and `symlinks.yml` carry a `version:` field. Files written before the field
+//! existed are version 0. When a file older than this build is loaded, the migrations from its
+//! version up are applied to the raw document before it is parsed, so fields can be renamed or
+//! restructured without breaking existing installs. dotme's own files are then written back in the
+//! new layout, after copying the original next to them (`config.yml.v0.bak`). Other configs, such
+//! as a baseline or a file passed to `add --from-config`, are only upgraded in memory.
+//!
+//! Files written by a newer version of dotme are refused rather than misread.
+//!
+//! To change the layout of a file, append a migration to its list; the version goes up with it.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+use crate::encryption;
+
+/// A step upgrading a document from one version to the next
+type Migration = fn(&mut Value) -> Result<()>;
+
+/// Migrations of the config; the one at index `n` upgrades version `n` to `n + 1`
+const CONFIG_MIGRATIONS: &[Migration] = &[unversioned, update_policy];
+
+/// Migrations of the symlink state; the one at index `n` upgrades version `n` to `n + 1`
+const STATE_MIGRATIONS: &[Migration] = &[unversioned];
+
+/// Version of the config layout this build writes
+pub const CONFIG_VERSION: u32 = CONFIG_MIGRATIONS.len() as u32;
+
+/// Version of the symlink state layout this build writes
+pub const STATE_VERSION: u32 = STATE_MIGRATIONS.len() as u32;
+
+/// Version of a file's layout; new files get the version this build writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Version<const CURRENT: u32>(pub u32);
+
+impl<const CURRENT: u32> Default for Version<CURRENT> {
+    fn default() -> Self {
+        Self(CURRENT)
+    }
+}
+
+/// Version of the config layout
+pub type ConfigVersion = Version<CONFIG_VERSION>;
+
+/// Version of the symlink state layout
+pub type StateVersion = Version<STATE_VERSION>;
+
+/// Version 0 had no `version:` field; nothing else changed
+fn unversioned(_document: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// Version 1 froze entries with `frozen: true`, which version 2 spells `update: frozen`
+fn update_policy(document: &mut Value) -> Result<()> {
+    let entries = document
+        .get_mut("dotfiles")
+        .and_then(Value::as_sequence_mut);
+    for entry in entries.into_iter().flatten() {
+        if let Value::Mapping(entry) = entry
+            && let Some(frozen) = entry.remove("frozen")
+            && frozen.as_bool() == Some(true)
+        {
+            entry.insert("update".into(), "frozen".into());
+        }
+    }
+    Ok(())
+}
+
+/// Version of a document; documents without one are version 0
+fn version_of(document: &Value) -> Result<u32> {
+    match document.get("version") {
+        None | Some(Value::Null) => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .with_context(|| format!("version must be a whole number, not {:?}", version)),
+    }
+}
+
+/// Apply the migrations `document` needs; returns the version it had, or `None` if it is
+/// current already
+fn upgrade(document: &mut Value, migrations: &[Migration], what: &str) -> Result<Option<u32>> {
+    let current = migrations.len() as u32;
+    let version = version_of(document)?;
+    if version > current {
+        anyhow::bail!(
+            "The {} is version {}, but this dotme only understands up to version {}. Upgrade dotme.",
+            what,
+            version,
+            current
+        );
+    }
+    if version == current {
+        return Ok(None);
+    }
+
+    for (from, migration) in migrations.iter().enumerate().skip(version as usize) {
+        migration(document)
+            .with_context(|| format!("Failed to upgrade the {} from version {}", what, from))?;
+    }
+    if let Value::Mapping(mapping) = document {
+        mapping.insert("version".into(), current.into());
+    }
+    log::debug!(
+        "Upgraded the {} from version {} to {}",
+        what,
+        version,
+        current
+    );
+    Ok(Some(version))
+}
+
+/// Parse a config file into a raw document, decrypting it if it is encrypted, or `None` if it
+/// can't be parsed (loading it reports why); fails only if it can't be decrypted
+pub fn read_document(path: &Path) -> Result<Option<Value>> {
+    let contents = if encryption::is_encrypted_file(path) {
+        encryption::read(path)?
+    } else {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(None),
+        }
+    };
+    let document = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yml" | "yaml") => serde_yaml::from_str(&contents).ok(),
+        Some("toml") => toml::from_str(&contents).ok(),
+        Some("json") => serde_json::from_str(&contents).ok(),
+        _ => None,
+    };
+    // An empty file has nothing to upgrade
+    Ok(document.filter(|document| matches!(document, Value::Mapping(_))))
+}
+
+/// Path of the backup of a file at `version`, e.g. `config.yml.v0.bak`
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    path.with_file_name(name)
+}
+
+/// Copy a file at version `from` next to itself before it is rewritten at version `to`
+pub fn backup(path: &Path, from: u32, to: u32) -> Result<()> {
+    let backup = backup_path(path, from);
+    std::fs::copy(path, &backup).with_context(|| {
+        format!(
+            "Failed to back up {} to {}",
+            path.display(),
+            backup.display()
+        )
+    })?;
+    log::info!(
+        "Upgraded {} to version {}; the original was saved as {}",
+        path.display(),
+        to,
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Upgrade the document of the config file at `path`; returns the version it had, or `None` if
+/// it is current
+pub fn upgrade_config(path: &Path, document: &mut Value) -> Result<Option<u32>> {
+    upgrade(
+        document,
+        CONFIG_MIGRATIONS,
+        &format!("config {}", path.display()),
+    )
+}
+
+/// Upgrade a symlink state document; returns the version it had, or `None` if it is current
+pub fn upgrade_state(document: &mut Value) -> Result<Option<u32>> {
+    upgrade(document, STATE_MIGRATIONS, "symlink state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_source(document: &mut Value) -> Result<()> {
+        for entry in document["dotfiles"].as_sequence_mut().into_iter().flatten() {
+            if let Value::Mapping(entry) = entry
+                && let Some(url) = entry.remove("url")
+            {
+                entry.insert("source".into(), url);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_documents_are_upgraded_step_by_step() {
+        let migrations: &[Migration] = &[unversioned, rename_source];
+        let mut document: Value =
+            serde_yaml::from_str("dotfiles:\n  - url: https://example.com/dots.git\n").unwrap();
+
+        assert_eq!(
+            upgrade(&mut document, migrations, "config").unwrap(),
+            Some(0)
+        );
+        assert_eq!(version_of(&document).unwrap(), 2);
+        assert_eq!(
+            document["dotfiles"][0]["source"].as_str(),
+            Some("https://example.com/dots.git")
+        );
+
+        // Current documents are left alone, newer ones are refused
+        assert_eq!(upgrade(&mut document, migrations, "config").unwrap(), None);
+        document["version"] = 3.into();
+        let error = upgrade(&mut document, migrations, "config").unwrap_err();
+        assert!(error.to_string().contains("Upgrade dotme"));
+    }
+
+    #[test]
+    fn test_frozen_entries_get_an_update_policy() {
+        let mut document: Value = serde_yaml::from_str(
+            "version: 1\ndotfiles:\n  - source: a\n    frozen: true\n  - source: b\n    frozen: false\n",
+        )
+        .unwrap();
+
+        upgrade_config(Path::new("config.yml"), &mut document).unwrap();
+        assert_eq!(document["dotfiles"][0]["update"].as_str(), Some("frozen"));
+        assert!(document["dotfiles"][0].get("frozen").is_none());
+        assert!(document["dotfiles"][1].get("update").is_none());
+        assert!(document["dotfiles"][1].get("frozen").is_none());
+    }
+
+    #[test]
+    fn test_backups_keep_the_old_version() {
+        assert_eq!(
+            backup_path(Path::new("/home/u/.dotme/config.yml"), 0),
+            Path::new("/home/u/.dotme/config.yml.v0.bak")
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Telling the user how updates nobody is watching went
+//!
+//! With the `notify` feature enabled, an update that runs without a terminal, e.g. one started
+//! by [`dotme schedule`](crate::schedule) or cron, sends a desktop notification when it pulled
+//! new commits or something failed, so scheduled updates aren't silent. Updates with nothing new
+//! stay quiet. Sending them needs dotme built with the `notify` feature.
+//!
+//! For machines without a desktop, the `notifications:` section names channels that get a JSON
+//! report of every update (or only some, with `on: changes` or `on: failure`): a shell command,
+//! which reads it on stdin, and a webhook URL it is POSTed to with `curl`, e.g. for ntfy or a
+//! Slack workflow.
+//!
+//! ```yaml
+//! features:
+//!   notify: true
+//! notifications:
+//!   command: ntfy publish dotfiles "$(jq -r .text)"
+//!   webhook_url: https://hooks.example.com/dotme
+//!   on: failure
+//! ```
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::conditions;
+use crate::config::{Config, NotificationsConfig, NotifyOn};
+use crate::dotfiles::UpdateSummary;
+use crate::features;
+use crate::scripts;
+
+/// What the notification channels get about an update
+#[derive(Debug, Serialize)]
+struct Report {
+    /// What happened, always `update`
+    event: &'static str,
+    /// `ok`, or `failed` if sources or scripts failed
+    status: &'static str,
+    /// Machine the update ran on
+    host: Option<String>,
+    /// When it finished
+    time: String,
+    /// The summary as one line of text, for channels that only show text
+    text: String,
+    /// Sources that got new commits or were fetched for the first time
+    pulled: usize,
+    /// Links that weren't there before
+    links_created: usize,
+    /// Sources that failed to update
+    failed_sources: usize,
+    /// Entry scripts that failed
+    failed_scripts: usize,
+    /// Exit code of `dotme update --summary`
+    exit_code: i32,
+}
+
+impl Report {
+    /// Report about the update that ended with `summary`
+    fn new(summary: &UpdateSummary) -> Self {
+        Self {
+            event: "update",
+            status: if summary.exit_code() == 0 {
+                "ok"
+            } else {
+                "failed"
+            },
+            host: conditions::hostname(),
+            time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            text: summary.to_string(),
+            pulled: summary.pulled,
+            links_created: summary.links_created,
+            failed_sources: summary.failed_sources,
+            failed_scripts: summary.failed_scripts,
+            exit_code: summary.exit_code(),
+        }
+    }
+}
+
+/// Check if the channels of `notifications` want to hear about the update that ended with
+/// `summary`
+fn wanted(notifications: &NotificationsConfig, summary: &UpdateSummary) -> bool {
+    match notifications.on {
+        NotifyOn::Always => true,
+        NotifyOn::Changes => summary.pulled > 0 || summary.exit_code() != 0,
+        NotifyOn::Failure => summary.exit_code() != 0,
+    }
+}
+
+/// Title and text of the notification about an update, if it is worth one
+fn message(summary: &UpdateSummary) -> Option<(&'static str, String)> {
+    if summary.exit_code() != 0 {
+        Some(("dotme update failed", summary.to_string()))
+    } else if summary.pulled > 0 {
+        Some(("Dotfiles updated", summary.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Tell the user how an update went: on the desktop if it ran in the background and there is
+/// news, and on the configured channels
+pub async fn update_finished(config: &Config, summary: &UpdateSummary) {
+    if config.feature_enabled(features::NOTIFY)
+        && !std::io::stderr().is_terminal()
+        && let Some((title, body)) = message(summary)
+        && let Err(e) = send(title, &body)
+    {
+        log::warn!("Failed to send a notification: {}", e);
+    }
+
+    let notifications = &config.notifications;
+    if !wanted(notifications, summary) {
+        return;
+    }
+    let report = match serde_json::to_string(&Report::new(summary)) {
+        Ok(report) => report,
+        Err(e) => {
+            log::warn!("Failed to write the update report: {}", e);
+            return;
+        }
+    };
+    if let Some(command) = &notifications.command
+        && let Err(e) = pipe(scripts::shell(command), &report, "notification command").await
+    {
+        log::warn!("{:#}", e);
+    }
+    if let Some(url) = &notifications.webhook_url {
+        let mut curl = Command::new("curl");
+        curl.args([
+            "-fsS",
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+        ])
+        .arg(url);
+        if let Err(e) = pipe(curl, &report, "webhook").await {
+            log::warn!("{:#}", e);
+        }
+    }
+}
+
+/// Run `command` with `input` on stdin, failing with what it printed on stderr
+async fn pipe(mut command: Command, input: &str, what: &str) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run the {}", what))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read the report isn't an error
+        let _ = stdin.write_all(input.as_bytes()).await;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "The {} failed: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Show a desktop notification
+#[cfg(feature = "notify")]
+fn send(title: &str, body: &str) -> Result<()> {
+    notify_rust::Notification::new()
+        .appname("dotme")
+        .summary(title)
+        .body(body)
+        .show()?;
+    Ok(())
+}
+
+/// Notifications need the notify-rust crate
+#[cfg(not(feature = "notify"))]
+fn send(_title: &str, _body: &str) -> Result<()> {
+    anyhow::bail!("dotme was built without the notify feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_news_is_notified() {
+        assert_eq!(message(&UpdateSummary::default()), None);
+
+        let pulled = UpdateSummary {
+            pulled: 1,
+            ..Default::default()
+        };
+        assert_eq!(message(&pulled).unwrap().0, "Dotfiles updated");
+
+        let failed = UpdateSummary {
+            failed_sources: 1,
+            ..Default::default()
+        };
+        assert_eq!(message(&failed).unwrap().0, "dotme update failed");
+    }
+
+    #[test]
+    fn test_channels_get_the_updates_they_asked_for() {
+        let quiet = UpdateSummary::default();
+        let pulled = UpdateSummary {
+            pulled: 2,
+            ..Default::default()
+        };
+        let failed = UpdateSummary {
+            failed_scripts: 1,
+            ..Default::default()
+        };
+        let on = |on| NotificationsConfig {
+            on,
+            ..Default::default()
+        };
+
+        assert!(wanted(&on(NotifyOn::Always), &quiet));
+        assert!(!wanted(&on(NotifyOn::Changes), &quiet));
+        assert!(wanted(&on(NotifyOn::Changes), &pulled));
+        assert!(!wanted(&on(NotifyOn::Failure), &pulled));
+        assert!(wanted(&on(NotifyOn::Failure), &failed));
+
+        let report: serde_json::Value = serde_json::to_value(Report::new(&failed)).unwrap();
+        assert_eq!(report["status"], "failed");
+        assert_eq!(report["failed_scripts"], 1);
+        assert_eq!(report["exit_code"], 3);
+    }
+}
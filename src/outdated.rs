@@ -0,0 +1,247 @@
+//! Upstream checks for git entries
+//!
+//! `dotme outdated` asks each remote for its latest commit with `git ls-remote`, without
+//! fetching or touching the checkout. The remote commits are cached in `~/.dotme/outdated.yml`,
+//! so running it again within `update.check_interval` doesn't hit the remotes again.
+//!
+//! ```yaml
+//! remotes:
+//!   "https://github.com/user/dotfiles.git#main":
+//!     commit: 5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0a1b2c3d4e
+//!     checked_at: "2024-01-15T12:45:00Z"
+//! ```
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::config::{DotfileEntry, GitConfig, SourceType};
+use crate::dotfiles;
+use crate::git;
+
+/// Remote commits from previous checks
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    #[serde(default)]
+    remotes: BTreeMap<String, CachedRemote>,
+}
+
+/// Latest commit of a remote branch at the time it was checked
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CachedRemote {
+    /// Commit hash, or none if the branch doesn't exist on the remote
+    commit: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+impl CachedRemote {
+    /// Check if the result is recent enough to be reused
+    fn is_fresh(&self, max_age: Duration, now: DateTime<Utc>) -> bool {
+        now.signed_duration_since(self.checked_at) < max_age
+    }
+}
+
+impl Cache {
+    fn path() -> Result<PathBuf> {
+        Ok(dotfiles::get_dotme_dir()?.join("outdated.yml"))
+    }
+
+    /// Load the cache, starting over if it is missing or unreadable
+    async fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .context("Failed to read outdated cache")?;
+        Ok(serde_yaml::from_str(&content).unwrap_or_else(|e| {
+            log::debug!("Ignoring unreadable outdated cache: {}", e);
+            Self::default()
+        }))
+    }
+
+    async fn save(&self) -> Result<()> {
+        let content = serde_yaml::to_string(self).context("Failed to serialize outdated cache")?;
+        tokio::fs::write(Self::path()?, content)
+            .await
+            .context("Failed to write outdated cache")
+    }
+}
+
+/// Cache key of an entry, as the same repository can be tracked on different branches
+fn cache_key(entry: &DotfileEntry) -> String {
+    format!(
+        "{}#{}",
+        entry.source,
+        entry.branch.as_deref().unwrap_or("HEAD")
+    )
+}
+
+/// How a checkout compares to its upstream
+#[derive(Debug, Clone, PartialEq)]
+pub enum Upstream {
+    /// The remote has no commits missing from the checkout
+    UpToDate,
+    /// The remote has commits that `dotme update` would pull
+    Behind {
+        /// Commit currently checked out
+        local: String,
+        /// Latest commit on the remote
+        remote: String,
+    },
+    /// Pinned to a revision, so never updated
+    Pinned(String),
+    /// Not cloned yet
+    NotCloned,
+    /// The tracked branch doesn't exist on the remote
+    MissingBranch,
+}
+
+/// Result of checking a single entry
+pub struct Check {
+    /// Entry that was checked
+    pub entry: DotfileEntry,
+    /// Upstream state, or why it couldn't be determined
+    pub status: Result<Upstream>,
+    /// Whether the remote commit came from the cache
+    pub cached: bool,
+}
+
+/// Check git entries for upstream commits, asking at most `concurrency` remotes at a time
+/// and reusing remote commits checked less than `max_age` ago
+pub async fn check(
+    entries: &[DotfileEntry],
+    git: &GitConfig,
+    max_age: Duration,
+    concurrency: usize,
+) -> Result<Vec<Check>> {
+    let mut cache = Cache::load().await?;
+    let now = Utc::now();
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+
+    for (index, entry) in entries
+        .iter()
+        .filter(|e| matches!(e.r#type, SourceType::Git))
+        .enumerate()
+    {
+        let entry = entry.clone();
+        let options = git::CloneOptions::new(&entry, git);
+        let cached = cache
+            .remotes
+            .get(&cache_key(&entry))
+            .filter(|cached| cached.is_fresh(max_age, now))
+            .cloned();
+        let semaphore = semaphore.clone();
+
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await?;
+            let result = check_entry(&entry, &options, cached).await;
+            anyhow::Ok((index, entry, result))
+        });
+    }
+
+    let mut checks = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        let (index, entry, result) = joined.context("Upstream check task failed")??;
+        let (status, cached) = match result {
+            Ok((status, Some(remote))) => {
+                cache.remotes.insert(cache_key(&entry), remote);
+                (Ok(status), false)
+            }
+            Ok((status, None)) => {
+                let compared = !matches!(status, Upstream::Pinned(_) | Upstream::NotCloned);
+                (Ok(status), compared)
+            }
+            Err(e) => (Err(e), false),
+        };
+        checks.push((
+            index,
+            Check {
+                entry,
+                status,
+                cached,
+            },
+        ));
+    }
+
+    cache.save().await?;
+
+    checks.sort_by_key(|(index, _)| *index);
+    Ok(checks.into_iter().map(|(_, check)| check).collect())
+}
+
+/// Compare a checkout with its remote, returning the remote commit if it had to be looked up
+async fn check_entry(
+    entry: &DotfileEntry,
+    options: &git::CloneOptions,
+    cached: Option<CachedRemote>,
+) -> Result<(Upstream, Option<CachedRemote>)> {
+    if let Some(rev) = &entry.rev {
+        return Ok((Upstream::Pinned(rev.clone()), None));
+    }
+
+    let repo_path = entry.local_path();
+    if !repo_path.exists() {
+        return Ok((Upstream::NotCloned, None));
+    }
+
+    let (remote, looked_up) = match cached {
+        Some(cached) => (cached.commit, None),
+        None => {
+            log::debug!("Checking upstream of {}", entry.source);
+            let commit = git::remote_commit(&repo_path, options).await?;
+            let looked_up = CachedRemote {
+                commit: commit.clone(),
+                checked_at: Utc::now(),
+            };
+            (commit, Some(looked_up))
+        }
+    };
+
+    let Some(remote) = remote else {
+        return Ok((Upstream::MissingBranch, looked_up));
+    };
+
+    let local = git::head_commit(&repo_path).await?;
+    let status = if local == remote || git::contains_commit(&repo_path, &remote).await {
+        Upstream::UpToDate
+    } else {
+        Upstream::Behind { local, remote }
+    };
+
+    Ok((status, looked_up))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_remote_expires() {
+        let now = Utc::now();
+        let cached = CachedRemote {
+            commit: Some("abc".to_string()),
+            checked_at: now - Duration::minutes(30),
+        };
+
+        assert!(cached.is_fresh(Duration::hours(1), now));
+        assert!(!cached.is_fresh(Duration::minutes(10), now));
+    }
+
+    #[test]
+    fn test_cache_key_includes_branch() {
+        let mut entry = DotfileEntry::new("https://example.com/dots.git", SourceType::Git);
+        assert_eq!(cache_key(&entry), "https://example.com/dots.git#HEAD");
+
+        entry.branch = Some("main".to_string());
+        assert_eq!(cache_key(&entry), "https://example.com/dots.git#main");
+    }
+}
@@ -0,0 +1,322 @@
+//! System packages declared in the config
+//!
+//! The `packages:` section lists the tools the dotfiles configure, so a new machine gets both in
+//! one run. Packages are installed with the first package manager found (brew, apt, dnf, or
+//! pacman), and names can be overridden where a manager calls a package differently.
+//!
+//! ```yaml
+//! packages:
+//!   - git
+//!   - neovim
+//!   - name: fd
+//!     apt: fd-find
+//! ```
+
+use anyhow::{Context, Result};
+use console::style;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::redact;
+
+/// A package to install, by name or with per-manager names
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Package {
+    /// Same name for every package manager
+    Name(String),
+    /// Name with overrides for some package managers
+    Detailed(PackageSpec),
+}
+
+/// Package name with overrides for package managers that call it differently
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PackageSpec {
+    /// Package name
+    pub name: String,
+    /// Name of the Homebrew formula
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brew: Option<String>,
+    /// Name of the apt package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apt: Option<String>,
+    /// Name of the dnf package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dnf: Option<String>,
+    /// Name of the pacman package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pacman: Option<String>,
+}
+
+impl Package {
+    /// Name the package is known by in the config
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Name(name) => name,
+            Self::Detailed(spec) => &spec.name,
+        }
+    }
+
+    /// Name of the package for a package manager
+    pub fn name_for(&self, manager: PackageManager) -> &str {
+        let Self::Detailed(spec) = self else {
+            return self.name();
+        };
+
+        let name = match manager {
+            PackageManager::Brew => &spec.brew,
+            PackageManager::Apt => &spec.apt,
+            PackageManager::Dnf => &spec.dnf,
+            PackageManager::Pacman => &spec.pacman,
+        };
+        name.as_deref().unwrap_or(&spec.name)
+    }
+}
+
+/// Supported package managers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PackageManager {
+    /// Homebrew (macOS and Linux)
+    Brew,
+    /// apt (Debian, Ubuntu)
+    Apt,
+    /// dnf (Fedora, RHEL)
+    Dnf,
+    /// pacman (Arch Linux)
+    Pacman,
+}
+
+impl fmt::Display for PackageManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Brew => write!(f, "brew"),
+            Self::Apt => write!(f, "apt"),
+            Self::Dnf => write!(f, "dnf"),
+            Self::Pacman => write!(f, "pacman"),
+        }
+    }
+}
+
+impl PackageManager {
+    /// Find the package manager of this machine. Homebrew is only preferred on macOS, as it is
+    /// usually an addition to the system package manager on Linux.
+    pub fn detect() -> Option<Self> {
+        let order: &[Self] = if cfg!(target_os = "macos") {
+            &[Self::Brew]
+        } else {
+            &[Self::Apt, Self::Dnf, Self::Pacman, Self::Brew]
+        };
+
+        order
+            .iter()
+            .copied()
+            .find(|manager| on_path(manager.program()))
+    }
+
+    /// Program that installs packages
+    fn program(&self) -> &'static str {
+        match self {
+            Self::Brew => "brew",
+            Self::Apt => "apt-get",
+            Self::Dnf => "dnf",
+            Self::Pacman => "pacman",
+        }
+    }
+
+    /// Whether installing needs root privileges
+    fn needs_root(&self) -> bool {
+        !matches!(self, Self::Brew)
+    }
+
+    /// Command line that installs `names`, run through sudo unless dotme already runs as root
+    pub fn install_command(&self, names: &[&str], root: bool) -> Vec<String> {
+        let mut command = Vec::new();
+        if self.needs_root() && !root {
+            command.push("sudo".to_string());
+        }
+        command.push(self.program().to_string());
+        command.extend(
+            match self {
+                Self::Brew => &["install"][..],
+                Self::Apt => &["install", "-y"][..],
+                Self::Dnf => &["install", "-y"][..],
+                Self::Pacman => &["-S", "--needed", "--noconfirm"][..],
+            }
+            .iter()
+            .map(|arg| arg.to_string()),
+        );
+        command.extend(names.iter().map(|name| name.to_string()));
+        command
+    }
+
+    /// Check if a package is already installed
+    pub async fn is_installed(&self, name: &str) -> bool {
+        let (program, args): (&str, &[&str]) = match self {
+            Self::Brew => ("brew", &["list", "--versions"]),
+            Self::Apt => ("dpkg", &["-s"]),
+            Self::Dnf => ("rpm", &["-q"]),
+            Self::Pacman => ("pacman", &["-Qi"]),
+        };
+
+        Command::new(program)
+            .args(args)
+            .arg(name)
+            .output()
+            .await
+            .is_ok_and(|output| output.status.success())
+    }
+}
+
+/// Check if a program can be found on the `PATH`
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| Path::new(&dir).join(program).is_file())
+    })
+}
+
+/// Check if dotme runs as root
+pub async fn is_root() -> bool {
+    Command::new("id")
+        .arg("-u")
+        .output()
+        .await
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "0")
+}
+
+/// Run an install command, letting it use the terminal (e.g. for the sudo password)
+pub async fn run(command: &[String]) -> Result<()> {
+    let (program, args) = command.split_first().context("Empty install command")?;
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .await
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    if !status.success() {
+        anyhow::bail!("'{}' failed", command.join(" "));
+    }
+
+    Ok(())
+}
+
+/// Load the declared packages and the package manager to use
+fn load(manager: Option<PackageManager>) -> Result<(Vec<Package>, PackageManager)> {
+    let config_path = crate::dotfiles::get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let packages = crate::config::Config::load(Some(config_path))?.packages;
+    let manager = match manager.or_else(PackageManager::detect) {
+        Some(manager) => manager,
+        None => anyhow::bail!(
+            "No supported package manager found (brew, apt, dnf, pacman). Use --manager to pick one."
+        ),
+    };
+
+    Ok((packages, manager))
+}
+
+/// List the declared packages and whether they are installed
+pub async fn list(manager: Option<PackageManager>) -> Result<()> {
+    let (packages, manager) = load(manager)?;
+
+    if packages.is_empty() {
+        println!("No packages are declared. Add them to the packages section of the config.");
+        return Ok(());
+    }
+
+    println!("Packages ({}):", manager);
+    println!("─────────────────────────────────────────");
+
+    for package in &packages {
+        let name = package.name_for(manager);
+        let status = if manager.is_installed(name).await {
+            style("✓ installed").green()
+        } else {
+            style("✗ missing").dim()
+        };
+
+        if name == package.name() {
+            println!("  {} {}", status, name);
+        } else {
+            println!("  {} {} ({})", status, package.name(), name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Install the declared packages that are missing
+pub async fn install(dry_run: bool, manager: Option<PackageManager>) -> Result<()> {
+    let (packages, manager) = load(manager)?;
+
+    let mut missing = Vec::new();
+    for package in &packages {
+        let name = package.name_for(manager);
+        if manager.is_installed(name).await {
+            log::debug!("Already installed: {}", name);
+        } else {
+            missing.push(name);
+        }
+    }
+
+    if missing.is_empty() {
+        println!("All {} package(s) are installed.", packages.len());
+        return Ok(());
+    }
+
+    let command = manager.install_command(&missing, is_root().await);
+    if dry_run {
+        println!(
+            "[DRY RUN] Would run: {}",
+            redact::redact(&command.join(" "))
+        );
+        return Ok(());
+    }
+
+    log::info!(
+        "Installing {} package(s) with {}...",
+        missing.len(),
+        manager
+    );
+    run(&command).await?;
+    log::info!("✓ Installed {}", missing.join(", "));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_can_be_overridden_per_manager() {
+        let packages: Vec<Package> =
+            serde_yaml::from_str("- git\n- name: fd\n  apt: fd-find\n").unwrap();
+
+        assert_eq!(packages[0].name_for(PackageManager::Apt), "git");
+        assert_eq!(packages[1].name(), "fd");
+        assert_eq!(packages[1].name_for(PackageManager::Apt), "fd-find");
+        assert_eq!(packages[1].name_for(PackageManager::Brew), "fd");
+    }
+
+    #[test]
+    fn test_install_command_uses_sudo_unless_root() {
+        assert_eq!(
+            PackageManager::Apt.install_command(&["git", "fd-find"], false),
+            ["sudo", "apt-get", "install", "-y", "git", "fd-find"]
+        );
+        assert_eq!(
+            PackageManager::Dnf.install_command(&["git"], true),
+            ["dnf", "install", "-y", "git"]
+        );
+        assert_eq!(
+            PackageManager::Brew.install_command(&["git"], false),
+            ["brew", "install", "git"]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Spinners for long operations
+//!
+//! Cloning, pulling, and linking large trees can take a while without logging anything at info
+//! level, so each shows a spinner on stderr while it runs, one line per operation when several
+//! entries are updated at once. Log messages are printed above the spinners. Spinners are only
+//! drawn on a terminal, and not at all with `-q` or JSON logs, which are meant for machines.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Spinners currently shown
+static BARS: LazyLock<MultiProgress> =
+    LazyLock::new(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr()));
+
+/// Whether spinners are shown
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Show or hide spinners for the rest of the run
+pub fn enable(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Start a spinner showing `message`; it disappears when finished or dropped
+pub fn spinner(message: impl Into<String>) -> ProgressBar {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+
+    let bar = BARS.add(ProgressBar::new_spinner().with_finish(ProgressFinish::AndClear));
+    bar.set_style(
+        ProgressStyle::with_template("{spinner:.green} {msg} {prefix:.dim}")
+            .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+    );
+    bar.set_message(message.into());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+/// Count one more item done on `bar`, shown next to its message
+pub fn advance(bar: &ProgressBar, unit: &str) {
+    bar.inc(1);
+    bar.set_prefix(format!("{} {}", bar.position(), unit));
+}
+
+/// Run `f`, e.g. printing a log line, with the spinners hidden so its output isn't mixed up with
+/// them
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    BARS.suspend(f)
+}
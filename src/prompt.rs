@@ -0,0 +1,109 @@
+//! Questions asked on the terminal, and their answers when nobody is there to give them
+//!
+//! With `--yes` (or `DOTME_NONINTERACTIVE=1`), dotme runs without a TTY, e.g. in provisioning
+//! scripts and CI: confirmations, selections and inputs take their default, so a question
+//! defaulting to no (such as removing links) is still answered no. Questions without a safe
+//! default, such as which entry to remove, fail with a hint at the argument that answers them
+//! instead.
+
+use anyhow::Result;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, Input, MultiSelect, Password, Select};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether questions are answered without asking
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Answer every question without asking for the rest of the run
+pub fn assume_yes(enabled: bool) {
+    ASSUME_YES.store(enabled, Ordering::Relaxed);
+}
+
+/// Check if questions can be asked on the terminal
+pub fn is_interactive() -> bool {
+    !ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// Fail with `hint` if nobody can be asked, for questions without a safe default
+pub fn require_interactive(hint: &str) -> Result<()> {
+    if !is_interactive() {
+        anyhow::bail!("Can't ask without a terminal (--yes). {}", hint);
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question; `--yes` takes `default`
+pub fn confirm(prompt: &str, default: bool) -> Result<bool> {
+    if !is_interactive() {
+        log::info!("{} {} (--yes)", prompt, if default { "yes" } else { "no" });
+        return Ok(default);
+    }
+    Ok(Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .interact()?)
+}
+
+/// Pick one of `items`; `--yes` picks `default`
+pub fn select<T: std::fmt::Display>(prompt: &str, items: &[T], default: usize) -> Result<usize> {
+    if !is_interactive() {
+        if let Some(item) = items.get(default) {
+            log::info!("{}: {} (--yes)", prompt, item);
+        }
+        return Ok(default);
+    }
+    Ok(Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .default(default)
+        .interact()?)
+}
+
+/// Pick any of `items`; `--yes` picks none
+pub fn multi_select<T: std::fmt::Display>(prompt: &str, items: &[T]) -> Result<Vec<usize>> {
+    if !is_interactive() {
+        return Ok(Vec::new());
+    }
+    Ok(MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .interact()?)
+}
+
+/// Ask for a line of text; `--yes` takes `default`
+pub fn input(prompt: &str, default: String) -> Result<String> {
+    if !is_interactive() {
+        log::info!("{}: {} (--yes)", prompt, default);
+        return Ok(default);
+    }
+    Ok(Input::<String>::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default)
+        .interact_text()?)
+}
+
+/// Ask for a secret without echoing it, twice if `confirm`; there is no default for `--yes`
+pub fn password(prompt: &str, confirm: bool) -> Result<String> {
+    require_interactive("Pass the secret through the environment instead.")?;
+    let theme = ColorfulTheme::default();
+    let mut password = Password::with_theme(&theme).with_prompt(prompt);
+    if confirm {
+        password = password.with_confirmation("Repeat it", "The two don't match");
+    }
+    Ok(password.interact()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assume_yes_takes_the_default() {
+        assume_yes(true);
+        assert!(confirm("Undo these changes?", true).unwrap());
+        assert!(!confirm("Remove 3 link(s)?", false).unwrap());
+        assert_eq!(select("Pick", &["a", "b"], 1).unwrap(), 1);
+        assert_eq!(input("Name", "Jane".to_string()).unwrap(), "Jane");
+        assert!(password("Passphrase", false).is_err());
+    }
+}
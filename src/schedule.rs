@@ -0,0 +1,351 @@
+//! Running `dotme update` on a schedule
+//!
+//! `dotme schedule install` hands the schedule to the service manager of the platform, so there
+//! is nothing of dotme's own running in between: a systemd user timer on Linux, a LaunchAgent on
+//! macOS. Both run `dotme --yes update --quiet` with the `PATH` of the shell that installed
+//! them, so `git` and password manager tools are found the same way.
+//!
+//! | Platform | Files                                                                 |
+//! |----------|-----------------------------------------------------------------------|
+//! | Linux    | `~/.config/systemd/user/dotme-update.service`, `dotme-update.timer`   |
+//! | macOS    | `~/Library/LaunchAgents/com.42bytelabs.dotme.update.plist`            |
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::layout;
+
+/// Name of the systemd units
+const UNIT: &str = "dotme-update";
+
+/// Label of the LaunchAgent
+const LABEL: &str = "com.42bytelabs.dotme.update";
+
+/// Service manager the schedule is given to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheduler {
+    /// systemd user units
+    Systemd,
+    /// launchd LaunchAgents
+    Launchd,
+}
+
+impl Scheduler {
+    /// Scheduler of the platform dotme runs on
+    fn current() -> Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(Scheduler::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Scheduler::Systemd)
+        } else {
+            anyhow::bail!(
+                "Scheduling isn't supported on this platform. Run 'dotme --yes update --quiet' from your system's scheduler instead."
+            )
+        }
+    }
+
+    /// Files the schedule consists of, with their contents if `command` runs every `seconds`
+    fn files(
+        self,
+        home: &Path,
+        command: &[String],
+        path: &str,
+        seconds: i64,
+    ) -> Vec<(PathBuf, String)> {
+        match self {
+            Scheduler::Systemd => {
+                let dir = config_home(home).join("systemd").join("user");
+                vec![
+                    (
+                        dir.join(format!("{}.service", UNIT)),
+                        systemd_service(command, path),
+                    ),
+                    (dir.join(timer()), systemd_timer(seconds)),
+                ]
+            }
+            Scheduler::Launchd => vec![(
+                home.join("Library")
+                    .join("LaunchAgents")
+                    .join(format!("{}.plist", LABEL)),
+                launchd_plist(command, path, seconds),
+            )],
+        }
+    }
+
+    /// Commands that start the schedule once its files are written
+    fn enable(self, files: &[(PathBuf, String)]) -> Vec<Vec<String>> {
+        match self {
+            Scheduler::Systemd => vec![
+                args(&["systemctl", "--user", "daemon-reload"]),
+                args(&["systemctl", "--user", "enable", "--now", &timer()]),
+            ],
+            Scheduler::Launchd => vec![args(&["launchctl", "load", "-w", &plist(files)])],
+        }
+    }
+
+    /// Command that stops the schedule before its files are deleted
+    fn disable(self, files: &[(PathBuf, String)]) -> Vec<String> {
+        match self {
+            Scheduler::Systemd => args(&["systemctl", "--user", "disable", "--now", &timer()]),
+            Scheduler::Launchd => args(&["launchctl", "unload", "-w", &plist(files)]),
+        }
+    }
+
+    /// Command that shows when the update last ran and runs next
+    fn status(self) -> Vec<String> {
+        match self {
+            Scheduler::Systemd => args(&[
+                "systemctl",
+                "--user",
+                "list-timers",
+                "--all",
+                "--no-pager",
+                &timer(),
+            ]),
+            Scheduler::Launchd => args(&["launchctl", "list", LABEL]),
+        }
+    }
+}
+
+/// Name of the systemd timer
+fn timer() -> String {
+    format!("{}.timer", UNIT)
+}
+
+/// Path of the LaunchAgent among the files of the schedule
+fn plist(files: &[(PathBuf, String)]) -> String {
+    files[0].0.display().to_string()
+}
+
+/// Owned copies of `args`
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Directory for user config, `$XDG_CONFIG_HOME` or `~/.config`
+fn config_home(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"))
+}
+
+/// Command line the schedule runs
+fn update_command() -> Result<Vec<String>> {
+    let exe = std::env::current_exe().context("Failed to find the dotme executable")?;
+    let mut command = vec![exe.display().to_string()];
+    if let Some(root) = layout::root() {
+        command.extend(["--root".to_string(), root.display().to_string()]);
+    }
+    command.extend(args(&["--yes", "update", "--quiet"]));
+    Ok(command)
+}
+
+/// `arg` quoted for an `ExecStart=` line
+fn systemd_quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c)) {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
+
+/// Service running `command` once
+fn systemd_service(command: &[String], path: &str) -> String {
+    let command: Vec<String> = command.iter().map(|arg| systemd_quote(arg)).collect();
+    format!(
+        "[Unit]\nDescription=Update dotfiles with dotme\n\n[Service]\nType=oneshot\nEnvironment={}\nExecStart={}\n",
+        systemd_quote(&format!("PATH={}", path)),
+        command.join(" ")
+    )
+}
+
+/// Timer starting the service every `seconds`
+fn systemd_timer(seconds: i64) -> String {
+    format!(
+        "[Unit]\nDescription=Update dotfiles with dotme regularly\n\n[Timer]\nOnBootSec=5min\nOnUnitActiveSec={}s\n\n[Install]\nWantedBy=timers.target\n",
+        seconds
+    )
+}
+
+/// `text` escaped for an XML string
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// LaunchAgent running `command` every `seconds`
+fn launchd_plist(command: &[String], path: &str, seconds: i64) -> String {
+    let arguments: String = command
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>PATH</key>
+        <string>{}</string>
+    </dict>
+    <key>StartInterval</key>
+    <integer>{}</integer>
+</dict>
+</plist>
+"#,
+        LABEL,
+        arguments,
+        xml_escape(path),
+        seconds
+    )
+}
+
+/// Run a service manager command, failing with its error output
+async fn run(command: &[String]) -> Result<String> {
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", command[0]))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "'{}' failed: {}",
+            command.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Schedule `dotme update` to run every `interval`
+pub async fn install(interval: chrono::Duration) -> Result<()> {
+    let seconds = interval.num_seconds();
+    if seconds < 60 {
+        anyhow::bail!("The interval must be at least a minute");
+    }
+    let scheduler = Scheduler::current()?;
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    let path = std::env::var("PATH").unwrap_or_default();
+    let files = scheduler.files(&home, &update_command()?, &path, seconds);
+
+    for (file, contents) in &files {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        fs::write(file, contents)
+            .await
+            .with_context(|| format!("Failed to write {}", file.display()))?;
+        log::info!("✓ Wrote {}", file.display());
+    }
+    // Loading a LaunchAgent again doesn't pick up changes
+    if scheduler == Scheduler::Launchd {
+        let _ = run(&scheduler.disable(&files)).await;
+    }
+    for command in scheduler.enable(&files) {
+        run(&command).await?;
+    }
+
+    println!("✓ dotme update runs every {}", describe(seconds));
+    Ok(())
+}
+
+/// Stop running `dotme update` on a schedule
+pub async fn remove() -> Result<()> {
+    let scheduler = Scheduler::current()?;
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    let files = scheduler.files(&home, &[], "", 0);
+    if !files.iter().any(|(file, _)| file.exists()) {
+        println!("dotme update isn't scheduled.");
+        return Ok(());
+    }
+
+    if let Err(e) = run(&scheduler.disable(&files)).await {
+        log::warn!("{}", e);
+    }
+    for (file, _) in &files {
+        match fs::remove_file(file).await {
+            Ok(()) => log::info!("✓ Removed {}", file.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to remove {}", file.display()));
+            }
+        }
+    }
+    if scheduler == Scheduler::Systemd
+        && let Err(e) = run(&args(&["systemctl", "--user", "daemon-reload"])).await
+    {
+        log::warn!("{}", e);
+    }
+
+    println!("✓ dotme update is no longer scheduled");
+    Ok(())
+}
+
+/// Show whether `dotme update` is scheduled, and when it last ran and runs next
+pub async fn status() -> Result<()> {
+    let scheduler = Scheduler::current()?;
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    let files = scheduler.files(&home, &[], "", 0);
+    if !files.iter().all(|(file, _)| file.exists()) {
+        println!("dotme update isn't scheduled. Schedule it with 'dotme schedule install'.");
+        return Ok(());
+    }
+
+    println!("dotme update is scheduled:");
+    for (file, _) in &files {
+        println!("  {}", file.display());
+    }
+    match run(&scheduler.status()).await {
+        Ok(output) => println!("\n{}", output.trim_end()),
+        Err(e) => log::warn!("{}", e),
+    }
+    Ok(())
+}
+
+/// `seconds` in the largest unit that divides them, e.g. `1d` or `90m`
+fn describe(seconds: i64) -> String {
+    [(604800, "w"), (86400, "d"), (3600, "h"), (60, "m")]
+        .iter()
+        .find(|(unit, _)| seconds % unit == 0)
+        .map_or_else(
+            || format!("{}s", seconds),
+            |(unit, suffix)| format!("{}{}", seconds / unit, suffix),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_files_run_update() {
+        let command = args(&["/opt/my tools/dotme", "--yes", "update", "--quiet"]);
+
+        let service = systemd_service(&command, "/usr/bin:/bin");
+        assert!(service.contains(
+            "Environment=PATH=/usr/bin:/bin\nExecStart=\"/opt/my tools/dotme\" --yes update --quiet\n"
+        ));
+        assert!(systemd_timer(86400).contains("OnUnitActiveSec=86400s\n"));
+
+        let plist = launchd_plist(&command, "/usr/bin", 3600);
+        assert!(plist.contains("<string>/opt/my tools/dotme</string>"));
+        assert!(plist.contains("<key>StartInterval</key>\n    <integer>3600</integer>"));
+
+        assert_eq!(describe(86400), "1d");
+        assert_eq!(describe(5400), "90m");
+        assert_eq!(describe(90), "90s");
+    }
+}
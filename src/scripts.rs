@@ -0,0 +1,460 @@
+//! Scripts run for entries: at points in their life, and from their sources with `dotme run`
+//!
+//! An entry can set up what its dotfiles need once they are in place, e.g. install editor
+//! plugins or compile terminfo files. `install` runs after the entry was first fetched and
+//! linked, `update` after `dotme update` refreshed and relinked it, and `remove` before
+//! `dotme remove` takes it away. `{{ name }}` in a script stands for the value of a
+//! [variable](crate::variables). Scripts run with `sh -c` (`cmd /C` on Windows) in the entry's
+//! local copy, with the entry's paths in the environment:
+//!
+//! - `DOTME_SOURCE`: the source as configured
+//! - `DOTME_NAME`: the entry's name
+//! - `DOTME_ROOT`: the local copy, e.g. the clone of a git repository
+//! - `DOTME_TARGET`: the entry's target
+//! - `DOTME_PATH`: the directory links are created in
+//! - `DOTME_STAGE`: `install`, `update`, or `remove`
+//!
+//! ```yaml
+//! dotfiles:
+//!   - source: https://github.com/user/nvim-config.git
+//!     scripts:
+//!       install: nvim --headless +PlugInstall +qa
+//!       update: nvim --headless +PlugUpdate +qa
+//! ```
+//!
+//! Sources can also ship scripts in a `scripts/` or `install.d/` directory, which `dotme run`
+//! runs in the order of their names, e.g. `10-fonts.sh` before `20-apt.linux.sh`. A `linux`,
+//! `macos`, or `windows` part in the name limits a script to that operating system. Executable
+//! scripts run directly, others with `sh`, with the same environment as above, `DOTME_STAGE`
+//! set to `run`, and each variable as `DOTME_VAR_<NAME>`. When each script last succeeded is
+//! kept in the state.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::config::{Config, DotfileEntry, Os};
+use crate::dotfiles;
+use crate::redact;
+use crate::sources;
+use crate::symlinks::{self, SymlinkState};
+use crate::variables::{self, Variable};
+
+/// Directories of a source holding scripts for `dotme run`, in the order they run
+const SCRIPT_DIRS: &[&str] = &["scripts", "install.d"];
+
+/// Scripts of an entry, by stage
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Scripts {
+    /// Run after the entry was first fetched and linked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install: Option<String>,
+    /// Run after `update` refreshed and relinked the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<String>,
+    /// Run before the entry is removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remove: Option<String>,
+}
+
+impl Scripts {
+    /// Check if no script is set, so the section can be left out of the config
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Script run at `stage`
+    pub fn get(&self, stage: Stage) -> Option<&str> {
+        match stage {
+            Stage::Install => self.install.as_deref(),
+            Stage::Update => self.update.as_deref(),
+            Stage::Remove => self.remove.as_deref(),
+        }
+    }
+}
+
+/// Point in an entry's life a script runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// After the entry was first fetched and linked
+    Install,
+    /// After the entry was refreshed and relinked
+    Update,
+    /// Before the entry is removed
+    Remove,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Install => write!(f, "install"),
+            Self::Update => write!(f, "update"),
+            Self::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+/// Shell command line running `script`
+pub fn shell(script: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        // Delayed expansion keeps the values of variables out of the parsing of the command line
+        let mut command = Command::new("cmd");
+        command.arg("/V:ON").arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(script);
+    command
+}
+
+/// Give `command` the paths of `entry` in its environment, and run it in the entry's local copy
+fn for_entry(command: &mut Command, entry: &DotfileEntry, stage: &str) -> Result<()> {
+    let root = sources::provider(entry.r#type).root(entry);
+    command
+        .env("DOTME_SOURCE", &entry.source)
+        .env("DOTME_NAME", dotfiles::entry_name(entry))
+        .env("DOTME_ROOT", &root)
+        .env("DOTME_TARGET", &entry.target)
+        .env("DOTME_PATH", dotfiles::base_path(entry)?)
+        .env("DOTME_STAGE", stage);
+    // File entries run next to their file
+    let dir = if root.is_dir() {
+        Some(root.as_path())
+    } else {
+        root.parent()
+    };
+    if let Some(dir) = dir.filter(|dir| dir.is_dir()) {
+        command.current_dir(dir);
+    }
+    Ok(())
+}
+
+/// Run the script of `entry` for `stage`, if it has one, with `variables` filled in
+pub async fn run(
+    entry: &DotfileEntry,
+    stage: Stage,
+    variables: &BTreeMap<String, Variable>,
+    dry_run: bool,
+) -> Result<()> {
+    let Some(script) = entry.scripts.get(stage) else {
+        return Ok(());
+    };
+    if dry_run {
+        println!(
+            "[DRY RUN] Would run the {} script of {}: {}",
+            stage,
+            redact::redact(&entry.source),
+            redact::redact(script)
+        );
+        return Ok(());
+    }
+
+    log::info!("Running the {} script of {}", stage, entry.source);
+    let rendered = variables::render(script, variables)
+        .with_context(|| format!("Can't run the {} script of {}", stage, entry.source))?;
+    let mut command = shell(&rendered.script);
+    command.envs(&rendered.env);
+    for_entry(&mut command, entry, &stage.to_string())?;
+
+    let status = command
+        .status()
+        .await
+        .with_context(|| format!("Failed to run the {} script of {}", stage, entry.source))?;
+    if !status.success() {
+        anyhow::bail!(
+            "The {} script of {} failed ({})",
+            stage,
+            entry.source,
+            status
+        );
+    }
+    Ok(())
+}
+
+/// A script shipped in an entry's source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceScript {
+    /// Path relative to the entry's local copy, e.g. `scripts/10-fonts.sh`
+    pub relative: String,
+    /// Where the script is
+    pub path: PathBuf,
+}
+
+impl SourceScript {
+    /// Key of the script's last run in the state
+    fn key(&self, entry: &DotfileEntry) -> String {
+        format!("{}#{}", entry.source, self.relative)
+    }
+
+    /// Check if `name` refers to the script: its path, its file name, or its file name without
+    /// extensions
+    fn is_named(&self, name: &str) -> bool {
+        let file_name = self.relative.rsplit('/').next().unwrap_or_default();
+        [
+            self.relative.as_str(),
+            file_name,
+            file_name.split('.').next().unwrap_or_default(),
+        ]
+        .contains(&name)
+    }
+}
+
+/// Operating system a script is limited to by a part of its name, e.g. `20-apt.linux.sh`
+fn script_os(file_name: &str) -> Option<Os> {
+    file_name.split('.').skip(1).find_map(|part| match part {
+        "linux" => Some(Os::Linux),
+        "macos" => Some(Os::Macos),
+        "windows" => Some(Os::Windows),
+        _ => None,
+    })
+}
+
+/// Scripts in the local copy at `root` that apply to `os`, in the order they run
+pub fn discover(root: &Path, os: Option<Os>) -> Vec<SourceScript> {
+    let mut scripts = Vec::new();
+    for dir in SCRIPT_DIRS {
+        let Ok(items) = std::fs::read_dir(root.join(dir)) else {
+            continue;
+        };
+        let mut names: Vec<_> = items
+            .flatten()
+            .filter(|item| item.path().is_file())
+            .map(|item| item.file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with('.'))
+            .filter(|name| script_os(name).is_none_or(|only| Some(only) == os))
+            .collect();
+        names.sort();
+        scripts.extend(names.into_iter().map(|name| SourceScript {
+            relative: format!("{}/{}", dir, name),
+            path: root.join(dir).join(name),
+        }));
+    }
+    scripts
+}
+
+/// Command running the script at `path`: directly if it is executable, else with `sh`
+#[cfg(unix)]
+fn script_command(path: &Path) -> Command {
+    use std::os::unix::fs::PermissionsExt;
+
+    let executable =
+        std::fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o111 != 0);
+    if executable {
+        Command::new(path)
+    } else {
+        let mut command = Command::new("sh");
+        command.arg(path);
+        command
+    }
+}
+
+/// Command running the script at `path`, through `cmd` so batch files work too
+#[cfg(not(unix))]
+fn script_command(path: &Path) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(path);
+    command
+}
+
+/// Options for running the scripts shipped in sources
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Only the script with this path, file name, or file name without extensions
+    pub script: Option<String>,
+    /// Only the scripts of the entry with this source or name
+    pub entry: Option<String>,
+    /// List the scripts and when they last ran instead of running them
+    pub list: bool,
+    /// Show what would run without running it
+    pub dry_run: bool,
+}
+
+/// Run the scripts shipped in the sources of the entries, entry by entry in the order they apply
+pub async fn run_source_scripts(options: RunOptions) -> Result<()> {
+    let config_path = dotfiles::get_config_path()?;
+
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+
+    let config = Config::load(Some(config_path))?;
+    let mut entries = dotfiles::active_entries(&config)?;
+    if let Some(name) = &options.entry {
+        entries = vec![dotfiles::find_entry(&entries, name)?.clone()];
+    }
+
+    let mut scripts = Vec::new();
+    for entry in &entries {
+        let root = sources::provider(entry.r#type).root(entry);
+        for script in discover(&root, Os::current()) {
+            scripts.push((entry, script));
+        }
+    }
+    if let Some(name) = &options.script {
+        scripts.retain(|(_, script)| script.is_named(name));
+        if scripts.is_empty() {
+            anyhow::bail!("No script '{}' in the sources of the entries", name);
+        }
+        if scripts.len() > 1 {
+            anyhow::bail!(
+                "'{}' could be any of {}. Pick the entry with --entry.",
+                name,
+                scripts
+                    .iter()
+                    .map(|(entry, script)| script.key(entry))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+
+    if scripts.is_empty() {
+        println!(
+            "No scripts found. Put them in a scripts/ or install.d/ directory of an entry's source."
+        );
+        return Ok(());
+    }
+
+    if options.list {
+        let runs = SymlinkState::load().await?.script_runs;
+        println!("Scripts:");
+        println!("─────────────────────────────────────────");
+        let mut current = None;
+        for (entry, script) in &scripts {
+            if current != Some(&entry.source) {
+                println!("  {}", entry.source);
+                current = Some(&entry.source);
+            }
+            let last_run = match runs.get(&script.key(entry)) {
+                Some(time) => format!("last run {}", dotfiles::format_timestamp(time)),
+                None => "never run".to_string(),
+            };
+            println!("    {:<32} {}", script.relative, last_run);
+        }
+        return Ok(());
+    }
+
+    let values = if options.dry_run {
+        BTreeMap::new()
+    } else {
+        variables::resolve_all(&config.variables)?
+    };
+    for (entry, script) in &scripts {
+        if options.dry_run {
+            println!(
+                "[DRY RUN] Would run {} of {}",
+                script.relative,
+                redact::redact(&entry.source)
+            );
+            continue;
+        }
+
+        log::info!("Running {} of {}", script.relative, entry.source);
+        let mut command = script_command(&script.path);
+        for_entry(&mut command, entry, "run")?;
+        for (name, value) in &values {
+            command.env(variables::env_name(name), value);
+        }
+        let status = command
+            .status()
+            .await
+            .with_context(|| format!("Failed to run {}", script.path.display()))?;
+        // Later scripts may rely on the earlier ones, so the first failure stops the run
+        if !status.success() {
+            anyhow::bail!(
+                "{} of {} failed ({})",
+                script.relative,
+                entry.source,
+                status
+            );
+        }
+        symlinks::record_script_run(&script.key(entry)).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_scripts_see_the_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("dots");
+        std::fs::create_dir(&source).unwrap();
+        let entry = DotfileEntry {
+            target: source.clone(),
+            path: Some(dir.path().join("home")),
+            scripts: Scripts {
+                install: Some("echo \"$DOTME_STAGE $DOTME_NAME $DOTME_PATH\" > out".to_string()),
+                update: Some("exit 3".to_string()),
+                ..Default::default()
+            },
+            ..DotfileEntry::new(
+                source.to_string_lossy().into_owned(),
+                crate::config::SourceType::Directory,
+            )
+        };
+
+        let variables = BTreeMap::new();
+        run(&entry, Stage::Install, &variables, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(source.join("out")).unwrap(),
+            format!("install dots {}\n", dir.path().join("home").display())
+        );
+        assert!(run(&entry, Stage::Update, &variables, false).await.is_err());
+        run(&entry, Stage::Remove, &variables, false).await.unwrap();
+    }
+
+    #[test]
+    fn test_source_scripts_run_in_order_for_their_os() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in [
+            "scripts/20-apt.linux.sh",
+            "scripts/10-fonts.sh",
+            "scripts/30-brew.macos.sh",
+            "scripts/.hidden",
+            "install.d/00-first-of-install.d",
+        ] {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("scripts/lib")).unwrap();
+
+        let found = |os| {
+            discover(dir.path(), os)
+                .into_iter()
+                .map(|script| script.relative)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            found(Some(Os::Linux)),
+            [
+                "scripts/10-fonts.sh",
+                "scripts/20-apt.linux.sh",
+                "install.d/00-first-of-install.d"
+            ]
+        );
+        assert_eq!(
+            found(Some(Os::Macos))[1],
+            "scripts/30-brew.macos.sh".to_string()
+        );
+
+        let script = &discover(dir.path(), Some(Os::Linux))[1];
+        assert!(script.is_named("20-apt"));
+        assert!(script.is_named("20-apt.linux.sh"));
+        assert!(script.is_named("scripts/20-apt.linux.sh"));
+        assert!(!script.is_named("apt"));
+    }
+}
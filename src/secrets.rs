@@ -0,0 +1,118 @@
+//! Secrets read from password managers while scripts are rendered
+//!
+//! A script can refer to a secret as `{{ secret "op://vault/item/field" }}` (1Password),
+//! `{{ secret "pass://email/smtp" }}` (pass), or `{{ secret "bw://item/field" }}` (Bitwarden)
+//! instead of keeping it in the repository. The scheme of the reference picks the [`SecretProvider`] that reads it,
+//! through the password manager's own command line tool, so dotme never stores credentials.
+//! Each secret is read once per run, and handed to the script in its environment.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{LazyLock, Mutex};
+
+use crate::redact;
+
+mod bitwarden;
+mod onepassword;
+mod pass;
+
+/// A password manager secrets can be read from
+pub trait SecretProvider: Send + Sync {
+    /// Scheme of the references the provider reads, e.g. `op` for `op://vault/item/field`
+    fn scheme(&self) -> &'static str;
+
+    /// Read the secret `reference` points to
+    fn read(&self, reference: &str) -> Result<String>;
+}
+
+/// All providers, by the scheme of their references
+static PROVIDERS: &[&dyn SecretProvider] = &[
+    &onepassword::OnePassword,
+    &pass::PasswordStore,
+    &bitwarden::Bitwarden,
+];
+
+/// Secrets read so far in this run, by reference
+static READ: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+/// Get the provider for a reference, by its scheme
+pub fn provider(reference: &str) -> Result<&'static dyn SecretProvider> {
+    let scheme = reference
+        .split_once("://")
+        .map(|(scheme, _)| scheme)
+        .with_context(|| format!("Secret reference '{}' has no scheme, e.g. op://", reference))?;
+    PROVIDERS
+        .iter()
+        .find(|provider| provider.scheme() == scheme)
+        .copied()
+        .with_context(|| {
+            let schemes: Vec<_> = PROVIDERS.iter().map(|p| p.scheme()).collect();
+            format!(
+                "Unknown secret scheme '{}', expected one of: {}",
+                scheme,
+                schemes.join(", ")
+            )
+        })
+}
+
+/// Read the secret `reference` points to, from its provider or from an earlier read
+pub fn read(reference: &str) -> Result<String> {
+    if let Some(secret) = READ
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(reference)
+    {
+        return Ok(secret.clone());
+    }
+    let secret = provider(reference)?.read(reference)?;
+    redact::register(&secret);
+    READ.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(reference.to_string(), secret.clone());
+    Ok(secret)
+}
+
+/// Run a password manager's `command`, named `tool` in errors, and return what it printed
+/// without the trailing newline
+fn output(mut command: Command, tool: &str, reference: &str) -> Result<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(std::process::Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run {}. Is the {} installed?", program, tool))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} couldn't read {}: {}",
+            program,
+            reference,
+            String::from_utf8_lossy(&output.stderr)
+                .trim()
+                .trim_end_matches('.')
+        );
+    }
+
+    let mut secret =
+        String::from_utf8(output.stdout).with_context(|| format!("{} is not text", reference))?;
+    if secret.ends_with('\n') {
+        secret.pop();
+        if secret.ends_with('\r') {
+            secret.pop();
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references_pick_their_provider() {
+        assert_eq!(provider("op://vault/item/field").unwrap().scheme(), "op");
+        assert_eq!(provider("pass://email/smtp").unwrap().scheme(), "pass");
+        assert_eq!(provider("bw://GitHub/token").unwrap().scheme(), "bw");
+        assert!(provider("vault/item/field").is_err());
+        assert!(provider("keychain://item").is_err());
+    }
+}
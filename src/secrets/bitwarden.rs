@@ -0,0 +1,146 @@
+//! Secrets in Bitwarden, read with its `bw` command line tool
+//!
+//! `bw` only reads an unlocked vault, identified by a session key. The key is taken from
+//! `BW_SESSION` if it is set; otherwise the vault is unlocked once, asking for the master
+//! password, and the key is kept for the rest of the run (never written to disk).
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::process::Command;
+use std::sync::Mutex;
+
+use super::SecretProvider;
+use crate::prompt;
+use crate::redact;
+
+/// Session key of the vault unlocked during this run
+static SESSION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Fields `bw get` reads directly; anything else is a custom field of the item
+const FIELDS: &[&str] = &["password", "username", "totp", "notes", "uri"];
+
+/// Provider for `bw://item` (the password) and `bw://item/field` references, the item being
+/// its name or id
+pub struct Bitwarden;
+
+/// What `bw status` reports
+#[derive(Deserialize)]
+struct Status {
+    /// `unauthenticated`, `locked`, or `unlocked`
+    status: String,
+}
+
+/// An item as `bw get item` prints it, with only its custom fields
+#[derive(Deserialize)]
+struct Item {
+    /// Custom fields of the item
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+/// A custom field of an item
+#[derive(Deserialize)]
+struct Field {
+    /// Name of the field
+    name: String,
+    /// Its value
+    value: Option<String>,
+}
+
+/// Split a reference into the item and the field read from it
+fn parse(reference: &str) -> Result<(&str, &str)> {
+    let path = reference
+        .strip_prefix("bw://")
+        .filter(|path| !path.is_empty())
+        .with_context(|| format!("Expected bw://item or bw://item/field, got '{}'", reference))?;
+    Ok(path.rsplit_once('/').unwrap_or((path, "password")))
+}
+
+/// Session key of an unlocked vault, unlocking it if needed
+fn session() -> Result<String> {
+    if let Ok(session) = std::env::var("BW_SESSION")
+        && !session.is_empty()
+    {
+        return Ok(session);
+    }
+    let mut cached = SESSION.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(session) = cached.as_ref() {
+        return Ok(session.clone());
+    }
+
+    let mut command = Command::new("bw");
+    command.arg("status");
+    let status: Status = serde_json::from_str(&super::output(command, "Bitwarden CLI", "status")?)
+        .context("Failed to parse the output of bw status")?;
+    match status.status.as_str() {
+        "unauthenticated" => anyhow::bail!("Not logged in to Bitwarden. Run 'bw login' first."),
+        "unlocked" => {
+            *cached = Some(String::new());
+            return Ok(String::new());
+        }
+        _ => {}
+    }
+    prompt::require_interactive(
+        "Unlock Bitwarden first with: export BW_SESSION=\"$(bw unlock --raw)\"",
+    )?;
+
+    // bw asks for the master password on stderr
+    let mut command = Command::new("bw");
+    command
+        .args(["unlock", "--raw"])
+        .stderr(std::process::Stdio::inherit());
+    let session = super::output(command, "Bitwarden CLI", "the vault")?;
+    redact::register(&session);
+    *cached = Some(session.clone());
+    Ok(session)
+}
+
+/// `bw` with the session key, never asking anything itself
+fn bw(session: &str) -> Command {
+    let mut command = Command::new("bw");
+    command.arg("--nointeraction");
+    if !session.is_empty() {
+        command.env("BW_SESSION", session);
+    }
+    command
+}
+
+impl SecretProvider for Bitwarden {
+    fn scheme(&self) -> &'static str {
+        "bw"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        let (item, field) = parse(reference)?;
+        let mut command = bw(&session()?);
+        if FIELDS.contains(&field) {
+            command.args(["get", field, item]);
+            return super::output(command, "Bitwarden CLI", reference);
+        }
+
+        command.args(["get", "item", item]);
+        let item: Item = serde_json::from_str(&super::output(command, "Bitwarden CLI", reference)?)
+            .with_context(|| format!("Failed to parse the item of {}", reference))?;
+        item.fields
+            .into_iter()
+            .find(|custom| custom.name == field)
+            .and_then(|custom| custom.value)
+            .with_context(|| format!("{} has no field '{}'", reference, field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references_name_an_item_and_a_field() {
+        assert_eq!(parse("bw://GitHub").unwrap(), ("GitHub", "password"));
+        assert_eq!(
+            parse("bw://GitHub/username").unwrap(),
+            ("GitHub", "username")
+        );
+        assert_eq!(parse("bw://Work/VPN/pin").unwrap(), ("Work/VPN", "pin"));
+        assert!(parse("bw://").is_err());
+    }
+}
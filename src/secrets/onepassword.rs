@@ -0,0 +1,21 @@
+//! Secrets in 1Password, read with its `op` command line tool
+
+use anyhow::Result;
+use std::process::Command;
+
+use super::SecretProvider;
+
+/// Provider for `op://vault/item/field` references
+pub struct OnePassword;
+
+impl SecretProvider for OnePassword {
+    fn scheme(&self) -> &'static str {
+        "op"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        let mut command = Command::new("op");
+        command.args(["read", "--no-newline", reference]);
+        super::output(command, "1Password CLI", reference)
+    }
+}
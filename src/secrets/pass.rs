@@ -0,0 +1,27 @@
+//! Secrets in the standard Unix password store, read with `pass`
+
+use anyhow::{Context, Result};
+use std::process::Command;
+
+use super::SecretProvider;
+
+/// Provider for `pass://path/in/store` references, which read the first line of the entry, the
+/// password by convention
+pub struct PasswordStore;
+
+impl SecretProvider for PasswordStore {
+    fn scheme(&self) -> &'static str {
+        "pass"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        let name = reference
+            .strip_prefix("pass://")
+            .filter(|name| !name.is_empty())
+            .with_context(|| format!("Expected pass://path/in/store, got '{}'", reference))?;
+        let mut command = Command::new("pass");
+        command.args(["show", name]);
+        let entry = super::output(command, "password store (pass)", reference)?;
+        Ok(entry.lines().next().unwrap_or_default().to_string())
+    }
+}
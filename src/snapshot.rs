@@ -0,0 +1,225 @@
+//! Snapshots of the whole setup, for `dotme snapshot`
+//!
+//! `dotme snapshot create [name]` keeps what makes up the setup on this machine in
+//! `snapshots/<name>` in the dotme directory, so a state known to work is on record before
+//! trying something out:
+//!
+//! | File           | What                                                       |
+//! |----------------|------------------------------------------------------------|
+//! | `snapshot.yml` | when it was taken, and the commit each repository is at    |
+//! | `config.yml`   | the config, with the files it includes merged in           |
+//! | `symlinks.yml` | the symlink state: every link and what it points to        |
+//!
+//! The files are encrypted like the config when [encryption](crate::encryption) is on. The
+//! name defaults to the time the snapshot is taken, e.g. `2026-10-16-131925`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::conditions;
+use crate::config::{Config, SourceType};
+use crate::dotfiles;
+use crate::encryption;
+use crate::git;
+use crate::sources;
+use crate::symlinks::SymlinkState;
+
+/// What a snapshot was taken of, besides the config and the state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    /// When it was taken (RFC 3339)
+    pub created: String,
+    /// Machine it was taken on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Version of dotme that took it
+    pub version: String,
+    /// Number of links in the symlink state
+    #[serde(default)]
+    pub links: usize,
+    /// Commit each git repository was at
+    #[serde(default)]
+    pub repositories: Vec<Repository>,
+}
+
+/// A git repository in a snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Repository {
+    /// Source of the entry
+    pub source: String,
+    /// Where it is cloned
+    pub path: PathBuf,
+    /// Commit it was at
+    pub commit: String,
+    /// Branch it was on, unless it was detached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+/// Directory the snapshots are kept in
+fn snapshots_dir() -> Result<PathBuf> {
+    Ok(dotfiles::get_dotme_dir()?.join("snapshots"))
+}
+
+/// Name for a snapshot taken at `time`
+fn default_name(time: chrono::DateTime<chrono::Local>) -> String {
+    time.format("%Y-%m-%d-%H%M%S").to_string()
+}
+
+/// Check that `name` can be used as the directory of a snapshot
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+    {
+        anyhow::bail!(
+            "Invalid snapshot name '{}': it can't be empty, start with '.', or contain slashes",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Commit and branch of each git repository of `config` that is on this machine
+async fn repositories(config: &Config) -> Result<Vec<Repository>> {
+    let mut repositories = Vec::new();
+    for entry in dotfiles::active_entries(config)? {
+        let path = sources::provider(entry.r#type).root(&entry);
+        if entry.r#type != SourceType::Git || !path.exists() {
+            continue;
+        }
+        match git::head_commit(&path).await {
+            Ok(commit) => repositories.push(Repository {
+                branch: git::current_branch(&path).await.ok().flatten(),
+                source: entry.source,
+                path,
+                commit,
+            }),
+            Err(e) => log::warn!("Leaving {} out of the snapshot: {}", entry.source, e),
+        }
+    }
+    Ok(repositories)
+}
+
+/// Capture the config, the symlink state, and the commit of each repository as `name`
+pub async fn create(name: Option<String>) -> Result<()> {
+    let config_path = dotfiles::get_config_path()?;
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+    let config = Config::load(Some(config_path))?;
+
+    let name = name.unwrap_or_else(|| default_name(chrono::Local::now()));
+    check_name(&name)?;
+    let dir = snapshots_dir()?.join(&name);
+    if dir.exists() {
+        anyhow::bail!(
+            "Snapshot '{}' already exists. Pick another name or delete {}.",
+            name,
+            dir.display()
+        );
+    }
+
+    let state = SymlinkState::load().await?;
+    let snapshot = Snapshot {
+        created: chrono::Utc::now().to_rfc3339(),
+        host: conditions::hostname(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        links: state.symlinks.len(),
+        repositories: repositories(&config).await?,
+    };
+    // Self-contained, so the included files can change without changing the snapshot
+    let mut merged = config.clone();
+    merged.include.clear();
+
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let written = write_files(&dir, &merged, &state, &snapshot);
+    if written.is_err() {
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+    written?;
+
+    println!(
+        "✓ Created snapshot '{}' of {} entry(ies), {} link(s), and {} repository(ies) in {}",
+        name,
+        config.dotfiles.len(),
+        snapshot.links,
+        snapshot.repositories.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Write the files of a snapshot into `dir`
+fn write_files(
+    dir: &Path,
+    config: &Config,
+    state: &SymlinkState,
+    snapshot: &Snapshot,
+) -> Result<()> {
+    encryption::write(&dir.join("config.yml"), &serde_yaml::to_string(config)?)?;
+    encryption::write(&dir.join("symlinks.yml"), &serde_yaml::to_string(state)?)?;
+    encryption::write(&dir.join("snapshot.yml"), &serde_yaml::to_string(snapshot)?)
+}
+
+/// List the snapshots taken, oldest first
+pub async fn list() -> Result<()> {
+    let dir = snapshots_dir()?;
+    let names: Vec<String> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| entry.path().join("snapshot.yml").exists())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    if names.is_empty() {
+        println!("No snapshots yet. Take one with 'dotme snapshot create'.");
+        return Ok(());
+    }
+
+    let mut snapshots = Vec::new();
+    for name in names {
+        let path = dir.join(&name).join("snapshot.yml");
+        let snapshot: Snapshot = serde_yaml::from_str(&encryption::read(&path)?)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        snapshots.push((name, snapshot));
+    }
+    snapshots.sort_by(|(_, a), (_, b)| a.created.cmp(&b.created));
+
+    println!("Snapshots in {}:", dir.display());
+    println!("─────────────────────────────────────────");
+    for (name, snapshot) in snapshots {
+        println!(
+            "  {:<24} {}  {} link(s), {} repository(ies)",
+            name,
+            dotfiles::format_timestamp(&snapshot.created),
+            snapshot.links,
+            snapshot.repositories.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_snapshot_names_are_directory_names() {
+        let time = chrono::Local
+            .with_ymd_and_hms(2026, 10, 16, 13, 19, 25)
+            .unwrap();
+        assert_eq!(default_name(time), "2026-10-16-131925");
+        assert!(check_name(&default_name(time)).is_ok());
+        assert!(check_name("before-nix").is_ok());
+
+        assert!(check_name("").is_err());
+        assert!(check_name("..").is_err());
+        assert!(check_name("a/b").is_err());
+        assert!(check_name("a\\b").is_err());
+    }
+}
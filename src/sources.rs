@@ -0,0 +1,231 @@
+//! Providers for the kinds of dotfile sources
+//!
+//! Every [`SourceType`] has a [`SourceProvider`] that knows how to put the source on this
+//! machine, keep it up to date, and list what gets linked from it. The commands only talk to
+//! the provider, so a new kind of source (archives, HTTP, generators, ...) is a new provider
+//! rather than another arm in every match.
+
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use crate::config::{DotfileEntry, GitConfig, PathsConfig, SourceType};
+
+mod directory;
+mod file;
+mod rclone;
+mod repository;
+
+pub use rclone::is_rclone_remote;
+pub use repository::{is_remote, repo_name};
+
+/// Boxed future returned by providers, as async trait methods can't be used through `dyn`
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Where the links of an entry come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkSource {
+    /// A single file, linked under its own name
+    File(PathBuf),
+    /// Every item inside a directory
+    Contents(PathBuf),
+}
+
+/// Everything the commands need to know about a kind of source
+pub trait SourceProvider: Send + Sync {
+    /// Location of the entry's content on this machine; its managed links point into it
+    fn root(&self, entry: &DotfileEntry) -> PathBuf;
+
+    /// Where the entry is kept when no target is given
+    fn default_target(&self, entry: &DotfileEntry) -> Result<PathBuf>;
+
+    /// Reject settings that don't apply to this kind of source
+    fn validate(&self, entry: &DotfileEntry) -> Result<()>;
+
+    /// Whether folders of the source are picked when it is added
+    fn selects_folders(&self) -> bool {
+        false
+    }
+
+    /// Put the source in place when it is added
+    fn fetch<'a>(&'a self, entry: &'a DotfileEntry, git: &'a GitConfig) -> BoxFuture<'a, ()>;
+
+    /// Bring the source up to date, fetching it if it is missing
+    fn refresh<'a>(
+        &'a self,
+        entry: &'a DotfileEntry,
+        git: &'a GitConfig,
+        dry_run: bool,
+    ) -> BoxFuture<'a, ()>;
+
+    /// What gets linked into the entry's base path
+    fn list_files(&self, entry: &DotfileEntry) -> Result<Vec<LinkSource>>;
+
+    /// Fields describing where the source is kept, for `status` and `show`
+    fn describe(&self, entry: &DotfileEntry) -> Vec<(&'static str, String)>;
+
+    /// Copy of the source that dotme keeps itself, deleted or archived along with the entry
+    fn managed_copy(&self, _entry: &DotfileEntry, _paths: &PathsConfig) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+/// Get the provider for a kind of source
+pub fn provider(source_type: SourceType) -> &'static dyn SourceProvider {
+    match source_type {
+        SourceType::File => &file::FileSource,
+        SourceType::Directory => &directory::DirectorySource,
+        SourceType::Git => &repository::GitSource,
+        SourceType::Rclone => &rclone::RcloneSource,
+    }
+}
+
+/// Resolve a local source path against the current directory
+fn absolute(source: &str) -> Result<PathBuf> {
+    let path = Path::new(source);
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+/// Reject git-only settings on sources that aren't git repositories
+fn reject_git_options(entry: &DotfileEntry) -> Result<()> {
+    if entry.clone_path.is_some() {
+        anyhow::bail!("--clone-path can only be used with remote git repositories");
+    }
+    if entry.branch.is_some() || entry.rev.is_some() || entry.depth.is_some() {
+        anyhow::bail!("--branch, --rev, and --depth can only be used with git repositories");
+    }
+    Ok(())
+}
+
+/// Default target of a local file or directory: its name inside the entry's base path
+fn target_in_base_path(entry: &DotfileEntry) -> Result<PathBuf> {
+    let base_path = match &entry.path {
+        Some(path) => path.clone(),
+        None => dirs::home_dir().context("Failed to get home directory")?,
+    };
+    let name = Path::new(&entry.source)
+        .file_name()
+        .context("Failed to get filename from source")?;
+    Ok(base_path.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_targets() {
+        let mut entry = DotfileEntry::new("https://github.com/user/dotfiles.git", SourceType::Git);
+        let target = provider(entry.r#type).default_target(&entry).unwrap();
+        assert!(target.ends_with(".dotme/git/dotfiles"));
+
+        entry.clone_path = Some(PathBuf::from("/opt/dotfiles"));
+        let target = provider(entry.r#type).default_target(&entry).unwrap();
+        assert_eq!(target, PathBuf::from("/opt/dotfiles"));
+
+        entry.source = "file:///srv/git/dotfiles.git".to_string();
+        entry.clone_path = None;
+        let target = provider(entry.r#type).default_target(&entry).unwrap();
+        assert!(target.ends_with(".dotme/git/dotfiles"));
+
+        let entry = DotfileEntry {
+            path: Some(PathBuf::from("/home/user")),
+            ..DotfileEntry::new("/srv/config/.vimrc", SourceType::File)
+        };
+        let target = provider(entry.r#type).default_target(&entry).unwrap();
+        assert_eq!(target, PathBuf::from("/home/user/.vimrc"));
+    }
+
+    #[test]
+    fn test_git_options_are_rejected_for_local_sources() {
+        let entry = DotfileEntry {
+            branch: Some("main".to_string()),
+            ..DotfileEntry::new("/srv/config", SourceType::Directory)
+        };
+        assert!(provider(entry.r#type).validate(&entry).is_err());
+
+        let entry = DotfileEntry {
+            clone_path: Some(PathBuf::from("/opt/repo")),
+            ..DotfileEntry::new("/srv/repo", SourceType::Git)
+        };
+        assert!(provider(entry.r#type).validate(&entry).is_err());
+    }
+
+    #[test]
+    fn test_rclone_remotes() {
+        assert!(is_rclone_remote("dropbox:dotfiles"));
+        assert!(is_rclone_remote("s3:my-bucket/config/dots"));
+        assert!(!is_rclone_remote("C:\\Users\\me\\dotfiles"));
+        assert!(!is_rclone_remote("git@github.com:user/dotfiles.git"));
+        assert!(!is_rclone_remote("https://example.com/dotfiles"));
+        assert!(!is_rclone_remote("./dotfiles"));
+
+        assert_eq!(rclone::remote_name("s3:my-bucket/config/dots/"), "dots");
+        assert_eq!(rclone::remote_name("gdrive:"), "gdrive");
+
+        let entry = DotfileEntry::new("dropbox:dotfiles", SourceType::Rclone);
+        let target = provider(entry.r#type).default_target(&entry).unwrap();
+        assert!(target.ends_with(".dotme/remote/dotfiles"));
+    }
+
+    #[test]
+    fn test_git_lists_selected_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("zsh")).unwrap();
+        std::fs::create_dir_all(dir.path().join("config/nvim")).unwrap();
+        std::fs::write(dir.path().join("zsh/aliases.zsh"), "").unwrap();
+
+        let folders = [
+            "zsh",
+            "missing",
+            "config/nvim",
+            "zsh/aliases.zsh",
+            "../outside",
+        ];
+        let entry = DotfileEntry {
+            target: dir.path().to_path_buf(),
+            folders: Some(folders.iter().map(|folder| folder.to_string()).collect()),
+            ..DotfileEntry::new(dir.path().to_string_lossy().to_string(), SourceType::Git)
+        };
+        assert_eq!(
+            provider(entry.r#type).list_files(&entry).unwrap(),
+            vec![
+                LinkSource::Contents(dir.path().join("zsh")),
+                LinkSource::Contents(dir.path().join("config/nvim")),
+                LinkSource::File(dir.path().join("zsh/aliases.zsh")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_git_expands_folder_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        for folder in [
+            "config/nvim",
+            "config/git",
+            "config/nvim/lua",
+            "zsh-plugins",
+            "zshrc.d",
+        ] {
+            std::fs::create_dir_all(dir.path().join(folder)).unwrap();
+        }
+
+        let entry = DotfileEntry {
+            target: dir.path().to_path_buf(),
+            folders: Some(vec!["config/*".to_string(), "zsh*".to_string()]),
+            ..DotfileEntry::new(dir.path().to_string_lossy().to_string(), SourceType::Git)
+        };
+        assert_eq!(
+            provider(entry.r#type).list_files(&entry).unwrap(),
+            ["config/git", "config/nvim", "zsh-plugins", "zshrc.d"]
+                .iter()
+                .map(|folder| LinkSource::Contents(dir.path().join(folder)))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Local directories, whose contents are linked in place
+
+use anyhow::Result;
+use std::path::PathBuf;
+
+use super::{BoxFuture, LinkSource, SourceProvider, reject_git_options, target_in_base_path};
+use crate::config::{DotfileEntry, GitConfig};
+
+/// Provider for local directories
+pub struct DirectorySource;
+
+impl SourceProvider for DirectorySource {
+    fn root(&self, entry: &DotfileEntry) -> PathBuf {
+        PathBuf::from(&entry.source)
+    }
+
+    fn default_target(&self, entry: &DotfileEntry) -> Result<PathBuf> {
+        target_in_base_path(entry)
+    }
+
+    fn validate(&self, entry: &DotfileEntry) -> Result<()> {
+        reject_git_options(entry)
+    }
+
+    fn fetch<'a>(&'a self, _entry: &'a DotfileEntry, _git: &'a GitConfig) -> BoxFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn refresh<'a>(
+        &'a self,
+        _entry: &'a DotfileEntry,
+        _git: &'a GitConfig,
+        _dry_run: bool,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn list_files(&self, entry: &DotfileEntry) -> Result<Vec<LinkSource>> {
+        Ok(vec![LinkSource::Contents(self.root(entry))])
+    }
+
+    fn describe(&self, entry: &DotfileEntry) -> Vec<(&'static str, String)> {
+        vec![("Target", entry.local_path().display().to_string())]
+    }
+}
@@ -0,0 +1,116 @@
+//! Remote storage synced with rclone (S3 buckets, Dropbox, Google Drive, ...)
+//!
+//! Sources are rclone paths such as `dropbox:dotfiles` or `s3:my-bucket/dotfiles`. The remote is
+//! mirrored into `~/.dotme/remote/<name>` and linked like a directory; `update` syncs it again.
+//! The mirror is overwritten on every sync, so changes have to be made on the remote.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use super::{BoxFuture, LinkSource, SourceProvider, reject_git_options};
+use crate::config::{DotfileEntry, GitConfig, PathsConfig};
+use crate::dotfiles;
+use crate::redact;
+
+/// Provider for rclone remotes
+pub struct RcloneSource;
+
+/// Check if a source is an rclone path (`remote:path`) rather than a local path or URL
+pub fn is_rclone_remote(source: &str) -> bool {
+    if source.contains("://") || source.starts_with("git@") {
+        return false;
+    }
+
+    // Remote names are at least two characters, so Windows drives (`C:\...`) don't match
+    source.split_once(':').is_some_and(|(remote, _)| {
+        remote.len() > 1
+            && remote
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ' '))
+    })
+}
+
+/// Name of the mirror directory: the last component of the remote path, or the remote name
+pub fn remote_name(source: &str) -> &str {
+    let (remote, path) = source.split_once(':').unwrap_or(("remote", source));
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(remote)
+}
+
+/// Make `mirror` identical to the remote
+async fn sync(source: &str, mirror: &Path) -> Result<()> {
+    log::info!("Syncing {} to {}", source, mirror.display());
+
+    let output = Command::new("rclone")
+        .arg("sync")
+        .arg(source)
+        .arg(mirror)
+        .output()
+        .await
+        .context("Failed to run rclone. Is it installed?")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "rclone failed to sync {}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+impl SourceProvider for RcloneSource {
+    fn root(&self, entry: &DotfileEntry) -> PathBuf {
+        entry.local_path()
+    }
+
+    fn default_target(&self, entry: &DotfileEntry) -> Result<PathBuf> {
+        Ok(dotfiles::get_remote_dir()?.join(remote_name(&entry.source)))
+    }
+
+    fn validate(&self, entry: &DotfileEntry) -> Result<()> {
+        reject_git_options(entry)
+    }
+
+    fn fetch<'a>(&'a self, entry: &'a DotfileEntry, _git: &'a GitConfig) -> BoxFuture<'a, ()> {
+        Box::pin(async move { sync(&entry.source, &entry.local_path()).await })
+    }
+
+    fn refresh<'a>(
+        &'a self,
+        entry: &'a DotfileEntry,
+        _git: &'a GitConfig,
+        dry_run: bool,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            if dry_run {
+                println!(
+                    "[DRY RUN] Would sync remote: {}",
+                    redact::redact(&entry.source)
+                );
+                return Ok(());
+            }
+            sync(&entry.source, &entry.local_path()).await
+        })
+    }
+
+    fn list_files(&self, entry: &DotfileEntry) -> Result<Vec<LinkSource>> {
+        Ok(vec![LinkSource::Contents(self.root(entry))])
+    }
+
+    fn describe(&self, entry: &DotfileEntry) -> Vec<(&'static str, String)> {
+        vec![("Mirror", entry.local_path().display().to_string())]
+    }
+
+    fn managed_copy(&self, entry: &DotfileEntry, _paths: &PathsConfig) -> Result<Option<PathBuf>> {
+        let mirror = entry.local_path();
+        Ok(mirror
+            .starts_with(dotfiles::get_remote_dir()?)
+            .then_some(mirror))
+    }
+}
@@ -0,0 +1,275 @@
+//! Git repositories, cloned into the dotme git directory (or used in place if local)
+
+use anyhow::{Context, Result};
+use globset::GlobBuilder;
+use std::path::{Component, Path, PathBuf};
+
+use super::{BoxFuture, LinkSource, SourceProvider, absolute};
+use crate::config::{DotfileEntry, GitConfig, PathsConfig, expand_home};
+use crate::dotfiles;
+use crate::git;
+use crate::redact;
+use crate::symlinks;
+
+/// Provider for git repositories
+pub struct GitSource;
+
+/// Check if a git source is a remote URL rather than a local repository
+pub fn is_remote(source: &str) -> bool {
+    ["https://", "http://", "git@", "ssh://"]
+        .iter()
+        .any(|prefix| source.starts_with(prefix))
+}
+
+/// Check if a git source is cloned into the git directory: remotes and `file://` URLs
+fn is_cloned(source: &str) -> bool {
+    is_remote(source) || source.starts_with("file://")
+}
+
+/// Name of the clone directory for a git URL (last path component without `.git`)
+pub fn repo_name(source: &str) -> &str {
+    source
+        .rsplit('/')
+        .next()
+        .unwrap_or("repo")
+        .trim_end_matches(".git")
+}
+
+/// Check that a selected path stays inside the repository
+fn is_inside(folder: &str) -> bool {
+    Path::new(folder)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Check if a selected folder is a glob pattern rather than a path
+fn is_pattern(folder: &str) -> bool {
+    folder.contains(['*', '?', '[', '{'])
+}
+
+/// Replace glob patterns in `folders` (e.g. `config/*`) with the paths of the repository they
+/// match, sorted, so folders added later are picked up without editing the config
+fn expand_patterns(repo_path: &Path, folders: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::new();
+    for folder in folders {
+        if !is_pattern(folder) {
+            expanded.push(folder.clone());
+            continue;
+        }
+
+        let matcher = GlobBuilder::new(folder)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid pattern in folders: '{}'", folder))?
+            .compile_matcher();
+        // Only `**` matches across directories, so other patterns don't need a deep walk
+        let depth = match folder.contains("**") {
+            true => usize::MAX,
+            false => folder.split('/').count(),
+        };
+
+        let mut matches = Vec::new();
+        let mut pending = vec![(repo_path.to_path_buf(), 0)];
+        while let Some((dir, level)) = pending.pop() {
+            for item in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = item.path();
+                if item.file_name() == ".git" {
+                    continue;
+                }
+                let Ok(relative) = path.strip_prefix(repo_path) else {
+                    continue;
+                };
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                if matcher.is_match(&relative) {
+                    matches.push(relative);
+                }
+                if path.is_dir() && level + 1 < depth {
+                    pending.push((path, level + 1));
+                }
+            }
+        }
+
+        if matches.is_empty() {
+            log::warn!("Pattern '{}' matches nothing in the repository", folder);
+        }
+        matches.sort();
+        log::debug!("Pattern '{}' matches: {}", folder, matches.join(", "));
+        expanded.extend(matches);
+    }
+
+    Ok(expanded)
+}
+
+/// Run `operation` with the URL of the entry's repository, falling back to its mirrors in order
+/// when it fails, and record which one was used
+async fn from_any_remote<F, Fut>(entry: &DotfileEntry, mut operation: F) -> Result<()>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    if entry.mirrors.is_empty() {
+        return operation(entry.source.clone()).await;
+    }
+
+    let mut first_error = None;
+    for url in std::iter::once(&entry.source).chain(&entry.mirrors) {
+        match operation(url.clone()).await {
+            Ok(()) => {
+                let mirror = (url != &entry.source).then_some(url.as_str());
+                if let Some(mirror) = mirror {
+                    log::info!("Used mirror {} for {}", mirror, entry.source);
+                }
+                return symlinks::record_mirror(&entry.source, mirror).await;
+            }
+            Err(e) => {
+                log::warn!("Failed to reach {}: {:#}", url, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error
+        .expect("the source is always tried")
+        .context("None of the mirrors could be reached either"))
+}
+
+impl SourceProvider for GitSource {
+    fn root(&self, entry: &DotfileEntry) -> PathBuf {
+        entry.local_path()
+    }
+
+    fn default_target(&self, entry: &DotfileEntry) -> Result<PathBuf> {
+        match &entry.clone_path {
+            Some(clone_path) => Ok(expand_home(clone_path)),
+            None if is_cloned(&entry.source) => {
+                Ok(dotfiles::get_git_dir()?.join(repo_name(&entry.source)))
+            }
+            // Local repositories are used where they are
+            None => absolute(&entry.source),
+        }
+    }
+
+    fn validate(&self, entry: &DotfileEntry) -> Result<()> {
+        if entry.clone_path.is_some() && !is_remote(&entry.source) {
+            anyhow::bail!("--clone-path can only be used with remote git repositories");
+        }
+        Ok(())
+    }
+
+    fn selects_folders(&self) -> bool {
+        true
+    }
+
+    fn fetch<'a>(&'a self, entry: &'a DotfileEntry, git: &'a GitConfig) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            git::check_git_available(git.backend).await?;
+
+            let repo_path = entry.local_path();
+            if is_cloned(&entry.source) {
+                let options = git::CloneOptions::new(entry, git);
+                from_any_remote(entry, |url| {
+                    let (repo_path, options) = (&repo_path, &options);
+                    async move { git::clone(&url, repo_path, options).await }
+                })
+                .await?;
+            } else {
+                log::info!("Using local git repository at: {}", repo_path.display());
+            }
+
+            Ok(())
+        })
+    }
+
+    fn refresh<'a>(
+        &'a self,
+        entry: &'a DotfileEntry,
+        git: &'a GitConfig,
+        dry_run: bool,
+    ) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let repo_path = entry.local_path();
+            let options = git::CloneOptions::new(entry, git);
+
+            if !repo_path.exists() {
+                if dry_run {
+                    println!(
+                        "[DRY RUN] Would clone repository: {}",
+                        redact::redact(&entry.source)
+                    );
+                } else {
+                    log::info!("Repository not found, cloning {}...", entry.source);
+                    from_any_remote(entry, |url| {
+                        let (repo_path, options) = (&repo_path, &options);
+                        async move { git::clone(&url, repo_path, options).await }
+                    })
+                    .await?;
+                }
+            } else if dry_run {
+                println!(
+                    "[DRY RUN] Would pull latest changes from: {}",
+                    redact::redact(&entry.source)
+                );
+            } else if entry.mirrors.is_empty() || !git::switches_remotes(git.backend) {
+                git::pull(&repo_path, &options).await?;
+            } else {
+                from_any_remote(entry, |url| {
+                    let (repo_path, options) = (&repo_path, &options);
+                    async move {
+                        git::set_origin(repo_path, &url).await?;
+                        git::pull(repo_path, options).await
+                    }
+                })
+                .await?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn list_files(&self, entry: &DotfileEntry) -> Result<Vec<LinkSource>> {
+        let repo_path = entry.local_path();
+        let Some(folders) = &entry.folders else {
+            // Link the contents of the repository, not the repository folder itself
+            return Ok(vec![LinkSource::Contents(repo_path)]);
+        };
+
+        let mut sources = Vec::new();
+        for folder in expand_patterns(&repo_path, folders)? {
+            let folder = &folder;
+            if !is_inside(folder) {
+                log::warn!("'{}' is outside the repository, skipping", folder);
+                continue;
+            }
+
+            let source_folder = repo_path.join(folder);
+            if source_folder.is_file() {
+                // Selected files are linked under their own name, e.g. `zsh/aliases.zsh`
+                log::debug!("Processing file: {}", folder);
+                sources.push(LinkSource::File(source_folder));
+            } else if source_folder.is_dir() {
+                // Link the contents of each selected folder into the base path
+                log::debug!("Processing folder: {}", folder);
+                sources.push(LinkSource::Contents(source_folder));
+            } else {
+                log::warn!("Folder '{}' does not exist in repository, skipping", folder);
+            }
+        }
+
+        Ok(sources)
+    }
+
+    fn describe(&self, entry: &DotfileEntry) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("Local", entry.local_path().display().to_string())];
+        if let Some(folders) = &entry.folders {
+            fields.push(("Folders", folders.join(", ")));
+        }
+        fields
+    }
+
+    fn managed_copy(&self, entry: &DotfileEntry, paths: &PathsConfig) -> Result<Option<PathBuf>> {
+        // Only clones inside the git directory are owned by dotme; local repositories aren't
+        let repo_path = entry.local_path();
+        Ok(repo_path
+            .starts_with(paths.get_git_dir()?)
+            .then_some(repo_path))
+    }
+}
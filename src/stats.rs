@@ -0,0 +1,313 @@
+//! Statistics about everything dotme manages, for `dotme stats`
+//!
+//! Meant for curating a setup that has grown over the years: how many entries of each type there
+//! are, how many links they make (and how many of those are broken), how much space the clones
+//! take, when each repository last got a commit and was last fetched, and which entries haven't
+//! changed in the longest time, as candidates for archiving.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::config::{Config, SourceType};
+use crate::diskspace;
+use crate::dotfiles;
+use crate::git;
+use crate::sources;
+use crate::symlinks;
+
+/// Number of entries listed as the stalest
+const STALEST: usize = 5;
+
+/// What is known about when an entry last changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Activity {
+    /// Source of the entry
+    source: String,
+    /// When its newest commit was made (git) or its newest file was modified (others)
+    changed: Option<DateTime<Utc>>,
+    /// When the repository was last fetched or cloned, for git entries
+    fetched: Option<DateTime<Utc>>,
+}
+
+/// Time a file was last modified
+fn modified(path: &Path) -> Option<DateTime<Utc>> {
+    let time: SystemTime = path.symlink_metadata().ok()?.modified().ok()?;
+    Some(time.into())
+}
+
+/// Newest modification time of `root` and anything inside it, leaving out `.git`
+fn newest_modified(root: &Path) -> Option<DateTime<Utc>> {
+    let mut newest = modified(root);
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.file_name() == Some(".git".as_ref()) {
+                continue;
+            }
+            newest = newest.max(modified(&path));
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                pending.push(path);
+            }
+        }
+    }
+    newest
+}
+
+/// `age` for humans, e.g. `3 days` or `just now`
+fn format_age(age: chrono::Duration) -> String {
+    let plural =
+        |count: i64, unit: &str| format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" });
+    match age {
+        age if age.num_weeks() >= 8 => plural(age.num_days() / 30, "month"),
+        age if age.num_days() >= 14 => plural(age.num_weeks(), "week"),
+        age if age.num_days() >= 1 => plural(age.num_days(), "day"),
+        age if age.num_hours() >= 1 => plural(age.num_hours(), "hour"),
+        age if age.num_minutes() >= 1 => plural(age.num_minutes(), "minute"),
+        _ => "just now".to_string(),
+    }
+}
+
+/// How long ago `time` was, or `unknown`
+fn ago(time: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String {
+    match time.map(|time| format_age(now - time)) {
+        Some(age) if age == "just now" => age,
+        Some(age) => format!("{} ago", age),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Print statistics about the entries, links, and clones
+pub async fn stats() -> Result<()> {
+    let config_path = dotfiles::get_config_path()?;
+    if !config_path.exists() {
+        anyhow::bail!("DotMe is not initialized. Run 'dotme init' first.");
+    }
+    let config = Config::load(Some(config_path))?;
+    let entries = dotfiles::active_entries(&config)?;
+    let inactive = config.dotfiles.len().saturating_sub(
+        entries
+            .iter()
+            .filter(|entry| config.dotfiles.contains(entry))
+            .count(),
+    );
+
+    let mut by_type: BTreeMap<String, usize> = BTreeMap::new();
+    for entry in &entries {
+        *by_type.entry(entry.r#type.to_string()).or_default() += 1;
+    }
+
+    let links = symlinks::list_symlinks().await?;
+    let broken = links
+        .iter()
+        .filter(|(_, status)| !matches!(status, Ok(true)))
+        .count();
+
+    let git_dir = dotfiles::get_git_dir()?;
+    let clones = diskspace::usage_of(&git_dir);
+
+    let mut activity = Vec::new();
+    for entry in &entries {
+        let root = sources::provider(entry.r#type).root(entry);
+        if !root.exists() {
+            continue;
+        }
+        activity.push(if entry.r#type == SourceType::Git {
+            let git = root.join(".git");
+            Activity {
+                source: entry.source.clone(),
+                changed: git::head_commit_time(&root).await.ok(),
+                fetched: modified(&git.join("FETCH_HEAD")).or_else(|| modified(&git.join("HEAD"))),
+            }
+        } else {
+            Activity {
+                source: entry.source.clone(),
+                changed: newest_modified(&root),
+                fetched: None,
+            }
+        });
+    }
+
+    let now = Utc::now();
+    let updated = config
+        .updated
+        .as_deref()
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.to_utc());
+    print!(
+        "{}",
+        render(&Stats {
+            by_type,
+            inactive,
+            links: links.len(),
+            broken,
+            clones: clones.bytes,
+            git_dir: git_dir.display().to_string(),
+            updated,
+            activity,
+            now,
+        })?
+    );
+    Ok(())
+}
+
+/// Everything `dotme stats` shows
+struct Stats {
+    /// Active entries by type
+    by_type: BTreeMap<String, usize>,
+    /// Entries whose conditions don't match this machine
+    inactive: usize,
+    /// Tracked links
+    links: usize,
+    /// Links that are gone or point elsewhere
+    broken: usize,
+    /// Bytes taken up by the clones
+    clones: u64,
+    /// Where the clones are
+    git_dir: String,
+    /// Last time every entry was updated at once
+    updated: Option<DateTime<Utc>>,
+    /// When each entry that is on this machine last changed
+    activity: Vec<Activity>,
+    /// Time the ages are relative to
+    now: DateTime<Utc>,
+}
+
+/// Render the statistics
+fn render(stats: &Stats) -> Result<String, std::fmt::Error> {
+    let mut out = String::new();
+    let entries: usize = stats.by_type.values().sum();
+    let types: Vec<String> = stats
+        .by_type
+        .iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect();
+
+    writeln!(out, "Inventory:")?;
+    writeln!(out, "─────────────────────────────────────────")?;
+    write!(out, "  Entries:      {}", entries)?;
+    if !types.is_empty() {
+        write!(out, " ({})", types.join(", "))?;
+    }
+    if stats.inactive > 0 {
+        write!(out, ", {} not active on this machine", stats.inactive)?;
+    }
+    writeln!(out)?;
+    write!(out, "  Links:        {}", stats.links)?;
+    if stats.broken > 0 {
+        write!(out, " ({} broken)", stats.broken)?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "  Clones:       {} in {}",
+        diskspace::format_size(stats.clones),
+        stats.git_dir
+    )?;
+    writeln!(out, "  Last update:  {}", ago(stats.updated, stats.now))?;
+
+    let repositories: Vec<&Activity> = stats
+        .activity
+        .iter()
+        .filter(|activity| activity.fetched.is_some())
+        .collect();
+    if !repositories.is_empty() {
+        writeln!(out, "\nRepositories (last commit, last fetched):")?;
+        writeln!(out, "─────────────────────────────────────────")?;
+        for activity in repositories {
+            writeln!(
+                out,
+                "  {:<16} {:<16} {}",
+                ago(activity.changed, stats.now),
+                ago(activity.fetched, stats.now),
+                activity.source
+            )?;
+        }
+    }
+
+    let mut stalest: Vec<&Activity> = stats
+        .activity
+        .iter()
+        .filter(|activity| activity.changed.is_some())
+        .collect();
+    stalest.sort_by_key(|activity| activity.changed);
+    if !stalest.is_empty() {
+        writeln!(out, "\nStalest entries (last changed):")?;
+        writeln!(out, "─────────────────────────────────────────")?;
+        for activity in stalest.into_iter().take(STALEST) {
+            writeln!(
+                out,
+                "  {:<16} {}",
+                ago(activity.changed, stats.now),
+                activity.source
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_are_rendered() {
+        assert_eq!(format_age(chrono::Duration::seconds(30)), "just now");
+        assert_eq!(format_age(chrono::Duration::hours(1)), "1 hour");
+        assert_eq!(format_age(chrono::Duration::days(3)), "3 days");
+        assert_eq!(format_age(chrono::Duration::days(20)), "2 weeks");
+        assert_eq!(format_age(chrono::Duration::days(400)), "13 months");
+
+        let now = Utc::now();
+        let activity = |source: &str, days: i64, fetched: bool| Activity {
+            source: source.to_string(),
+            changed: Some(now - chrono::Duration::days(days)),
+            fetched: fetched.then_some(now),
+        };
+        let stats = Stats {
+            by_type: BTreeMap::from([("directory".to_string(), 1), ("git".to_string(), 2)]),
+            inactive: 1,
+            links: 10,
+            broken: 2,
+            clones: 2048,
+            git_dir: "/home/jane/.dotme/git".to_string(),
+            updated: None,
+            activity: vec![
+                activity("https://github.com/jane/dots", 2, true),
+                activity("https://github.com/jane/vim", 300, true),
+                activity("/home/jane/notes", 40, false),
+            ],
+            now,
+        };
+        let out = render(&stats).unwrap();
+        assert!(
+            out.contains("  Entries:      3 (1 directory, 2 git), 1 not active on this machine\n")
+        );
+        assert!(out.contains("  Links:        10 (2 broken)\n"));
+        assert!(out.contains("  Clones:       2.0 KiB in /home/jane/.dotme/git\n"));
+        assert!(out.contains("  Last update:  unknown\n"));
+        assert!(out.contains("  2 days ago       just now         https://github.com/jane/dots\n"));
+
+        let stalest = out.split("Stalest entries").nth(1).unwrap();
+        let order: Vec<&str> = stalest
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .filter(|source| source.contains('/'))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "https://github.com/jane/vim",
+                "/home/jane/notes",
+                "https://github.com/jane/dots"
+            ]
+        );
+    }
+}
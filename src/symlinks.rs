@@ -8,9 +8,12 @@
 //!
 //! - **State Tracking**: All symlinks are tracked in `~/.dotme/symlinks.yml`
 //! - **Verification**: Verifies filesystem state before operations
-//! - **Timestamps**: Tracks creation and last verification time
+//! - **Timestamps**: Tracks creation and last verification time, so links that haven't been
+//!   verified in a while can be re-checked on their own
 //! - **Cross-platform**: Supports both Unix and Windows
 //! - **Safe Operations**: Prevents overwriting existing files/directories
+//! - **Ownership Markers**: Every link dotme creates gets a marker in `~/.dotme/markers`, so
+//!   links created by other tools (stow, home-manager, ...) are never removed
 //!
 //! # Example Usage
 //!
@@ -23,7 +26,7 @@
 //!     // Create a symlink
 //!     let target = Path::new("/home/user/dotfiles/bashrc");
 //!     let link = Path::new("/home/user/.bashrc");
-//!     create_symlink(link, target).await?;
+//!     create_symlink(link, target, false).await?;
 //!
 //!     // List all managed symlinks
 //!     let symlinks = list_symlinks().await?;
@@ -40,7 +43,9 @@
 //!
 //! # State File Format
 //!
-//! The state is stored in `~/.dotme/symlinks.yml` in YAML format:
+//! The state is stored in `~/.dotme/symlinks.yml` in YAML format, or in an SQLite database
+//! (`~/.dotme/state.db`) with `symlinks.store: sqlite`, which updates one link at a time instead
+//! of rewriting the whole file (requires the `sqlite-state` build feature):
 //!
 //! ```yaml
 //! symlinks:
@@ -49,11 +54,51 @@
 //!     created_at: "2024-01-15T10:30:00Z"
 //!     last_verified: "2024-01-15T12:45:00Z"
 //! ```
+//!
+//! # Ownership Markers
+//!
+//! Extended attributes can't be set on symlinks on most platforms, so ownership is recorded in a
+//! sidecar file per link instead. A marker stores the link, its target, and (on Unix) the device
+//! and inode of the link itself. An identical-looking link created by another tool has a
+//! different inode, so it is recognized as foreign even if the state file lists it.
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tokio::fs;
+use tokio::sync::Mutex;
+
+use crate::config::{Config, LinkMode, StateBackend};
+use crate::layout;
+use crate::migrate::{self, STATE_VERSION, StateVersion};
+
+#[cfg(feature = "sqlite-state")]
+mod sqlite_store;
+
+/// Name of the YAML state file in the dotme directory
+const STATE_FILE: &str = "symlinks.yml";
+
+/// Name of the SQLite state database in the dotme directory
+const STATE_DB: &str = "state.db";
+
+/// Serializes changes to the state file, as symlinks may be created from concurrent tasks
+static STATE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Copies and hardlinks left in place during this run because they were edited
+static EDITED: std::sync::Mutex<BTreeSet<PathBuf>> = std::sync::Mutex::new(BTreeSet::new());
+
+/// Check if `path` is a copy or hardlink dotme created that was left in place during this run
+/// because it was edited, so it must not be overwritten when it is linked again
+pub fn was_edited(path: &Path) -> bool {
+    EDITED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains(path)
+}
 
 /// Represents a single symlink entry in the state file
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -67,83 +112,336 @@ pub struct SymlinkEntry {
     /// Last verified timestamp (ISO 8601 format)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub last_verified: Option<String>,
+    /// Whether this is a symlink, a copy, or a hardlink
+    #[serde(default, skip_serializing_if = "LinkMode::is_default")]
+    pub mode: LinkMode,
+    /// SHA-256 of the contents put in place, for copies and hardlinks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+impl SymlinkEntry {
+    /// Entry for `link`, put in place for `target` with `mode` just now
+    pub fn new(link: PathBuf, target: PathBuf, mode: LinkMode, checksum: Option<String>) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Self {
+            link,
+            target,
+            created_at: now.clone(),
+            last_verified: Some(now),
+            mode,
+            checksum,
+        }
+    }
+
+    /// Record that the link was put in place again for `target` with `mode` just now
+    pub fn refresh(&mut self, target: PathBuf, mode: LinkMode, checksum: Option<String>) {
+        self.target = target;
+        self.last_verified = Some(chrono::Utc::now().to_rfc3339());
+        self.mode = mode;
+        self.checksum = checksum;
+    }
+
+    /// Check if the link hasn't been verified within `max_age` (never verified counts as stale)
+    pub fn is_stale(&self, max_age: chrono::Duration, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.last_verified
+            .as_deref()
+            .and_then(|verified| chrono::DateTime::parse_from_rfc3339(verified).ok())
+            .is_none_or(|verified| now.signed_duration_since(verified) > max_age)
+    }
+
+    /// Check if a symlink was replaced by a regular file after dotme created it, as apps that
+    /// save by writing a new file and renaming it over the old one do
+    pub fn replaced_by_file(&self) -> bool {
+        if !self.mode.is_default() {
+            return false;
+        }
+        let Ok(metadata) = std::fs::symlink_metadata(&self.link) else {
+            return false;
+        };
+        if !metadata.is_file() {
+            return false;
+        }
+
+        match chrono::DateTime::parse_from_rfc3339(&self.created_at) {
+            Ok(created) => metadata
+                .modified()
+                .is_ok_and(|modified| chrono::DateTime::<chrono::Utc>::from(modified) > created),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Sidecar marker recording that dotme created a symlink
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct LinkMarker {
+    /// The path to the symlink
+    link: PathBuf,
+    /// The target the symlink was created with
+    target: PathBuf,
+    /// Device and inode of the symlink itself (Unix only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<(u64, u64)>,
+}
+
+/// Who owns a symlink, according to its marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ownership {
+    /// Created by dotme
+    Managed,
+    /// Has a marker, but the link was replaced by someone else
+    Foreign,
+    /// No marker, e.g. links created by older versions of dotme or by other tools
+    Unmarked,
 }
 
 /// State manager for all symlinks created by dotme
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SymlinkState {
+    /// Version of the state layout, upgraded automatically when an older state is loaded
+    #[serde(default)]
+    pub version: StateVersion,
     /// List of managed symlinks
     #[serde(default)]
     pub symlinks: Vec<SymlinkEntry>,
+    /// Shell completions installed with `dotme completions --install`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completions: Vec<InstalledCompletions>,
+    /// Mirror each git source was last cloned or pulled from, for sources whose own URL couldn't
+    /// be reached
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mirrors: BTreeMap<String, String>,
+    /// When each script run with `dotme run` last succeeded, by `<source>#<path in the source>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub script_runs: BTreeMap<String, String>,
 }
 
-impl SymlinkState {
-    /// Load symlink state from ~/.dotme/symlinks.yml
-    pub async fn load() -> Result<Self> {
-        let path = Self::get_state_path()?;
+/// Completion script dotme installed for a shell
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InstalledCompletions {
+    /// Shell the script is for
+    pub shell: String,
+    /// Where the script was written
+    pub script: PathBuf,
+    /// Shell startup file a block loading the script was added to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rc_file: Option<PathBuf>,
+}
 
-        if !path.exists() {
-            log::debug!("Symlink state file does not exist, returning empty state");
-            return Ok(Self::default());
-        }
+/// Where the symlink state is kept
+pub trait StateStore: Send + Sync {
+    /// Load the whole state
+    fn load(&self) -> Result<SymlinkState>;
+    /// Replace the whole state
+    fn save(&self, state: &SymlinkState) -> Result<()>;
+    /// Entry of `link`, if it is tracked
+    fn entry(&self, link: &Path) -> Result<Option<SymlinkEntry>>;
+    /// Record that `link` was put in place for `target` with `mode` just now
+    fn track(
+        &self,
+        link: &Path,
+        target: &Path,
+        mode: LinkMode,
+        checksum: Option<String>,
+    ) -> Result<()>;
+    /// Stop tracking `link`; returns whether it was tracked
+    fn forget(&self, link: &Path) -> Result<bool>;
+    /// File the state is kept in
+    fn path(&self) -> Result<PathBuf>;
+}
 
-        let contents = fs::read_to_string(&path)
-            .await
-            .context("Failed to read symlink state file")?;
+/// State kept in `symlinks.yml`, which is rewritten whenever a link changes
+struct YamlStore;
 
-        let state: Self =
+impl YamlStore {
+    /// Read the state file at `path`, upgrading it; returns the version it had if it was upgraded
+    fn read(path: &Path) -> Result<(SymlinkState, Option<u32>)> {
+        let contents =
+            crate::encryption::read(path).context("Failed to read symlink state file")?;
+
+        let mut document: serde_yaml::Value =
             serde_yaml::from_str(&contents).context("Failed to parse symlink state file")?;
+        let upgraded = match document {
+            serde_yaml::Value::Mapping(_) => migrate::upgrade_state(&mut document)?,
+            _ => None,
+        };
+        let state: SymlinkState =
+            serde_yaml::from_value(document).context("Failed to parse symlink state file")?;
+        Ok((state, upgraded))
+    }
+}
 
-        log::debug!("Loaded {} symlink entries from state", state.symlinks.len());
+impl StateStore for YamlStore {
+    fn load(&self) -> Result<SymlinkState> {
+        let path = self.path()?;
+
+        if !path.exists() {
+            log::debug!("Symlink state file does not exist, returning empty state");
+            return Ok(SymlinkState::default());
+        }
+
+        let (state, upgraded) = Self::read(&path)
+            .or_else(|e| crate::atomic::recover(&path, e, || Self::read(&path)))?;
+        if let Some(version) = upgraded {
+            migrate::backup(&path, version, STATE_VERSION)?;
+            self.save(&state)?;
+        }
 
         Ok(state)
     }
 
-    /// Save symlink state to ~/.dotme/symlinks.yml
-    pub async fn save(&self) -> Result<()> {
-        let path = Self::get_state_path()?;
+    fn save(&self, state: &SymlinkState) -> Result<()> {
+        let path = self.path()?;
 
         // Ensure parent directory exists
         if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)
-                .await
-                .context("Failed to create .dotme directory")?;
+            std::fs::create_dir_all(parent).context("Failed to create .dotme directory")?;
         }
 
-        let contents = serde_yaml::to_string(self).context("Failed to serialize symlink state")?;
+        let contents = serde_yaml::to_string(state).context("Failed to serialize symlink state")?;
 
-        fs::write(&path, contents)
-            .await
-            .context("Failed to write symlink state file")?;
+        crate::encryption::write(&path, &contents).context("Failed to write symlink state file")
+    }
+
+    fn entry(&self, link: &Path) -> Result<Option<SymlinkEntry>> {
+        Ok(self.load()?.find_entry(link).cloned())
+    }
+
+    fn track(
+        &self,
+        link: &Path,
+        target: &Path,
+        mode: LinkMode,
+        checksum: Option<String>,
+    ) -> Result<()> {
+        let mut state = self.load()?;
+        state.add_file_entry(link.to_path_buf(), target.to_path_buf(), mode, checksum);
+        self.save(&state)
+    }
+
+    fn forget(&self, link: &Path) -> Result<bool> {
+        let mut state = self.load()?;
+        let removed = state.remove_entry(link);
+        if removed {
+            self.save(&state)?;
+        }
+        Ok(removed)
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(layout::state_dir()?.join(STATE_FILE))
+    }
+}
+
+/// Store selected with `symlinks.store` in the config
+fn configured_backend() -> StateBackend {
+    crate::dotfiles::get_config_path()
+        .ok()
+        .filter(|path| path.exists())
+        .and_then(|path| Config::load(Some(path)).ok())
+        .map(|config| config.symlinks.store)
+        .unwrap_or_default()
+}
+
+/// Open the store selected in the config, moving the state over from the other one once
+fn open_store() -> Result<Box<dyn StateStore>> {
+    let db = layout::state_dir()?.join(STATE_DB);
+    match configured_backend() {
+        #[cfg(feature = "sqlite-state")]
+        StateBackend::Sqlite => Ok(Box::new(sqlite_store::SqliteStore::open(&db, &YamlStore)?)),
+        #[cfg(feature = "sqlite-state")]
+        StateBackend::Yaml => {
+            sqlite_store::move_out(&db, &YamlStore)?;
+            Ok(Box::new(YamlStore))
+        }
+        #[cfg(not(feature = "sqlite-state"))]
+        backend => {
+            if db.exists() && !YamlStore.path()?.exists() {
+                anyhow::bail!(
+                    "The symlink state is kept in {}, but dotme was built without the sqlite-state feature",
+                    db.display()
+                );
+            }
+            if backend == StateBackend::Sqlite {
+                log::warn!(
+                    "dotme was built without the sqlite-state feature, keeping the symlink state in {}",
+                    STATE_FILE
+                );
+            }
+            Ok(Box::new(YamlStore))
+        }
+    }
+}
+
+/// Store the symlink state is kept in, opened the first time it is needed
+fn store() -> Result<&'static dyn StateStore> {
+    static STORE: OnceLock<Box<dyn StateStore>> = OnceLock::new();
+
+    if let Some(store) = STORE.get() {
+        return Ok(store.as_ref());
+    }
+    let store = open_store()?;
+    Ok(STORE.get_or_init(|| store).as_ref())
+}
+
+/// What the link at `link` points to, if dotme tracks it
+pub fn tracked_target(link: &Path) -> Result<Option<PathBuf>> {
+    Ok(store()?.entry(link)?.map(|entry| entry.target))
+}
+
+/// Every link dotme tracks, read right away rather than through `SymlinkState::load`, so it
+/// works outside of async code
+pub fn tracked_links() -> Result<Vec<SymlinkEntry>> {
+    Ok(store()?.load()?.symlinks)
+}
 
+/// File the symlink state is kept in
+pub fn state_path() -> Result<PathBuf> {
+    store()?.path()
+}
+
+impl SymlinkState {
+    /// Load symlink state from ~/.dotme/symlinks.yml
+    pub async fn load() -> Result<Self> {
+        let state = store()?.load()?;
+        log::debug!("Loaded {} symlink entries from state", state.symlinks.len());
+
+        Ok(state)
+    }
+
+    /// Save symlink state to ~/.dotme/symlinks.yml
+    pub async fn save(&self) -> Result<()> {
+        store()?.save(self)?;
         log::debug!("Saved {} symlink entries to state", self.symlinks.len());
 
         Ok(())
     }
 
-    /// Get the path to the symlink state file
-    fn get_state_path() -> Result<PathBuf> {
-        let home = dirs::home_dir().context("Failed to get home directory")?;
-        Ok(home.join(".dotme").join("symlinks.yml"))
+    /// Get the directory holding ownership markers
+    fn get_markers_dir() -> Result<PathBuf> {
+        Ok(layout::state_dir()?.join("markers"))
     }
 
     /// Add a new symlink entry to the state
     pub fn add_entry(&mut self, link: PathBuf, target: PathBuf) {
-        let now = chrono::Utc::now().to_rfc3339();
+        self.add_file_entry(link, target, LinkMode::Symlink, None);
+    }
 
+    /// Add a new entry put in place with `mode` to the state
+    pub fn add_file_entry(
+        &mut self,
+        link: PathBuf,
+        target: PathBuf,
+        mode: LinkMode,
+        checksum: Option<String>,
+    ) {
         // Check if entry already exists and update it
         if let Some(entry) = self.symlinks.iter_mut().find(|e| e.link == link) {
-            entry.target = target;
-            entry.last_verified = Some(now);
+            entry.refresh(target, mode, checksum);
             log::debug!("Updated existing symlink entry: {:?}", link);
         } else {
-            let entry = SymlinkEntry {
-                link,
-                target,
-                created_at: now.clone(),
-                last_verified: Some(now),
-            };
-            self.symlinks.push(entry);
+            self.symlinks
+                .push(SymlinkEntry::new(link, target, mode, checksum));
             log::debug!("Added new symlink entry");
         }
     }
@@ -182,7 +480,7 @@ impl SymlinkState {
         let mut results = Vec::new();
 
         for entry in &mut self.symlinks {
-            let status = Self::verify_symlink(&entry.link, &entry.target).await;
+            let status = Self::verify_entry(entry).await;
 
             if status.is_ok() {
                 entry.last_verified = Some(chrono::Utc::now().to_rfc3339());
@@ -194,6 +492,50 @@ impl SymlinkState {
         results
     }
 
+    /// Verify a tracked entry, whatever its mode
+    async fn verify_entry(entry: &SymlinkEntry) -> Result<bool> {
+        match entry.mode {
+            LinkMode::Symlink => Self::verify_symlink(&entry.link, &entry.target).await,
+            LinkMode::Copy | LinkMode::Hardlink | LinkMode::Reflink => {
+                Self::verify_file(entry).await
+            }
+            LinkMode::Junction => Self::verify_junction(entry).await,
+        }
+    }
+
+    /// Verify a directory junction resolves to its target
+    async fn verify_junction(entry: &SymlinkEntry) -> Result<bool> {
+        if fs::symlink_metadata(&entry.link).await.is_err() {
+            return Err(anyhow::anyhow!("Junction does not exist"));
+        }
+
+        let actual = fs::canonicalize(&entry.link)
+            .await
+            .context("Failed to resolve junction")?;
+        let expected = fs::canonicalize(&entry.target)
+            .await
+            .context("Failed to resolve junction target")?;
+
+        Ok(actual == expected)
+    }
+
+    /// Verify a copy or hardlink: `Ok(false)` means it changed since it was put in place
+    async fn verify_file(entry: &SymlinkEntry) -> Result<bool> {
+        let metadata = fs::symlink_metadata(&entry.link)
+            .await
+            .map_err(|_| anyhow::anyhow!("File does not exist"))?;
+
+        if !metadata.is_file() {
+            return Err(anyhow::anyhow!("Path exists but is not a file"));
+        }
+
+        if entry.mode == LinkMode::Hardlink && same_file(&entry.link, &entry.target).await {
+            return Ok(true);
+        }
+
+        Ok(entry.checksum.as_deref() == Some(checksum(&entry.link).await?.as_str()))
+    }
+
     /// Verify a single symlink
     async fn verify_symlink(link: &Path, expected_target: &Path) -> Result<bool> {
         if !link.exists() && link.symlink_metadata().is_err() {
@@ -208,22 +550,136 @@ impl SymlinkState {
             return Err(anyhow::anyhow!("Path exists but is not a symlink"));
         }
 
+        if ownership(link).await? == Ownership::Foreign {
+            return Err(anyhow::anyhow!("Symlink was replaced by another tool"));
+        }
+
         let actual_target = fs::read_link(link)
             .await
             .context("Failed to read symlink target")?;
 
         // Normalize paths for comparison
         let expected = normalize_path(expected_target)?;
-        let actual = normalize_path(&actual_target)?;
+        let actual = resolve_link_target(link, &actual_target)?;
+
+        if expected == actual && !link.exists() {
+            return Err(anyhow::anyhow!("Target does not exist"));
+        }
 
         Ok(expected == actual)
     }
 }
 
-/// Create a symlink from `link` to `target`
-/// Verifies the system state before creating and updates the state file
-pub async fn create_symlink(link: &Path, target: &Path) -> Result<()> {
+/// Get the marker file of a link (named after a hash of the link path)
+fn marker_path(link: &Path) -> Result<PathBuf> {
+    // FNV-1a, stable across Rust versions unlike `DefaultHasher`
+    let hash = link
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+        });
+
+    Ok(SymlinkState::get_markers_dir()?.join(format!("{:016x}.yml", hash)))
+}
+
+/// Get the device and inode of a symlink itself
+#[cfg(unix)]
+fn link_identity(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Get the device and inode of a symlink itself
+#[cfg(not(unix))]
+fn link_identity(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Record that dotme created the symlink at `link`
+async fn write_marker(link: &Path, target: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(link).await?;
+    let marker = LinkMarker {
+        link: link.to_path_buf(),
+        target: target.to_path_buf(),
+        identity: link_identity(&metadata),
+    };
+
+    let path = marker_path(link)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .context("Failed to create markers directory")?;
+    }
+
+    let contents = serde_yaml::to_string(&marker).context("Failed to serialize marker")?;
+    fs::write(&path, contents)
+        .await
+        .context("Failed to write ownership marker")?;
+
+    Ok(())
+}
+
+/// Remove the ownership marker of a link
+async fn remove_marker(link: &Path) -> Result<()> {
+    let path = marker_path(link)?;
+    if path.exists() {
+        fs::remove_file(&path)
+            .await
+            .context("Failed to remove ownership marker")?;
+    }
+    Ok(())
+}
+
+/// Check who owns the symlink at `link`
+pub async fn ownership(link: &Path) -> Result<Ownership> {
+    let path = marker_path(link)?;
+    if !path.exists() {
+        return Ok(Ownership::Unmarked);
+    }
+
+    let contents = fs::read_to_string(&path)
+        .await
+        .context("Failed to read ownership marker")?;
+    let marker: LinkMarker =
+        serde_yaml::from_str(&contents).context("Failed to parse ownership marker")?;
+
+    // Nothing to own if the link is gone
+    let Ok(metadata) = fs::symlink_metadata(link).await else {
+        return Ok(Ownership::Unmarked);
+    };
+
+    Ok(if marker_matches(&marker, link, &metadata) {
+        Ownership::Managed
+    } else {
+        Ownership::Foreign
+    })
+}
+
+/// Check if a marker describes the link currently on disk
+fn marker_matches(marker: &LinkMarker, link: &Path, metadata: &std::fs::Metadata) -> bool {
+    marker.link == link
+        && metadata.is_symlink()
+        && std::fs::read_link(link).is_ok_and(|target| target == marker.target)
+        && marker.identity == link_identity(metadata)
+}
+
+/// Create a symlink from `link` to `target`, pointing at it with a relative path if `relative`
+/// is set. Verifies the system state before creating and updates the state file
+pub async fn create_symlink(link: &Path, target: &Path, relative: bool) -> Result<()> {
     log::debug!("Creating symlink: {:?} -> {:?}", link, target);
+    let _lock = STATE_LOCK.lock().await;
+
+    // Without symlink privileges, fall back to a junction or a copy
+    if !can_symlink() {
+        let mode = if target.is_dir() {
+            LinkMode::Junction
+        } else {
+            LinkMode::Copy
+        };
+        return materialize_locked(link, target, mode).await;
+    }
 
     // Verify target exists
     if !target.exists() {
@@ -241,15 +697,30 @@ pub async fn create_symlink(link: &Path, target: &Path) -> Result<()> {
             // It's a symlink - check if it points to the right place
             let current_target = fs::read_link(link).await?;
             let expected = normalize_path(target)?;
-            let actual = normalize_path(&current_target)?;
+            let actual = resolve_link_target(link, &current_target)?;
 
             if expected == actual {
                 log::debug!("Symlink already exists and points to correct target");
 
+                let store = store()?;
+                match ownership(link).await? {
+                    Ownership::Managed => {}
+                    // Links from older versions of dotme are tracked but have no marker yet
+                    Ownership::Unmarked if store.entry(link)?.is_some() => {
+                        write_marker(link, &current_target).await?;
+                    }
+                    _ => {
+                        log::info!(
+                            "{} is already linked by another tool, leaving it alone",
+                            link.display()
+                        );
+                        store.forget(link)?;
+                        return Ok(());
+                    }
+                }
+
                 // Update state
-                let mut state = SymlinkState::load().await?;
-                state.add_entry(link.to_path_buf(), target.to_path_buf());
-                state.save().await?;
+                store.track(link, target, LinkMode::Symlink, None)?;
 
                 return Ok(());
             } else {
@@ -275,29 +746,36 @@ pub async fn create_symlink(link: &Path, target: &Path) -> Result<()> {
     }
 
     // Create parent directory if needed
-    if let Some(parent) = link.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent)
-                .await
-                .context("Failed to create parent directory for symlink")?;
-            log::debug!("Created parent directory: {:?}", parent);
-        }
+    if let Some(parent) = link.parent()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent)
+            .await
+            .context("Failed to create parent directory for symlink")?;
+        log::debug!("Created parent directory: {:?}", parent);
     }
 
+    // The path stored in the link; the state file always records the absolute target
+    let link_target = if relative {
+        relative_target(link, target)?
+    } else {
+        target.to_path_buf()
+    };
+
     // Create the symlink
     #[cfg(unix)]
-    fs::symlink(target, link)
+    fs::symlink(&link_target, link)
         .await
         .context("Failed to create symlink")?;
 
     #[cfg(windows)]
     {
         if target.is_dir() {
-            fs::symlink_dir(target, link)
+            fs::symlink_dir(&link_target, link)
                 .await
                 .context("Failed to create directory symlink")?;
         } else {
-            fs::symlink_file(target, link)
+            fs::symlink_file(&link_target, link)
                 .await
                 .context("Failed to create file symlink")?;
         }
@@ -306,13 +784,13 @@ pub async fn create_symlink(link: &Path, target: &Path) -> Result<()> {
     log::debug!(
         "✓ Created symlink: {} -> {}",
         link.display(),
-        target.display()
+        link_target.display()
     );
 
+    write_marker(link, &link_target).await?;
+
     // Update state
-    let mut state = SymlinkState::load().await?;
-    state.add_entry(link.to_path_buf(), target.to_path_buf());
-    state.save().await?;
+    store()?.track(link, target, LinkMode::Symlink, None)?;
 
     Ok(())
 }
@@ -321,6 +799,14 @@ pub async fn create_symlink(link: &Path, target: &Path) -> Result<()> {
 /// Only removes if the path is actually a symlink
 pub async fn remove_symlink(link: &Path) -> Result<()> {
     log::debug!("Removing symlink: {:?}", link);
+    let _lock = STATE_LOCK.lock().await;
+
+    // Copies and hardlinks are plain files and are handled separately
+    let store = store()?;
+    let tracked = store.entry(link)?;
+    if let Some(entry) = tracked.as_ref().filter(|e| !e.mode.is_default()) {
+        return remove_file_entry(entry).await;
+    }
 
     // Verify it's a symlink before removing
     if link.symlink_metadata().is_ok() {
@@ -333,6 +819,17 @@ pub async fn remove_symlink(link: &Path) -> Result<()> {
             );
         }
 
+        // Never remove links that belong to another tool, just stop tracking them
+        let owner = ownership(link).await?;
+        if owner == Ownership::Foreign || (owner == Ownership::Unmarked && tracked.is_none()) {
+            store.forget(link)?;
+            remove_marker(link).await?;
+            anyhow::bail!(
+                "Symlink was not created by dotme: {}. Will not remove.",
+                link.display()
+            );
+        }
+
         // Remove the symlink
         fs::remove_file(link)
             .await
@@ -343,14 +840,246 @@ pub async fn remove_symlink(link: &Path) -> Result<()> {
         log::warn!("Symlink does not exist: {:?}", link);
     }
 
+    remove_marker(link).await?;
+
     // Update state
-    let mut state = SymlinkState::load().await?;
-    state.remove_entry(link);
-    state.save().await?;
+    store.forget(link)?;
+
+    Ok(())
+}
+
+/// Remove a tracked copy or hardlink, unless it was edited since dotme put it in place
+async fn remove_file_entry(entry: &SymlinkEntry) -> Result<()> {
+    if entry.mode == LinkMode::Junction {
+        // Removing a junction never touches the directory it points to
+        if fs::symlink_metadata(&entry.link).await.is_ok() {
+            fs::remove_dir(&entry.link)
+                .await
+                .context("Failed to remove junction")?;
+            log::debug!("✓ Removed junction: {}", entry.link.display());
+        }
+    } else if fs::symlink_metadata(&entry.link).await.is_ok() {
+        if SymlinkState::verify_file(entry)
+            .await
+            .is_ok_and(|unchanged| !unchanged)
+        {
+            // Keep the edits, but stop managing the file
+            store()?.forget(&entry.link)?;
+            EDITED
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(entry.link.clone());
+            anyhow::bail!(
+                "{} was modified since it was created as a {}. Will not remove.",
+                entry.link.display(),
+                entry.mode
+            );
+        }
+
+        fs::remove_file(&entry.link)
+            .await
+            .with_context(|| format!("Failed to remove {}", entry.mode))?;
+        log::debug!("✓ Removed {}: {}", entry.mode, entry.link.display());
+    } else {
+        log::warn!("File does not exist: {:?}", entry.link);
+    }
+
+    store()?.forget(&entry.link)?;
+
+    Ok(())
+}
+
+/// Copy or hardlink the file `target` to `link` and track it with a checksum, so `update` can
+/// refresh it as long as it hasn't been edited
+pub async fn materialize(link: &Path, target: &Path, mode: LinkMode) -> Result<()> {
+    let _lock = STATE_LOCK.lock().await;
+    materialize_locked(link, target, mode).await
+}
+
+/// Put `target` in place at `link` with `mode`; the caller must hold `STATE_LOCK`
+async fn materialize_locked(link: &Path, target: &Path, mode: LinkMode) -> Result<()> {
+    log::debug!("Creating {}: {:?} -> {:?}", mode, link, target);
+
+    if mode == LinkMode::Junction && !target.is_dir() {
+        anyhow::bail!(
+            "Target is not a directory: {}. Only directories can be junctions.",
+            target.display()
+        );
+    } else if mode != LinkMode::Junction && !target.is_file() {
+        anyhow::bail!(
+            "Target is not a file: {}. Only files can be copied or hardlinked.",
+            target.display()
+        );
+    }
+
+    if link.symlink_metadata().is_ok() {
+        anyhow::bail!(
+            "Path already exists: {}. Please move or remove it first.",
+            link.display()
+        );
+    }
+
+    // Create parent directory if needed
+    if let Some(parent) = link.parent()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent)
+            .await
+            .context("Failed to create parent directory")?;
+    }
+
+    match mode {
+        LinkMode::Copy => {
+            fs::copy(target, link)
+                .await
+                .context("Failed to copy file")?;
+        }
+        LinkMode::Hardlink => {
+            fs::hard_link(target, link)
+                .await
+                .context("Failed to create hardlink")?;
+        }
+        LinkMode::Reflink => reflink(link, target).await?,
+        LinkMode::Junction => create_junction(link, target).await?,
+        LinkMode::Symlink => anyhow::bail!("Symlinks are created with create_symlink"),
+    }
+
+    log::debug!(
+        "✓ Created {}: {} -> {}",
+        mode,
+        link.display(),
+        target.display()
+    );
+
+    let checksum = match mode {
+        LinkMode::Junction => None,
+        _ => Some(checksum(link).await?),
+    };
+    store()?.track(link, target, mode, checksum)?;
+
+    Ok(())
+}
+
+/// Clone `target` to `link` with copy-on-write, copying it where the filesystem can't clone
+async fn reflink(link: &Path, target: &Path) -> Result<()> {
+    let mut command = tokio::process::Command::new("cp");
+    if cfg!(target_os = "macos") {
+        command.arg("-c");
+    } else {
+        command.arg("--reflink=always");
+    }
+
+    let cloned = command
+        .arg(target)
+        .arg(link)
+        .output()
+        .await
+        .is_ok_and(|output| output.status.success());
+
+    if !cloned {
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        WARNED.call_once(|| {
+            log::warn!("The filesystem doesn't support reflinks, copying files instead");
+        });
+
+        // A failed clone can leave an empty file behind
+        if fs::symlink_metadata(link).await.is_ok() {
+            fs::remove_file(link).await?;
+        }
+        fs::copy(target, link)
+            .await
+            .context("Failed to copy file")?;
+    }
+
+    Ok(())
+}
+
+/// Create a directory junction at `link` pointing to `target`
+#[cfg(windows)]
+async fn create_junction(link: &Path, target: &Path) -> Result<()> {
+    let output = tokio::process::Command::new("cmd")
+        .args(["/C", "mklink", "/J"])
+        .arg(link)
+        .arg(target)
+        .output()
+        .await
+        .context("Failed to run mklink")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create junction: {}{}",
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
 
     Ok(())
 }
 
+/// Create a directory junction at `link` pointing to `target`
+#[cfg(not(windows))]
+async fn create_junction(_link: &Path, _target: &Path) -> Result<()> {
+    anyhow::bail!("Junctions are only supported on Windows")
+}
+
+/// Check once whether this process may create symlinks.
+///
+/// Windows only allows it with Developer Mode enabled or as administrator.
+#[cfg(windows)]
+fn can_symlink() -> bool {
+    static CAN_SYMLINK: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+    *CAN_SYMLINK.get_or_init(|| {
+        let dir = std::env::temp_dir();
+        let probe = dir.join(format!("dotme-symlink-probe-{}", std::process::id()));
+        let allowed = std::os::windows::fs::symlink_dir(&dir, &probe).is_ok();
+        let _ = std::fs::remove_dir(&probe);
+
+        if !allowed {
+            log::info!(
+                "Symlinks need Developer Mode or administrator rights, \
+                using junctions for directories and copies for files"
+            );
+        }
+        allowed
+    })
+}
+
+/// Check once whether this process may create symlinks
+#[cfg(not(windows))]
+fn can_symlink() -> bool {
+    true
+}
+
+/// SHA-256 of a file's contents as a hex string
+pub async fn checksum(path: &Path) -> Result<String> {
+    let contents = fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    Ok(Sha256::digest(&contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Check if two paths are hardlinks to the same file
+#[cfg(unix)]
+async fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a).await, fs::metadata(b).await) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Check if two paths are hardlinks to the same file
+#[cfg(not(unix))]
+async fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
 /// Verify a symlink and return its status
 pub async fn verify_symlink(link: &Path, expected_target: &Path) -> Result<bool> {
     SymlinkState::verify_symlink(link, expected_target).await
@@ -362,59 +1091,381 @@ pub async fn list_symlinks() -> Result<Vec<(SymlinkEntry, Result<bool>)>> {
     let mut results = Vec::new();
 
     for entry in &state.symlinks {
-        let status = SymlinkState::verify_symlink(&entry.link, &entry.target).await;
+        let status = SymlinkState::verify_entry(entry).await;
         results.push((entry.clone(), status));
     }
 
     Ok(results)
 }
 
-/// Clean up broken or invalid symlinks from the state
-/// Returns the number of entries cleaned up
-pub async fn cleanup_broken_symlinks() -> Result<usize> {
+/// Verify managed symlinks, optionally only those not verified within `stale`.
+///
+/// Links that verify successfully get their `last_verified` timestamp refreshed.
+pub async fn verify_symlinks(
+    stale: Option<chrono::Duration>,
+) -> Result<Vec<(SymlinkEntry, Result<bool>)>> {
+    let _lock = STATE_LOCK.lock().await;
     let mut state = SymlinkState::load().await?;
-    let original_count = state.symlinks.len();
+    let now = chrono::Utc::now();
+    let mut results = Vec::new();
 
-    let mut to_remove = Vec::new();
+    for entry in &mut state.symlinks {
+        if stale.is_some_and(|max_age| !entry.is_stale(max_age, now)) {
+            continue;
+        }
 
-    for entry in &state.symlinks {
-        let status = SymlinkState::verify_symlink(&entry.link, &entry.target).await;
+        let status = SymlinkState::verify_entry(entry).await;
+        if status.is_ok() {
+            entry.last_verified = Some(now.to_rfc3339());
+        }
+
+        results.push((entry.clone(), status));
+    }
+
+    if !results.is_empty() {
+        state.save().await?;
+    }
+
+    Ok(results)
+}
+
+/// Parse an age such as `30d`, `12h`, or `2w` (a bare number is a number of days)
+pub fn parse_age(value: &str) -> Result<chrono::Duration> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(
+        value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len()),
+    );
+    let number: i64 = number
+        .parse()
+        .with_context(|| format!("Invalid age '{}', expected e.g. 30d", value))?;
+
+    match unit {
+        "s" => Ok(chrono::Duration::seconds(number)),
+        "m" => Ok(chrono::Duration::minutes(number)),
+        "h" => Ok(chrono::Duration::hours(number)),
+        "" | "d" => Ok(chrono::Duration::days(number)),
+        "w" => Ok(chrono::Duration::weeks(number)),
+        _ => anyhow::bail!("Invalid age unit '{}', expected one of s, m, h, d, w", unit),
+    }
+}
+
+/// A problem with the state file, usually left behind by editing it by hand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// The same link is tracked more than once
+    Duplicate(PathBuf),
+    /// The link path isn't absolute
+    RelativeLink(PathBuf),
+    /// The target path isn't absolute
+    RelativeTarget {
+        /// The tracked link
+        link: PathBuf,
+        /// Its relative target
+        target: PathBuf,
+    },
+    /// Tracked as a symlink, but a file or directory is there instead
+    NotASymlink(PathBuf),
+    /// A timestamp that isn't in ISO 8601 format
+    InvalidTimestamp {
+        /// The tracked link
+        link: PathBuf,
+        /// Name of the timestamp field
+        field: &'static str,
+    },
+    /// An installed completion script was deleted
+    MissingCompletions(PathBuf),
+}
 
-        // Remove entries where the symlink doesn't exist
-        if status.is_err() {
-            to_remove.push(entry.link.clone());
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate(link) => write!(f, "{} is tracked more than once", link.display()),
+            Self::RelativeLink(link) => write!(f, "{} is not an absolute path", link.display()),
+            Self::RelativeTarget { link, target } => write!(
+                f,
+                "{} has a relative target: {}",
+                link.display(),
+                target.display()
+            ),
+            Self::NotASymlink(link) => write!(
+                f,
+                "{} is tracked as a symlink but is a file or directory",
+                link.display()
+            ),
+            Self::InvalidTimestamp { link, field } => {
+                write!(f, "{} has an invalid {}", link.display(), field)
+            }
+            Self::MissingCompletions(script) => {
+                write!(f, "installed completions {} are missing", script.display())
+            }
         }
     }
+}
+
+/// Check if an optional timestamp is missing or in ISO 8601 format
+fn valid_timestamp(timestamp: Option<&str>) -> bool {
+    timestamp.is_none_or(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).is_ok())
+}
 
-    for link in to_remove {
-        state.remove_entry(&link);
+impl SymlinkState {
+    /// Find entries that don't match each other or the filesystem
+    pub fn fsck(&self) -> Vec<Inconsistency> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+
+        for entry in &self.symlinks {
+            let link = entry.link.clone();
+            if !seen.insert(&entry.link) {
+                problems.push(Inconsistency::Duplicate(link.clone()));
+            }
+            if entry.link.is_relative() {
+                problems.push(Inconsistency::RelativeLink(link.clone()));
+            }
+            if entry.target.is_relative() {
+                problems.push(Inconsistency::RelativeTarget {
+                    link: link.clone(),
+                    target: entry.target.clone(),
+                });
+            }
+            if entry.mode.is_default()
+                && std::fs::symlink_metadata(&entry.link).is_ok_and(|m| !m.is_symlink())
+            {
+                problems.push(Inconsistency::NotASymlink(link.clone()));
+            }
+            if !valid_timestamp(Some(&entry.created_at)) {
+                problems.push(Inconsistency::InvalidTimestamp {
+                    link: link.clone(),
+                    field: "created_at",
+                });
+            }
+            if !valid_timestamp(entry.last_verified.as_deref()) {
+                problems.push(Inconsistency::InvalidTimestamp {
+                    link,
+                    field: "last_verified",
+                });
+            }
+        }
+
+        for completions in &self.completions {
+            if !completions.script.exists() {
+                problems.push(Inconsistency::MissingCompletions(
+                    completions.script.clone(),
+                ));
+            }
+        }
+
+        problems
+    }
+
+    /// Fix what `fsck` finds: relative links are resolved against `home` and relative targets
+    /// against the link's directory, duplicates and entries for files that aren't symlinks are
+    /// dropped, and unreadable timestamps are reset
+    pub fn repair(&mut self, home: &Path) {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut seen = HashSet::new();
+
+        self.symlinks = std::mem::take(&mut self.symlinks)
+            .into_iter()
+            .filter_map(|mut entry| {
+                if entry.link.is_relative() {
+                    entry.link = clean_path(&home.join(&entry.link));
+                }
+                if entry.target.is_relative() {
+                    let parent = entry.link.parent().unwrap_or(home);
+                    entry.target = clean_path(&parent.join(&entry.target));
+                }
+                if !seen.insert(entry.link.clone()) {
+                    return None;
+                }
+                if entry.mode.is_default()
+                    && std::fs::symlink_metadata(&entry.link).is_ok_and(|m| !m.is_symlink())
+                {
+                    return None;
+                }
+                if !valid_timestamp(Some(&entry.created_at)) {
+                    entry.created_at = now.clone();
+                }
+                if !valid_timestamp(entry.last_verified.as_deref()) {
+                    entry.last_verified = None;
+                }
+                Some(entry)
+            })
+            .collect();
+        // Reinstall with `dotme completions --install`
+        self.completions
+            .retain(|completions| completions.script.exists());
+    }
+}
+
+/// Check the state file for inconsistencies, repairing and saving it if `repair` is set
+pub async fn check_state(repair: bool) -> Result<Vec<Inconsistency>> {
+    let _lock = STATE_LOCK.lock().await;
+    let mut state = SymlinkState::load().await?;
+    let problems = state.fsck();
+
+    if repair && !problems.is_empty() {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        state.repair(&home);
+        state.save().await?;
+    }
+
+    Ok(problems)
+}
+
+/// What `verify --fix` did about a link that failed verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    /// The link was missing and has been created again
+    Recreated,
+    /// The target is gone, so the link was removed and is no longer tracked
+    Forgotten,
+    /// Another tool replaced the link, so it is no longer tracked
+    Released,
+    /// Left alone, as fixing it could overwrite changes made by hand
+    Untouched,
+}
+
+/// Fix a link that failed verification: recreate it if it is missing, or stop tracking it
+/// (removing a dangling symlink) if its target is gone
+pub async fn repair_symlink(entry: &SymlinkEntry, relative: bool) -> Result<Repair> {
+    let link_exists = fs::symlink_metadata(&entry.link).await.is_ok();
+
+    if fs::symlink_metadata(&entry.target).await.is_err() {
+        if link_exists && matches!(entry.mode, LinkMode::Symlink | LinkMode::Junction) {
+            remove_symlink(&entry.link).await?;
+        } else {
+            // Copies are kept, as they may be the last copy of the content
+            forget(&entry.link).await?;
+        }
+        return Ok(Repair::Forgotten);
     }
 
-    let removed_count = original_count - state.symlinks.len();
+    if link_exists {
+        if entry.mode.is_default() && ownership(&entry.link).await? == Ownership::Foreign {
+            forget(&entry.link).await?;
+            return Ok(Repair::Released);
+        }
+        return Ok(Repair::Untouched);
+    }
 
-    if removed_count > 0 {
+    match entry.mode {
+        LinkMode::Symlink => create_symlink(&entry.link, &entry.target, relative).await?,
+        mode => materialize(&entry.link, &entry.target, mode).await?,
+    }
+    Ok(Repair::Recreated)
+}
+
+/// Record the current contents of a copy as the ones put in place, e.g. after they were synced
+/// back into the source
+pub async fn record_checksum(link: &Path) -> Result<()> {
+    let _lock = STATE_LOCK.lock().await;
+    let mut state = SymlinkState::load().await?;
+    let checksum = checksum(link).await?;
+    if let Some(entry) = state.symlinks.iter_mut().find(|e| e.link == link) {
+        entry.checksum = Some(checksum);
         state.save().await?;
-        log::info!("Cleaned up {} broken symlink entries", removed_count);
     }
+    Ok(())
+}
 
-    Ok(removed_count)
+/// Record that `source` was last cloned or pulled from `mirror`, or from its own URL if `None`
+pub async fn record_mirror(source: &str, mirror: Option<&str>) -> Result<()> {
+    let _lock = STATE_LOCK.lock().await;
+    let mut state = SymlinkState::load().await?;
+    let previous = match mirror {
+        Some(mirror) => state.mirrors.insert(source.to_string(), mirror.to_string()),
+        None => state.mirrors.remove(source),
+    };
+    if previous.as_deref() != mirror {
+        state.save().await?;
+    }
+    Ok(())
+}
+
+/// Record that the script `key` (`<source>#<path in the source>`) just ran successfully
+pub async fn record_script_run(key: &str) -> Result<()> {
+    let _lock = STATE_LOCK.lock().await;
+    let mut state = SymlinkState::load().await?;
+    state
+        .script_runs
+        .insert(key.to_string(), chrono::Utc::now().to_rfc3339());
+    state.save().await
+}
+
+/// Stop tracking a link without touching it
+async fn forget(link: &Path) -> Result<()> {
+    let _lock = STATE_LOCK.lock().await;
+    store()?.forget(link)?;
+    remove_marker(link).await
 }
 
 /// Normalize a path for comparison by resolving it to an absolute path
-fn normalize_path(path: &Path) -> Result<PathBuf> {
+pub fn normalize_path(path: &Path) -> Result<PathBuf> {
     if path.is_absolute() {
-        Ok(path.to_path_buf())
+        Ok(clean_path(path))
     } else {
         // For relative paths, try to make them absolute from current directory
         std::env::current_dir()
-            .map(|cwd| cwd.join(path))
+            .map(|cwd| clean_path(&cwd.join(path)))
             .context("Failed to normalize path")
     }
 }
 
+/// Resolve the target stored in a symlink; relative targets are relative to the link's directory
+fn resolve_link_target(link: &Path, link_target: &Path) -> Result<PathBuf> {
+    match link.parent() {
+        Some(parent) if link_target.is_relative() => normalize_path(&parent.join(link_target)),
+        _ => normalize_path(link_target),
+    }
+}
+
+/// Compute the path of `target` relative to the directory containing `link`
+fn relative_target(link: &Path, target: &Path) -> Result<PathBuf> {
+    let from = normalize_path(link.parent().unwrap_or(Path::new("")))?;
+    let to = normalize_path(target)?;
+
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        // Nothing in common (e.g. different drives on Windows), keep the absolute path
+        return Ok(to);
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in from.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(to.components().skip(common));
+
+    Ok(relative)
+}
+
+/// Remove `.` and `..` components without touching the filesystem
+fn clean_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                cleaned.pop();
+            }
+            other => cleaned.push(other),
+        }
+    }
+    cleaned
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_symlink_state_default() {
@@ -464,4 +1515,237 @@ mod tests {
         let not_found = state.find_entry(Path::new("/nonexistent"));
         assert!(not_found.is_none());
     }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d").unwrap(), chrono::Duration::days(30));
+        assert_eq!(parse_age("12h").unwrap(), chrono::Duration::hours(12));
+        assert_eq!(parse_age("2w").unwrap(), chrono::Duration::weeks(2));
+        assert_eq!(parse_age("7").unwrap(), chrono::Duration::days(7));
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("30y").is_err());
+    }
+
+    #[test]
+    fn test_relative_target() {
+        let link = Path::new("/home/user/.config/nvim");
+        let target = Path::new("/home/user/.dotme/git/dotfiles/nvim");
+
+        let relative = relative_target(link, target).unwrap();
+        assert_eq!(relative, PathBuf::from("../.dotme/git/dotfiles/nvim"));
+        assert_eq!(
+            resolve_link_target(link, &relative).unwrap(),
+            target.to_path_buf()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_is_tracked_by_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("config");
+        let link = dir.path().join(".config");
+        std::fs::write(&target, "v1").unwrap();
+        std::fs::copy(&target, &link).unwrap();
+
+        let entry = SymlinkEntry {
+            link: link.clone(),
+            target,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            last_verified: None,
+            mode: LinkMode::Copy,
+            checksum: Some(checksum(&link).await.unwrap()),
+        };
+        assert!(SymlinkState::verify_file(&entry).await.unwrap());
+
+        std::fs::write(&link, "edited").unwrap();
+        assert!(!SymlinkState::verify_file(&entry).await.unwrap());
+
+        std::fs::remove_file(&link).unwrap();
+        assert!(SymlinkState::verify_file(&entry).await.is_err());
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let now = chrono::Utc::now();
+        let mut entry = SymlinkEntry {
+            link: PathBuf::from("/home/user/.bashrc"),
+            target: PathBuf::from("/home/user/dotfiles/bashrc"),
+            created_at: now.to_rfc3339(),
+            last_verified: None,
+            mode: LinkMode::Symlink,
+            checksum: None,
+        };
+        assert!(entry.is_stale(chrono::Duration::days(30), now));
+
+        entry.last_verified = Some((now - chrono::Duration::days(10)).to_rfc3339());
+        assert!(!entry.is_stale(chrono::Duration::days(30), now));
+        assert!(entry.is_stale(chrono::Duration::days(7), now));
+    }
+
+    prop_compose! {
+        fn symlink_entry()(
+            link in "/[a-zA-Z0-9_./-]{1,32}",
+            target in "/[a-zA-Z0-9_./-]{1,32}",
+            created_at in "[0-9T:.+-]{1,32}",
+            last_verified in proptest::option::of("[0-9T:.+-]{1,32}"),
+            mode in prop_oneof![
+                Just(LinkMode::Symlink),
+                Just(LinkMode::Copy),
+                Just(LinkMode::Hardlink),
+                Just(LinkMode::Reflink),
+                Just(LinkMode::Junction),
+            ],
+            checksum in proptest::option::of("[0-9a-f]{64}"),
+        ) -> SymlinkEntry {
+            SymlinkEntry {
+                link: PathBuf::from(link),
+                target: PathBuf::from(target),
+                created_at,
+                last_verified,
+                mode,
+                checksum,
+            }
+
+        }
+    }
+
+    #[test]
+    fn test_replaced_by_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("settings.json");
+        std::fs::write(&link, "{}").unwrap();
+
+        let mut entry = SymlinkEntry {
+            link: link.clone(),
+            target: dir.path().join("dotfiles/settings.json"),
+            created_at: (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339(),
+            last_verified: None,
+            mode: LinkMode::Symlink,
+            checksum: None,
+        };
+        assert!(entry.replaced_by_file());
+
+        // Files that were there before the link was created weren't saved over it
+        entry.created_at = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+        assert!(!entry.replaced_by_file());
+
+        entry.created_at = chrono::Utc::now().to_rfc3339();
+        entry.mode = LinkMode::Copy;
+        assert!(!entry.replaced_by_file());
+    }
+
+    #[test]
+    fn test_fsck_and_repair() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path();
+        std::fs::write(home.join(".plain"), "").unwrap();
+
+        let entry = |link: &str, target: &str, created_at: &str| SymlinkEntry {
+            link: PathBuf::from(link),
+            target: PathBuf::from(target),
+            created_at: created_at.to_string(),
+            last_verified: None,
+            mode: LinkMode::Symlink,
+            checksum: None,
+        };
+        let now = chrono::Utc::now().to_rfc3339();
+        let vimrc = home.join(".vimrc").to_string_lossy().to_string();
+        let plain = home.join(".plain").to_string_lossy().to_string();
+        let mut state = SymlinkState {
+            symlinks: vec![
+                entry(&vimrc, "/dotfiles/.vimrc", &now),
+                entry(&vimrc, "/dotfiles/.vimrc", "yesterday"),
+                entry(".config/nvim", "../dotfiles/nvim", &now),
+                entry(&plain, "/dotfiles/.plain", &now),
+            ],
+            ..Default::default()
+        };
+
+        let problems = state.fsck();
+        assert_eq!(problems.len(), 5);
+        assert!(problems.contains(&Inconsistency::Duplicate(PathBuf::from(&vimrc))));
+        assert!(problems.contains(&Inconsistency::NotASymlink(PathBuf::from(&plain))));
+
+        state.repair(home);
+        assert!(state.fsck().is_empty());
+        assert_eq!(state.symlinks.len(), 2);
+        assert_eq!(state.symlinks[1].link, home.join(".config/nvim"));
+        assert_eq!(state.symlinks[1].target, home.join("dotfiles/nvim"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_dangling_symlink_fails_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        let link = dir.path().join("link");
+        std::fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        assert!(verify_symlink(&link, &target).await.unwrap());
+
+        std::fs::remove_file(&target).unwrap();
+        assert!(verify_symlink(&link, &target).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_marker_detects_replaced_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        let link = dir.path().join("link");
+        std::fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let marker = LinkMarker {
+            link: link.clone(),
+            target: target.clone(),
+            identity: link_identity(&std::fs::symlink_metadata(&link).unwrap()),
+        };
+        assert!(marker_matches(
+            &marker,
+            &link,
+            &std::fs::symlink_metadata(&link).unwrap()
+        ));
+
+        // An identical-looking link created by another tool
+        let other = dir.path().join("other");
+        std::os::unix::fs::symlink(&target, &other).unwrap();
+        std::fs::rename(&other, &link).unwrap();
+        assert!(!marker_matches(
+            &marker,
+            &link,
+            &std::fs::symlink_metadata(&link).unwrap()
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn test_symlink_state_round_trip(
+            symlinks in proptest::collection::vec(symlink_entry(), 0..8)
+        ) {
+            let state = SymlinkState { symlinks, ..Default::default() };
+
+            let yaml = serde_yaml::to_string(&state).unwrap();
+            let loaded: SymlinkState = serde_yaml::from_str(&yaml).unwrap();
+
+            prop_assert_eq!(loaded, state);
+        }
+
+        #[test]
+        fn test_add_then_remove_entry(entries in proptest::collection::vec(symlink_entry(), 1..8)) {
+            let mut state = SymlinkState::default();
+            for entry in &entries {
+                state.add_entry(entry.link.clone(), entry.target.clone());
+            }
+
+            // Links are unique, the last target added wins
+            let last = entries.last().unwrap();
+            prop_assert_eq!(&state.find_entry(&last.link).unwrap().target, &last.target);
+
+            for entry in &entries {
+                state.remove_entry(&entry.link);
+            }
+            prop_assert!(state.symlinks.is_empty());
+        }
+    }
 }
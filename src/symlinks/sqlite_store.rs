@@ -0,0 +1,348 @@
+//! Symlink state kept in an SQLite database, selected with `symlinks.store: sqlite`
+//!
+//! Every tracked link is a row holding its entry as JSON, so creating or removing a link writes
+//! only that row instead of rewriting the whole state. The layout version is the database's
+//! `user_version`, and the installed completions, mirrors in use, and script runs are kept in
+//! `meta`. Loading assembles the same document `symlinks.yml` holds, so the state migrations
+//! apply to both stores.
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use super::{StateStore, SymlinkEntry, SymlinkState};
+use crate::config::LinkMode;
+use crate::migrate::{self, STATE_VERSION};
+
+/// Tables of the database
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS links (link TEXT PRIMARY KEY, entry TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+";
+
+/// State kept in an SQLite database
+pub struct SqliteStore {
+    /// Where the database is
+    path: PathBuf,
+    /// Open connection to it
+    connection: Mutex<Connection>,
+}
+
+/// Key of the row of `link`
+fn key(link: &Path) -> String {
+    link.to_string_lossy().into_owned()
+}
+
+/// Entry of `link` as of `transaction`
+fn entry(transaction: &Transaction, link: &Path) -> Result<Option<SymlinkEntry>> {
+    let entry: Option<String> = transaction
+        .query_row(
+            "SELECT entry FROM links WHERE link = ?1",
+            params![key(link)],
+            |row| row.get(0),
+        )
+        .optional()?;
+    entry
+        .map(|entry| serde_json::from_str(&entry).context("Failed to parse symlink entry"))
+        .transpose()
+}
+
+/// Insert `entry`, or replace the one of its link in place
+fn put(transaction: &Transaction, entry: &SymlinkEntry) -> Result<()> {
+    transaction.execute(
+        "INSERT INTO links (link, entry) VALUES (?1, ?2)
+         ON CONFLICT (link) DO UPDATE SET entry = excluded.entry",
+        params![key(&entry.link), serde_json::to_string(entry)?],
+    )?;
+    Ok(())
+}
+
+/// Replace everything in the database with `state`
+fn write_all(connection: &mut Connection, state: &SymlinkState) -> Result<()> {
+    let transaction = connection.transaction()?;
+    transaction.execute("DELETE FROM links", [])?;
+    for entry in &state.symlinks {
+        put(&transaction, entry)?;
+    }
+    transaction.execute(
+        "INSERT INTO meta (key, value) VALUES ('completions', ?1)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![serde_json::to_string(&state.completions)?],
+    )?;
+    transaction.execute(
+        "INSERT INTO meta (key, value) VALUES ('mirrors', ?1)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![serde_json::to_string(&state.mirrors)?],
+    )?;
+    transaction.execute(
+        "INSERT INTO meta (key, value) VALUES ('script_runs', ?1)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![serde_json::to_string(&state.script_runs)?],
+    )?;
+    transaction.pragma_update(None, "user_version", state.version.0)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+impl SqliteStore {
+    /// Open the database at `path`, creating it with the state of `yaml` if it doesn't exist
+    pub fn open(path: &Path, yaml: &dyn StateStore) -> Result<Self> {
+        let created = !path.exists();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create .dotme directory")?;
+        }
+        let connection =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        connection.execute_batch(SCHEMA)?;
+
+        let store = Self {
+            path: path.to_path_buf(),
+            connection: Mutex::new(connection),
+        };
+        if created {
+            store
+                .connection()
+                .pragma_update(None, "user_version", STATE_VERSION)?;
+            let file = yaml.path()?;
+            if file.exists() {
+                store.save(&yaml.load()?)?;
+                set_aside(&file)?;
+                log::info!(
+                    "Moved the symlink state from {} to {}",
+                    file.display(),
+                    path.display()
+                );
+            }
+        } else {
+            // Entries are read one at a time, so an outdated database is upgraded up front
+            store.load()?;
+        }
+        Ok(store)
+    }
+
+    /// The connection, usable again if a thread panicked while holding it
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn load(&self) -> Result<SymlinkState> {
+        let mut connection = self.connection();
+        let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let symlinks = connection
+            .prepare("SELECT entry FROM links ORDER BY rowid")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .map(|entry| Ok(serde_json::from_str::<serde_yaml::Value>(&entry?)?))
+            .collect::<Result<Vec<_>>>()
+            .context("Failed to parse symlink state database")?;
+        let meta = |key: &str| -> Result<Option<String>> {
+            Ok(connection
+                .query_row(
+                    "SELECT value FROM meta WHERE key = ?1",
+                    params![key],
+                    |row| row.get(0),
+                )
+                .optional()?)
+        };
+        let completions = meta("completions")?;
+        let mirrors = meta("mirrors")?;
+        let script_runs = meta("script_runs")?;
+
+        let mut document = serde_yaml::Mapping::new();
+        document.insert("version".into(), version.into());
+        document.insert("symlinks".into(), serde_yaml::Value::Sequence(symlinks));
+        if let Some(completions) = completions {
+            document.insert(
+                "completions".into(),
+                serde_json::from_str(&completions).context("Failed to parse completions")?,
+            );
+        }
+        if let Some(mirrors) = mirrors {
+            document.insert(
+                "mirrors".into(),
+                serde_json::from_str(&mirrors).context("Failed to parse mirrors")?,
+            );
+        }
+        if let Some(script_runs) = script_runs {
+            document.insert(
+                "script_runs".into(),
+                serde_json::from_str(&script_runs).context("Failed to parse script runs")?,
+            );
+        }
+        let mut document = serde_yaml::Value::Mapping(document);
+        let upgraded = migrate::upgrade_state(&mut document)?;
+        let state: SymlinkState =
+            serde_yaml::from_value(document).context("Failed to parse symlink state database")?;
+
+        if let Some(version) = upgraded {
+            migrate::backup(&self.path, version, STATE_VERSION)?;
+            write_all(&mut connection, &state)?;
+        }
+        Ok(state)
+    }
+
+    fn save(&self, state: &SymlinkState) -> Result<()> {
+        write_all(&mut self.connection(), state)
+    }
+
+    fn entry(&self, link: &Path) -> Result<Option<SymlinkEntry>> {
+        let mut connection = self.connection();
+        entry(&connection.transaction()?, link)
+    }
+
+    fn track(
+        &self,
+        link: &Path,
+        target: &Path,
+        mode: LinkMode,
+        checksum: Option<String>,
+    ) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        let entry = match entry(&transaction, link)? {
+            Some(mut entry) => {
+                entry.refresh(target.to_path_buf(), mode, checksum);
+                entry
+            }
+            None => SymlinkEntry::new(link.to_path_buf(), target.to_path_buf(), mode, checksum),
+        };
+        put(&transaction, &entry)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn forget(&self, link: &Path) -> Result<bool> {
+        let removed = self
+            .connection()
+            .execute("DELETE FROM links WHERE link = ?1", params![key(link)])?;
+        Ok(removed > 0)
+    }
+
+    fn path(&self) -> Result<PathBuf> {
+        Ok(self.path.clone())
+    }
+}
+
+/// Rename a state file that was moved into the other store, so it isn't mistaken for the state
+fn set_aside(path: &Path) -> Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".migrated");
+    std::fs::rename(path, path.with_file_name(name))
+        .with_context(|| format!("Failed to rename {}", path.display()))
+}
+
+/// Move the state from the database at `path` to `yaml`, after switching back to it
+pub fn move_out(path: &Path, yaml: &dyn StateStore) -> Result<()> {
+    let file = yaml.path()?;
+    if !path.exists() || file.exists() {
+        return Ok(());
+    }
+
+    let state = SqliteStore::open(path, yaml)?.load()?;
+    yaml.save(&state)?;
+    set_aside(path)?;
+    log::info!(
+        "Moved the symlink state from {} to {}",
+        path.display(),
+        file.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// YAML store at a fixed path
+    struct File(PathBuf);
+
+    impl StateStore for File {
+        fn load(&self) -> Result<SymlinkState> {
+            Ok(serde_yaml::from_str(&std::fs::read_to_string(&self.0)?)?)
+        }
+        fn save(&self, state: &SymlinkState) -> Result<()> {
+            Ok(std::fs::write(&self.0, serde_yaml::to_string(state)?)?)
+        }
+        fn entry(&self, link: &Path) -> Result<Option<SymlinkEntry>> {
+            Ok(self.load()?.find_entry(link).cloned())
+        }
+        fn track(
+            &self,
+            link: &Path,
+            target: &Path,
+            mode: LinkMode,
+            checksum: Option<String>,
+        ) -> Result<()> {
+            let mut state = self.load()?;
+            state.add_file_entry(link.to_path_buf(), target.to_path_buf(), mode, checksum);
+            self.save(&state)
+        }
+        fn forget(&self, link: &Path) -> Result<bool> {
+            let mut state = self.load()?;
+            let removed = state.remove_entry(link);
+            self.save(&state)?;
+            Ok(removed)
+        }
+        fn path(&self) -> Result<PathBuf> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_links_are_tracked_one_row_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml = File(dir.path().join("symlinks.yml"));
+        let mut state = SymlinkState::default();
+        state.add_entry("/home/u/.vimrc".into(), "/dots/vimrc".into());
+        yaml.save(&state).unwrap();
+
+        // The YAML state is moved into a new database
+        let db = dir.path().join("state.db");
+        let store = SqliteStore::open(&db, &yaml).unwrap();
+        assert!(!yaml.0.exists());
+        assert_eq!(store.load().unwrap(), state);
+
+        store
+            .track(
+                Path::new("/home/u/.zshrc"),
+                Path::new("/dots/zshrc"),
+                LinkMode::Copy,
+                Some("abc".to_string()),
+            )
+            .unwrap();
+        store
+            .track(
+                Path::new("/home/u/.vimrc"),
+                Path::new("/dots/nvim"),
+                LinkMode::Symlink,
+                None,
+            )
+            .unwrap();
+        let links: Vec<_> = store
+            .load()
+            .unwrap()
+            .symlinks
+            .into_iter()
+            .map(|entry| (entry.link, entry.target))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("/home/u/.vimrc".into(), "/dots/nvim".into()),
+                ("/home/u/.zshrc".into(), "/dots/zshrc".into())
+            ]
+        );
+        assert!(store.forget(Path::new("/home/u/.vimrc")).unwrap());
+        assert!(!store.forget(Path::new("/home/u/.vimrc")).unwrap());
+        drop(store);
+
+        // And back out when switching to YAML again
+        move_out(&db, &yaml).unwrap();
+        assert!(!db.exists());
+        assert_eq!(yaml.load().unwrap().symlinks.len(), 1);
+    }
+}
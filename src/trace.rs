@@ -0,0 +1,179 @@
+//! Structured traces of what the linker decided, for bug reports
+//!
+//! With `--trace-file out.json`, every decision made while linking during the run is recorded:
+//! which entry was linked into which base path, and for every path the rule that applied, the
+//! action taken, and how it went. The trace is written as JSON when dotme exits, so it can be
+//! attached to a bug report instead of describing the layout of a home directory.
+//!
+//! Paths inside the home directory are written as `~/...`, the hostname is left out, and entries
+//! marked `private: true` aren't recorded. Other paths are kept as they are, so review the file
+//! before sharing it.
+//!
+//! ```json
+//! {
+//!   "version": "0.3.0",
+//!   "os": "linux",
+//!   "command": "dotme update --dry-run",
+//!   "events": [
+//!     { "rule": "exists", "action": "skip", "link": "~/.bashrc", "target": "~/.dotme/git/dotfiles/.bashrc", "outcome": "dry run" }
+//!   ]
+//! }
+//! ```
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Trace of the current run, if one was requested
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+/// Everything recorded during a run
+#[derive(Debug, Clone, Serialize)]
+struct Trace {
+    /// Where the trace is written
+    #[serde(skip)]
+    path: PathBuf,
+    /// Home directory, replaced by `~` in recorded paths
+    #[serde(skip)]
+    home: Option<PathBuf>,
+    /// Version of dotme that made the decisions
+    version: &'static str,
+    /// Operating system dotme ran on
+    os: &'static str,
+    /// Command line of the run
+    command: String,
+    /// When the run started (ISO 8601 format)
+    started_at: String,
+    /// Decisions in the order they were made
+    events: Vec<Event>,
+}
+
+/// A decision about a single path, or about where an entry is linked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    /// Rule that applied, e.g. `exists`, `exclude`, or `on_conflict: backup`
+    pub rule: String,
+    /// What was done about it, e.g. `symlink`, `skip`, or `descend`
+    pub action: String,
+    /// Path the decision is about
+    pub link: PathBuf,
+    /// What the path links to, or the source being linked
+    pub target: PathBuf,
+    /// How carrying out the action went (`done`, `dry run`, or the error)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+}
+
+impl Event {
+    /// Decision about linking `link` to `target`
+    pub fn new(
+        rule: impl Into<String>,
+        action: impl Into<String>,
+        link: &Path,
+        target: &Path,
+    ) -> Self {
+        Self {
+            rule: rule.into(),
+            action: action.into(),
+            link: link.to_path_buf(),
+            target: target.to_path_buf(),
+            outcome: None,
+        }
+    }
+
+    /// Record how carrying out the action went
+    pub fn outcome(mut self, outcome: impl Into<String>) -> Self {
+        self.outcome = Some(outcome.into());
+        self
+    }
+}
+
+/// Start recording the decisions of this run into `path`
+pub fn start(path: &Path) {
+    let home = dirs::home_dir();
+    let mut command = vec!["dotme".to_string()];
+    command.extend(std::env::args().skip(1).map(|arg| {
+        redact(home.as_deref(), Path::new(&arg))
+            .display()
+            .to_string()
+    }));
+
+    *lock() = Some(Trace {
+        path: path.to_path_buf(),
+        home,
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        command: command.join(" "),
+        started_at: chrono::Utc::now().to_rfc3339(),
+        events: Vec::new(),
+    });
+}
+
+/// Record a decision, if a trace was requested
+pub fn record(mut event: Event) {
+    let mut trace = lock();
+    let Some(trace) = trace.as_mut() else {
+        return;
+    };
+
+    let home = trace.home.as_deref();
+    event.link = redact(home, &event.link);
+    event.target = redact(home, &event.target);
+    trace.events.push(event);
+}
+
+/// Write the trace file, if a trace was requested
+pub fn finish() {
+    let Some(trace) = lock().take() else {
+        return;
+    };
+
+    let written = serde_json::to_string_pretty(&trace)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| std::fs::write(&trace.path, json).map_err(anyhow::Error::from));
+    match written {
+        Ok(()) => log::info!(
+            "Wrote trace of {} decision(s) to {}",
+            trace.events.len(),
+            trace.path.display()
+        ),
+        Err(e) => log::error!("Failed to write trace to {}: {}", trace.path.display(), e),
+    }
+}
+
+/// Lock the trace, recovering it if a thread panicked while recording
+fn lock() -> std::sync::MutexGuard<'static, Option<Trace>> {
+    TRACE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Write a path inside the home directory as `~/...`
+fn redact(home: Option<&Path>, path: &Path) -> PathBuf {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(relative) if relative.as_os_str().is_empty() => PathBuf::from("~"),
+        Some(relative) => Path::new("~").join(relative),
+        None => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_home_is_redacted() {
+        let home = Path::new("/home/alice");
+        assert_eq!(
+            redact(Some(home), Path::new("/home/alice/.config/nvim")),
+            Path::new("~/.config/nvim")
+        );
+        assert_eq!(
+            redact(Some(home), Path::new("/home/alicea/.vimrc")),
+            Path::new("/home/alicea/.vimrc")
+        );
+        assert_eq!(redact(Some(home), home), Path::new("~"));
+        assert_eq!(
+            redact(None, Path::new("/etc/hosts")),
+            Path::new("/etc/hosts")
+        );
+    }
+}
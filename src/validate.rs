@@ -0,0 +1,552 @@
+//! Checks for `dotme config validate`
+//!
+//! The config is parsed the way every command parses it, so values of the wrong type and
+//! missing fields are reported with the key they belong to. The entries are then linted:
+//!
+//! - sources have to look like what their type expects (a git URL, an rclone remote), and local
+//!   sources of entries that apply to this machine have to exist
+//! - settings that don't apply to the kind of source, and invalid `exclude`/`include` patterns
+//! - entries listed twice, entries sharing a target, and clones nested inside each other
+//! - entries linked into their own source
+//! - dependencies on entries that aren't in the config, and entries depending on each other
+//! - entries that link the same path, of which only the one applied last wins (a warning)
+//!
+//! Errors make the command exit non-zero; warnings are only printed.
+
+use anyhow::Result;
+use globset::Glob;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::conditions::Facts;
+use crate::config::{Config, DotfileEntry, SourceType};
+use crate::dotfiles::{self, EntryFilter};
+use crate::sources::{self, LinkSource, is_rclone_remote, is_remote};
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The config won't work as written
+    Error,
+    /// The config works, but probably not as intended
+    Warning,
+}
+
+/// A problem found in the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// How bad it is
+    pub severity: Severity,
+    /// Source of the entry it is about, if any
+    pub entry: Option<String>,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let marker = match self.severity {
+            Severity::Error => "✗",
+            Severity::Warning => "⚠",
+        };
+        match &self.entry {
+            Some(entry) => write!(f, "{} {}: {}", marker, entry, self.message),
+            None => write!(f, "{} {}", marker, self.message),
+        }
+    }
+}
+
+/// Collects the findings about one entry
+struct Findings<'a> {
+    /// Name of the entry in messages
+    entry: String,
+    /// Where findings are added
+    into: &'a mut Vec<Finding>,
+}
+
+impl Findings<'_> {
+    fn error(&mut self, message: impl Into<String>) {
+        self.push(Severity::Error, message.into());
+    }
+
+    fn warning(&mut self, message: impl Into<String>) {
+        self.push(Severity::Warning, message.into());
+    }
+
+    fn push(&mut self, severity: Severity, message: String) {
+        self.into.push(Finding {
+            severity,
+            entry: Some(self.entry.clone()),
+            message,
+        });
+    }
+}
+
+/// Check if a remote git URL has a host and a repository path
+fn is_valid_git_url(source: &str) -> bool {
+    let (host, path) = if let Some(rest) = source.strip_prefix("git@") {
+        match rest.split_once(':') {
+            Some(parts) => parts,
+            None => return false,
+        }
+    } else {
+        let rest = ["https://", "http://", "ssh://"]
+            .iter()
+            .find_map(|scheme| source.strip_prefix(scheme))
+            .unwrap_or(source);
+        let rest = rest.split_once('@').map_or(rest, |(_, rest)| rest);
+        match rest.split_once('/') {
+            Some(parts) => parts,
+            None => return false,
+        }
+    };
+
+    !host.is_empty() && !host.contains(char::is_whitespace) && !path.trim_matches('/').is_empty()
+}
+
+/// Check that the source of an entry looks like what its type expects
+fn check_source(entry: &DotfileEntry, applies: bool, findings: &mut Findings) {
+    let local = Path::new(&entry.source);
+    match entry.r#type {
+        SourceType::Git if is_remote(&entry.source) => {
+            if !is_valid_git_url(&entry.source) {
+                findings.error(format!("'{}' isn't a valid git URL", entry.source));
+            }
+        }
+        // Local repositories, including file:// URLs, are cloned from where they are
+        SourceType::Git => {
+            let path = entry
+                .source
+                .strip_prefix("file://")
+                .map_or(local, Path::new);
+            if applies && !path.join(".git").exists() && !path.join("HEAD").exists() {
+                findings.error(format!(
+                    "'{}' is neither a git URL nor a git repository on this machine",
+                    entry.source
+                ));
+            }
+        }
+        SourceType::Rclone => {
+            if !is_rclone_remote(&entry.source) {
+                findings.error(format!(
+                    "'{}' isn't an rclone remote (remote:path)",
+                    entry.source
+                ));
+            }
+        }
+        SourceType::Directory if applies && !local.is_dir() => {
+            findings.error(if local.exists() {
+                format!("{} isn't a directory; use type: file", entry.source)
+            } else {
+                format!("{} doesn't exist", entry.source)
+            });
+        }
+        SourceType::File if applies && !local.is_file() => {
+            findings.error(if local.exists() {
+                format!("{} isn't a file; use type: directory", entry.source)
+            } else {
+                format!("{} doesn't exist", entry.source)
+            });
+        }
+        SourceType::Directory | SourceType::File => {}
+    }
+}
+
+/// Check the settings of a single entry
+fn check_entry(entry: &DotfileEntry, applies: bool, findings: &mut Findings) {
+    if entry.source.trim().is_empty() {
+        findings.error("source is empty");
+        return;
+    }
+    check_source(entry, applies, findings);
+
+    if !entry.mirrors.is_empty() && !matches!(entry.r#type, SourceType::Git) {
+        findings.warning("mirrors are only used for git repositories");
+    }
+    for mirror in &entry.mirrors {
+        if is_remote(mirror) && !is_valid_git_url(mirror) {
+            findings.error(format!("mirror '{}' isn't a valid git URL", mirror));
+        }
+    }
+
+    let provider = sources::provider(entry.r#type);
+    if let Err(e) = provider.validate(entry) {
+        findings.error(e.to_string());
+    }
+
+    for (kind, patterns) in [("exclude", &entry.exclude), ("include", &entry.include)] {
+        for pattern in patterns.iter().flatten() {
+            if let Err(e) = Glob::new(pattern) {
+                findings.error(format!("invalid {} pattern '{}': {}", kind, pattern, e));
+            }
+        }
+    }
+
+    if let Ok(base) = dotfiles::base_path(entry) {
+        let root = provider.root(entry);
+        if !root.as_os_str().is_empty() && base.starts_with(&root) {
+            findings.error(format!(
+                "it is linked into {}, inside its own source",
+                base.display()
+            ));
+        }
+    }
+}
+
+/// Paths an entry links directly into its base path, and whether each is a directory
+fn top_level_links(entry: &DotfileEntry) -> Vec<(PathBuf, bool)> {
+    let provider = sources::provider(entry.r#type);
+    let (Ok(base), Ok(files)) = (dotfiles::base_path(entry), provider.list_files(entry)) else {
+        return Vec::new();
+    };
+    let Ok(filter) = EntryFilter::new(entry, &provider.root(entry)) else {
+        return Vec::new();
+    };
+
+    let mut items = Vec::new();
+    for source in files {
+        match source {
+            LinkSource::File(path) => items.push(path),
+            LinkSource::Contents(dir) => {
+                let Ok(entries) = std::fs::read_dir(&dir) else {
+                    continue;
+                };
+                items.extend(
+                    entries
+                        .flatten()
+                        .map(|item| item.path())
+                        .filter(|path| path.file_name().is_some_and(|name| name != ".git")),
+                );
+            }
+        }
+    }
+
+    items
+        .into_iter()
+        .filter(|path| !filter.is_excluded(path))
+        .filter_map(|path| {
+            let (name, _) = dotfiles::link_name(path.file_name()?, entry.naming);
+            Some((base.join(name), path.is_dir()))
+        })
+        .collect()
+}
+
+/// Lint the entries of a parsed config
+pub fn lint(config: &Config, facts: &Facts) -> Vec<Finding> {
+    let mut found = Vec::new();
+    let label = |index: usize, entry: &DotfileEntry| {
+        if entry.source.trim().is_empty() {
+            format!("dotfiles[{}]", index)
+        } else {
+            entry.source.clone()
+        }
+    };
+
+    let mut unresolved = false;
+    for (index, entry) in config.dotfiles.iter().enumerate() {
+        let mut findings = Findings {
+            entry: label(index, entry),
+            into: &mut found,
+        };
+        check_entry(entry, entry.applies_to(facts), &mut findings);
+        for dependency in &entry.depends_on {
+            if let Err(e) = dotfiles::find_entry(&config.dotfiles, dependency) {
+                unresolved = true;
+                findings.error(format!("depends on {}: {}", dependency, e));
+            }
+        }
+
+        for other in &config.dotfiles[..index] {
+            if other.source == entry.source {
+                findings.error("listed more than once");
+                continue;
+            }
+            if let Some(name) = &entry.name
+                && other.name.as_ref() == Some(name)
+            {
+                findings.error(format!("name '{}' is also used by {}", name, other.source));
+            }
+
+            let (path, other_path) = (entry.local_path(), other.local_path());
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            if path == other_path {
+                findings.error(format!(
+                    "target {} is also used by {}",
+                    path.display(),
+                    other.source
+                ));
+            } else if matches!(entry.r#type, SourceType::Git)
+                && matches!(other.r#type, SourceType::Git)
+                && dotfiles::clones_collide(&config.dotfiles, (entry, &path), (other, &other_path))
+            {
+                findings.error(format!(
+                    "clone {} overlaps the clone {} of {}",
+                    path.display(),
+                    other_path.display(),
+                    other.source
+                ));
+            }
+        }
+    }
+
+    // With every dependency found, ordering the entries can only fail on a cycle
+    if !unresolved && let Err(e) = dotfiles::order_by_dependencies(config.dotfiles.clone()) {
+        found.push(Finding {
+            severity: Severity::Error,
+            entry: None,
+            message: e.to_string(),
+        });
+    }
+
+    // Entries linking the same path; directories are merged, anything else is taken by the entry
+    // applied last, in order of priority
+    let mut ranked: Vec<_> = config.dotfiles.iter().enumerate().collect();
+    ranked.sort_by_key(|(_, entry)| entry.priority.unwrap_or_default());
+    let mut linked: BTreeMap<PathBuf, (String, bool)> = BTreeMap::new();
+    for (index, entry) in ranked {
+        if !entry.applies_to(facts) {
+            continue;
+        }
+        for (link, is_dir) in top_level_links(entry) {
+            match linked.get(&link) {
+                Some((first, first_is_dir)) if !(is_dir && *first_is_dir) => {
+                    Findings {
+                        entry: label(index, entry),
+                        into: &mut found,
+                    }
+                    .warning(format!(
+                        "{} is also linked by {}, which this entry replaces as it applies later",
+                        link.display(),
+                        first
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    linked.insert(link, (label(index, entry), is_dir));
+                }
+            }
+        }
+    }
+
+    found.sort_by_key(|finding| finding.severity);
+    found
+}
+
+/// Validate the config at `file`, or the dotme config; fails if there are errors
+pub async fn validate(file: Option<PathBuf>) -> Result<()> {
+    let path = match file {
+        Some(file) => file,
+        None => dotfiles::get_config_path()?,
+    };
+    if !path.exists() {
+        anyhow::bail!("{} doesn't exist", path.display());
+    }
+
+    let config = Config::load_file(&path)
+        .map_err(|e| anyhow::anyhow!("{} can't be parsed: {:#}", path.display(), e))?;
+    let mut findings = lint(&config, &Facts::detect());
+    for include in crate::config::includes_of(&path)? {
+        if !include.exists() {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                entry: None,
+                message: format!(
+                    "included file {} doesn't exist, so it is skipped",
+                    include.display()
+                ),
+            });
+        }
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    if errors > 0 {
+        anyhow::bail!(
+            "{} has {} error(s) and {} warning(s)",
+            path.display(),
+            errors,
+            findings.len() - errors
+        );
+    }
+
+    println!(
+        "✓ {} is valid ({} entries, {} warning(s))",
+        path.display(),
+        config.dotfiles.len(),
+        findings.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source: &str, r#type: SourceType, target: &str) -> DotfileEntry {
+        DotfileEntry {
+            target: PathBuf::from(target),
+            ..DotfileEntry::new(source, r#type)
+        }
+    }
+
+    #[test]
+    fn test_git_urls_are_checked() {
+        assert!(is_valid_git_url("https://github.com/user/dotfiles.git"));
+        assert!(is_valid_git_url("git@github.com:user/dotfiles.git"));
+        assert!(is_valid_git_url("ssh://git@host:2222/team/dots"));
+        assert!(!is_valid_git_url("https://github.com"));
+        assert!(!is_valid_git_url("git@github.com"));
+        assert!(!is_valid_git_url("https:///user/dots"));
+    }
+
+    #[test]
+    fn test_duplicates_and_overlaps_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = dir.path().to_string_lossy().to_string();
+        let config = Config {
+            dotfiles: vec![
+                entry(
+                    "https://github.com/a/dots.git",
+                    SourceType::Git,
+                    "/git/dots",
+                ),
+                entry(
+                    "https://github.com/b/dots.git",
+                    SourceType::Git,
+                    "/git/dots",
+                ),
+                entry(
+                    "git@github.com:c/nested",
+                    SourceType::Git,
+                    "/git/dots/nested",
+                ),
+                entry(&local, SourceType::Directory, &local),
+                entry(&local, SourceType::Directory, &local),
+                entry("not a url", SourceType::Rclone, "/remote/x"),
+                DotfileEntry {
+                    exclude: Some(vec!["[".to_string()]),
+                    ..entry("/does/not/exist", SourceType::File, "/does/not/exist")
+                },
+                DotfileEntry {
+                    mirrors: vec!["https://github.com".to_string()],
+                    name: Some("d".to_string()),
+                    ..entry("https://github.com/d/dots.git", SourceType::Git, "/git/d")
+                },
+                DotfileEntry {
+                    name: Some("d".to_string()),
+                    depends_on: vec!["omz".to_string()],
+                    ..entry("https://github.com/e/dots.git", SourceType::Git, "/git/e")
+                },
+            ],
+            ..Default::default()
+        };
+
+        let messages: Vec<String> = lint(&config, &Facts::default())
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect();
+        let has = |needle: &str| messages.iter().any(|message| message.contains(needle));
+        assert!(has("b/dots.git: target /git/dots is also used by"));
+        assert!(has("c/nested: clone /git/dots/nested overlaps"));
+        assert!(has("listed more than once"));
+        assert!(has("isn't an rclone remote"));
+        assert!(has("/does/not/exist doesn't exist"));
+        assert!(has("invalid exclude pattern '['"));
+        assert!(has("mirror 'https://github.com' isn't a valid git URL"));
+        assert!(has(
+            "e/dots.git: name 'd' is also used by https://github.com/d/dots.git"
+        ));
+        assert!(has(
+            "e/dots.git: depends on omz: Source 'omz' is not being managed"
+        ));
+        assert!(
+            !lint(&config, &Facts::default())
+                .iter()
+                .any(|finding| finding.entry.as_deref() == Some("https://github.com/a/dots.git"))
+        );
+    }
+
+    #[test]
+    fn test_clones_nest_in_the_clones_they_depend_on() {
+        let framework = entry(
+            "https://github.com/ohmyzsh/oh-my-zsh.git",
+            SourceType::Git,
+            "/git/oh-my-zsh",
+        );
+        let plugin = |depends_on: &[&str]| DotfileEntry {
+            depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+            ..entry(
+                "https://github.com/zsh-users/zsh-autosuggestions.git",
+                SourceType::Git,
+                "/git/oh-my-zsh/custom/plugins/zsh-autosuggestions",
+            )
+        };
+        let lint_with = |plugin: DotfileEntry| {
+            let config = Config {
+                dotfiles: vec![framework.clone(), plugin],
+                ..Default::default()
+            };
+            lint(&config, &Facts::default())
+        };
+
+        assert!(lint_with(plugin(&["oh-my-zsh"])).is_empty());
+        let findings = lint_with(plugin(&[]));
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert!(findings[0].message.contains("overlaps the clone"));
+
+        let config = Config {
+            dotfiles: vec![
+                DotfileEntry {
+                    depends_on: vec!["zsh-autosuggestions".to_string()],
+                    ..framework.clone()
+                },
+                plugin(&["oh-my-zsh"]),
+            ],
+            ..Default::default()
+        };
+        assert!(
+            lint(&config, &Facts::default())
+                .iter()
+                .any(|finding| finding.message.starts_with("Entries depend on each other"))
+        );
+    }
+
+    #[test]
+    fn test_paths_linked_twice_are_warnings() {
+        let dir = tempfile::tempdir().unwrap();
+        let (first, second, home) = (
+            dir.path().join("first"),
+            dir.path().join("second"),
+            dir.path().join("home"),
+        );
+        for source in [&first, &second] {
+            std::fs::create_dir_all(source.join(".config")).unwrap();
+            std::fs::write(source.join(".bashrc"), "").unwrap();
+        }
+        let linked = |source: &Path| DotfileEntry {
+            path: Some(home.clone()),
+            ..entry(
+                &source.to_string_lossy(),
+                SourceType::Directory,
+                &source.to_string_lossy(),
+            )
+        };
+        let config = Config {
+            dotfiles: vec![linked(&first), linked(&second)],
+            ..Default::default()
+        };
+
+        let findings = lint(&config, &Facts::default());
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert!(findings[0].message.contains(".bashrc is also linked by"));
+    }
+}
@@ -0,0 +1,330 @@
+//! Variables for entry scripts, from the config, the environment, or asked once
+//!
+//! The `variables:` section names values the machine's scripts need, such as an email address
+//! or a GitHub user. A value is either written in the config, read from an environment
+//! variable, or asked for the first time it is needed and kept in `vars.yml` in the dotme
+//! directory, so it is asked only once per machine. Entry scripts refer to variables as
+//! `{{ name }}`, and scripts run with `dotme run` get them as `DOTME_VAR_<NAME>`. Entry scripts
+//! get them in the environment too: `{{ name }}` is replaced with a reference to
+//! `DOTME_VAR_<NAME>`, quoted to fit where it is written, so a value is never run as part of
+//! the script. Entry scripts can also read a [secret](crate::secrets) with
+//! `{{ secret "op://vault/item/field" }}`, passed the same way in `DOTME_SECRET_<n>`.
+//! Values in this machine's [host file](crate::host) take the place of variables of the same
+//! name.
+//!
+//! ```yaml
+//! variables:
+//!   email: jane@example.com
+//!   github_user:
+//!     env: GITHUB_USER
+//!   name:
+//!     prompt: Your full name
+//!     default: Jane Doe
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::atomic;
+use crate::dotfiles;
+use crate::host;
+use crate::prompt;
+use crate::secrets;
+
+/// Where the value of a variable comes from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Variable {
+    /// Written in the config
+    Value(String),
+    /// Read from the environment or asked for
+    Lookup(Lookup),
+}
+
+/// Ways to find the value of a variable, tried in order: the environment variable, the cached
+/// answer to the prompt (or asking it), the default
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Lookup {
+    /// Environment variable holding the value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<String>,
+    /// Question asked once for the value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Value if nothing else gives one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// File the answers to prompts are kept in
+fn cache_path() -> Result<PathBuf> {
+    Ok(dotfiles::get_dotme_dir()?.join("vars.yml"))
+}
+
+/// Answers given to prompts so far
+fn load_answers() -> Result<BTreeMap<String, String>> {
+    let path = cache_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_yaml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Keep the answer to the prompt of `name`
+fn save_answer(name: &str, value: &str) -> Result<()> {
+    let mut answers = load_answers()?;
+    answers.insert(name.to_string(), value.to_string());
+    let path = cache_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    atomic::write(&path, serde_yaml::to_string(&answers)?.as_bytes())
+}
+
+/// Find the value of the variable `name`, asking for it if it has a prompt that wasn't answered
+pub fn resolve(name: &str, variable: &Variable) -> Result<String> {
+    let lookup = match variable {
+        Variable::Value(value) => return Ok(value.clone()),
+        Variable::Lookup(lookup) => lookup,
+    };
+
+    if let Some(value) = lookup
+        .env
+        .as_ref()
+        .and_then(|env| std::env::var(env).ok())
+        .filter(|value| !value.is_empty())
+    {
+        return Ok(value);
+    }
+    if let Some(question) = &lookup.prompt {
+        if let Some(answer) = load_answers()?.remove(name) {
+            return Ok(answer);
+        }
+        // Without a terminal, the default is used without keeping it as the answer
+        if prompt::is_interactive() {
+            let answer = prompt::input(question, lookup.default.clone().unwrap_or_default())?;
+            save_answer(name, &answer)?;
+            return Ok(answer);
+        }
+    }
+    if let Some(default) = &lookup.default {
+        return Ok(default.clone());
+    }
+
+    match &lookup.env {
+        Some(env) => anyhow::bail!("Variable '{}' has no value: ${} is not set", name, env),
+        None => anyhow::bail!("Variable '{}' has no value", name),
+    }
+}
+
+/// `variables` along with the values of the host file, which replace those of the same name
+fn with_host(variables: &BTreeMap<String, Variable>) -> Result<BTreeMap<String, Variable>> {
+    let mut merged = variables.clone();
+    for (name, value) in host::load()? {
+        merged.insert(name, Variable::Value(value));
+    }
+    Ok(merged)
+}
+
+/// Values of all variables, asking for the ones that weren't answered yet
+pub fn resolve_all(variables: &BTreeMap<String, Variable>) -> Result<BTreeMap<String, String>> {
+    with_host(variables)?
+        .iter()
+        .map(|(name, variable)| Ok((name.clone(), resolve(name, variable)?)))
+        .collect()
+}
+
+/// A script with references to the variables and secrets it uses, and their values to run it
+/// with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rendered {
+    /// The script, with a reference in place of each `{{ name }}`
+    pub script: String,
+    /// Environment variables holding the values referenced
+    pub env: BTreeMap<String, String>,
+}
+
+/// Replace each `{{ name }}` in `text` with a reference to the environment variable its value is
+/// passed in, asking only for the variables used, and each `{{ secret "reference" }}` with one
+/// to `DOTME_SECRET_<n>`, holding the secret
+pub fn render(text: &str, variables: &BTreeMap<String, Variable>) -> Result<Rendered> {
+    let variables = &with_host(variables)?;
+    let mut rendered = Rendered::default();
+    let mut quote = None;
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let expression = rest[start + 2..start + end].trim();
+        quote = quote_after(&rest[..start], quote);
+        rendered.script.push_str(&rest[..start]);
+        let secrets = rendered
+            .env
+            .keys()
+            .filter(|name| name.starts_with("DOTME_SECRET_"))
+            .count();
+        let (name, value) = evaluate(expression, variables, secrets)?;
+        rendered.script.push_str(&reference(&name, quote));
+        rendered.env.insert(name, value);
+        rest = &rest[start + end + 2..];
+    }
+    rendered.script.push_str(rest);
+    Ok(rendered)
+}
+
+/// Value of what is written between `{{` and `}}`: a variable name or `secret "reference"`,
+/// along with the environment variable it is passed in. Secrets are numbered after the `secrets`
+/// found before them.
+fn evaluate(
+    expression: &str,
+    variables: &BTreeMap<String, Variable>,
+    secrets: usize,
+) -> Result<(String, String)> {
+    if let Some(argument) = expression.strip_prefix("secret")
+        && argument.starts_with(char::is_whitespace)
+    {
+        let reference = argument
+            .trim()
+            .strip_prefix('"')
+            .and_then(|argument| argument.strip_suffix('"'))
+            .context(
+                r#"Expected a quoted reference, as in {{ secret "op://vault/item/field" }}"#,
+            )?;
+        return Ok((
+            format!("DOTME_SECRET_{}", secrets + 1),
+            secrets::read(reference)?,
+        ));
+    }
+
+    let variable = variables
+        .get(expression)
+        .with_context(|| format!("Unknown variable '{}'", expression))?;
+    Ok((env_name(expression), resolve(expression, variable)?))
+}
+
+/// Quote open at the end of `text`, a piece of a `sh` script, when `quote` was open at its start
+fn quote_after(text: &str, mut quote: Option<char>) -> Option<char> {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('"'), '"') => quote = None,
+            _ => {}
+        }
+    }
+    quote
+}
+
+/// Reference to the environment variable `name` in a script, where `quote` is open. The value is
+/// expanded after the script is parsed, so it can't run commands whatever it holds.
+fn reference(name: &str, quote: Option<char>) -> String {
+    if cfg!(windows) {
+        // Delayed expansion, which `cmd /V:ON` does after parsing the command line
+        return format!("!{}!", name);
+    }
+    match quote {
+        Some('\'') => format!("'\"${{{}}}\"'", name),
+        Some(_) => format!("${{{}}}", name),
+        None => format!("\"${{{}}}\"", name),
+    }
+}
+
+/// Name of the environment variable a variable is passed to scripts in, e.g. `DOTME_VAR_EMAIL`
+pub fn env_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("DOTME_VAR_{}", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables_are_rendered() {
+        let variables: BTreeMap<String, Variable> = serde_yaml::from_str(
+            "
+email: jane@example.com
+shell:
+  env: DOTME_TEST_UNSET_VARIABLE
+  default: zsh
+path:
+  env: PATH
+",
+        )
+        .unwrap();
+
+        let rendered = render("git config user.email {{ email }} # {{shell}}", &variables).unwrap();
+        assert_eq!(
+            rendered.script,
+            r#"git config user.email "${DOTME_VAR_EMAIL}" # "${DOTME_VAR_SHELL}""#
+        );
+        assert_eq!(
+            rendered.env,
+            BTreeMap::from([
+                (
+                    "DOTME_VAR_EMAIL".to_string(),
+                    "jane@example.com".to_string()
+                ),
+                ("DOTME_VAR_SHELL".to_string(), "zsh".to_string()),
+            ])
+        );
+        assert_eq!(
+            render("echo {{ path }}", &variables).unwrap().env["DOTME_VAR_PATH"],
+            std::env::var("PATH").unwrap()
+        );
+        assert_eq!(render("no {{ end", &variables).unwrap().script, "no {{ end");
+        assert!(render("{{ github_user }}", &variables).is_err());
+        assert!(render("{{ secret op://vault/item/field }}", &variables).is_err());
+        assert!(render(r#"{{ secret "keychain://item" }}"#, &variables).is_err());
+        assert_eq!(env_name("github-user"), "DOTME_VAR_GITHUB_USER");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_values_are_never_run_as_part_of_the_script() {
+        let value = r#"x'; touch pwned; echo "$(touch pwned)`touch pwned`"#;
+        let variables = BTreeMap::from([("v".to_string(), Variable::Value(value.to_string()))]);
+        let dir = tempfile::tempdir().unwrap();
+
+        for script in [
+            "printf %s {{ v }}",
+            "printf %s '{{ v }}'",
+            r#"printf %s "{{ v }}""#,
+            r#"printf %s "a \" b" '"' {{v}}"#,
+        ] {
+            let rendered = render(script, &variables).unwrap();
+            let output = crate::scripts::shell(&rendered.script)
+                .envs(&rendered.env)
+                .current_dir(dir.path())
+                .output()
+                .await
+                .unwrap();
+            assert!(output.status.success(), "{}", script);
+            assert!(
+                String::from_utf8_lossy(&output.stdout).ends_with(value),
+                "{}",
+                script
+            );
+            assert!(!dir.path().join("pwned").exists(), "{}", script);
+        }
+    }
+}
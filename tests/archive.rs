@@ -0,0 +1,39 @@
+mod common;
+
+use common::{Sandbox, file_url, read, repository};
+
+#[test]
+fn archiving_unlinks_an_entry_and_unarchive_links_it_again() {
+    let sandbox = Sandbox::new();
+    let remote = sandbox.path("dots");
+    repository(&remote, &[(".zshrc", "export EDITOR=vim")]);
+    let (source, home) = (file_url(&remote), sandbox.home());
+    let link = home.join(".zshrc");
+
+    let clone = home.join(".dotme/git/dots");
+    sandbox.write_config(&format!(
+        "dotfiles:\n  - source: {}\n    type: git\n    target: {}\n    path: {}\n",
+        source,
+        clone.display(),
+        home.display()
+    ));
+    sandbox.ok(&["update"]);
+    assert!(link.is_symlink());
+    assert!(clone.is_dir());
+
+    sandbox.ok(&["archive", &source]);
+    assert!(link.symlink_metadata().is_err());
+    assert!(!read(&sandbox.config_path()).contains(&source));
+    // The entry and its clone are kept in the archive
+    let archived = home.join(".dotme/archive/dots");
+    assert!(read(&archived.join("entry.yml")).contains(&source));
+    assert!(archived.join("repo/.zshrc").exists());
+    assert!(!clone.exists());
+
+    sandbox.ok(&["unarchive", "dots"]);
+    assert!(link.is_symlink());
+    assert_eq!(read(&link), "export EDITOR=vim");
+    assert!(read(&sandbox.config_path()).contains(&source));
+    assert!(clone.join(".zshrc").exists());
+    assert!(!archived.exists());
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::{Sandbox, file_url, read, repository};
+
+#[test]
+fn bootstrap_clones_and_links_the_configured_entries() {
+    let sandbox = Sandbox::new();
+    let home = sandbox.home();
+    let dots = sandbox.path("dots");
+    repository(&dots, &[(".zshrc", "export EDITOR=vim")]);
+    // The config comes from another machine, so its target is recomputed for this one
+    let setup = sandbox.path("setup");
+    let config = format!(
+        "dotfiles:\n  - source: {}\n    type: git\n    target: /elsewhere/dots\n    path: {}\n",
+        file_url(&dots),
+        home.display()
+    );
+    repository(&setup, &[("machines/laptop.yml", &config)]);
+
+    sandbox.ok(&[
+        "bootstrap",
+        &file_url(&setup),
+        "--file",
+        "machines/laptop.yml",
+    ]);
+
+    assert!(read(&sandbox.config_path()).contains(&file_url(&dots)));
+    assert!(home.join(".dotme/git/setup/machines/laptop.yml").exists());
+    assert!(home.join(".dotme/git/dots/.zshrc").exists());
+    assert!(home.join(".zshrc").is_symlink());
+    assert_eq!(read(&home.join(".zshrc")), "export EDITOR=vim");
+
+    // Running it again would replace the config
+    assert!(
+        !sandbox
+            .dotme(&["bootstrap", &file_url(&setup)])
+            .status
+            .success()
+    );
+}
@@ -0,0 +1,138 @@
+//! A throwaway home directory to run the dotme binary in
+
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A home directory of its own, with dotme's files in `~/.dotme`
+pub struct Sandbox {
+    dir: tempfile::TempDir,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        let sandbox = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        std::fs::create_dir_all(sandbox.home()).unwrap();
+        sandbox
+    }
+
+    /// Directory for anything besides the home directory, e.g. sources and remotes
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    pub fn home(&self) -> PathBuf {
+        self.path("home")
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.home().join(".dotme/config.yml")
+    }
+
+    /// Replace the config with `yaml`
+    pub fn write_config(&self, yaml: &str) {
+        let path = self.config_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, yaml).unwrap();
+    }
+
+    /// Run dotme without a terminal and with nothing from the user's environment
+    pub fn dotme(&self, args: &[&str]) -> Output {
+        self.dotme_with_path(args, &std::env::var_os("PATH").unwrap_or_default())
+    }
+
+    /// Run dotme like [`Sandbox::dotme`], looking up programs in `path` only
+    pub fn dotme_with_path(&self, args: &[&str], path: &OsStr) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_dotme"))
+            .arg("--disable-banner")
+            .args(args)
+            .env_clear()
+            .env("PATH", path)
+            .env("HOME", self.home())
+            .env("DOTME_NONINTERACTIVE", "1")
+            .envs(git_env())
+            .current_dir(self.home())
+            .output()
+            .unwrap();
+        log(args, &output);
+        output
+    }
+
+    /// Run dotme and fail the test if it fails
+    pub fn ok(&self, args: &[&str]) -> Output {
+        let output = self.dotme(args);
+        assert!(output.status.success(), "dotme {} failed", args.join(" "));
+        output
+    }
+}
+
+/// Environment that keeps git away from the user's config and gives commits an author
+fn git_env() -> [(&'static str, &'static str); 6] {
+    [
+        ("GIT_CONFIG_NOSYSTEM", "1"),
+        ("GIT_CONFIG_GLOBAL", "/dev/null"),
+        ("GIT_AUTHOR_NAME", "Jane Doe"),
+        ("GIT_AUTHOR_EMAIL", "jane@example.com"),
+        ("GIT_COMMITTER_NAME", "Jane Doe"),
+        ("GIT_COMMITTER_EMAIL", "jane@example.com"),
+    ]
+}
+
+fn log(args: &[&str], output: &Output) {
+    eprintln!(
+        "$ dotme {}\n{}{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Run git in `dir`
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .envs(git_env())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Create a git repository at `dir` with `files` committed on `main`
+pub fn repository(dir: &Path, files: &[(&str, &str)]) {
+    std::fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "--quiet", "--initial-branch=main"]);
+    commit(dir, files, "Initial commit");
+}
+
+/// Write `files` into the repository at `dir` and commit them
+pub fn commit(dir: &Path, files: &[(&str, &str)], message: &str) -> String {
+    for (name, contents) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    git(dir, &["add", "--all"]);
+    git(dir, &["commit", "--quiet", "--message", message]);
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+/// `file://` URL of a local path
+pub fn file_url(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+/// Contents of a file
+pub fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
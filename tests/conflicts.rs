@@ -0,0 +1,35 @@
+mod common;
+
+use common::{Sandbox, read};
+
+#[test]
+fn edited_copies_are_backed_up_instead_of_overwritten() {
+    let sandbox = Sandbox::new();
+    let source = sandbox.path("dotfiles");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join(".vimrc"), "set number").unwrap();
+    let home = sandbox.home();
+
+    sandbox.ok(&["init"]);
+    sandbox.ok(&[
+        "add",
+        source.to_str().unwrap(),
+        "--path",
+        home.to_str().unwrap(),
+        "--mode",
+        "copy",
+        "--on-conflict",
+        "overwrite",
+    ]);
+    assert_eq!(read(&home.join(".vimrc")), "set number");
+
+    // The edited copy is left alone when the entry is unlinked, and then in the way of its link
+    std::fs::write(home.join(".vimrc"), "set relativenumber").unwrap();
+    sandbox.ok(&["update"]);
+
+    assert_eq!(read(&home.join(".vimrc")), "set number");
+    assert_eq!(
+        read(&home.join(".vimrc.dotme-backup")),
+        "set relativenumber"
+    );
+}
@@ -0,0 +1,39 @@
+#![cfg(all(feature = "gix-backend", unix))]
+
+mod common;
+
+use std::path::Path;
+
+use common::{Sandbox, commit, file_url, git, read, repository};
+
+#[test]
+fn updates_with_the_gix_backend_need_no_git_command() {
+    let sandbox = Sandbox::new();
+    let home = sandbox.home();
+    let remote = sandbox.path("dots");
+    repository(&remote, &[(".zshrc", "export EDITOR=vi")]);
+    let clone = home.join(".dotme/git/dots");
+    sandbox.write_config(&format!(
+        "git:\n  backend: gix\ndotfiles:\n  - source: {}\n    type: git\n    target: {}\n    path: {}\n",
+        file_url(&remote),
+        clone.display(),
+        home.display()
+    ));
+    sandbox.ok(&["update"]);
+    assert!(clone.join(".zshrc").exists());
+
+    commit(&remote, &[(".zshrc", "export EDITOR=vim")], "Use vim");
+    // The local transport runs the server side of a fetch, which for remote hosts is on the
+    // other end, so that is all there is of git
+    let bin = sandbox.path("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let exec_path = git(&remote, &["--exec-path"]);
+    std::os::unix::fs::symlink(
+        Path::new(&exec_path).join("git-upload-pack"),
+        bin.join("git-upload-pack"),
+    )
+    .unwrap();
+    let output = sandbox.dotme_with_path(&["update"], bin.as_os_str());
+    assert!(output.status.success(), "dotme update failed without git");
+    assert_eq!(read(&home.join(".zshrc")), "export EDITOR=vim");
+}
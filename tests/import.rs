@@ -0,0 +1,39 @@
+mod common;
+
+use common::{Sandbox, read};
+
+#[test]
+fn imported_entries_leave_their_scripts_behind_unless_asked() {
+    let sandbox = Sandbox::new();
+    let home = sandbox.home();
+    let source = sandbox.path("dotfiles");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join(".vimrc"), "set number").unwrap();
+    let marker = sandbox.path("ran");
+    let shared = sandbox.path("shared.yml");
+    std::fs::write(
+        &shared,
+        format!(
+            "dotfiles:\n  - source: {}\n    type: directory\n    target: {}\n    path: {}\n    scripts:\n      install: touch {}\n      update: touch {}\n",
+            source.display(),
+            source.display(),
+            home.display(),
+            marker.display(),
+            marker.display()
+        ),
+    )
+    .unwrap();
+
+    sandbox.ok(&["init"]);
+    let output = sandbox.ok(&["--yes", "add", "--from-config", shared.to_str().unwrap()]);
+    // The commands are shown even though nobody is asked
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains(&format!("touch {}", marker.display()))
+    );
+    assert!(read(&sandbox.config_path()).contains(&source.display().to_string()));
+    assert!(!read(&sandbox.config_path()).contains("scripts"));
+
+    sandbox.ok(&["update"]);
+    assert!(home.join(".vimrc").is_symlink());
+    assert!(!marker.exists());
+}
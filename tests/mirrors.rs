@@ -0,0 +1,31 @@
+mod common;
+
+use common::{Sandbox, file_url, git, read, repository};
+
+#[test]
+fn clones_fall_back_to_the_next_mirror() {
+    let sandbox = Sandbox::new();
+    let home = sandbox.home();
+    let mirror = sandbox.path("mirror/dots");
+    repository(&mirror, &[(".zshrc", "export EDITOR=vim")]);
+    // Nothing was ever pushed to the source itself
+    let source = file_url(&sandbox.path("dots"));
+    let clone = home.join(".dotme/git/dots");
+    sandbox.write_config(&format!(
+        "dotfiles:\n  - source: {}\n    type: git\n    target: {}\n    path: {}\n    mirrors:\n      - {}\n",
+        source,
+        clone.display(),
+        home.display(),
+        file_url(&mirror)
+    ));
+
+    let output = sandbox.ok(&["update"]);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(&format!("Failed to reach {}", source))
+    );
+    assert_eq!(read(&home.join(".zshrc")), "export EDITOR=vim");
+    assert_eq!(
+        git(&clone, &["remote", "get-url", "origin"]),
+        file_url(&mirror)
+    );
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::{Sandbox, commit, file_url, git, read, repository};
+
+#[test]
+fn pinned_entries_stay_put_until_unpinned() {
+    let sandbox = Sandbox::new();
+    let remote = sandbox.path("dots");
+    repository(&remote, &[(".zshrc", "export EDITOR=vi")]);
+    let pinned = git(&remote, &["rev-parse", "HEAD"]);
+    let latest = commit(&remote, &[(".zshrc", "export EDITOR=vim")], "Use vim");
+    let (source, home) = (file_url(&remote), sandbox.home());
+    let clone = home.join(".dotme/git/dots");
+    let entry = format!(
+        "dotfiles:\n  - source: {}\n    type: git\n    target: {}\n    path: {}\n",
+        source,
+        clone.display(),
+        home.display()
+    );
+
+    sandbox.write_config(&format!("{}    rev: {}\n", entry, pinned));
+    sandbox.ok(&["update"]);
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), pinned);
+    assert_eq!(read(&home.join(".zshrc")), "export EDITOR=vi");
+
+    // Neither new commits nor moving the checkout get past the pin
+    commit(&remote, &[(".bashrc", "set -o vi")], "Add bashrc");
+    git(&clone, &["checkout", "--quiet", &latest]);
+    sandbox.ok(&["update"]);
+    assert_eq!(git(&clone, &["rev-parse", "HEAD"]), pinned);
+    assert!(!home.join(".bashrc").exists());
+
+    sandbox.write_config(&entry);
+    sandbox.ok(&["update"]);
+    assert_ne!(git(&clone, &["rev-parse", "HEAD"]), pinned);
+    assert_eq!(read(&home.join(".zshrc")), "export EDITOR=vim");
+    assert!(home.join(".bashrc").is_symlink());
+}
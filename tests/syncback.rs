@@ -0,0 +1,61 @@
+mod common;
+
+use common::{Sandbox, read};
+
+/// Sandbox with `.vimrc` of a local source deployed as a copy
+fn deployed() -> Sandbox {
+    let sandbox = Sandbox::new();
+    let source = sandbox.path("dotfiles");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join(".vimrc"), "set number").unwrap();
+    let home = sandbox.home();
+
+    sandbox.ok(&["init"]);
+    sandbox.ok(&[
+        "add",
+        source.to_str().unwrap(),
+        "--path",
+        home.to_str().unwrap(),
+        "--mode",
+        "copy",
+    ]);
+    assert_eq!(read(&home.join(".vimrc")), "set number");
+    sandbox
+}
+
+#[test]
+fn edited_copies_are_synced_back_into_their_source() {
+    let sandbox = deployed();
+    let (source, copy) = (
+        sandbox.path("dotfiles/.vimrc"),
+        sandbox.home().join(".vimrc"),
+    );
+    std::fs::write(&copy, "set relativenumber").unwrap();
+
+    sandbox.ok(&["syncback", "--dry-run"]);
+    assert_eq!(read(&source), "set number");
+
+    sandbox.ok(&["syncback"]);
+    assert_eq!(read(&source), "set relativenumber");
+    assert_eq!(read(&copy), "set relativenumber");
+
+    // The synced copy counts as deployed again
+    let output = sandbox.ok(&["syncback"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Nothing to sync back"));
+}
+
+#[test]
+fn copies_are_not_synced_back_when_their_source_changed_too() {
+    let sandbox = deployed();
+    let (source, copy) = (
+        sandbox.path("dotfiles/.vimrc"),
+        sandbox.home().join(".vimrc"),
+    );
+    std::fs::write(&copy, "set relativenumber").unwrap();
+    std::fs::write(&source, "set nonumber").unwrap();
+
+    let output = sandbox.ok(&["syncback"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("its source changed as well"));
+    assert_eq!(read(&source), "set nonumber");
+    assert_eq!(read(&copy), "set relativenumber");
+}